@@ -1,9 +1,13 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::config::StorageDir;
+use crate::block_validator::TransactionValidator;
+use crate::config::{MempoolParameters, StorageDir};
+use crate::consensus::linearizer::CommittedSubDag;
 use crate::data::Data;
+use crate::fast_path::{FastPathCertificates, TransactionCertificate, VoteCollector};
 use crate::log::TransactionLog;
+use crate::mempool::Mempool;
 use crate::metrics::UtilizationTimerExt;
 use crate::metrics::UtilizationTimerVecExt;
 use crate::runtime::TimeInstant;
@@ -18,10 +22,11 @@ use crate::{
     runtime,
 };
 use minibytes::Bytes;
+use parking_lot::Mutex;
 use std::env;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
 pub trait BlockHandler: Send + Sync {
     fn handle_blocks(
@@ -32,6 +37,9 @@ pub trait BlockHandler: Send + Sync {
 
     fn handle_proposal(&mut self, block: &Data<StatementBlock>);
 
+    /// Called with the sub-dags committed since the last call.
+    fn handle_commit(&mut self, _committed: &[CommittedSubDag]) {}
+
     fn state(&self) -> Bytes;
 
     fn recover_state(&mut self, _state: &Bytes);
@@ -58,13 +66,12 @@ pub struct BenchmarkFastPathBlockHandler {
     block_store: BlockStore,
     metrics: Arc<Metrics>,
     receiver: mpsc::Receiver<Vec<Transaction>>,
-    pending_transactions: usize,
+    mempool: Mempool,
     consensus_only: bool,
 }
 
-// BenchmarkFastPathBlockHandler can push up to 2x of SOFT_MAX_PROPOSED_PER_BLOCK into block
-// So the value here should be chosen so that 2*SOFT_MAX_PROPOSED_PER_BLOCK*TRANSACTION_SIZE
-// is lower then the maximum allowed block size in the system
+// Generated transactions are only received while fewer transactions than this wait in the mempool,
+// so that the generator is held back by the channel instead of having its transactions evicted
 #[cfg(not(test))]
 const SOFT_MAX_PROPOSED_PER_BLOCK: usize = 10 * 1000;
 #[cfg(test)]
@@ -78,6 +85,7 @@ impl BenchmarkFastPathBlockHandler {
         block_store: BlockStore,
         metrics: Arc<Metrics>,
         transaction_time: TransactionTimeMap,
+        mempool: MempoolParameters,
     ) -> (Self, mpsc::Sender<Vec<Transaction>>) {
         let (sender, receiver) = mpsc::channel(1024);
         let transaction_log = TransactionLog::start(config.certified_transactions_log())
@@ -92,21 +100,43 @@ impl BenchmarkFastPathBlockHandler {
             block_store,
             metrics,
             receiver,
-            pending_transactions: 0, // todo - need to initialize correctly when loaded from disk
+            mempool: Mempool::new(mempool),
             consensus_only,
         };
         (this, sender)
     }
+
+    /// Block handler for the next epoch, which keeps receiving the transactions of this one.
+    pub fn next_epoch(
+        self,
+        committee: Arc<Committee>,
+        config: &StorageDir,
+        block_store: BlockStore,
+    ) -> Self {
+        let transaction_log = TransactionLog::start(config.certified_transactions_log())
+            .expect("Failed to open certified transaction log for write");
+        Self {
+            transaction_votes: TransactionAggregator::with_handler(transaction_log),
+            committee,
+            block_store,
+            ..self
+        }
+    }
 }
 
 impl BenchmarkFastPathBlockHandler {
-    fn receive_with_limit(&mut self) -> Option<Vec<Transaction>> {
-        if self.pending_transactions >= SOFT_MAX_PROPOSED_PER_BLOCK {
-            return None;
+    fn receive_with_limit(&mut self) {
+        while self.mempool.pending_transactions() < SOFT_MAX_PROPOSED_PER_BLOCK {
+            let Ok(received) = self.receiver.try_recv() else {
+                return;
+            };
+            for transaction in received {
+                // Generated transactions have no submitter to notify
+                if let Err(error) = self.mempool.insert(transaction, 0, None) {
+                    tracing::debug!("Generated transaction rejected by the mempool: {error:?}");
+                }
+            }
         }
-        let received = self.receiver.try_recv().ok()?;
-        self.pending_transactions += received.len();
-        Some(received)
     }
 
     /// Expose a metric for certified transactions.
@@ -154,13 +184,18 @@ impl BlockHandler for BenchmarkFastPathBlockHandler {
             .metrics
             .utilization_timer
             .utilization_timer("BlockHandler::handle_blocks");
+        for block in blocks {
+            self.mempool.included(block);
+        }
+        self.receive_with_limit();
         let mut response = vec![];
         if require_response {
-            while let Some(data) = self.receive_with_limit() {
-                for tx in data {
-                    response.push(BaseStatement::Share(tx));
-                }
-            }
+            response.extend(
+                self.mempool
+                    .next_batch()
+                    .into_iter()
+                    .map(BaseStatement::Share),
+            );
         }
         let transaction_time = self.transaction_time.lock();
         for block in blocks {
@@ -190,8 +225,7 @@ impl BlockHandler for BenchmarkFastPathBlockHandler {
     }
 
     fn handle_proposal(&mut self, block: &Data<StatementBlock>) {
-        // todo - this is not super efficient
-        self.pending_transactions -= block.shared_transactions().count();
+        self.mempool.proposed(block);
         let mut transaction_time = self.transaction_time.lock();
         for (locator, _) in block.shared_transactions() {
             transaction_time.insert(locator, TimeInstant::now());
@@ -204,6 +238,10 @@ impl BlockHandler for BenchmarkFastPathBlockHandler {
         }
     }
 
+    fn handle_commit(&mut self, committed: &[CommittedSubDag]) {
+        self.mempool.committed(committed);
+    }
+
     fn state(&self) -> Bytes {
         self.transaction_votes.state()
     }
@@ -332,22 +370,36 @@ impl BlockHandler for TestBlockHandler {
     }
 }
 
+/// Transaction submitted to the validator by a client.
+pub struct TransactionSubmission {
+    pub transaction: Vec<u8>,
+    /// Transactions with a higher priority, e.g. paying a higher fee, are proposed first.
+    pub priority: u64,
+    /// Receives the locator of the transaction once it is in a block.
+    pub notify_when_proposed: oneshot::Sender<TransactionLocator>,
+}
+
 pub struct SimpleBlockHandler {
-    receiver: mpsc::Receiver<(Vec<u8>, tokio::sync::oneshot::Sender<()>)>,
+    receiver: mpsc::Receiver<TransactionSubmission>,
+    mempool: Mempool,
 }
 
-const MAX_PROPOSED_PER_BLOCK: usize = 10000;
 const CHANNEL_SIZE: usize = 1024;
 
 impl SimpleBlockHandler {
-    #[allow(clippy::type_complexity)]
-    pub fn new() -> (
-        Self,
-        mpsc::Sender<(Vec<u8>, tokio::sync::oneshot::Sender<()>)>,
-    ) {
+    pub fn new() -> (Self, mpsc::Sender<TransactionSubmission>) {
+        Self::with_mempool(MempoolParameters::default())
+    }
+
+    pub fn with_mempool(
+        parameters: MempoolParameters,
+    ) -> (Self, mpsc::Sender<TransactionSubmission>) {
         let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
 
-        let this = Self { receiver };
+        let this = Self {
+            receiver,
+            mempool: Mempool::new(parameters),
+        };
         (this, sender)
     }
 }
@@ -355,33 +407,46 @@ impl SimpleBlockHandler {
 impl BlockHandler for SimpleBlockHandler {
     fn handle_blocks(
         &mut self,
-        _blocks: &[Data<StatementBlock>],
+        blocks: &[Data<StatementBlock>],
         require_response: bool,
     ) -> Vec<BaseStatement> {
+        for block in blocks {
+            self.mempool.included(block);
+        }
+        while let Ok(submission) = self.receiver.try_recv() {
+            // The submitter is notified of the rejection by dropping the notification channel
+            if let Err(error) = self.mempool.insert(
+                Transaction::new(submission.transaction),
+                submission.priority,
+                Some(submission.notify_when_proposed),
+            ) {
+                tracing::debug!(
+                    "Submitted transaction rejected by the mempool with {} transactions ({} bytes): {error:?}",
+                    self.mempool.pending_transactions(),
+                    self.mempool.pending_bytes()
+                );
+            }
+        }
         if !require_response {
             return vec![];
         }
 
         // Returns transactions to be sequenced so that they will be
         // proposed to DAG shortly.
-        let mut response = vec![];
-
-        while let Ok((tx_bytes, notify_when_done)) = self.receiver.try_recv() {
-            response.push(BaseStatement::Share(
-                // tx_bytes is bcs-serialized bytes of ConsensusTransaction
-                Transaction::new(tx_bytes),
-            ));
-            // We don't mind if the receiver is dropped.
-            notify_when_done.send(()).ok();
+        self.mempool
+            .next_batch()
+            .into_iter()
+            .map(BaseStatement::Share)
+            .collect()
+    }
 
-            if response.len() >= MAX_PROPOSED_PER_BLOCK {
-                break;
-            }
-        }
-        response
+    fn handle_proposal(&mut self, block: &Data<StatementBlock>) {
+        self.mempool.proposed(block);
     }
 
-    fn handle_proposal(&mut self, _block: &Data<StatementBlock>) {}
+    fn handle_commit(&mut self, committed: &[CommittedSubDag]) {
+        self.mempool.committed(committed);
+    }
 
     // No crash recovery at the moment.
     fn state(&self) -> Bytes {
@@ -393,3 +458,150 @@ impl BlockHandler for SimpleBlockHandler {
 
     fn cleanup(&self) {}
 }
+
+/// Block handler for production validators: proposes the submitted transactions like
+/// [`SimpleBlockHandler`], votes on the transactions of every block it receives as decided by the
+/// transaction validator, and publishes a certificate for each transaction certified or rejected
+/// on the fast path.
+pub struct FastPathBlockHandler {
+    submissions: SimpleBlockHandler,
+    // Only accessed through `&mut self`, the mutex makes the handler `Sync` without locking
+    transaction_validator: Mutex<Box<dyn TransactionValidator>>,
+    transaction_votes: TransactionAggregator<QuorumThreshold, VoteCollector>,
+    committee: Arc<Committee>,
+    block_store: BlockStore,
+    certificates: FastPathCertificates,
+}
+
+impl FastPathBlockHandler {
+    pub fn new(
+        committee: Arc<Committee>,
+        block_store: BlockStore,
+        transaction_validator: impl TransactionValidator,
+        mempool: MempoolParameters,
+    ) -> (
+        Self,
+        mpsc::Sender<TransactionSubmission>,
+        FastPathCertificates,
+    ) {
+        let (submissions, sender) = SimpleBlockHandler::with_mempool(mempool);
+        let certificates = FastPathCertificates::default();
+        let this = Self {
+            submissions,
+            transaction_validator: Mutex::new(Box::new(transaction_validator)),
+            transaction_votes: Default::default(),
+            committee,
+            block_store,
+            certificates: certificates.clone(),
+        };
+        (this, sender, certificates)
+    }
+
+    /// Block handler for the next epoch. Transactions still pending at the end of the epoch are not
+    /// certified on the fast path.
+    pub fn next_epoch(mut self, committee: Arc<Committee>, block_store: BlockStore) -> Self {
+        self.transaction_votes = Default::default();
+        self.committee = committee;
+        self.block_store = block_store;
+        self
+    }
+
+    fn publish_certificates(&mut self, proposed: Option<&Data<StatementBlock>>) {
+        for collected in self.transaction_votes.handler_mut().take_certified() {
+            let votes: Option<Vec<_>> = collected
+                .voters
+                .into_iter()
+                .map(|reference| {
+                    // The own block being proposed is not in the block store yet
+                    let signature = match proposed {
+                        Some(block) if *block.reference() == reference => *block.signature(),
+                        _ => *self.block_store.get_block(reference)?.signature(),
+                    };
+                    Some((reference, signature))
+                })
+                .collect();
+            // Voting blocks below the store horizon may already be pruned, a certificate missing
+            // their signatures could not be verified
+            let Some(votes) = votes else {
+                tracing::warn!(
+                    "Dropped certificate of {:?}, a voting block is no longer in the block store",
+                    collected.locator
+                );
+                continue;
+            };
+            self.certificates.add(TransactionCertificate {
+                locator: collected.locator,
+                rejected: collected.rejected,
+                votes,
+            });
+        }
+    }
+}
+
+impl BlockHandler for FastPathBlockHandler {
+    fn handle_blocks(
+        &mut self,
+        blocks: &[Data<StatementBlock>],
+        require_response: bool,
+    ) -> Vec<BaseStatement> {
+        let mut response = self.submissions.handle_blocks(blocks, require_response);
+        for block in blocks {
+            let response_option: Option<&mut Vec<BaseStatement>> = if require_response {
+                Some(&mut response)
+            } else {
+                None
+            };
+            self.transaction_votes.process_block_with_validator(
+                block,
+                response_option,
+                &self.committee,
+                self.transaction_validator.get_mut().as_mut(),
+            );
+        }
+        self.publish_certificates(None);
+        response
+    }
+
+    fn handle_proposal(&mut self, block: &Data<StatementBlock>) {
+        self.submissions.handle_proposal(block);
+        // Own blocks are not handed to handle_blocks, this counts the own votes they carry
+        self.transaction_votes
+            .process_block(block, None, &self.committee);
+        self.publish_certificates(Some(block));
+    }
+
+    fn handle_commit(&mut self, committed: &[CommittedSubDag]) {
+        self.submissions.handle_commit(committed);
+    }
+
+    fn state(&self) -> Bytes {
+        let state = (
+            &self.transaction_votes.state(),
+            self.transaction_votes.handler(),
+        );
+        let bytes =
+            bincode::serialize(&state).expect("Failed to serialize transaction aggregator state");
+        bytes.into()
+    }
+
+    fn recover_state(&mut self, state: &Bytes) {
+        // Validators that ran the SimpleBlockHandler have an empty state
+        if state.is_empty() {
+            return;
+        }
+        let (transaction_votes, collector) = bincode::deserialize(state)
+            .expect("Failed to deserialize transaction aggregator state");
+        self.transaction_votes.with_state(&transaction_votes);
+        *self.transaction_votes.handler_mut() = collector;
+    }
+
+    fn cleanup(&self) {
+        self.certificates.cleanup();
+    }
+}
+
+impl Drop for FastPathBlockHandler {
+    fn drop(&mut self) {
+        self.certificates.close();
+    }
+}
@@ -3,11 +3,12 @@
 
 use crate::block_store::{BlockStore, BlockWriter};
 use crate::data::Data;
+use crate::equivocation::EquivocationProof;
 use crate::metrics::Metrics;
 use crate::wal::WalPosition;
 use crate::{
     committee::Committee,
-    types::{BlockReference, StatementBlock},
+    types::{format_authority_index, BlockReference, StatementBlock},
 };
 use std::{
     collections::{HashMap, HashSet, VecDeque},
@@ -59,13 +60,16 @@ impl BlockManager {
         let mut newly_blocks_processed: Vec<(WalPosition, Data<StatementBlock>)> = vec![];
         // missing references that we see them for first time
         let mut missing_references = HashSet::new();
+        // Blocks below the horizon might have been pruned, they are considered committed and are never fetched
+        let horizon = self.block_store.horizon();
         while let Some(block) = blocks.pop_front() {
             // Update the highest known round number.
 
             // check whether we have already processed this block and skip it if so.
             let block_reference = block.reference();
 
-            if self.block_store.block_exists(*block_reference)
+            if block_reference.round < horizon
+                || self.block_store.block_exists(*block_reference)
                 || self.blocks_pending.contains_key(block_reference)
             {
                 continue;
@@ -74,7 +78,9 @@ impl BlockManager {
             let mut processed = true;
             for included_reference in block.includes() {
                 // If we are missing a reference then we insert into pending and update the waiting index
-                if !self.block_store.block_exists(*included_reference) {
+                if included_reference.round >= horizon
+                    && !self.block_store.block_exists(*included_reference)
+                {
                     processed = false;
 
                     // we inserted the missing reference for the first time and the block has not been
@@ -106,6 +112,7 @@ impl BlockManager {
                 let block_reference = *block_reference;
 
                 // Block can be processed. So need to update indexes etc
+                self.check_equivocation(&block, block_writer);
                 let position = block_writer.insert_block(block.clone());
                 newly_blocks_processed.push((position, block.clone()));
 
@@ -135,6 +142,46 @@ impl BlockManager {
         (newly_blocks_processed, missing_references)
     }
 
+    /// Records an equivocation proof if the store already holds a different block from the same
+    /// author at the same round. The block itself is still accepted: equivocations are handled by
+    /// the consensus rules, the proof only serves as evidence against the author.
+    fn check_equivocation(
+        &self,
+        block: &Data<StatementBlock>,
+        block_writer: &mut impl BlockWriter,
+    ) {
+        let (author, round) = block.author_round();
+        if !self
+            .block_store
+            .block_exists_at_authority_round(author, round)
+            || self.block_store.equivocation_exists(author, round)
+        {
+            return;
+        }
+        let Some(existing) = self
+            .block_store
+            .get_blocks_at_authority_round(author, round)
+            .into_iter()
+            .find(|existing| existing.digest() != block.digest())
+        else {
+            return;
+        };
+        let Some(proof) = EquivocationProof::new(existing, block.clone()) else {
+            return;
+        };
+        tracing::warn!(
+            "Authority {} equivocated at round {round}: {} and {}",
+            format_authority_index(author),
+            proof.first().reference(),
+            proof.second().reference()
+        );
+        self.metrics
+            .equivocations_detected
+            .with_label_values(&[&author.to_string()])
+            .inc();
+        block_writer.insert_equivocation(proof);
+    }
+
     pub fn missing_blocks(&self) -> &[HashSet<BlockReference>] {
         &self.missing
     }
@@ -147,8 +194,10 @@ impl BlockManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::BlockDigest;
     use crate::test_util::TestBlockWriter;
     use crate::types::Dag;
+    use crate::wal::walf;
     use prometheus::Registry;
     use rand::prelude::StdRng;
     use rand::SeedableRng;
@@ -227,6 +276,83 @@ mod tests {
         assert_eq!(bm.block_store.len_expensive(), dag.len());
     }
 
+    #[test]
+    fn test_block_manager_detects_equivocation() {
+        let (metrics, _reporter) = Metrics::new(&Registry::new(), None);
+        let dag = Dag::draw("A1:[A0, B0]; B1:[A0, B0]").add_genesis_blocks();
+        let committee = dag.committee();
+        let file = tempfile::tempfile().unwrap();
+        let (mut wal_writer, wal_reader) = walf(file.try_clone().unwrap()).unwrap();
+        let (recovered, _) = BlockStore::open(
+            0,
+            Arc::new(wal_reader),
+            &wal_writer,
+            metrics.clone(),
+            &committee,
+        );
+        let block_store = recovered.block_store;
+        let mut bm = BlockManager::new(block_store.clone(), &committee, metrics.clone());
+
+        let blocks: Vec<_> = dag.iter_rev().cloned().collect();
+        let b1 = blocks
+            .iter()
+            .find(|b| b.author_round() == (1, 1))
+            .unwrap()
+            .clone();
+        bm.add_blocks(blocks, &mut (&mut wal_writer, &block_store));
+        assert!(block_store.equivocations().is_empty());
+
+        let b1_equivocation =
+            Data::new(StatementBlock::clone(&b1).with_test_digest(BlockDigest::new_test(1)));
+        let (processed, _missing) = bm.add_blocks(
+            vec![b1_equivocation.clone()],
+            &mut (&mut wal_writer, &block_store),
+        );
+        assert_eq!(processed.len(), 1);
+        let equivocations = block_store.equivocations_by(1);
+        assert_eq!(equivocations.len(), 1);
+        assert_eq!(equivocations[0].round(), 1);
+        assert_eq!(equivocations[0].first().reference(), b1.reference());
+        assert_eq!(
+            equivocations[0].second().reference(),
+            b1_equivocation.reference()
+        );
+        assert!(block_store.equivocations_by(0).is_empty());
+        assert_eq!(
+            metrics
+                .equivocations_detected
+                .with_label_values(&["1"])
+                .get(),
+            1
+        );
+
+        // A third block for the same slot does not produce another proof
+        let b1_equivocation =
+            Data::new(StatementBlock::clone(&b1).with_test_digest(BlockDigest::new_test(2)));
+        bm.add_blocks(vec![b1_equivocation], &mut (&mut wal_writer, &block_store));
+        assert_eq!(block_store.equivocations().len(), 1);
+
+        // The proof is recovered from the wal
+        drop(wal_writer);
+        let (wal_writer, wal_reader) = walf(file).unwrap();
+        let (recovered, _) =
+            BlockStore::open(0, Arc::new(wal_reader), &wal_writer, metrics, &committee);
+        let equivocations = recovered.block_store.equivocations();
+        assert_eq!(equivocations.len(), 1);
+        assert_eq!(equivocations[0].author(), 1);
+        assert_eq!(equivocations[0].round(), 1);
+
+        // Proofs below the horizon are dropped with the blocks, and not recorded again
+        recovered.block_store.prune(2, WalPosition::default());
+        assert!(recovered.block_store.equivocations().is_empty());
+        let b1_equivocation =
+            Data::new(StatementBlock::clone(&b1).with_test_digest(BlockDigest::new_test(3)));
+        recovered
+            .block_store
+            .insert_equivocation(EquivocationProof::new(b1, b1_equivocation).unwrap());
+        assert!(recovered.block_store.equivocations().is_empty());
+    }
+
     fn rng(s: u8) -> StdRng {
         let mut seed = [0; 32];
         seed[0] = s;
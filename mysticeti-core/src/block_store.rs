@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::commit_observer::CommitObserverRecoveredState;
+use crate::equivocation::EquivocationProof;
 use crate::metrics::{Metrics, UtilizationTimerExt};
 use crate::state::{CoreRecoveredState, RecoveredStateBuilder};
 use crate::types::{AuthorityIndex, BlockDigest, BlockReference, RoundNumber, StatementBlock};
@@ -33,11 +34,16 @@ struct BlockStoreInner {
     authority: AuthorityIndex,
     last_seen_by_authority: Vec<RoundNumber>,
     last_own_block: Option<BlockReference>,
+    // At most one proof is kept per (author, round) slot, and only at or above the horizon
+    equivocations: BTreeMap<(AuthorityIndex, RoundNumber), EquivocationProof>,
+    // Blocks below this round might have been dropped from the wal, see BlockStore::prune
+    horizon: RoundNumber,
 }
 
 pub trait BlockWriter {
     fn insert_block(&mut self, block: Data<StatementBlock>) -> WalPosition;
     fn insert_own_block(&mut self, block: &OwnBlockData);
+    fn insert_equivocation(&mut self, proof: EquivocationProof);
 }
 
 #[derive(Clone)]
@@ -46,6 +52,14 @@ enum IndexEntry {
     Loaded(WalPosition, Data<StatementBlock>),
 }
 
+impl IndexEntry {
+    fn position(&self) -> WalPosition {
+        match self {
+            IndexEntry::WalPosition(position) | IndexEntry::Loaded(position, _) => *position,
+        }
+    }
+}
+
 impl BlockStore {
     pub fn open(
         authority: AuthorityIndex,
@@ -95,6 +109,26 @@ impl BlockStore {
                     builder.commit_data(commit_data, state);
                     continue;
                 }
+                WAL_ENTRY_EQUIVOCATION => {
+                    let proof = bincode::deserialize(&data)
+                        .expect("Failed to deserialize equivocation proof from wal");
+                    inner.add_equivocation(proof);
+                    continue;
+                }
+                WAL_ENTRY_LEADER_SCHEDULE => {
+                    builder.leader_schedule(data);
+                    continue;
+                }
+                WAL_ENTRY_EPOCH_CHANGE => {
+                    builder.epoch_change(data);
+                    continue;
+                }
+                WAL_ENTRY_HORIZON => {
+                    let horizon = bincode::deserialize(&data)
+                        .expect("Failed to deserialize horizon from wal");
+                    inner.raise_horizon(horizon);
+                    continue;
+                }
                 _ => panic!("Unknown wal tag {tag} at position {pos}"),
             };
             // todo - we want to keep some last blocks in the cache
@@ -247,6 +281,58 @@ impl BlockStore {
         self.inner.read().last_own_block()
     }
 
+    /// Round below which blocks might be missing from the store, because they were pruned or
+    /// because the validator was bootstrapped from a snapshot. Such blocks are considered committed.
+    pub fn horizon(&self) -> RoundNumber {
+        self.inner.read().horizon
+    }
+
+    /// Lowest wal position of the blocks at or above the given round.
+    pub fn first_position_from_round(&self, round: RoundNumber) -> Option<WalPosition> {
+        self.inner
+            .read()
+            .index
+            .range(round..)
+            .flat_map(|(_, map)| map.values())
+            .map(IndexEntry::position)
+            .min()
+    }
+
+    /// Raises the horizon and removes blocks stored before the given wal position from the index,
+    /// so that the wal segments holding them can be dropped. All blocks at or above the horizon
+    /// must be stored at or after the position. Equivocation proofs below the horizon are dropped.
+    pub fn prune(&self, horizon: RoundNumber, before: WalPosition) -> usize {
+        let pruned = self.inner.write().prune(horizon, before);
+        self.metrics.block_store_pruned_blocks.inc_by(pruned as u64);
+        pruned
+    }
+
+    pub fn insert_equivocation(&self, proof: EquivocationProof) {
+        self.inner.write().add_equivocation(proof);
+    }
+
+    pub fn equivocation_exists(&self, authority: AuthorityIndex, round: RoundNumber) -> bool {
+        self.inner
+            .read()
+            .equivocations
+            .contains_key(&(authority, round))
+    }
+
+    /// Returns all equivocation proofs known to this node, ordered by (author, round).
+    pub fn equivocations(&self) -> Vec<EquivocationProof> {
+        self.inner.read().equivocations.values().cloned().collect()
+    }
+
+    /// Returns equivocation proofs against the given authority, ordered by round.
+    pub fn equivocations_by(&self, authority: AuthorityIndex) -> Vec<EquivocationProof> {
+        self.inner
+            .read()
+            .equivocations
+            .range((authority, RoundNumber::MIN)..=(authority, RoundNumber::MAX))
+            .map(|(_, proof)| proof.clone())
+            .collect()
+    }
+
     fn read_index(&self, entry: IndexEntry) -> Data<StatementBlock> {
         match entry {
             IndexEntry::WalPosition(position) => {
@@ -369,6 +455,25 @@ impl BlockStoreInner {
             .cloned()
     }
 
+    pub fn prune(&mut self, horizon: RoundNumber, before: WalPosition) -> usize {
+        self.raise_horizon(horizon);
+        let mut pruned = 0usize;
+        for (round, map) in self.index.range_mut(..horizon) {
+            map.retain(|(authority, digest), entry| {
+                if entry.position() >= before {
+                    return true;
+                }
+                if *authority == self.authority && self.own_blocks.get(round) == Some(digest) {
+                    self.own_blocks.remove(round);
+                }
+                pruned += 1;
+                false
+            });
+        }
+        self.index.retain(|_, map| !map.is_empty());
+        pruned
+    }
+
     // todo - also specify LRU criteria
     /// Unload all entries from below or equal threshold_round
     pub fn unload_below_round(&mut self, threshold_round: RoundNumber) -> usize {
@@ -491,6 +596,22 @@ impl BlockStoreInner {
     pub fn last_own_block(&self) -> Option<BlockReference> {
         self.last_own_block
     }
+
+    /// Raises the horizon and drops the equivocation proofs below it, like the blocks they prove.
+    fn raise_horizon(&mut self, horizon: RoundNumber) {
+        self.horizon = max(self.horizon, horizon);
+        let horizon = self.horizon;
+        self.equivocations.retain(|(_, round), _| *round >= horizon);
+    }
+
+    pub fn add_equivocation(&mut self, proof: EquivocationProof) {
+        if proof.round() < self.horizon {
+            return;
+        }
+        self.equivocations
+            .entry((proof.author(), proof.round()))
+            .or_insert(proof);
+    }
 }
 
 pub const WAL_ENTRY_BLOCK: Tag = 1;
@@ -500,6 +621,14 @@ pub const WAL_ENTRY_STATE: Tag = 4;
 // Commit entry includes both commit interpreter incremental state and committed transactions aggregator
 // todo - They could be separated for better performance, but this will require catching up for committed transactions aggregator state
 pub const WAL_ENTRY_COMMIT: Tag = 5;
+pub const WAL_ENTRY_EQUIVOCATION: Tag = 6;
+// Blocks below the horizon round are not (or no longer) in the wal, written when the wal is
+// seeded from a snapshot and when old wal segments are dropped
+pub const WAL_ENTRY_HORIZON: Tag = 7;
+// State of the leader schedule, written right before the commit entry it corresponds to
+pub const WAL_ENTRY_LEADER_SCHEDULE: Tag = 8;
+// Decision to close the epoch, carrying the committee of the next epoch. Last entry of the epoch
+pub const WAL_ENTRY_EPOCH_CHANGE: Tag = 9;
 
 impl BlockWriter for (&mut WalWriter, &BlockStore) {
     fn insert_block(&mut self, block: Data<StatementBlock>) -> WalPosition {
@@ -515,6 +644,14 @@ impl BlockWriter for (&mut WalWriter, &BlockStore) {
         let block_pos = data.write_to_wal(self.0);
         self.1.insert_block(data.block.clone(), block_pos);
     }
+
+    fn insert_equivocation(&mut self, proof: EquivocationProof) {
+        let data = bincode::serialize(&proof).expect("Serialization failed");
+        self.0
+            .write(WAL_ENTRY_EQUIVOCATION, &data)
+            .expect("Writing to wal failed");
+        self.1.insert_equivocation(proof);
+    }
 }
 
 // This data structure has a special serialization in/from Bytes, see OwnBlockData::from_bytes/write_to_wal
@@ -549,7 +686,7 @@ impl OwnBlockData {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 /// CommitData is a serializable version of the CommittedSubDag.
 /// Main difference is that CommittedSubDag has Data<Block> and can be used by downstream consensus handler.
 /// CommitData instead only stores BlockReference, and can be written to the wal.
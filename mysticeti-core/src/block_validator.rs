@@ -3,7 +3,7 @@
 
 use async_trait::async_trait;
 
-use crate::types::StatementBlock;
+use crate::types::{StatementBlock, Transaction, TransactionLocator, Vote};
 
 /// The interfaces to verify the legitimacy of a statement block's contents.
 #[async_trait]
@@ -25,3 +25,20 @@ impl BlockVerifier for AcceptAllBlockVerifier {
         Ok(())
     }
 }
+
+/// Application defined validity of the transactions shared by the blocks of other authorities.
+pub trait TransactionValidator: Send + 'static {
+    /// Decides how to vote on a transaction. A rejection can name the transaction it conflicts
+    /// with.
+    fn vote(&mut self, locator: TransactionLocator, transaction: &Transaction) -> Vote;
+}
+
+/// Simple validator that votes to accept all transactions.
+#[derive(Clone)]
+pub struct AcceptAllTransactionValidator;
+
+impl TransactionValidator for AcceptAllTransactionValidator {
+    fn vote(&mut self, _locator: TransactionLocator, _transaction: &Transaction) -> Vote {
+        Vote::Accept
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Adversarial authorities for the simulated network. A byzantine authority runs an honest core,
+//! but every message it sends goes through its behavior, which can rewrite, drop or add own blocks.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::crypto::{BlockDigest, Signer};
+use crate::data::Data;
+use crate::network::NetworkMessage;
+use crate::types::{AuthorityIndex, BaseStatement, BlockReference, StatementBlock, Vote};
+
+/// Number of own blocks remembered per peer to replay them later.
+const REPLAYED_BLOCKS: usize = 16;
+
+#[derive(Clone, Debug)]
+pub enum ByzantineBehavior {
+    /// Sends a second block for every round to the upper half of the committee, ahead of the
+    /// block it actually built on.
+    Equivocate,
+    /// Never sends its blocks to the given authorities, directly or in sync responses.
+    Withhold(Vec<AuthorityIndex>),
+    /// Sends the upper half of the committee a second block for every round that rejects the
+    /// transactions its block accepts, ahead of the block it actually built on.
+    ConflictingVotes,
+    /// Votes both to accept and to reject every transaction it accepts in the same block, which
+    /// fails verification.
+    InvalidVotes,
+    /// Includes a reference to a block that does not exist in every block.
+    BadIncludes,
+    /// Sends its blocks of earlier rounds again along with every new block.
+    ReplayOldRounds,
+}
+
+/// Outgoing messages from a byzantine authority to a single peer.
+pub struct ByzantineLink {
+    behavior: ByzantineBehavior,
+    signer: Arc<Signer>,
+    authority: AuthorityIndex,
+    peer: AuthorityIndex,
+    committee_size: usize,
+    sent: VecDeque<Data<StatementBlock>>,
+}
+
+impl ByzantineLink {
+    pub fn new(
+        behavior: ByzantineBehavior,
+        signer: Arc<Signer>,
+        authority: AuthorityIndex,
+        peer: AuthorityIndex,
+        committee_size: usize,
+    ) -> Self {
+        Self {
+            behavior,
+            signer,
+            authority,
+            peer,
+            committee_size,
+            sent: VecDeque::with_capacity(REPLAYED_BLOCKS),
+        }
+    }
+
+    /// Rewrites a message sent to the peer, None if the message is dropped.
+    pub fn outgoing(&mut self, message: NetworkMessage) -> Option<NetworkMessage> {
+        match message {
+            NetworkMessage::Blocks(blocks) => {
+                let blocks = self.blocks(blocks);
+                (!blocks.is_empty()).then_some(NetworkMessage::Blocks(blocks))
+            }
+            NetworkMessage::RequestBlocksResponse(blocks) => {
+                let blocks = self.blocks(blocks);
+                (!blocks.is_empty()).then_some(NetworkMessage::RequestBlocksResponse(blocks))
+            }
+            message => Some(message),
+        }
+    }
+
+    fn blocks(&mut self, blocks: Vec<Data<StatementBlock>>) -> Vec<Data<StatementBlock>> {
+        let mut result = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            // Blocks of other authorities are relayed untouched
+            if block.author() != self.authority {
+                result.push(block);
+                continue;
+            }
+            match &self.behavior {
+                ByzantineBehavior::Equivocate => {
+                    if self.peer as usize >= self.committee_size / 2 {
+                        // A different creation time is enough for a validly signed second block
+                        let conflicting = StatementBlock::new_with_signer(
+                            block.author(),
+                            block.round(),
+                            block.includes().clone(),
+                            block.statements().clone(),
+                            block.meta_creation_time_ns() + 1,
+                            block.epoch_changed(),
+                            block.epoch(),
+                            &self.signer,
+                        );
+                        result.push(Data::new(conflicting));
+                    }
+                    result.push(block);
+                }
+                ByzantineBehavior::Withhold(peers) => {
+                    if !peers.contains(&self.peer) {
+                        result.push(block);
+                    }
+                }
+                ByzantineBehavior::ConflictingVotes => {
+                    if self.peer as usize >= self.committee_size / 2 {
+                        let statements = block
+                            .statements()
+                            .iter()
+                            .flat_map(|statement| match statement {
+                                BaseStatement::Vote(locator, Vote::Accept) => {
+                                    vec![BaseStatement::Vote(*locator, Vote::Reject(None))]
+                                }
+                                BaseStatement::VoteRange(range) => range
+                                    .locators()
+                                    .map(|locator| BaseStatement::Vote(locator, Vote::Reject(None)))
+                                    .collect(),
+                                statement => vec![statement.clone()],
+                            })
+                            .collect();
+                        result.push(self.rebuild(&block, block.includes().clone(), statements));
+                    }
+                    result.push(block);
+                }
+                ByzantineBehavior::InvalidVotes => {
+                    // Reject votes go after the original statements so that the transactions
+                    // shared by the block keep their locators
+                    let mut statements = block.statements().clone();
+                    for statement in block.statements() {
+                        match statement {
+                            BaseStatement::Vote(locator, Vote::Accept) => {
+                                statements.push(BaseStatement::Vote(*locator, Vote::Reject(None)))
+                            }
+                            BaseStatement::VoteRange(range) => {
+                                statements.extend(range.locators().map(|locator| {
+                                    BaseStatement::Vote(locator, Vote::Reject(None))
+                                }))
+                            }
+                            _ => {}
+                        }
+                    }
+                    result.push(self.rebuild(&block, block.includes().clone(), statements));
+                }
+                ByzantineBehavior::BadIncludes => {
+                    let mut includes = block.includes().clone();
+                    includes.push(BlockReference {
+                        authority: (self.authority + 1) % self.committee_size as AuthorityIndex,
+                        round: block.round().saturating_sub(1),
+                        digest: BlockDigest::new_test(u8::MAX),
+                    });
+                    result.push(self.rebuild(&block, includes, block.statements().clone()));
+                }
+                ByzantineBehavior::ReplayOldRounds => {
+                    result.extend(self.sent.iter().cloned());
+                    if self.sent.len() == REPLAYED_BLOCKS {
+                        self.sent.pop_front();
+                    }
+                    self.sent.push_back(block.clone());
+                    result.push(block);
+                }
+            }
+        }
+        result
+    }
+
+    /// Same block with the given includes and statements, signed again by the authority.
+    fn rebuild(
+        &self,
+        block: &StatementBlock,
+        includes: Vec<BlockReference>,
+        statements: Vec<BaseStatement>,
+    ) -> Data<StatementBlock> {
+        Data::new(StatementBlock::new_with_signer(
+            block.author(),
+            block.round(),
+            includes,
+            statements,
+            block.meta_creation_time_ns(),
+            block.epoch_changed(),
+            block.epoch(),
+            &self.signer,
+        ))
+    }
+}
+
+/// Drops the own blocks relayed back to a byzantine authority by its peers. Its core only knows the
+/// blocks it built on and treats any other own block as a corrupted store.
+pub fn incoming(authority: AuthorityIndex, message: NetworkMessage) -> Option<NetworkMessage> {
+    let others_blocks = |blocks: Vec<Data<StatementBlock>>| -> Vec<_> {
+        blocks
+            .into_iter()
+            .filter(|block| block.author() != authority)
+            .collect()
+    };
+    match message {
+        NetworkMessage::Blocks(blocks) => {
+            let blocks = others_blocks(blocks);
+            (!blocks.is_empty()).then_some(NetworkMessage::Blocks(blocks))
+        }
+        NetworkMessage::RequestBlocksResponse(blocks) => {
+            let blocks = others_blocks(blocks);
+            (!blocks.is_empty()).then_some(NetworkMessage::RequestBlocksResponse(blocks))
+        }
+        message => Some(message),
+    }
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::block_store::{BlockStore, CommitData};
+use crate::commit_stream::CommitHistory;
 use crate::committee::{
     Committee, ProcessedTransactionHandler, QuorumThreshold, TransactionAggregator,
 };
@@ -204,6 +205,8 @@ pub struct SimpleCommitObserver {
     /// A channel to send committed sub-dags to the consumer of consensus output.
     /// TODO: We will need to figure out a solution to handle back pressure.
     sender: tokio::sync::mpsc::UnboundedSender<CommittedSubDag>,
+    /// Commits served to the remote subscribers of the commit stream.
+    commit_history: Option<CommitHistory>,
     metrics: Arc<Metrics>,
 }
 
@@ -223,12 +226,19 @@ impl SimpleCommitObserver {
             block_store: block_store.clone(),
             commit_interpreter: Linearizer::new(block_store),
             sender,
+            commit_history: None,
             metrics,
         };
         observer.recover_committed(last_sent_height, recover_state);
         observer
     }
 
+    /// Records the new commits in `commit_history`, which must already hold the recovered ones.
+    pub fn with_commit_history(mut self, commit_history: CommitHistory) -> Self {
+        self.commit_history = Some(commit_history);
+        self
+    }
+
     fn recover_committed(
         &mut self,
         last_sent_height: u64,
@@ -280,6 +290,9 @@ impl CommitObserver for SimpleCommitObserver {
                 tracing::error!("Failed to send committed sub-dag: {:?}", err);
             }
         }
+        if let Some(commit_history) = &self.commit_history {
+            commit_history.add(&committed);
+        }
         self.report_metrics(&committed);
         committed
     }
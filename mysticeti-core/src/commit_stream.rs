@@ -0,0 +1,260 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use tokio::sync::watch;
+
+use crate::block_store::{BlockStore, CommitData};
+use crate::consensus::linearizer::CommittedSubDag;
+use crate::types::Epoch;
+
+/// Most commits kept in the history, for when the block store is never pruned.
+const MAX_COMMITS: usize = 16 * 1024;
+
+/// Commits of the current epoch that can still be rebuilt from the block store, so that
+/// subscribers can (re)start the commit stream from any of these heights.
+/// Commits are dropped once their leader falls below the horizon of the block store, or once the
+/// history holds more than `max_commits` commits.
+#[derive(Clone)]
+pub struct CommitHistory {
+    inner: Arc<RwLock<CommitHistoryInner>>,
+    updates: Arc<watch::Sender<()>>,
+}
+
+struct CommitHistoryInner {
+    epoch: Epoch,
+    block_store: BlockStore,
+    commits: BTreeMap<u64, CommitData>,
+    max_commits: usize,
+    // Height of the commit following the last one added to the history
+    next_height: u64,
+}
+
+/// Stream of the commits of one epoch, starting from a given height.
+pub struct CommitSubscription {
+    history: CommitHistory,
+    updates: watch::Receiver<()>,
+    epoch: Epoch,
+    next_height: u64,
+}
+
+impl CommitHistory {
+    /// Creates the history of the given epoch from the commits recovered from its wal.
+    pub fn new(epoch: Epoch, block_store: BlockStore, recovered: &[CommitData]) -> Self {
+        let (updates, _) = watch::channel(());
+        let this = Self {
+            inner: Arc::new(RwLock::new(CommitHistoryInner {
+                epoch,
+                block_store,
+                commits: Default::default(),
+                max_commits: MAX_COMMITS,
+                next_height: 1,
+            })),
+            updates: Arc::new(updates),
+        };
+        this.inner.write().extend(recovered.iter().cloned());
+        this
+    }
+
+    pub fn with_max_commits(self, max_commits: usize) -> Self {
+        let mut inner = self.inner.write();
+        inner.max_commits = max_commits;
+        inner.extend(std::iter::empty());
+        drop(inner);
+        self
+    }
+
+    /// Replaces the history with the one of the next epoch. Subscriptions to the previous epoch end.
+    pub fn start_epoch(&self, epoch: Epoch, block_store: BlockStore, recovered: &[CommitData]) {
+        let mut inner = self.inner.write();
+        inner.epoch = epoch;
+        inner.block_store = block_store;
+        inner.commits.clear();
+        inner.next_height = 1;
+        inner.extend(recovered.iter().cloned());
+        drop(inner);
+        self.updates.send_replace(());
+    }
+
+    pub fn add(&self, committed: &[CommittedSubDag]) {
+        if committed.is_empty() {
+            return;
+        }
+        self.inner
+            .write()
+            .extend(committed.iter().map(CommitData::from));
+        self.updates.send_replace(());
+    }
+
+    pub fn epoch(&self) -> Epoch {
+        self.inner.read().epoch
+    }
+
+    /// Height of the first commit that is still available, or the height of the next commit if
+    /// there are no commits in the history.
+    pub fn first_height(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.first_height()
+    }
+
+    /// Subscribes to the commits of `epoch` starting from `from_height`, which can be in the
+    /// future. Returns None if `epoch` is not the current epoch, or if the commit at `from_height`
+    /// is no longer available.
+    pub fn subscribe(&self, epoch: Epoch, from_height: u64) -> Option<CommitSubscription> {
+        let updates = self.updates.subscribe();
+        let inner = self.inner.read();
+        if epoch != inner.epoch || from_height < inner.first_height() {
+            return None;
+        }
+        Some(CommitSubscription {
+            history: self.clone(),
+            updates,
+            epoch: inner.epoch,
+            next_height: from_height,
+        })
+    }
+}
+
+impl CommitHistoryInner {
+    fn extend(&mut self, commits: impl Iterator<Item = CommitData>) {
+        for commit in commits {
+            self.next_height = self.next_height.max(commit.height + 1);
+            self.commits.insert(commit.height, commit);
+        }
+        // Blocks of the leaders below the horizon might have been pruned from the store
+        let horizon = self.block_store.horizon();
+        while let Some(entry) = self.commits.first_entry() {
+            if entry.get().leader.round >= horizon {
+                break;
+            }
+            entry.remove();
+        }
+        while self.commits.len() > self.max_commits {
+            self.commits.pop_first();
+        }
+    }
+
+    fn first_height(&self) -> u64 {
+        self.commits
+            .first_key_value()
+            .map_or(self.next_height, |(height, _)| *height)
+    }
+}
+
+impl CommitSubscription {
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
+    /// Waits for the next commit. Returns None once the epoch of the subscription is over, or if
+    /// the subscriber fell so far behind that the next commit is no longer available.
+    pub async fn next(&mut self) -> Option<CommittedSubDag> {
+        loop {
+            self.updates.borrow_and_update();
+            {
+                let inner = self.history.inner.read();
+                if inner.epoch != self.epoch || self.next_height < inner.first_height() {
+                    return None;
+                }
+                if let Some(commit) = inner.commits.get(&self.next_height) {
+                    let committed =
+                        CommittedSubDag::new_from_commit_data(commit.clone(), &inner.block_store);
+                    self.next_height += 1;
+                    return Some(committed);
+                }
+            }
+            if self.updates.changed().await.is_err() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::test_util::{committee, TestBlockWriter};
+    use crate::types::StatementBlock;
+    use crate::wal::WalPosition;
+
+    fn commit(writer: &mut TestBlockWriter, height: u64) -> (CommittedSubDag, WalPosition) {
+        let block = Data::new(StatementBlock::new(
+            0,
+            height,
+            vec![],
+            vec![],
+            0,
+            false,
+            0,
+            Default::default(),
+        ));
+        let position = writer.add_block(block.clone());
+        let committed = CommittedSubDag::new(*block.reference(), vec![block], 0, height);
+        (committed, position)
+    }
+
+    #[tokio::test]
+    async fn test_commit_history_resume() {
+        let committee = committee(4);
+        let mut writer = TestBlockWriter::new(&committee);
+        let commits: Vec<_> = (1..=3).map(|h| commit(&mut writer, h).0).collect();
+        let recovered: Vec<_> = commits[..2].iter().map(CommitData::from).collect();
+        let history = CommitHistory::new(0, writer.block_store(), &recovered);
+        assert_eq!(history.first_height(), 1);
+
+        // Resume from a recovered commit, then follow the new ones
+        let mut subscription = history.subscribe(0, 2).unwrap();
+        let next = subscription.next().await.unwrap();
+        assert_eq!(next.height, 2);
+        history.add(&commits[2..]);
+        let next = subscription.next().await.unwrap();
+        assert_eq!(next.height, 3);
+        assert_eq!(next.anchor, *commits[2].blocks[0].reference());
+
+        // The subscription ends with the epoch
+        let next = tokio::spawn(async move { subscription.next().await });
+        history.start_epoch(1, writer.block_store(), &[]);
+        assert!(next.await.unwrap().is_none());
+        assert_eq!(history.first_height(), 1);
+        assert!(history.subscribe(0, 1).is_none());
+        assert!(history.subscribe(1, 1).is_some());
+    }
+
+    #[test]
+    fn test_commit_history_drops_pruned_commits() {
+        let committee = committee(4);
+        let mut writer = TestBlockWriter::new(&committee);
+        let (commits, positions): (Vec<_>, Vec<_>) =
+            (1..=3).map(|h| commit(&mut writer, h)).unzip();
+        let block_store = writer.block_store();
+        block_store.prune(2, positions[1]);
+        let recovered: Vec<_> = commits.iter().map(CommitData::from).collect();
+        let history = CommitHistory::new(0, block_store, &recovered);
+        assert_eq!(history.first_height(), 2);
+        assert!(history.subscribe(0, 1).is_none());
+
+        // The history keeps its height once all commits are pruned
+        history.inner.write().commits.clear();
+        assert_eq!(history.first_height(), 4);
+    }
+
+    #[test]
+    fn test_commit_history_max_commits() {
+        let committee = committee(4);
+        let mut writer = TestBlockWriter::new(&committee);
+        let commits: Vec<_> = (1..=5).map(|h| commit(&mut writer, h).0).collect();
+        let recovered: Vec<_> = commits[..3].iter().map(CommitData::from).collect();
+        let history = CommitHistory::new(0, writer.block_store(), &recovered).with_max_commits(2);
+        assert_eq!(history.first_height(), 2);
+
+        // The oldest commits make room for the new ones
+        history.add(&commits[3..]);
+        assert_eq!(history.first_height(), 4);
+        assert!(history.subscribe(0, 3).is_none());
+        assert!(history.subscribe(0, 4).is_some());
+    }
+}
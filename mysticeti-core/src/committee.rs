@@ -1,6 +1,7 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::block_validator::TransactionValidator;
 use crate::crypto::{dummy_public_key, PublicKey};
 use crate::range_map::RangeMap;
 use crate::types::{
@@ -193,7 +194,7 @@ impl Committee {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Authority {
     stake: Stake,
     public_key: PublicKey,
@@ -260,9 +261,18 @@ pub struct StakeAggregator<TH> {
     _phantom: PhantomData<TH>,
 }
 
-/// Tracks votes for pending transactions and outputs certified transactions to a handler
+/// Accept and reject votes for a range of pending transactions.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+struct TransactionVotes<TH> {
+    accept: StakeAggregator<TH>,
+    reject: StakeAggregator<TH>,
+}
+
+/// Tracks votes for pending transactions and outputs certified and rejected transactions to a
+/// handler. A transaction is rejected once the reject votes reach the threshold.
 pub struct TransactionAggregator<TH, H = HashSet<TransactionLocator>> {
-    pending: HashMap<BlockReference, RangeMap<u64, StakeAggregator<TH>>>,
+    pending: HashMap<BlockReference, RangeMap<u64, TransactionVotes<TH>>>,
     // todo - need to figure out serialization story with this
     // Currently we skip serialization for test handler,
     // but it also means some invariants wrt unknown_transaction might be potentially broken in some tests
@@ -282,6 +292,13 @@ pub trait ProcessedTransactionHandler<K> {
     fn transaction_processed(&mut self, k: K);
     fn duplicate_transaction(&mut self, _k: K, _from: AuthorityIndex) {}
     fn unknown_transaction(&mut self, _k: K, _from: AuthorityIndex) {}
+    /// Called once the reject votes for a transaction reach the threshold. The transaction is
+    /// no longer pending, but it is not processed.
+    fn transaction_rejected(&mut self, _k: K) {}
+    /// Called with every vote counted towards a pending transaction, before the transaction is
+    /// processed or rejected. The block sharing a transaction counts as the accept vote of its
+    /// author.
+    fn transaction_voted(&mut self, _k: K, _voter: BlockReference, _vote: &Vote) {}
 }
 
 impl<K: TransactionAggregatorKey> ProcessedTransactionHandler<K> for HashSet<K> {
@@ -319,6 +336,10 @@ impl<TH: CommitteeThreshold> StakeAggregator<TH> {
         TH::is_threshold(committee, self.stake)
     }
 
+    pub fn is_threshold(&self, committee: &Committee) -> bool {
+        TH::is_threshold(committee, self.stake)
+    }
+
     pub fn clear(&mut self) {
         self.votes.clear();
         self.stake = 0;
@@ -327,6 +348,10 @@ impl<TH: CommitteeThreshold> StakeAggregator<TH> {
     pub fn voters(&self) -> impl Iterator<Item = AuthorityIndex> + '_ {
         self.votes.present()
     }
+
+    pub fn contains(&self, voter: AuthorityIndex) -> bool {
+        self.votes.contains(voter)
+    }
 }
 
 impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator> + Default>
@@ -376,9 +401,14 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
                     self.handler.duplicate_transaction(k, vote);
                 }
             } else {
-                let mut aggregator = StakeAggregator::<TH>::new();
-                aggregator.add(vote, committee);
-                *aggregator_opt = Some(aggregator);
+                for l in range {
+                    let k = TransactionLocator::new(*locator_range.block(), l);
+                    self.handler
+                        .transaction_voted(k, *locator_range.block(), &Vote::Accept);
+                }
+                let mut votes = TransactionVotes::<TH>::new();
+                votes.accept.add(vote, committee);
+                *aggregator_opt = Some(votes);
             }
         });
     }
@@ -386,10 +416,13 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
     fn vote(
         &mut self,
         locator_range: TransactionLocatorRange,
-        vote: AuthorityIndex,
+        voter: &BlockReference,
+        vote: &Vote,
         committee: &Committee,
         processed: &mut Vec<TransactionLocator>,
     ) {
+        let accept = matches!(vote, Vote::Accept);
+        let from = voter.authority;
         if let Some(range_map) = self.pending.get_mut(locator_range.block()) {
             range_map.mutate_range(locator_range.range(), |range, aggregator_opt| {
                 match aggregator_opt {
@@ -397,16 +430,29 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
                         for l in range {
                             let k = TransactionLocator::new(*locator_range.block(), l);
                             // todo - make unknown_transaction take TransactionLocatorRange instead
-                            self.handler.unknown_transaction(k, vote);
+                            self.handler.unknown_transaction(k, from);
                         }
                     }
-                    Some(aggregator) => {
-                        if aggregator.add(vote, committee) {
+                    Some(votes) => {
+                        for l in range.clone() {
+                            let k = TransactionLocator::new(*locator_range.block(), l);
+                            self.handler.transaction_voted(k, *voter, vote);
+                        }
+                        let aggregator = if accept {
+                            &mut votes.accept
+                        } else {
+                            &mut votes.reject
+                        };
+                        if aggregator.add(from, committee) {
                             for l in range {
                                 let k = TransactionLocator::new(*locator_range.block(), l);
                                 // todo - make transaction_processed take TransactionLocatorRange instead
-                                self.handler.transaction_processed(k);
-                                processed.push(k);
+                                if accept {
+                                    self.handler.transaction_processed(k);
+                                    processed.push(k);
+                                } else {
+                                    self.handler.transaction_rejected(k);
+                                }
                             }
                             *aggregator_opt = None;
                         }
@@ -419,11 +465,19 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
         } else {
             for l in locator_range.locators() {
                 // todo - make unknown_transaction take TransactionLocatorRange instead
-                self.handler.unknown_transaction(l, vote);
+                self.handler.unknown_transaction(l, from);
             }
         }
     }
 
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
     pub fn len(&self) -> usize {
         self.pending.len()
     }
@@ -450,37 +504,106 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
     pub fn process_block(
         &mut self,
         block: &Data<StatementBlock>,
-        mut response: Option<&mut Vec<BaseStatement>>,
+        response: Option<&mut Vec<BaseStatement>>,
+        committee: &Committee,
+    ) -> Vec<TransactionLocator> {
+        self.process_block_inner(block, response, committee, None)
+    }
+
+    /// Same as process_block, but the response votes on the transactions shared by the block as
+    /// decided by `validator`, rather than accepting all of them.
+    pub fn process_block_with_validator(
+        &mut self,
+        block: &Data<StatementBlock>,
+        response: Option<&mut Vec<BaseStatement>>,
+        committee: &Committee,
+        validator: &mut dyn TransactionValidator,
+    ) -> Vec<TransactionLocator> {
+        self.process_block_inner(block, response, committee, Some(validator))
+    }
+
+    fn process_block_inner(
+        &mut self,
+        block: &Data<StatementBlock>,
+        response: Option<&mut Vec<BaseStatement>>,
         committee: &Committee,
+        validator: Option<&mut dyn TransactionValidator>,
     ) -> Vec<TransactionLocator> {
         let mut processed = vec![];
         for range in block.shared_ranges() {
             self.register(range, block.author(), committee);
-            if let Some(ref mut response) = response {
-                response.push(BaseStatement::VoteRange(range));
+        }
+        if let Some(response) = response {
+            match validator {
+                None => response.extend(
+                    block
+                        .shared_ranges()
+                        .into_iter()
+                        .map(BaseStatement::VoteRange),
+                ),
+                Some(validator) => {
+                    // Accepted transactions are batched in ranges, like without a validator
+                    let mut accepted = VoteRangeBuilder::default();
+                    let accepted_range = |range| {
+                        BaseStatement::VoteRange(TransactionLocatorRange::new(
+                            *block.reference(),
+                            range,
+                        ))
+                    };
+                    for (locator, transaction) in block.shared_transactions() {
+                        match validator.vote(locator, transaction) {
+                            Vote::Accept => {
+                                if let Some(range) = accepted.add(locator.offset()) {
+                                    response.push(accepted_range(range));
+                                }
+                            }
+                            reject => response.push(BaseStatement::Vote(locator, reject)),
+                        }
+                    }
+                    if let Some(range) = accepted.finish() {
+                        response.push(accepted_range(range));
+                    }
+                }
             }
         }
         for statement in block.statements() {
             match statement {
-                BaseStatement::Share(_transaction) => {}
-                BaseStatement::Vote(locator, vote) => match vote {
-                    Vote::Accept => self.vote(
-                        TransactionLocatorRange::one(*locator),
-                        block.author(),
-                        committee,
-                        &mut processed,
-                    ),
-                    Vote::Reject(_) => unimplemented!(),
-                },
-                BaseStatement::VoteRange(range) => {
-                    self.vote(*range, block.author(), committee, &mut processed);
-                }
+                BaseStatement::Share(_) | BaseStatement::NextCommittee(_) => {}
+                BaseStatement::Vote(locator, vote) => self.vote(
+                    TransactionLocatorRange::one(*locator),
+                    block.reference(),
+                    vote,
+                    committee,
+                    &mut processed,
+                ),
+                BaseStatement::VoteRange(range) => self.vote(
+                    *range,
+                    block.reference(),
+                    &Vote::Accept,
+                    committee,
+                    &mut processed,
+                ),
             }
         }
         processed
     }
 }
 
+impl<TH: CommitteeThreshold> TransactionVotes<TH> {
+    fn new() -> Self {
+        Self {
+            accept: StakeAggregator::new(),
+            reject: StakeAggregator::new(),
+        }
+    }
+}
+
+impl<TH: CommitteeThreshold> Default for TransactionVotes<TH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<TH: CommitteeThreshold> Default for StakeAggregator<TH> {
     fn default() -> Self {
         Self::new()
@@ -527,6 +650,47 @@ impl VoteRangeBuilder {
 mod test {
     use super::*;
 
+    #[test]
+    fn transaction_aggregator_reject_quorum() {
+        let committee = Committee::new_test(vec![1, 1, 1, 1]);
+        let block = |authority, statements| {
+            Data::new(StatementBlock::new(
+                authority,
+                1,
+                vec![],
+                statements,
+                0,
+                false,
+                0,
+                Default::default(),
+            ))
+        };
+        let shared = block(
+            0,
+            vec![BaseStatement::Share(crate::types::Transaction::new(vec![]))],
+        );
+        let (locator, _) = shared.shared_transactions().next().unwrap();
+        let mut aggregator = TransactionAggregator::<QuorumThreshold>::new();
+        aggregator.process_block(&shared, None, &committee);
+
+        // Rejections are counted apart from the accept vote of the proposer
+        let accept = block(1, vec![BaseStatement::Vote(locator, Vote::Accept)]);
+        assert!(aggregator
+            .process_block(&accept, None, &committee)
+            .is_empty());
+        for authority in 1..4 {
+            let reject = block(
+                authority,
+                vec![BaseStatement::Vote(locator, Vote::Reject(None))],
+            );
+            assert!(aggregator
+                .process_block(&reject, None, &committee)
+                .is_empty());
+        }
+        assert!(aggregator.is_empty());
+        assert!(!aggregator.is_processed(&locator));
+    }
+
     #[test]
     fn vote_range_builder_test() {
         let mut b = VoteRangeBuilder::default();
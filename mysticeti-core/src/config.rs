@@ -8,10 +8,12 @@ use std::{
     time::Duration,
 };
 
+use crate::committee::Committee;
 use crate::crypto::dummy_public_key;
+use eyre::ensure;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::types::{AuthorityIndex, PublicKey, RoundNumber};
+use crate::types::{AuthorityIndex, Epoch, PublicKey, RoundNumber};
 
 pub trait Print: Serialize + DeserializeOwned {
     fn print<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
@@ -28,17 +30,22 @@ pub trait Print: Serialize + DeserializeOwned {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Identifier {
     pub public_key: PublicKey,
     pub network_address: SocketAddr,
     pub metrics_address: SocketAddr,
+    /// Address of the transaction submission endpoint, none if the validator does not accept
+    /// transactions from clients.
+    #[serde(default)]
+    pub rpc_address: Option<SocketAddr>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Parameters {
     pub identifiers: Vec<Identifier>,
     pub wave_length: RoundNumber,
+    /// The time to wait for the leaders of a round, the initial one when the timeout is adaptive.
     pub leader_timeout: Duration,
     pub rounds_in_epoch: RoundNumber,
     pub shutdown_grace_period: Duration,
@@ -52,6 +59,35 @@ pub struct Parameters {
     /// When detected connection latency is >= the `network_connection_max_latency`, then the connection breaks as best effort to fix any
     /// transient connection issues.
     pub network_connection_max_latency: Duration,
+    /// The transport used for connections between authorities.
+    #[serde(default)]
+    pub network_transport: NetworkTransport,
+    /// Compression for block batches. Disabled by default; when enabled it must be enabled on every
+    /// authority, since peers without compression do not take part in the negotiation.
+    #[serde(default)]
+    pub network_compression: NetworkCompression,
+    #[serde(default)]
+    pub snapshot_parameters: SnapshotParameters,
+    #[serde(default)]
+    pub leader_reputation: LeaderReputationParameters,
+    /// Drop wal segments that are no longer needed for recovery. Blocks below the store retain rounds
+    /// are then no longer available to be served to other validators.
+    #[serde(default = "Parameters::default_enable_wal_compaction")]
+    pub enable_wal_compaction: bool,
+    /// Track the fast path finality of transactions as blocks are added to the dag. This costs
+    /// memory and cpu in proportion to the transactions pending in the retained rounds.
+    #[serde(default)]
+    pub enable_finality_tracking: bool,
+    #[serde(default)]
+    pub mempool: MempoolParameters,
+    /// Protocol limits on the size of blocks, enforced on own and received blocks.
+    #[serde(default)]
+    pub block_limits: BlockLimits,
+    /// Rate limits and misbehavior scoring of the connected peers.
+    #[serde(default)]
+    pub peer_limits: PeerLimitsParameters,
+    #[serde(default)]
+    pub leader_timeout_parameters: LeaderTimeoutParameters,
 }
 
 impl Default for Parameters {
@@ -68,10 +104,38 @@ impl Default for Parameters {
             enable_pipelining: true,
             enable_cleanup: true,
             synchronizer_parameters: SynchronizerParameters::default(),
+            network_transport: NetworkTransport::default(),
+            network_compression: NetworkCompression::default(),
+            snapshot_parameters: SnapshotParameters::default(),
+            leader_reputation: LeaderReputationParameters::default(),
+            enable_wal_compaction: Self::default_enable_wal_compaction(),
+            enable_finality_tracking: false,
+            mempool: MempoolParameters::default(),
+            block_limits: BlockLimits::default(),
+            peer_limits: PeerLimitsParameters::default(),
+            leader_timeout_parameters: LeaderTimeoutParameters::default(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NetworkTransport {
+    /// Plain tcp, peers are identified by their network address.
+    #[default]
+    Tcp,
+    /// Encrypted Noise channel, peers prove possession of their committee key during the handshake.
+    Noise,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NetworkCompression {
+    /// No compression, and no negotiation: the wire protocol of nodes without compression support.
+    #[default]
+    None,
+    /// Negotiated on every connection, so it must be enabled on all nodes of the committee.
+    Lz4,
+}
+
 // TODO: A central controller will eventually dynamically update these parameters.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SynchronizerParameters {
@@ -98,6 +162,184 @@ impl Default for SynchronizerParameters {
         }
     }
 }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotParameters {
+    /// Produce a signed snapshot every `interval` commits, zero disables snapshots.
+    pub interval: u64,
+    /// The number of rounds below the snapshot leader for which all blocks are kept in the snapshot.
+    pub retain_rounds: RoundNumber,
+}
+
+impl Default for SnapshotParameters {
+    fn default() -> Self {
+        Self {
+            interval: 0,
+            retain_rounds: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LeaderReputationParameters {
+    /// Update the leader schedule every `window` committed leaders, zero (the default) disables
+    /// reputation based election.
+    pub window: u64,
+    /// The maximum stake (in percent of the total stake) of the authorities with the lowest reputation
+    /// that are replaced by the authorities with the highest reputation.
+    pub swap_stake_percentage: u64,
+}
+
+impl Default for LeaderReputationParameters {
+    fn default() -> Self {
+        Self {
+            window: 0,
+            swap_stake_percentage: 20,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MempoolParameters {
+    /// The maximum number of transactions waiting to be proposed.
+    pub max_transactions: usize,
+    /// The maximum total size in bytes of the transactions waiting to be proposed.
+    pub max_bytes: usize,
+    /// The maximum number of transactions proposed in a single block.
+    pub max_block_transactions: usize,
+    /// The maximum total size in bytes of the transactions proposed in a single block. Larger
+    /// transactions are rejected on submission. Capped to the block limits, see `within`.
+    pub max_block_bytes: usize,
+    /// The number of digests of proposed transactions remembered to reject their resubmission.
+    pub seen_capacity: usize,
+}
+
+impl MempoolParameters {
+    /// The parameters with the block batches capped to the largest transaction that fits in a
+    /// block, so that transactions that could never be proposed are rejected on submission.
+    pub fn within(&self, limits: &BlockLimits) -> Self {
+        Self {
+            max_block_bytes: self.max_block_bytes.min(limits.max_transaction_bytes()),
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for MempoolParameters {
+    fn default() -> Self {
+        Self {
+            max_transactions: 100_000,
+            max_bytes: 64 * 1024 * 1024,
+            max_block_transactions: 10_000,
+            max_block_bytes: 4 * 1024 * 1024,
+            seen_capacity: 1_000_000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockLimits {
+    /// The maximum number of blocks a block includes. Must be at least the committee size, so
+    /// that a block can include a quorum of blocks of the previous round (see `validate`).
+    pub max_includes: usize,
+    /// The maximum number of statements in a block.
+    pub max_statements: usize,
+    /// The maximum size in bytes of a serialized block.
+    pub max_bytes: usize,
+}
+
+impl BlockLimits {
+    /// Checks that a block can include a block of every authority of the committee.
+    pub fn validate(&self, committee: &Committee) -> eyre::Result<()> {
+        ensure!(
+            self.max_includes >= committee.len(),
+            "Block limits allow {} includes, fewer than the {} authorities of the committee",
+            self.max_includes,
+            committee.len()
+        );
+        Ok(())
+    }
+
+    /// The size of the largest transaction that fits in a block along with the most includes.
+    pub fn max_transaction_bytes(&self) -> usize {
+        self.max_bytes.saturating_sub(
+            crate::core::BLOCK_HEADER_BYTES
+                + self.max_includes * crate::core::INCLUDE_BYTES
+                + crate::core::SHARE_STATEMENT_BYTES,
+        )
+    }
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        Self {
+            max_includes: 1_000,
+            max_statements: 100_000,
+            max_bytes: crate::wal::MAX_ENTRY_SIZE / 2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeerLimitsParameters {
+    /// The number of blocks a peer may request by reference per second, a subscription to our own
+    /// blocks counts as one. Zero disables the limit.
+    pub requests_per_second: u64,
+    /// The number of blocks a peer may request at once after being idle.
+    pub requests_burst: u64,
+    /// The number of block bytes a peer may push to us per second. Zero disables the limit.
+    pub bytes_per_second: u64,
+    /// The number of block bytes a peer may push at once after being idle.
+    pub bytes_burst: u64,
+    /// Peers whose misbehavior score reaches this threshold are banned.
+    pub ban_score: u64,
+    /// The misbehavior score forgiven every minute.
+    pub score_decay_per_minute: u64,
+    /// Banned peers are disconnected, and their connections are refused for this duration.
+    pub ban_duration: Duration,
+}
+
+impl Default for PeerLimitsParameters {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 10_000,
+            requests_burst: 100_000,
+            bytes_per_second: 256 * 1024 * 1024,
+            bytes_burst: 512 * 1024 * 1024,
+            ban_score: 100,
+            score_decay_per_minute: 10,
+            ban_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LeaderTimeoutParameters {
+    /// Adapt the leader timeout to the observed round latency (opt-in), otherwise `leader_timeout`
+    /// is used for every round.
+    pub adaptive: bool,
+    pub min_timeout: Duration,
+    pub max_timeout: Duration,
+    /// The timeout after rounds completed without a timeout, in percent of their average latency.
+    pub latency_percentage: u64,
+    /// The percentage by which the timeout grows on every consecutive timeout.
+    pub backoff_percentage: u64,
+    /// The maximum percentage by which the timeout shrinks after a round completed without a timeout.
+    pub shrink_percentage: u64,
+}
+
+impl Default for LeaderTimeoutParameters {
+    fn default() -> Self {
+        Self {
+            adaptive: false,
+            min_timeout: Duration::from_millis(250),
+            max_timeout: Duration::from_secs(10),
+            latency_percentage: 300,
+            backoff_percentage: 50,
+            shrink_percentage: 10,
+        }
+    }
+}
+
 impl Parameters {
     pub const DEFAULT_FILENAME: &'static str = "parameters.yaml";
 
@@ -123,12 +365,15 @@ impl Parameters {
             let public_key = dummy_public_key(); // todo - fix
             let network_port = Self::BENCHMARK_PORT_OFFSET + i as u16;
             let metrics_port = benchmark_port_offset + network_port;
+            let rpc_port = benchmark_port_offset + metrics_port;
             let network_address = SocketAddr::new(ip, network_port);
             let metrics_address = SocketAddr::new(ip, metrics_port);
+            let rpc_address = SocketAddr::new(ip, rpc_port);
             identifiers.push(Identifier {
                 public_key,
                 network_address,
                 metrics_address,
+                rpc_address: Some(rpc_address),
             });
         }
         Self {
@@ -137,12 +382,19 @@ impl Parameters {
         }
     }
 
+    fn default_enable_wal_compaction() -> bool {
+        true
+    }
+
     pub fn with_port_offset(mut self, port_offset: u16) -> Self {
         for id in self.identifiers.iter_mut() {
             id.network_address
                 .set_port(id.network_address.port() + port_offset);
             id.metrics_address
                 .set_port(id.metrics_address.port() + port_offset);
+            if let Some(rpc_address) = id.rpc_address.as_mut() {
+                rpc_address.set_port(rpc_address.port() + port_offset);
+            }
         }
         self
     }
@@ -157,6 +409,16 @@ impl Parameters {
         self
     }
 
+    pub fn with_network_transport(mut self, network_transport: NetworkTransport) -> Self {
+        self.network_transport = network_transport;
+        self
+    }
+
+    pub fn with_network_compression(mut self, network_compression: NetworkCompression) -> Self {
+        self.network_compression = network_compression;
+        self
+    }
+
     /// Return all network addresses (including our own) in the order of the authority index.
     pub fn all_network_addresses(&self) -> impl Iterator<Item = SocketAddr> + '_ {
         self.identifiers.iter().map(|id| id.network_address)
@@ -179,6 +441,12 @@ impl Parameters {
             .map(|id| id.metrics_address)
     }
 
+    pub fn rpc_address(&self, authority: AuthorityIndex) -> Option<SocketAddr> {
+        self.identifiers
+            .get(authority as usize)
+            .and_then(|id| id.rpc_address)
+    }
+
     pub fn wave_length(&self) -> RoundNumber {
         self.wave_length
     }
@@ -248,4 +516,17 @@ impl StorageDir {
     pub fn wal(&self) -> PathBuf {
         self.path.join("wal")
     }
+
+    /// Every epoch has its own wal, the wal of the first epoch is the one at `wal()`.
+    pub fn epoch_wal(&self, epoch: Epoch) -> PathBuf {
+        if epoch == 0 {
+            self.wal()
+        } else {
+            self.path.join(format!("wal-epoch-{epoch}"))
+        }
+    }
+
+    pub fn snapshot(&self) -> PathBuf {
+        self.path.join("snapshot")
+    }
 }
@@ -13,7 +13,7 @@ use crate::{
     types::{format_authority_round, AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
 
-use super::{LeaderStatus, DEFAULT_WAVE_LENGTH};
+use super::{leader_schedule::LeaderSchedule, LeaderStatus, DEFAULT_WAVE_LENGTH};
 
 /// The consensus protocol operates in 'waves'. Each wave is composed of a leader round, at least one
 /// voting round, and one decision round.
@@ -50,6 +50,8 @@ pub struct BaseCommitter {
     block_store: BlockStore,
     /// The options used by this committer
     options: BaseCommitterOptions,
+    /// Elects the leaders, the committee schedule is used if not set
+    leader_schedule: Option<Arc<LeaderSchedule>>,
 }
 
 impl BaseCommitter {
@@ -58,6 +60,7 @@ impl BaseCommitter {
             committee,
             block_store,
             options: BaseCommitterOptions::default(),
+            leader_schedule: None,
         }
     }
 
@@ -67,6 +70,11 @@ impl BaseCommitter {
         self
     }
 
+    pub fn with_leader_schedule(mut self, leader_schedule: Arc<LeaderSchedule>) -> Self {
+        self.leader_schedule = Some(leader_schedule);
+        self
+    }
+
     /// Return the wave in which the specified round belongs.
     fn wave_number(&self, round: RoundNumber) -> WaveNumber {
         round.saturating_sub(self.options.round_offset) / self.options.wave_length
@@ -95,10 +103,11 @@ impl BaseCommitter {
         }
 
         let offset = self.options.leader_offset as RoundNumber;
-        Some(AuthorityRound::new(
-            self.committee.elect_leader(round, offset),
-            round,
-        ))
+        let leader = match &self.leader_schedule {
+            Some(leader_schedule) => leader_schedule.elect_leader(round, offset),
+            None => self.committee.elect_leader(round, offset),
+        };
+        Some(AuthorityRound::new(leader, round))
     }
 
     /// Find which block is supported at (author, round) by the given block.
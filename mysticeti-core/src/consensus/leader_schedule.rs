@@ -0,0 +1,331 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{mem, sync::Arc};
+
+use minibytes::Bytes;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::Committee,
+    config::LeaderReputationParameters,
+    metrics::Metrics,
+    types::{AuthorityIndex, RoundNumber, Stake, StatementBlock},
+};
+
+/// Elects the leaders of each round, replacing the authorities with the lowest reputation by the
+/// authorities with the highest reputation.
+///
+/// Every committed leader gives a point to the authors of the blocks of the previous round it includes,
+/// so authorities that crashed or are too slow for their blocks to be included by the leaders end up with
+/// a low score. Every `window` committed leaders the scores are turned into a new swap table, which applies
+/// to the leader rounds above the last scored leader. The schedule only depends on the sequence of committed
+/// leaders, so all honest validators agree on it.
+pub struct LeaderSchedule {
+    committee: Arc<Committee>,
+    metrics: Arc<Metrics>,
+    number_of_leaders: usize,
+    parameters: LeaderReputationParameters,
+    state: RwLock<LeaderScheduleState>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct LeaderScheduleState {
+    // Scores of the current window
+    scores: Vec<u64>,
+    // Number of committed leaders scored in the current window
+    scored: u64,
+    current: LeaderSwapTable,
+    // Applies to the rounds up to current.from_round
+    previous: LeaderSwapTable,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+struct LeaderSwapTable {
+    // The table applies to the leader rounds above this round
+    from_round: RoundNumber,
+    // Authorities removed from the schedule
+    bad: Vec<AuthorityIndex>,
+    // Authorities replacing them, best first
+    good: Vec<AuthorityIndex>,
+}
+
+impl LeaderSchedule {
+    pub fn new(
+        committee: Arc<Committee>,
+        metrics: Arc<Metrics>,
+        number_of_leaders: usize,
+        parameters: LeaderReputationParameters,
+    ) -> Self {
+        let state = LeaderScheduleState {
+            scores: vec![0; committee.len()],
+            ..Default::default()
+        };
+        Self {
+            committee,
+            metrics,
+            number_of_leaders,
+            parameters,
+            state: RwLock::new(state),
+        }
+    }
+
+    /// Leader schedule that always elects the leaders of the committee.
+    pub fn disabled(
+        committee: Arc<Committee>,
+        metrics: Arc<Metrics>,
+        number_of_leaders: usize,
+    ) -> Self {
+        let parameters = LeaderReputationParameters {
+            window: 0,
+            ..Default::default()
+        };
+        Self::new(committee, metrics, number_of_leaders, parameters)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.parameters.window > 0
+    }
+
+    pub fn elect_leader(&self, round: RoundNumber, offset: u64) -> AuthorityIndex {
+        let state = self.state.read();
+        let table = if round > state.current.from_round {
+            &state.current
+        } else {
+            &state.previous
+        };
+        table.elect_leader(&self.committee, self.number_of_leaders, round, offset)
+    }
+
+    /// Adds the score of a committed leader. Returns true if the leader completes a window and
+    /// the schedule has changed, in which case the leaders above its round must be elected again.
+    pub fn add_committed_leader(&self, leader: &StatementBlock) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+        let mut state = self.state.write();
+        for include in leader.includes() {
+            if include.round + 1 == leader.round() {
+                state.scores[include.authority as usize] += 1;
+            }
+        }
+        state.scored += 1;
+        if state.scored < self.parameters.window {
+            return false;
+        }
+
+        let table = LeaderSwapTable::new(
+            &self.committee,
+            &state.scores,
+            self.parameters.swap_stake_percentage,
+            leader.round(),
+        );
+        tracing::debug!(
+            "Leader schedule from round {}: scores {:?}, swapping out {:?} for {:?}",
+            table.from_round,
+            state.scores,
+            table.bad,
+            table.good
+        );
+        for (authority, score) in state.scores.iter().enumerate() {
+            self.metrics
+                .leader_reputation_score
+                .with_label_values(&[&authority.to_string()])
+                .set(*score as i64);
+        }
+        self.metrics.leader_schedule_updates.inc();
+        self.metrics
+            .leader_schedule_swapped
+            .set(table.bad.len() as i64);
+        state.previous = mem::replace(&mut state.current, table);
+        state.scores.iter_mut().for_each(|score| *score = 0);
+        state.scored = 0;
+        true
+    }
+
+    pub fn state(&self) -> Bytes {
+        bincode::serialize(&*self.state.read())
+            .expect("Serialization failed")
+            .into()
+    }
+
+    pub fn recover_state(&self, state: &Bytes) {
+        let state: LeaderScheduleState =
+            bincode::deserialize(state).expect("Failed to deserialize leader schedule state");
+        assert_eq!(state.scores.len(), self.committee.len());
+        self.metrics
+            .leader_schedule_swapped
+            .set(state.current.bad.len() as i64);
+        *self.state.write() = state;
+    }
+}
+
+impl LeaderSwapTable {
+    fn new(
+        committee: &Committee,
+        scores: &[u64],
+        swap_stake_percentage: u64,
+        from_round: RoundNumber,
+    ) -> Self {
+        let total_stake: Stake = committee
+            .authorities()
+            .filter_map(|authority| committee.get_stake(authority))
+            .sum();
+        let max_stake = total_stake * swap_stake_percentage / 100;
+        let score = |authority: &AuthorityIndex| scores[*authority as usize];
+        let mut authorities: Vec<_> = committee.authorities().collect();
+        // Lowest score first, ties are broken by the authority index to keep the order deterministic
+        authorities.sort_by_key(|authority| (score(authority), *authority));
+        let mut bad = Self::take_stake(committee, authorities.iter().copied(), max_stake);
+        let mut good = Self::take_stake(committee, authorities.iter().rev().copied(), max_stake);
+        // Authorities are only replaced by authorities with a strictly better score
+        let min_good_score = good.iter().map(score).min().unwrap_or_default();
+        bad.retain(|authority| score(authority) < min_good_score);
+        let max_bad_score = bad.iter().map(score).max();
+        good.retain(|authority| Some(score(authority)) > max_bad_score);
+        if bad.is_empty() {
+            good.clear();
+        }
+        Self {
+            from_round,
+            bad,
+            good,
+        }
+    }
+
+    fn take_stake(
+        committee: &Committee,
+        authorities: impl Iterator<Item = AuthorityIndex>,
+        max_stake: Stake,
+    ) -> Vec<AuthorityIndex> {
+        let mut stake = 0;
+        authorities
+            .take_while(|authority| {
+                stake += committee.get_stake(*authority).unwrap_or_default();
+                stake <= max_stake
+            })
+            .collect()
+    }
+
+    fn elect_leader(
+        &self,
+        committee: &Committee,
+        number_of_leaders: usize,
+        round: RoundNumber,
+        offset: u64,
+    ) -> AuthorityIndex {
+        if self.bad.is_empty() {
+            return committee.elect_leader(round, offset);
+        }
+        // All leaders of the round are elected, so that a replacement is never already a leader of the same round
+        let count = number_of_leaders.max(offset as usize + 1);
+        let mut leaders: Vec<_> = (0..count as u64)
+            .map(|offset| committee.elect_leader(round, offset))
+            .collect();
+        for offset in 0..=offset as usize {
+            if !self.bad.contains(&leaders[offset]) {
+                continue;
+            }
+            let replacement = (0..self.good.len())
+                .map(|i| self.good[(round as usize + i) % self.good.len()])
+                .find(|candidate| !leaders.contains(candidate));
+            if let Some(replacement) = replacement {
+                leaders[offset] = replacement;
+            }
+        }
+        leaders[offset as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{committee, test_metrics};
+    use crate::types::BlockReference;
+
+    fn leader(round: RoundNumber, includes: &[AuthorityIndex]) -> StatementBlock {
+        let includes = includes
+            .iter()
+            .map(|authority| BlockReference::new_test(*authority, round - 1))
+            .collect();
+        StatementBlock::new(0, round, includes, vec![], 0, false, 0, Default::default())
+    }
+
+    #[test]
+    fn swap_table_replaces_lowest_scores() {
+        let committee = committee(10);
+        let table = LeaderSwapTable::new(&committee, &[5, 5, 5, 5, 5, 5, 5, 5, 5, 5], 20, 1);
+        assert!(table.bad.is_empty());
+        assert!(table.good.is_empty());
+
+        let table = LeaderSwapTable::new(&committee, &[9, 1, 5, 5, 0, 5, 5, 8, 5, 5], 20, 1);
+        assert_eq!(table.bad, vec![4, 1]);
+        assert_eq!(table.good, vec![0, 7]);
+
+        // Only strictly better authorities replace bad ones
+        let table = LeaderSwapTable::new(&committee, &[5, 5, 5, 5, 0, 5, 5, 5, 5, 5], 20, 1);
+        assert_eq!(table.bad, vec![4]);
+        assert_eq!(table.good, vec![9, 8]);
+    }
+
+    #[test]
+    fn swapped_leaders_are_unique() {
+        let committee = committee(4);
+        let table = LeaderSwapTable {
+            from_round: 0,
+            bad: vec![1, 2],
+            good: vec![3],
+        };
+        for round in 1..20 {
+            let leaders: Vec<_> = (0..2)
+                .map(|offset| table.elect_leader(&committee, 2, round, offset))
+                .collect();
+            assert_ne!(leaders[0], leaders[1]);
+            for leader in &leaders {
+                assert!(!table.bad.contains(leader) || leaders.contains(&3));
+            }
+        }
+    }
+
+    #[test]
+    fn schedule_updates_after_window() {
+        let committee = committee(4);
+        let parameters = LeaderReputationParameters {
+            window: 3,
+            swap_stake_percentage: 25,
+        };
+        let schedule = LeaderSchedule::new(committee.clone(), test_metrics(), 1, parameters);
+        for round in 1..=6 {
+            assert_eq!(
+                schedule.elect_leader(round, 0),
+                committee.elect_leader(round, 0)
+            );
+        }
+        // Authority 3 is never included by the leaders
+        assert!(!schedule.add_committed_leader(&leader(1, &[0, 1, 2])));
+        assert!(!schedule.add_committed_leader(&leader(2, &[0, 1, 2])));
+        assert!(schedule.add_committed_leader(&leader(3, &[0, 1, 2])));
+
+        // Rounds up to the last scored leader keep their leaders
+        for round in 1..=3 {
+            assert_eq!(
+                schedule.elect_leader(round, 0),
+                committee.elect_leader(round, 0)
+            );
+        }
+        for round in 4..20 {
+            assert_ne!(schedule.elect_leader(round, 0), 3);
+        }
+
+        // The schedule survives a restart
+        let recovered = LeaderSchedule::disabled(committee.clone(), test_metrics(), 1);
+        recovered.recover_state(&schedule.state());
+        for round in 1..20 {
+            assert_eq!(
+                recovered.elect_leader(round, 0),
+                schedule.elect_leader(round, 0)
+            );
+        }
+    }
+}
@@ -47,6 +47,8 @@ impl CommittedSubDag {
         let blocks = commit_data
             .sub_dag
             .into_iter()
+            // Blocks below the horizon might have been pruned from the store
+            .filter(|block_ref| block_ref.round >= block_store.horizon())
             .enumerate()
             .map(|(idx, block_ref)| {
                 let block = block_store
@@ -118,6 +120,32 @@ impl Linearizer {
             // Leader must be part of the subdag and hence should have been inserted in the loop above.
             assert!(self.committed.contains(&commit.leader));
         }
+        if self.block_store.horizon() > 0 {
+            // Commits that only contain blocks below the horizon might not be in the wal anymore. Everything in
+            // the causal history of the last commit leader was committed, but only the part above the horizon is
+            // known locally (blocks below the horizon are skipped when collecting sub-dags).
+            if let Some(last_commit) = recovered_state.sub_dags.last() {
+                self.mark_committed_history(last_commit.leader);
+            }
+        }
+    }
+
+    fn mark_committed_history(&mut self, leader: BlockReference) {
+        let horizon = self.block_store.horizon();
+        let mut visited = HashSet::new();
+        let mut buffer = vec![leader];
+        while let Some(reference) = buffer.pop() {
+            let block = self
+                .block_store
+                .get_block(reference)
+                .expect("Blocks above the horizon should be in the store");
+            for include in block.includes() {
+                if include.round >= horizon && visited.insert(*include) {
+                    self.committed.insert(*include);
+                    buffer.push(*include);
+                }
+            }
+        }
     }
 
     /// Collect the sub-dag from a specific anchor excluding any duplicates or blocks that
@@ -128,10 +156,15 @@ impl Linearizer {
         let timestamp_ms = leader_block.meta_creation_time_ms();
         let leader_block_ref = *leader_block.reference();
         let mut buffer = vec![leader_block];
+        let horizon = self.block_store.horizon();
         assert!(self.committed.insert(leader_block_ref));
         while let Some(x) = buffer.pop() {
             to_commit.push(x.clone());
             for reference in x.includes() {
+                // Blocks below the horizon are considered committed.
+                if reference.round < horizon {
+                    continue;
+                }
                 // The block manager may have cleaned up blocks passed the latest committed rounds.
                 let block = self
                     .block_store
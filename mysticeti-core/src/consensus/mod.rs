@@ -10,6 +10,7 @@ use crate::{
 };
 
 pub mod base_committer;
+pub mod leader_schedule;
 pub mod linearizer;
 pub mod universal_committer;
 
@@ -12,7 +12,10 @@ use crate::{
     types::{format_authority_round, AuthorityIndex, RoundNumber},
 };
 
-use super::{base_committer::BaseCommitter, Decision, LeaderStatus, DEFAULT_WAVE_LENGTH};
+use super::{
+    base_committer::BaseCommitter, leader_schedule::LeaderSchedule, Decision, LeaderStatus,
+    DEFAULT_WAVE_LENGTH,
+};
 
 /// A universal committer uses a collection of committers to commit a sequence of leaders.
 /// It can be configured to use a combination of different commit strategies, including
@@ -129,6 +132,7 @@ pub struct UniversalCommitterBuilder {
     wave_length: RoundNumber,
     number_of_leaders: usize,
     pipeline: bool,
+    leader_schedule: Option<Arc<LeaderSchedule>>,
 }
 
 impl UniversalCommitterBuilder {
@@ -140,6 +144,7 @@ impl UniversalCommitterBuilder {
             wave_length: DEFAULT_WAVE_LENGTH,
             number_of_leaders: 1,
             pipeline: false,
+            leader_schedule: None,
         }
     }
 
@@ -158,6 +163,12 @@ impl UniversalCommitterBuilder {
         self
     }
 
+    /// Elect leaders with the given schedule instead of the committee schedule.
+    pub fn with_leader_schedule(mut self, leader_schedule: Arc<LeaderSchedule>) -> Self {
+        self.leader_schedule = Some(leader_schedule);
+        self
+    }
+
     pub fn build(self) -> UniversalCommitter {
         let mut committers = Vec::new();
         let pipeline_stages = if self.pipeline { self.wave_length } else { 1 };
@@ -168,9 +179,12 @@ impl UniversalCommitterBuilder {
                     round_offset,
                     leader_offset: leader_offset as RoundNumber,
                 };
-                let committer =
+                let mut committer =
                     BaseCommitter::new(self.committee.clone(), self.block_store.clone())
                         .with_options(options);
+                if let Some(leader_schedule) = &self.leader_schedule {
+                    committer = committer.with_leader_schedule(leader_schedule.clone());
+                }
                 committers.push(committer);
             }
         }
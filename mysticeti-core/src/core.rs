@@ -4,9 +4,11 @@
 use crate::committee::{Authority, Committee};
 use crate::crypto::Signer;
 use crate::data::Data;
-use crate::epoch_close::EpochManager;
+use crate::epoch_close::{EpochChange, EpochManager, NextCommittee};
+use crate::finalization_interpreter::{FinalizationInterpreter, FinalizedTransaction};
 use crate::metrics::UtilizationTimerVecExt;
 use crate::runtime::timestamp_utc;
+use crate::snapshot::{Snapshot, SnapshotWriter};
 use crate::state::CoreRecoveredState;
 use crate::threshold_clock::ThresholdClockAggregator;
 use crate::types::{
@@ -20,19 +22,32 @@ use crate::{
 use crate::{block_manager::BlockManager, metrics::Metrics};
 use crate::{
     block_store::{
-        BlockStore, BlockWriter, CommitData, OwnBlockData, WAL_ENTRY_COMMIT, WAL_ENTRY_PAYLOAD,
-        WAL_ENTRY_STATE,
+        BlockStore, BlockWriter, CommitData, OwnBlockData, WAL_ENTRY_COMMIT,
+        WAL_ENTRY_EPOCH_CHANGE, WAL_ENTRY_EQUIVOCATION, WAL_ENTRY_HORIZON,
+        WAL_ENTRY_LEADER_SCHEDULE, WAL_ENTRY_PAYLOAD, WAL_ENTRY_STATE,
     },
-    consensus::universal_committer::UniversalCommitter,
+    consensus::{leader_schedule::LeaderSchedule, universal_committer::UniversalCommitter},
+};
+use crate::{
+    config::{BlockLimits, Parameters, SnapshotParameters},
+    consensus::linearizer::CommittedSubDag,
 };
-use crate::{config::Parameters, consensus::linearizer::CommittedSubDag};
 use itertools::Itertools;
 use minibytes::Bytes;
 use std::collections::{HashSet, VecDeque};
 use std::mem;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 use std::time::Instant;
+use tokio::sync::broadcast;
+
+/// Upper bound on the serialized size of a block without its includes and statements.
+pub(crate) const BLOCK_HEADER_BYTES: usize = 256;
+/// Serialized size of a block reference.
+pub(crate) const INCLUDE_BYTES: usize = 56;
+/// Serialized size of a shared transaction statement, without the transaction bytes.
+pub(crate) const SHARE_STATEMENT_BYTES: usize = 12;
 
 pub struct Core<H: BlockHandler> {
     block_manager: BlockManager,
@@ -47,11 +62,23 @@ pub struct Core<H: BlockHandler> {
     block_store: BlockStore,
     pub(crate) metrics: Arc<Metrics>,
     options: CoreOptions,
-    signer: Signer,
+    signer: Arc<Signer>,
     epoch_manager: EpochManager,
     rounds_in_epoch: RoundNumber,
     committer: UniversalCommitter,
+    leader_schedule: Arc<LeaderSchedule>,
     store_retain_rounds: u64,
+    snapshot_parameters: SnapshotParameters,
+    snapshot_writer: Option<SnapshotWriter>,
+    enable_wal_compaction: bool,
+    // Positions of the last state and commit entries written by this instance
+    last_state_position: WalPosition,
+    last_commit_position: WalPosition,
+    // Tracks the fast path finality of the transactions of the blocks added after the core
+    // opened, if enabled
+    finalization_interpreter: Option<FinalizationInterpreter>,
+    finalized_transactions: Option<broadcast::Sender<FinalizedTransaction>>,
+    block_limits: BlockLimits,
 }
 
 pub struct CoreOptions {
@@ -75,7 +102,7 @@ impl<H: BlockHandler> Core<H> {
         recovered: CoreRecoveredState,
         mut wal_writer: WalWriter,
         options: CoreOptions,
-        signer: Signer,
+        signer: Arc<Signer>,
     ) -> Self {
         let CoreRecoveredState {
             block_store,
@@ -84,6 +111,8 @@ impl<H: BlockHandler> Core<H> {
             state,
             unprocessed_blocks,
             last_committed_leader,
+            leader_schedule: leader_schedule_state,
+            epoch_change,
         } = recovered;
         let mut threshold_clock = ThresholdClockAggregator::new(0, metrics.clone());
         let last_own_block = if let Some(own_block) = last_own_block {
@@ -121,13 +150,36 @@ impl<H: BlockHandler> Core<H> {
             block_handler.recover_state(&state);
         }
 
-        let epoch_manager = EpochManager::new();
+        let mut epoch_manager = EpochManager::new();
+        if let Some(epoch_change) = epoch_change {
+            let epoch_change =
+                EpochChange::from_bytes(&epoch_change).expect("Failed to deserialize epoch change");
+            tracing::info!(
+                "Epoch {} was closed before restart, next epoch is {}",
+                epoch_change.epoch,
+                epoch_change.next_committee.epoch()
+            );
+            epoch_manager.recover_epoch_change(epoch_change);
+        }
 
+        let leader_schedule = Arc::new(LeaderSchedule::new(
+            committee.clone(),
+            metrics.clone(),
+            parameters.number_of_leaders,
+            parameters.leader_reputation.clone(),
+        ));
+        if let Some(state) = leader_schedule_state {
+            leader_schedule.recover_state(&state);
+        }
         let committer =
             UniversalCommitterBuilder::new(committee.clone(), block_store.clone(), metrics.clone())
                 .with_number_of_leaders(parameters.number_of_leaders)
                 .with_pipeline(parameters.enable_pipelining)
+                .with_leader_schedule(leader_schedule.clone())
                 .build();
+        let finalization_interpreter = parameters
+            .enable_finality_tracking
+            .then(|| FinalizationInterpreter::new(committee.clone()));
 
         let mut this = Self {
             block_manager,
@@ -147,6 +199,15 @@ impl<H: BlockHandler> Core<H> {
             rounds_in_epoch: parameters.rounds_in_epoch(),
             store_retain_rounds: parameters.store_retain_rounds,
             committer,
+            leader_schedule,
+            snapshot_parameters: parameters.snapshot_parameters.clone(),
+            snapshot_writer: None,
+            enable_wal_compaction: parameters.enable_wal_compaction,
+            last_state_position: WalPosition::default(),
+            last_commit_position: WalPosition::default(),
+            finalization_interpreter,
+            finalized_transactions: None,
+            block_limits: parameters.block_limits.clone(),
         };
 
         if !unprocessed_blocks.is_empty() {
@@ -156,6 +217,7 @@ impl<H: BlockHandler> Core<H> {
             );
             this.run_block_handler(&unprocessed_blocks);
         }
+        this.recover_finality();
 
         this
     }
@@ -165,6 +227,28 @@ impl<H: BlockHandler> Core<H> {
         self
     }
 
+    /// Periodically write a snapshot to the given path, see SnapshotParameters::interval.
+    pub fn with_snapshot_path(mut self, path: PathBuf) -> Self {
+        self.snapshot_writer = Some(SnapshotWriter::start(path));
+        self
+    }
+
+    /// Committee of the next epoch, taken into the epoch change decision once the epoch is safe to close.
+    pub fn with_next_committee(mut self, next_committee: NextCommittee) -> Self {
+        self.epoch_manager.set_next_committee(next_committee);
+        self
+    }
+
+    /// Sends the transactions that become final on the fast path to `sender`, when finality
+    /// tracking is enabled.
+    pub fn with_finalized_transactions(
+        mut self,
+        sender: broadcast::Sender<FinalizedTransaction>,
+    ) -> Self {
+        self.finalized_transactions = Some(sender);
+        self
+    }
+
     // Note that generally when you update this function you also want to change genesis initialization above.
     // The method returns the missing references in order to successfully process the provided blocks. The missing
     // references though will be returned only the first time that a block is provided for processing.
@@ -178,9 +262,11 @@ impl<H: BlockHandler> Core<H> {
             .block_manager
             .add_blocks(blocks, &mut (&mut self.wal_writer, &self.block_store));
         let mut result = Vec::with_capacity(processed.len());
+        // Sorted by reference, rather than in the order of the hash sets of the block manager, so
+        // that the simulator can reproduce a run from its seed
         for (position, processed) in processed
             .into_iter()
-            .sorted_by(|b1, b2| b1.1.round().cmp(&b2.1.round()))
+            .sorted_by_key(|(_, block)| *block.reference())
         {
             // report latency
             let hostname = self.committee.authority_safe(processed.author()).hostname();
@@ -202,8 +288,12 @@ impl<H: BlockHandler> Core<H> {
         self.metrics
             .threshold_clock_round
             .set(self.threshold_clock.get_round() as i64);
+        // Included blocks are interpreted first
+        for block in result.iter().sorted_by_key(|block| block.round()) {
+            self.interpret_finality(block);
+        }
         self.run_block_handler(&result);
-        missing_references.into_iter().collect()
+        missing_references.into_iter().sorted().collect()
     }
 
     fn run_block_handler(&mut self, processed: &[Data<StatementBlock>]) {
@@ -249,31 +339,101 @@ impl<H: BlockHandler> Core<H> {
         let mut taken = self.pending.split_off(first_include_index);
         // Split off returns the "tail", what we want is keep the tail in "pending" and get the head
         mem::swap(&mut taken, &mut self.pending);
+        let mut candidates = vec![];
+        let mut payloads = vec![];
+        for (position, statement) in taken.into_iter() {
+            match statement {
+                MetaStatement::Include(include) => candidates.push((position, include)),
+                MetaStatement::Payload(payload) => {
+                    if !self.epoch_changing() {
+                        payloads.push((position, payload));
+                    }
+                }
+            }
+        }
         // Compress the references in the block
         // Iterate through all the include statements in the block, and make a set of all the references in their includes.
-        let mut references_in_block: HashSet<BlockReference> = HashSet::new();
-        references_in_block.extend(self.last_own_block.block.includes());
-        for (_, statement) in &taken {
-            if let MetaStatement::Include(block_ref) = statement {
-                // for all the includes in the block, add the references in the block to the set
-                if let Some(block) = self.block_store.get_block(*block_ref) {
-                    references_in_block.extend(block.includes());
+        let mut references_in_block = self.references_in_block(&candidates);
+        let compressed = candidates
+            .iter()
+            .filter(|(_, include)| !references_in_block.contains(include))
+            .count();
+        // Includes and statements over the block limits are proposed in the next blocks
+        let mut deferred = vec![];
+        let max_new_includes = self.block_limits.max_includes.saturating_sub(1);
+        if compressed > max_new_includes {
+            // Blocks of the latest rounds are included first, so that the block includes a quorum
+            // of the previous round. Only the included blocks compress the references now.
+            candidates.sort_by_key(|(_, include)| std::cmp::Reverse(include.round));
+            let over_limit = candidates.split_off(max_new_includes);
+            references_in_block = self.references_in_block(&candidates);
+            for (position, include) in over_limit {
+                if !references_in_block.contains(&include) {
+                    deferred.push((position, MetaStatement::Include(include)));
                 }
             }
         }
         includes.push(*self.last_own_block.block.reference());
-        for (_, statement) in taken.into_iter() {
-            match statement {
-                MetaStatement::Include(include) => {
-                    if !references_in_block.contains(&include) {
-                        includes.push(include);
+        includes.extend(
+            candidates
+                .into_iter()
+                .map(|(_, include)| include)
+                .filter(|include| !references_in_block.contains(include)),
+        );
+        // Payloads may vote on the blocks pending before them, so they wait for deferred includes
+        let include_cutoff = deferred.iter().map(|(position, _)| *position).min();
+        let mut bytes = BLOCK_HEADER_BYTES + includes.len() * INCLUDE_BYTES;
+        let mut full = false;
+        for (position, payload) in payloads {
+            if include_cutoff.map_or(false, |cutoff| position > cutoff) {
+                deferred.push((position, MetaStatement::Payload(payload)));
+                continue;
+            }
+            let mut over_limit = vec![];
+            for statement in payload {
+                if !full {
+                    let size = bincode::serialized_size(&statement)
+                        .expect("Statement serialization failed")
+                        as usize;
+                    if BLOCK_HEADER_BYTES + INCLUDE_BYTES + size > self.block_limits.max_bytes {
+                        // Never fits in a block, deferring it would hold back every statement after it
+                        tracing::warn!(
+                            "Dropped a statement of {size} bytes over the block size limit"
+                        );
+                        continue;
                     }
-                }
-                MetaStatement::Payload(payload) => {
-                    if !self.epoch_changing() {
-                        statements.extend(payload);
+                    full = statements.len() >= self.block_limits.max_statements
+                        || bytes + size > self.block_limits.max_bytes;
+                    if !full {
+                        bytes += size;
+                        statements.push(statement);
+                        continue;
                     }
                 }
+                over_limit.push(statement);
+            }
+            if !over_limit.is_empty() {
+                deferred.push((position, MetaStatement::Payload(over_limit)));
+            }
+        }
+        deferred.sort_by_key(|(position, _)| *position);
+        for entry in deferred.into_iter().rev() {
+            self.pending.push_front(entry);
+        }
+        // Blocks closing the epoch carry no payload, the first one proposes the next committee
+        if let Some(proposal) = self
+            .epoch_manager
+            .propose_next_committee(self.committee.epoch())
+        {
+            let statement = BaseStatement::NextCommittee(Box::new(proposal));
+            let size = bincode::serialized_size(&statement).expect("Statement serialization failed")
+                as usize;
+            if statements.len() < self.block_limits.max_statements
+                && bytes + size <= self.block_limits.max_bytes
+            {
+                statements.push(statement);
+            } else {
+                tracing::warn!("Next committee of {size} bytes is over the block size limit");
             }
         }
 
@@ -297,7 +457,11 @@ impl<H: BlockHandler> Core<H> {
         );
 
         let block = Data::new(block);
-        if block.serialized_bytes().len() > crate::wal::MAX_ENTRY_SIZE / 2 {
+        if block.serialized_bytes().len() > crate::wal::MAX_ENTRY_SIZE / 2
+            || block
+                .verify_limits(&self.block_limits, block.serialized_bytes().len())
+                .is_err()
+        {
             // Sanity check for now
             panic!(
                 "Created an oversized block(check all limits set properly!): {:?}",
@@ -307,6 +471,7 @@ impl<H: BlockHandler> Core<H> {
         self.threshold_clock
             .add_block(*block.reference(), &self.committee);
         self.block_handler.handle_proposal(&block);
+        self.interpret_finality(&block);
         self.proposed_block_stats(&block);
         let next_entry = if let Some((pos, _)) = self.pending.get(0) {
             *pos
@@ -346,6 +511,21 @@ impl<H: BlockHandler> Core<H> {
         self.threshold_clock.get_round()
     }
 
+    fn references_in_block(
+        &self,
+        includes: &[(WalPosition, BlockReference)],
+    ) -> HashSet<BlockReference> {
+        let mut references_in_block: HashSet<BlockReference> = HashSet::new();
+        references_in_block.extend(self.last_own_block.block.includes());
+        for (_, block_ref) in includes {
+            // for all the includes in the block, add the references in the block to the set
+            if let Some(block) = self.block_store.get_block(*block_ref) {
+                references_in_block.extend(block.includes());
+            }
+        }
+        references_in_block
+    }
+
     fn proposed_block_stats(&self, block: &Data<StatementBlock>) {
         self.metrics
             .proposed_block_size_bytes
@@ -357,6 +537,7 @@ impl<H: BlockHandler> Core<H> {
                 BaseStatement::Share(_) => transactions += 1,
                 BaseStatement::Vote(_, _) => votes += 1,
                 BaseStatement::VoteRange(range) => votes += range.len(),
+                BaseStatement::NextCommittee(_) => {}
             }
         }
         self.metrics
@@ -366,11 +547,25 @@ impl<H: BlockHandler> Core<H> {
     }
 
     pub fn try_commit(&mut self) -> Vec<Data<StatementBlock>> {
-        let sequence: Vec<_> = self.committer.try_commit(self.last_decided_leader);
-
-        if let Some(last) = sequence.last() {
-            self.last_decided_leader = last.clone().into_decided_author_round();
-            self.metrics.commit_round.set(last.round() as i64);
+        let mut committed = vec![];
+        loop {
+            let sequence: Vec<_> = self.committer.try_commit(self.last_decided_leader);
+            let mut schedule_updated = false;
+            for leader in sequence {
+                self.last_decided_leader = leader.clone().into_decided_author_round();
+                self.metrics.commit_round.set(leader.round() as i64);
+                if let Some(block) = leader.into_committed_block() {
+                    schedule_updated = self.leader_schedule.add_committed_leader(&block);
+                    committed.push(block);
+                    // Leaders after the end of a reputation window need to be decided with the new schedule
+                    if schedule_updated {
+                        break;
+                    }
+                }
+            }
+            if !schedule_updated {
+                break;
+            }
         }
 
         // todo: should ideally come from execution result of epoch smart contract
@@ -378,22 +573,73 @@ impl<H: BlockHandler> Core<H> {
             self.epoch_manager.epoch_change_begun();
         }
 
-        sequence
-            .into_iter()
-            .filter_map(|leader| leader.into_committed_block())
-            .collect()
+        committed
     }
 
-    pub fn cleanup(&self) {
-        self.block_store.cleanup(
-            self.last_decided_leader
-                .round()
-                .saturating_sub(self.store_retain_rounds),
-        );
+    pub fn cleanup(&mut self) {
+        let threshold_round = self
+            .last_decided_leader
+            .round()
+            .saturating_sub(self.store_retain_rounds);
+        self.block_store.cleanup(threshold_round);
+        if let Some(finalization_interpreter) = &mut self.finalization_interpreter {
+            finalization_interpreter.cleanup(threshold_round);
+        }
 
         self.block_handler.cleanup();
     }
 
+    // Rebuilds the votes of the pending transactions from the blocks that are still in the store.
+    // Transactions that become final again are not reported, they were before the restart.
+    fn recover_finality(&mut self) {
+        let Some(finalization_interpreter) = &mut self.finalization_interpreter else {
+            return;
+        };
+        let threshold_round = self
+            .last_decided_leader
+            .round()
+            .saturating_sub(self.store_retain_rounds);
+        finalization_interpreter.cleanup(threshold_round);
+        let highest_round = self.block_store.highest_round();
+        for round in threshold_round..=highest_round {
+            for block in self.block_store.get_blocks_by_round(round) {
+                finalization_interpreter.add_block(&block);
+            }
+        }
+        self.metrics
+            .finalization_pending_certificates
+            .set(finalization_interpreter.pending_certificates() as i64);
+    }
+
+    fn interpret_finality(&mut self, block: &Data<StatementBlock>) {
+        let Some(finalization_interpreter) = &mut self.finalization_interpreter else {
+            return;
+        };
+        let finalized = finalization_interpreter.add_block(block);
+        self.metrics
+            .finalization_pending_certificates
+            .set(finalization_interpreter.pending_certificates() as i64);
+        if finalized.is_empty() {
+            return;
+        }
+        let now = timestamp_utc();
+        self.metrics
+            .transactions_finalized
+            .inc_by(finalized.len() as u64);
+        for transaction in finalized {
+            if let Some(shared_by) = self.block_store.get_block(*transaction.locator.block()) {
+                self.metrics.transaction_finalized_latency_s.observe(
+                    now.saturating_sub(shared_by.meta_creation_time())
+                        .as_secs_f64(),
+                );
+            }
+            if let Some(finalized_transactions) = &self.finalized_transactions {
+                // There might be no subscribers
+                finalized_transactions.send(transaction).ok();
+            }
+        }
+    }
+
     /// This only checks readiness in terms of helping liveness for commit rule,
     /// try_new_block might still return None if threshold clock is not ready
     ///
@@ -454,6 +700,7 @@ impl<H: BlockHandler> Core<H> {
         committed: Vec<CommittedSubDag>,
         state: &Bytes,
     ) -> Vec<CommitData> {
+        let epoch_closed = self.epoch_closed();
         let mut commit_data = vec![];
         for commit in &committed {
             for block in &commit.blocks {
@@ -462,8 +709,15 @@ impl<H: BlockHandler> Core<H> {
             }
             commit_data.push(CommitData::from(commit));
         }
+        self.block_handler.handle_commit(&committed);
         self.write_state(); // todo - this can be done less frequently to reduce IO
+        self.write_leader_schedule();
         self.write_commits(&commit_data, state);
+        if !epoch_closed {
+            self.write_epoch_change();
+        }
+        self.try_write_snapshot(&commit_data, state);
+        self.try_compact_wal();
         // todo - We should also persist state of the epoch manager, otherwise if validator
         // restarts during epoch change it will fork on the epoch change state.
         commit_data
@@ -477,18 +731,117 @@ impl<H: BlockHandler> Core<H> {
             // We need to put some limit/backpressure on the accumulator state
             return;
         }
-        self.wal_writer
+        self.last_state_position = self
+            .wal_writer
             .write(WAL_ENTRY_STATE, &self.block_handler().state())
             .expect("Write to wal has failed");
     }
 
+    fn write_leader_schedule(&mut self) {
+        if !self.leader_schedule.enabled() {
+            return;
+        }
+        self.wal_writer
+            .write(WAL_ENTRY_LEADER_SCHEDULE, &self.leader_schedule.state())
+            .expect("Write to wal has failed");
+    }
+
+    fn write_epoch_change(&mut self) {
+        let Some(epoch_change) = self.epoch_manager.epoch_change() else {
+            return;
+        };
+        self.wal_writer
+            .write(WAL_ENTRY_EPOCH_CHANGE, &epoch_change.to_bytes())
+            .expect("Write to wal has failed");
+    }
+
     pub fn write_commits(&mut self, commits: &[CommitData], state: &Bytes) {
         let commits = bincode::serialize(&(commits, state)).expect("Commits serialization failed");
-        self.wal_writer
+        self.last_commit_position = self
+            .wal_writer
             .write(WAL_ENTRY_COMMIT, &commits)
             .expect("Write to wal has failed");
     }
 
+    /// Drops wal segments that are not needed to recover anymore: everything before the last state and commit
+    /// entries, the pending statements of the next own block and the blocks above the cleanup threshold.
+    fn try_compact_wal(&mut self) {
+        if !self.enable_wal_compaction || self.wal_writer.segments() <= 1 {
+            return;
+        }
+        let horizon = self
+            .last_decided_leader
+            .round()
+            .saturating_sub(self.store_retain_rounds)
+            .min(self.last_proposed());
+        if horizon <= self.block_store.horizon() {
+            return;
+        }
+        let position = [
+            self.last_state_position,
+            self.last_commit_position,
+            self.last_own_block.next_entry,
+        ]
+        .into_iter()
+        .chain(self.block_store.first_position_from_round(horizon))
+        .min()
+        .expect("Not empty");
+        if self.wal_writer.segments_before(position) == 0 {
+            return;
+        }
+        // Everything required for recovery without the dropped segments is written before they are dropped
+        let horizon_entry = bincode::serialize(&horizon).expect("Serialization failed");
+        self.wal_writer
+            .write(WAL_ENTRY_HORIZON, &horizon_entry)
+            .expect("Write to wal has failed");
+        self.block_store.prune(horizon, position);
+        // Only the proofs at or above the horizon survive the pruning
+        for proof in self.block_store.equivocations() {
+            let proof = bincode::serialize(&proof).expect("Serialization failed");
+            self.wal_writer
+                .write(WAL_ENTRY_EQUIVOCATION, &proof)
+                .expect("Write to wal has failed");
+        }
+        let dropped = self
+            .wal_writer
+            .compact(position)
+            .expect("Failed to compact wal");
+        self.metrics.wal_dropped_segments.inc_by(dropped as u64);
+        self.metrics
+            .wal_segments
+            .set(self.wal_writer.segments() as i64);
+        tracing::debug!("Compacted wal up to {position}, horizon is now round {horizon}");
+    }
+
+    // Only gathers the snapshot, it is serialized and written by the snapshot writer
+    fn try_write_snapshot(&self, commit_data: &[CommitData], state: &Bytes) {
+        let interval = self.snapshot_parameters.interval;
+        let Some(snapshot_writer) = &self.snapshot_writer else {
+            return;
+        };
+        if interval == 0 || !commit_data.iter().any(|c| c.height % interval == 0) {
+            return;
+        }
+        let last_commit = commit_data.last().expect("Commit data is not empty");
+        snapshot_writer.write(self.snapshot(last_commit.clone(), state.clone()));
+    }
+
+    /// Create a signed snapshot at the given commit. The committed state is the state of the
+    /// commit observer right after this commit.
+    pub fn snapshot(&self, commit: CommitData, committed_state: Bytes) -> Snapshot {
+        Snapshot::new(
+            self.authority,
+            &self.committee,
+            commit,
+            committed_state,
+            self.block_handler.state(),
+            self.leader_schedule.state(),
+            &self.block_store,
+            self.snapshot_parameters.retain_rounds,
+            &self.signer,
+        )
+    }
+
     pub fn block_store(&self) -> &BlockStore {
         &self.block_store
     }
@@ -517,10 +870,18 @@ impl<H: BlockHandler> Core<H> {
         &mut self.block_handler
     }
 
+    pub fn into_block_handler(self) -> H {
+        self.block_handler
+    }
+
     pub fn committee(&self) -> &Arc<Committee> {
         &self.committee
     }
 
+    pub fn block_limits(&self) -> &BlockLimits {
+        &self.block_limits
+    }
+
     pub fn epoch_closed(&self) -> bool {
         self.epoch_manager.closed()
     }
@@ -529,6 +890,11 @@ impl<H: BlockHandler> Core<H> {
         self.epoch_manager.changing()
     }
 
+    /// The decision to close this epoch, once it is safe to close and the next committee is known.
+    pub fn epoch_change(&self) -> Option<&EpochChange> {
+        self.epoch_manager.epoch_change()
+    }
+
     pub fn epoch_closing_time(&self) -> Arc<AtomicU64> {
         self.epoch_manager.closing_time()
     }
@@ -553,11 +919,19 @@ impl CoreOptions {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::test_util::{committee_and_cores, committee_and_cores_persisted};
+    use crate::block_handler::TestBlockHandler;
+    use crate::commit_observer::{CommitObserver, TestCommitObserver};
+    use crate::config::{LeaderReputationParameters, MempoolParameters};
+    use crate::test_util::{
+        committee_and_cores, committee_and_cores_persisted,
+        committee_and_cores_persisted_epoch_duration,
+    };
     use crate::threshold_clock;
+    use crate::types::Transaction;
     use rand::prelude::StdRng;
     use rand::{Rng, SeedableRng};
     use std::fmt::Write;
+    use std::net::{IpAddr, Ipv4Addr};
 
     #[test]
     fn test_core_simple_exchange() {
@@ -761,6 +1135,318 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_core_wal_compaction() {
+        let tmp = tempdir::TempDir::new("test_core_wal_compaction").unwrap();
+        let parameters = Parameters {
+            store_retain_rounds: 5,
+            ..Default::default()
+        };
+        let (_committee, mut cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &parameters);
+        run_rounds(&mut cores, &mut observers, 300);
+        let last_proposed = cores[0].last_proposed();
+        for core in &cores {
+            assert!(core.metrics.wal_dropped_segments.get() > 0);
+            assert!(core.block_store().horizon() > 0);
+            assert!(core.block_store().horizon() < last_proposed);
+        }
+        drop(cores);
+        drop(observers);
+
+        // Validators recover from the compacted wal and keep committing
+        let (_committee, mut cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &parameters);
+        let last_decided = cores[0].last_decided_leader.round();
+        for core in &cores {
+            assert_eq!(core.last_proposed(), last_proposed);
+            assert!(core.block_store().horizon() > 0);
+        }
+        run_rounds(&mut cores, &mut observers, 10);
+        for core in &cores {
+            assert_eq!(core.last_proposed(), last_proposed + 10);
+            assert!(core.last_decided_leader.round() > last_decided);
+        }
+    }
+
+    #[test]
+    fn test_core_epoch_change() {
+        let tmp = tempdir::TempDir::new("test_core_epoch_change").unwrap();
+        let parameters = Parameters {
+            rounds_in_epoch: 10,
+            ..Default::default()
+        };
+        let authorities = (0..3).map(|_| Authority::test_from_stake(1)).collect();
+        let next_committee = NextCommittee::default();
+        let identifiers =
+            Parameters::new_for_benchmarks(vec![IpAddr::V4(Ipv4Addr::LOCALHOST); 3]).identifiers;
+        next_committee
+            .set(&Committee::new(authorities, 1), identifiers)
+            .unwrap();
+        let (_committee, cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &parameters);
+        let mut cores: Vec<_> = cores
+            .into_iter()
+            .map(|core| core.with_next_committee(next_committee.clone()))
+            .collect();
+        run_rounds(&mut cores, &mut observers, 30);
+        for core in &cores {
+            assert!(core.epoch_closed());
+            let epoch_change = core.epoch_change().expect("Epoch change must be decided");
+            assert_eq!(epoch_change.epoch, 0);
+            assert_eq!(epoch_change.next_committee.epoch(), 1);
+            assert_eq!(epoch_change.next_committee.len(), 3);
+        }
+        drop(cores);
+        drop(observers);
+
+        // The decision survives a restart
+        let (_committee, cores, ..) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &parameters);
+        for core in &cores {
+            assert!(core.epoch_closed());
+            let epoch_change = core.epoch_change().expect("Epoch change must be recovered");
+            assert_eq!(epoch_change.next_committee.epoch(), 1);
+        }
+    }
+
+    #[test]
+    fn test_core_leader_reputation() {
+        let parameters = Parameters {
+            leader_reputation: LeaderReputationParameters {
+                window: 5,
+                swap_stake_percentage: 25,
+            },
+            ..Default::default()
+        };
+        let (_committee, mut cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, None, &parameters);
+        // Authority 3 has crashed
+        cores.truncate(3);
+        observers.truncate(3);
+        run_rounds(&mut cores, &mut observers, 30);
+        let last_proposed = cores[0].last_proposed();
+        for core in &cores {
+            assert!(core.metrics.leader_schedule_updates.get() > 0);
+            assert!(core.last_decided_leader == cores[0].last_decided_leader);
+            // The crashed authority is no longer elected
+            for round in last_proposed..last_proposed + 8 {
+                assert!(!core.committer.get_leaders(round).contains(&3));
+            }
+        }
+    }
+
+    #[test]
+    fn test_core_finality_tracking() {
+        let parameters = Parameters {
+            enable_finality_tracking: true,
+            ..Default::default()
+        };
+        let (_committee, cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, None, &parameters);
+        let mut subscribers = vec![];
+        let mut cores: Vec<_> = cores
+            .into_iter()
+            .map(|core| {
+                let (sender, receiver) = broadcast::channel(1024);
+                subscribers.push(receiver);
+                core.with_finalized_transactions(sender)
+            })
+            .collect();
+        run_rounds(&mut cores, &mut observers, 6);
+
+        let second_round: Vec<_> = cores
+            .iter()
+            .flat_map(|core| core.block_handler.proposed.iter())
+            .filter(|locator| locator.block().round == 2)
+            .copied()
+            .collect();
+        assert_eq!(second_round.len(), 4);
+        for (core, subscriber) in cores.iter_mut().zip(subscribers.iter_mut()) {
+            let mut finalized = HashSet::new();
+            while let Ok(transaction) = subscriber.try_recv() {
+                assert!(transaction.certifying_blocks.len() >= 3);
+                assert!(finalized.insert(transaction.locator));
+            }
+            for locator in &second_round {
+                assert!(finalized.contains(locator), "{locator} is not final");
+            }
+            assert_eq!(
+                core.metrics.transactions_finalized.get(),
+                finalized.len() as u64
+            );
+            core.cleanup();
+        }
+    }
+
+    #[test]
+    fn test_core_finality_recovery() {
+        let tmp = tempdir::TempDir::new("test_core_finality_recovery").unwrap();
+        let parameters = Parameters {
+            enable_finality_tracking: true,
+            ..Default::default()
+        };
+        let (_committee, mut cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &parameters);
+        // Transactions of the second round are voted for in the third round, but not final yet
+        run_rounds(&mut cores, &mut observers, 3);
+        let second_round: Vec<_> = cores
+            .iter()
+            .flat_map(|core| core.block_handler.proposed.iter())
+            .filter(|locator| locator.block().round == 2)
+            .copied()
+            .collect();
+        assert_eq!(second_round.len(), 4);
+        for core in &cores {
+            assert_eq!(core.metrics.transactions_finalized.get(), 0);
+        }
+        drop(cores);
+        drop(observers);
+
+        // The votes from before the restart are recovered from the block store
+        let (_committee, cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &parameters);
+        let mut subscribers = vec![];
+        let mut cores: Vec<_> = cores
+            .into_iter()
+            .map(|core| {
+                let (sender, receiver) = broadcast::channel(1024);
+                subscribers.push(receiver);
+                core.with_finalized_transactions(sender)
+            })
+            .collect();
+        run_rounds(&mut cores, &mut observers, 1);
+        for subscriber in &mut subscribers {
+            let mut finalized = HashSet::new();
+            while let Ok(transaction) = subscriber.try_recv() {
+                finalized.insert(transaction.locator);
+            }
+            for locator in &second_round {
+                assert!(finalized.contains(locator), "{locator} is not final");
+            }
+        }
+    }
+
+    #[test]
+    fn test_core_block_limits() {
+        // Fewer includes than the committee size defer includes, and the payloads after them
+        for max_includes in [4, 3] {
+            let block_limits = BlockLimits {
+                max_includes,
+                max_statements: 1,
+                ..Default::default()
+            };
+            let parameters = Parameters {
+                block_limits: block_limits.clone(),
+                ..Default::default()
+            };
+            let (committee, mut cores, mut observers, _reporters) =
+                committee_and_cores_persisted_epoch_duration(4, None, &parameters);
+            run_rounds(&mut cores, &mut observers, 10);
+
+            for core in &cores {
+                let mut statements = 0;
+                for round in 1..=10 {
+                    let block = core
+                        .block_store()
+                        .get_blocks_at_authority_round(core.authority(), round)
+                        .pop()
+                        .expect("Own block must be in the store");
+                    block.verify(&committee).unwrap();
+                    block
+                        .verify_limits(&block_limits, block.serialized_bytes().len())
+                        .unwrap();
+                    statements += block.statements().len();
+                }
+                assert!(core
+                    .pending
+                    .iter()
+                    .any(|(_, statement)| matches!(statement, MetaStatement::Payload(_))));
+                if max_includes == 4 {
+                    // Statements over the limit are proposed in later blocks
+                    assert!(statements >= 8);
+                    assert!(core.last_decided_leader.round() > 0);
+                }
+            }
+        }
+
+        let block = StatementBlock::new(0, 1, vec![], vec![], 0, false, 0, Default::default());
+        assert!(Data::new(block).serialized_bytes().len() <= BLOCK_HEADER_BYTES);
+        let include = BlockReference::default();
+        assert_eq!(
+            bincode::serialized_size(&include).unwrap() as usize,
+            INCLUDE_BYTES
+        );
+        let share = BaseStatement::Share(Transaction::new(vec![]));
+        assert_eq!(
+            bincode::serialized_size(&share).unwrap() as usize,
+            SHARE_STATEMENT_BYTES
+        );
+    }
+
+    #[test]
+    fn test_core_oversized_statement() {
+        let block_limits = BlockLimits {
+            max_includes: 10,
+            max_bytes: 4096,
+            ..Default::default()
+        };
+        let parameters = Parameters {
+            block_limits: block_limits.clone(),
+            ..Default::default()
+        };
+        let (_committee, mut cores, _observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, None, &parameters);
+        let core = &mut cores[0];
+
+        // A statement that does not fit in any block is dropped instead of holding back the
+        // statements after it
+        let oversized = BaseStatement::Share(Transaction::new(vec![0; 4096]));
+        let statement = BaseStatement::Share(Transaction::new(vec![1; 16]));
+        core.pending.push_back((
+            WalPosition::default(),
+            MetaStatement::Payload(vec![oversized, statement.clone()]),
+        ));
+        let block = core.try_new_block().unwrap();
+        assert!(block.statements().contains(&statement));
+        assert!(block
+            .statements()
+            .iter()
+            .all(|statement| bincode::serialized_size(statement).unwrap() < 4096));
+        assert!(!core
+            .pending
+            .iter()
+            .any(|(_, statement)| matches!(statement, MetaStatement::Payload(_))));
+
+        // The mempool rejects such transactions on submission
+        let mempool = MempoolParameters::default().within(&block_limits);
+        let largest = BaseStatement::Share(Transaction::new(vec![0; mempool.max_block_bytes]));
+        assert!(
+            BLOCK_HEADER_BYTES
+                + block_limits.max_includes * INCLUDE_BYTES
+                + bincode::serialized_size(&largest).unwrap() as usize
+                <= block_limits.max_bytes
+        );
+    }
+
+    fn run_rounds(
+        cores: &mut [Core<TestBlockHandler>],
+        observers: &mut [TestCommitObserver],
+        rounds: usize,
+    ) {
+        for _ in 0..rounds {
+            let blocks: Vec<_> = cores
+                .iter_mut()
+                .map(|core| core.try_new_block().expect("Must be able to create block"))
+                .collect();
+            for (core, observer) in cores.iter_mut().zip(observers.iter_mut()) {
+                core.add_blocks(blocks.clone());
+                let committed = observer.handle_commit(core.try_commit());
+                core.handle_committed_subdag(committed, &observer.aggregator_state());
+            }
+        }
+    }
+
     fn push_all(
         p: &mut Vec<Vec<Data<StatementBlock>>>,
         except: AuthorityIndex,
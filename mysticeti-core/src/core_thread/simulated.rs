@@ -6,6 +6,7 @@ use std::collections::HashSet;
 use crate::block_handler::BlockHandler;
 use crate::commit_observer::CommitObserver;
 use crate::data::Data;
+use crate::sim_trace;
 use crate::syncer::{Syncer, SyncerSignals};
 use crate::types::{AuthoritySet, BlockReference};
 use crate::types::{RoundNumber, StatementBlock};
@@ -33,17 +34,20 @@ impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver +
         blocks: Vec<Data<StatementBlock>>,
         connected_authorities: AuthoritySet,
     ) -> Vec<BlockReference> {
-        self.syncer.lock().add_blocks(blocks, connected_authorities)
+        let mut syncer = self.syncer.lock();
+        let missing = syncer.add_blocks(blocks, connected_authorities);
+        sim_trace::record_node_state(syncer.core());
+        missing
     }
 
     pub async fn force_new_block(&self, round: RoundNumber, connected_authorities: AuthoritySet) {
-        self.syncer
-            .lock()
-            .force_new_block(round, connected_authorities);
+        let mut syncer = self.syncer.lock();
+        syncer.force_new_block(round, connected_authorities);
+        sim_trace::record_node_state(syncer.core());
     }
 
     pub async fn cleanup(&self) {
-        self.syncer.lock().core().cleanup();
+        self.syncer.lock().cleanup();
     }
 
     pub async fn get_missing_blocks(&self) -> Vec<HashSet<BlockReference>> {
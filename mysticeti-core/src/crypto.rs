@@ -2,11 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::serde::{ByteRepr, BytesVisitor};
-#[cfg(not(test))]
 use crate::types::Vote;
 use crate::types::{
     AuthorityIndex, BaseStatement, BlockReference, Epoch, EpochStatus, RoundNumber, StatementBlock,
-    TimestampNs,
+    TimestampNs, Transaction,
 };
 use digest::Digest;
 #[cfg(not(test))]
@@ -21,6 +20,10 @@ pub const BLOCK_DIGEST_SIZE: usize = 32;
 #[derive(Clone, Copy, Eq, Ord, PartialOrd, PartialEq, Default, Hash)]
 pub struct BlockDigest([u8; BLOCK_DIGEST_SIZE]);
 
+/// Digest of the transaction bytes, used to recognize the same transaction across blocks.
+#[derive(Clone, Copy, Eq, Ord, PartialOrd, PartialEq, Hash, Debug)]
+pub struct TransactionDigest([u8; BLOCK_DIGEST_SIZE]);
+
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
 pub struct PublicKey(pub ed25519_consensus::VerificationKey);
 
@@ -30,11 +33,9 @@ pub struct SignatureBytes([u8; SIGNATURE_SIZE]);
 // Box ensures value is not copied in memory when Signer itself is moved around for better security
 pub struct Signer(pub Box<ed25519_consensus::SigningKey>);
 
-#[cfg(not(test))]
 type BlockHasher = blake2::Blake2b<digest::consts::U32>;
 
 impl BlockDigest {
-    #[cfg(not(test))]
     pub fn new(
         authority: AuthorityIndex,
         round: RoundNumber,
@@ -61,17 +62,8 @@ impl BlockDigest {
     }
 
     #[cfg(test)]
-    pub fn new(
-        _authority: AuthorityIndex,
-        _round: RoundNumber,
-        _includes: &[BlockReference],
-        _statements: &[BaseStatement],
-        _meta_creation_time_ns: TimestampNs,
-        _epoch_marker: EpochStatus,
-        _epoch: Epoch,
-        _signature: &SignatureBytes,
-    ) -> Self {
-        Default::default()
+    pub fn new_test(byte: u8) -> Self {
+        Self([byte; BLOCK_DIGEST_SIZE])
     }
 
     /// There is a bit of a complexity around what is considered block digest and what is being signed
@@ -81,7 +73,6 @@ impl BlockDigest {
     ///
     /// This is not very beautiful, but it allows to optimize block synchronization,
     /// by skipping signature verification for all the descendants of the certified block.
-    #[cfg(not(test))]
     fn digest_without_signature(
         hasher: &mut BlockHasher,
         authority: AuthorityIndex,
@@ -120,6 +111,10 @@ impl BlockDigest {
                     [4].crypto_hash(hasher);
                     range.crypto_hash(hasher);
                 }
+                BaseStatement::NextCommittee(proposal) => {
+                    [5].crypto_hash(hasher);
+                    proposal.crypto_hash(hasher);
+                }
             }
         }
         meta_creation_time_ns.crypto_hash(hasher);
@@ -192,6 +187,26 @@ impl PublicKey {
     pub fn verify_block(&self, _block: &StatementBlock) -> Result<(), ed25519_consensus::Error> {
         Ok(())
     }
+
+    /// Verifies the signature produced by [`Signer::sign_handshake`] for the given transcript.
+    pub fn verify_handshake(
+        &self,
+        transcript: &[u8],
+        signature: &SignatureBytes,
+    ) -> Result<(), ed25519_consensus::Error> {
+        let signature = ed25519_consensus::Signature::from(signature.0);
+        self.0.verify(&signature, &handshake_digest(transcript))
+    }
+
+    /// Verifies the signature produced by [`Signer::sign_snapshot`] for the given snapshot content.
+    pub fn verify_snapshot(
+        &self,
+        content: &[u8],
+        signature: &SignatureBytes,
+    ) -> Result<(), ed25519_consensus::Error> {
+        let signature = ed25519_consensus::Signature::from(signature.0);
+        self.0.verify(&signature, &snapshot_digest(content))
+    }
 }
 
 impl Signer {
@@ -236,11 +251,46 @@ impl Signer {
         Default::default()
     }
 
+    /// Signs the transcript of a network handshake, proving possession of the authority key
+    /// to the remote peer. Unlike block signatures this is never stubbed in tests.
+    pub fn sign_handshake(&self, transcript: &[u8]) -> SignatureBytes {
+        SignatureBytes(self.0.sign(&handshake_digest(transcript)).to_bytes())
+    }
+
+    /// Signs the serialized content of a state snapshot. Like handshake signatures this is
+    /// never stubbed in tests.
+    pub fn sign_snapshot(&self, content: &[u8]) -> SignatureBytes {
+        SignatureBytes(self.0.sign(&snapshot_digest(content)).to_bytes())
+    }
+
     pub fn public_key(&self) -> PublicKey {
         PublicKey(self.0.verification_key())
     }
 }
 
+// Domain separated digests, so that handshake and snapshot signatures can never be replayed
+// as block signatures (or as each other)
+fn handshake_digest(transcript: &[u8]) -> [u8; BLOCK_DIGEST_SIZE] {
+    domain_digest(b"mysticeti-handshake", transcript)
+}
+
+fn snapshot_digest(content: &[u8]) -> [u8; BLOCK_DIGEST_SIZE] {
+    domain_digest(b"mysticeti-snapshot", content)
+}
+
+impl TransactionDigest {
+    pub fn new(transaction: &Transaction) -> Self {
+        Self(domain_digest(b"mysticeti-transaction", transaction.data()))
+    }
+}
+
+fn domain_digest(domain: &[u8], data: &[u8]) -> [u8; BLOCK_DIGEST_SIZE] {
+    let mut hasher = blake2::Blake2b::<digest::consts::U32>::default();
+    hasher.update(domain);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
 impl AsRef<[u8]> for BlockDigest {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -277,6 +327,12 @@ impl fmt::Display for BlockDigest {
     }
 }
 
+impl fmt::Debug for SignatureBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
 impl fmt::Debug for Signer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Signer(public_key={:?})", self.public_key())
@@ -289,6 +345,12 @@ impl fmt::Display for Signer {
     }
 }
 
+impl SignatureBytes {
+    pub fn new(bytes: [u8; SIGNATURE_SIZE]) -> Self {
+        Self(bytes)
+    }
+}
+
 impl Default for SignatureBytes {
     fn default() -> Self {
         Self([0u8; 64])
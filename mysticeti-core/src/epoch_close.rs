@@ -1,15 +1,64 @@
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use crate::committee::{Committee, QuorumThreshold, StakeAggregator};
+use digest::Digest;
+use eyre::ensure;
+use minibytes::Bytes;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::committee::{Authority, Committee, QuorumThreshold, StakeAggregator};
+use crate::config::Identifier;
+use crate::crypto::CryptoHash;
 use crate::data::Data;
 use crate::runtime::timestamp_utc;
-use crate::types::{InternalEpochStatus, StatementBlock};
+use crate::types::{AuthorityIndex, AuthoritySet, Epoch, InternalEpochStatus, StatementBlock};
 
 pub struct EpochManager {
     epoch_status: InternalEpochStatus,
     change_aggregator: StakeAggregator<QuorumThreshold>,
     epoch_close_time: Arc<AtomicU64>,
+    next_committee: NextCommittee,
+    // Whether this validator already proposed the committee of the next epoch
+    proposed: bool,
+    // Committees of the next epoch proposed in committed blocks, with the stake of the proposers
+    proposals: Vec<(CommitteeProposal, StakeAggregator<QuorumThreshold>)>,
+    // Only the first committed block closing the epoch of each authority counts as its proposal
+    proposers: HashSet<AuthorityIndex>,
+    epoch_change: Option<EpochChange>,
+}
+
+/// The committee of the next epoch, as decided by the external mechanism that triggers the
+/// epoch change. Each validator proposes it in its first block closing the epoch, so it has to be
+/// set before the validator starts to close the epoch.
+#[derive(Clone, Default)]
+pub struct NextCommittee(Arc<Mutex<NextCommitteeInner>>);
+
+#[derive(Default)]
+struct NextCommitteeInner {
+    proposal: Option<CommitteeProposal>,
+    // Latest epoch this validator started to close
+    closing: Option<Epoch>,
+}
+
+/// Committee of the next epoch and the addresses of its authorities, as proposed by a validator
+/// in the statements of its first block closing the epoch.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitteeProposal {
+    epoch: Epoch,
+    authorities: Vec<Authority>,
+    identifiers: Vec<Identifier>,
+}
+
+/// The decision to close an epoch, reached once the epoch is safe to close. Validators of the
+/// next committee start the next epoch from its genesis blocks.
+#[derive(Clone)]
+pub struct EpochChange {
+    pub epoch: Epoch,
+    pub next_committee: Arc<Committee>,
+    /// Addresses of the authorities of the next committee, in the same order.
+    pub identifiers: Vec<Identifier>,
 }
 
 impl EpochManager {
@@ -18,9 +67,27 @@ impl EpochManager {
             epoch_status: Default::default(),
             change_aggregator: StakeAggregator::new(),
             epoch_close_time: Arc::new(AtomicU64::new(0)),
+            next_committee: Default::default(),
+            proposed: false,
+            proposals: vec![],
+            proposers: HashSet::new(),
+            epoch_change: None,
         }
     }
 
+    pub fn set_next_committee(&mut self, next_committee: NextCommittee) {
+        self.next_committee = next_committee;
+    }
+
+    /// Restores an epoch change decided before a restart. The grace period to close
+    /// the epoch starts again from now.
+    pub fn recover_epoch_change(&mut self, epoch_change: EpochChange) {
+        self.epoch_status = InternalEpochStatus::SafeToClose;
+        self.epoch_close_time
+            .store(timestamp_utc().as_millis() as u64, Ordering::Relaxed);
+        self.epoch_change = Some(epoch_change);
+    }
+
     pub fn epoch_change_begun(&mut self) {
         if let InternalEpochStatus::Open = self.epoch_status {
             self.epoch_status = InternalEpochStatus::BeginChange;
@@ -28,8 +95,29 @@ impl EpochManager {
         }
     }
 
+    /// The committee to propose in the first own block closing `epoch`, None once proposed or if
+    /// the next committee is not known. A next committee set later is rejected.
+    pub fn propose_next_committee(&mut self, epoch: Epoch) -> Option<CommitteeProposal> {
+        if !self.changing() || self.proposed {
+            return None;
+        }
+        self.proposed = true;
+        let proposal = self.next_committee.propose(epoch);
+        if proposal.is_none() {
+            tracing::warn!("No committee to propose for the epoch after epoch {epoch}");
+        }
+        proposal
+    }
+
     pub fn observe_committed_block(&mut self, block: &Data<StatementBlock>, committee: &Committee) {
         if block.epoch_changed() {
+            if self.epoch_status != InternalEpochStatus::SafeToClose
+                && self.proposers.insert(block.author())
+            {
+                if let Some(proposal) = block.committee_proposal() {
+                    self.add_proposal(block.author(), proposal, committee);
+                }
+            }
             let is_quorum = self.change_aggregator.add(block.author(), committee);
             if is_quorum && (self.epoch_status != InternalEpochStatus::SafeToClose) {
                 assert!(self.epoch_status == InternalEpochStatus::BeginChange); // Agreement and total ordering property of BA
@@ -37,10 +125,62 @@ impl EpochManager {
                 self.epoch_close_time
                     .store(timestamp_utc().as_millis() as u64, Ordering::Relaxed);
                 tracing::info!("Epoch is now safe to close");
+                self.epoch_change = self.decide_epoch_change(committee);
             }
         }
     }
 
+    fn add_proposal(
+        &mut self,
+        author: AuthorityIndex,
+        proposal: &CommitteeProposal,
+        committee: &Committee,
+    ) {
+        let index = match self
+            .proposals
+            .iter()
+            .position(|(proposed, _)| proposed == proposal)
+        {
+            Some(index) => index,
+            None => {
+                self.proposals
+                    .push((proposal.clone(), StakeAggregator::new()));
+                self.proposals.len() - 1
+            }
+        };
+        self.proposals[index].1.add(author, committee);
+    }
+
+    // Every validator decides on the same committed blocks, so they all agree on the next committee
+    fn decide_epoch_change(&self, committee: &Committee) -> Option<EpochChange> {
+        let Some((proposal, _)) = self
+            .proposals
+            .iter()
+            .find(|(_, proposers)| proposers.is_threshold(committee))
+        else {
+            tracing::warn!(
+                "No committee for the next epoch was proposed by a quorum, epoch {} is the last one",
+                committee.epoch()
+            );
+            return None;
+        };
+        let next_committee = proposal.committee();
+        tracing::info!(
+            "Next epoch {} has {} authorities",
+            next_committee.epoch(),
+            next_committee.len()
+        );
+        Some(EpochChange {
+            epoch: committee.epoch(),
+            next_committee,
+            identifiers: proposal.identifiers.clone(),
+        })
+    }
+
+    pub fn epoch_change(&self) -> Option<&EpochChange> {
+        self.epoch_change.as_ref()
+    }
+
     pub fn changing(&self) -> bool {
         self.epoch_status != InternalEpochStatus::Open
     }
@@ -53,3 +193,221 @@ impl EpochManager {
         self.epoch_close_time.clone()
     }
 }
+
+impl NextCommittee {
+    /// Sets the committee of the next epoch along with the addresses of its authorities. Fails if
+    /// this validator already started to close the epoch before it, as the committee would never
+    /// be proposed.
+    pub fn set(&self, committee: &Committee, identifiers: Vec<Identifier>) -> eyre::Result<()> {
+        let proposal = CommitteeProposal {
+            epoch: committee.epoch(),
+            authorities: committee
+                .authorities()
+                .map(|authority| committee.authority_safe(authority).clone())
+                .collect(),
+            identifiers,
+        };
+        proposal.verify(committee.epoch().saturating_sub(1))?;
+        let mut inner = self.0.lock();
+        if let Some(closing) = inner.closing {
+            ensure!(
+                committee.epoch() > closing + 1,
+                "Committee of epoch {} is set too late, epoch {closing} is already closing",
+                committee.epoch()
+            );
+        }
+        inner.proposal = Some(proposal);
+        Ok(())
+    }
+
+    fn propose(&self, epoch: Epoch) -> Option<CommitteeProposal> {
+        let mut inner = self.0.lock();
+        inner.closing = inner.closing.max(Some(epoch));
+        inner
+            .proposal
+            .clone()
+            .filter(|proposal| proposal.epoch == epoch + 1)
+    }
+}
+
+impl CommitteeProposal {
+    /// Checks that a proposal of another validator describes a valid committee for the epoch
+    /// after `epoch`.
+    pub fn verify(&self, epoch: Epoch) -> eyre::Result<()> {
+        ensure!(
+            self.epoch == epoch + 1,
+            "Committee of epoch {} proposed in epoch {epoch}",
+            self.epoch
+        );
+        ensure!(
+            !self.authorities.is_empty() && self.authorities.len() <= AuthoritySet::MAX_SIZE,
+            "Committee has {} authorities",
+            self.authorities.len()
+        );
+        ensure!(
+            self.authorities
+                .iter()
+                .all(|authority| authority.stake() > 0),
+            "Committee has an authority without stake"
+        );
+        ensure!(
+            self.authorities
+                .iter()
+                .try_fold(0u64, |total, authority| total
+                    .checked_add(authority.stake()))
+                .is_some(),
+            "Committee stake overflows"
+        );
+        ensure!(
+            self.identifiers.len() == self.authorities.len()
+                && self
+                    .identifiers
+                    .iter()
+                    .zip(&self.authorities)
+                    .all(|(identifier, authority)| {
+                        &identifier.public_key == authority.public_key()
+                    }),
+            "Committee addresses do not match its authorities"
+        );
+        Ok(())
+    }
+
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
+    fn committee(&self) -> Arc<Committee> {
+        Committee::new(self.authorities.clone(), self.epoch)
+    }
+}
+
+impl CryptoHash for CommitteeProposal {
+    fn crypto_hash(&self, state: &mut impl Digest) {
+        state.update(bincode::serialize(self).expect("Serialization failed"));
+    }
+}
+
+impl EpochChange {
+    pub fn to_bytes(&self) -> Bytes {
+        bincode::serialize(&(self.epoch, &*self.next_committee, &self.identifiers))
+            .expect("Serialization failed")
+            .into()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        let (epoch, next_committee, identifiers): (Epoch, Committee, Vec<Identifier>) =
+            bincode::deserialize(bytes)?;
+        Ok(Self {
+            epoch,
+            next_committee: Arc::new(next_committee),
+            identifiers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::config::Parameters;
+    use crate::crypto::SignatureBytes;
+    use crate::types::BaseStatement;
+
+    fn proposal(size: usize) -> CommitteeProposal {
+        let committee = Committee::new_test(vec![1; size]);
+        let ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST); size];
+        CommitteeProposal {
+            epoch: 1,
+            authorities: committee
+                .authorities()
+                .map(|authority| committee.authority_safe(authority).clone())
+                .collect(),
+            identifiers: Parameters::new_for_benchmarks(ips).identifiers,
+        }
+    }
+
+    fn closing_block(
+        author: AuthorityIndex,
+        proposal: Option<&CommitteeProposal>,
+    ) -> Data<StatementBlock> {
+        let statements = proposal
+            .map(|proposal| BaseStatement::NextCommittee(Box::new(proposal.clone())))
+            .into_iter()
+            .collect();
+        Data::new(StatementBlock::new(
+            author,
+            1,
+            vec![],
+            statements,
+            0,
+            true,
+            0,
+            SignatureBytes::default(),
+        ))
+    }
+
+    #[test]
+    fn test_epoch_change_quorum_proposal() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let (three, two) = (proposal(3), proposal(2));
+        let mut manager = EpochManager::new();
+        manager.epoch_change_begun();
+        manager.observe_committed_block(&closing_block(0, Some(&three)), &committee);
+        // Only the first block closing the epoch of an authority is its proposal
+        manager.observe_committed_block(&closing_block(0, Some(&two)), &committee);
+        manager.observe_committed_block(&closing_block(1, Some(&three)), &committee);
+        assert!(!manager.closed());
+        manager.observe_committed_block(&closing_block(2, Some(&three)), &committee);
+        assert!(manager.closed());
+        let epoch_change = manager
+            .epoch_change()
+            .expect("Epoch change must be decided");
+        assert_eq!(epoch_change.epoch, 0);
+        assert_eq!(epoch_change.next_committee.epoch(), 1);
+        assert_eq!(epoch_change.next_committee.len(), 3);
+        assert_eq!(epoch_change.identifiers, three.identifiers);
+    }
+
+    #[test]
+    fn test_epoch_change_without_quorum_proposal() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let (three, two) = (proposal(3), proposal(2));
+        let mut manager = EpochManager::new();
+        manager.epoch_change_begun();
+        manager.observe_committed_block(&closing_block(0, Some(&three)), &committee);
+        manager.observe_committed_block(&closing_block(1, Some(&two)), &committee);
+        manager.observe_committed_block(&closing_block(2, None), &committee);
+        assert!(manager.closed());
+        assert!(manager.epoch_change().is_none());
+        // A proposal committed after the epoch is safe to close does not change the decision
+        manager.observe_committed_block(&closing_block(3, Some(&three)), &committee);
+        assert!(manager.epoch_change().is_none());
+    }
+
+    #[test]
+    fn test_next_committee_set_too_late() {
+        let three = proposal(3);
+        let next_committee = NextCommittee::default();
+        next_committee
+            .set(&three.committee(), three.identifiers.clone())
+            .unwrap();
+        let mut manager = EpochManager::new();
+        manager.set_next_committee(next_committee.clone());
+        assert!(manager.propose_next_committee(0).is_none());
+        manager.epoch_change_begun();
+        assert!(manager.propose_next_committee(0) == Some(three.clone()));
+        assert!(manager.propose_next_committee(0).is_none());
+        assert!(next_committee
+            .set(&three.committee(), three.identifiers.clone())
+            .is_err());
+        // Addresses must match the authorities of the committee
+        let mut identifiers = three.identifiers.clone();
+        identifiers.pop();
+        let committee = Committee::new(three.authorities.clone(), 2);
+        assert!(next_committee.set(&committee, identifiers).is_err());
+        assert!(next_committee
+            .set(&committee, three.identifiers.clone())
+            .is_ok());
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use eyre::ensure;
+use serde::{Deserialize, Serialize};
+
+use crate::committee::Committee;
+use crate::data::Data;
+use crate::types::{AuthorityIndex, RoundNumber, StatementBlock};
+
+/// Evidence that an authority signed two different blocks for the same round.
+///
+/// The proof carries both full blocks (including their signatures), so it can be checked
+/// by anyone holding the committee, independently of the local block store.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EquivocationProof {
+    first: Data<StatementBlock>,
+    second: Data<StatementBlock>,
+}
+
+impl EquivocationProof {
+    /// Creates a proof from two blocks of the same (author, round) slot. Returns `None` if the
+    /// blocks are not conflicting.
+    pub fn new(first: Data<StatementBlock>, second: Data<StatementBlock>) -> Option<Self> {
+        if first.author_round() != second.author_round() || first.digest() == second.digest() {
+            return None;
+        }
+        Some(Self { first, second })
+    }
+
+    /// Checks that both blocks are correctly signed by the same authority for the same round
+    /// and that they are different.
+    pub fn verify(&self, committee: &Committee) -> eyre::Result<()> {
+        ensure!(
+            self.first.author() == self.second.author(),
+            "Blocks have different authors: {} and {}",
+            self.first.author(),
+            self.second.author()
+        );
+        ensure!(
+            self.first.round() == self.second.round(),
+            "Blocks have different rounds: {} and {}",
+            self.first.round(),
+            self.second.round()
+        );
+        ensure!(
+            self.first.digest() != self.second.digest(),
+            "Blocks are identical: {}",
+            self.first.reference()
+        );
+        self.first.verify(committee)?;
+        self.second.verify(committee)?;
+        Ok(())
+    }
+
+    pub fn author(&self) -> AuthorityIndex {
+        self.first.author()
+    }
+
+    pub fn round(&self) -> RoundNumber {
+        self.first.round()
+    }
+
+    pub fn first(&self) -> &Data<StatementBlock> {
+        &self.first
+    }
+
+    pub fn second(&self) -> &Data<StatementBlock> {
+        &self.second
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::BlockDigest;
+    use crate::test_util::committee;
+
+    fn block(authority: AuthorityIndex, round: RoundNumber, digest: u8) -> Data<StatementBlock> {
+        let block = StatementBlock::new(
+            authority,
+            round,
+            vec![],
+            vec![],
+            0,
+            false,
+            0,
+            Default::default(),
+        );
+        Data::new(block.with_test_digest(BlockDigest::new_test(digest)))
+    }
+
+    #[test]
+    fn proof_requires_conflicting_blocks() {
+        assert!(EquivocationProof::new(block(0, 1, 1), block(0, 1, 2)).is_some());
+        assert!(EquivocationProof::new(block(0, 1, 1), block(0, 1, 1)).is_none());
+        assert!(EquivocationProof::new(block(0, 1, 1), block(1, 1, 2)).is_none());
+        assert!(EquivocationProof::new(block(0, 1, 1), block(0, 2, 2)).is_none());
+    }
+
+    #[test]
+    fn verify_rejects_mismatched_blocks() {
+        let committee = committee(4);
+        let proof = EquivocationProof {
+            first: block(0, 1, 1),
+            second: block(1, 1, 2),
+        };
+        assert!(proof.verify(&committee).is_err());
+        let proof = EquivocationProof {
+            first: block(0, 1, 1),
+            second: block(0, 1, 1),
+        };
+        assert!(proof.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn proof_serialization() {
+        let proof = EquivocationProof::new(block(2, 3, 1), block(2, 3, 2)).unwrap();
+        let bytes = bincode::serialize(&proof).unwrap();
+        let proof: EquivocationProof = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(proof.author(), 2);
+        assert_eq!(proof.round(), 3);
+        assert_ne!(proof.first().digest(), proof.second().digest());
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fast path finality: a transaction is final once a quorum of authorities voted for it, and the
+//! blocks carrying these votes form a certificate anyone holding the blocks can check. A quorum
+//! of reject votes likewise certifies that a transaction will never be final on the fast path.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use eyre::{bail, ensure};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::committee::{Committee, ProcessedTransactionHandler};
+use crate::crypto::SignatureBytes;
+use crate::data::Data;
+use crate::types::{BaseStatement, BlockReference, StatementBlock, TransactionLocator, Vote};
+
+/// Number of certificates kept after their transaction is certified, to answer late requests.
+const RETAINED_CERTIFICATES: usize = 100_000;
+
+/// Proof that a quorum of authorities voted for, or against, a transaction on the fast path.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionCertificate {
+    pub locator: TransactionLocator,
+    /// Whether the quorum voted to reject the transaction.
+    pub rejected: bool,
+    /// Blocks voting for the transaction, or against it if rejected, along with their signatures.
+    /// The block sharing the transaction is the accept vote of its author.
+    pub votes: Vec<(BlockReference, SignatureBytes)>,
+}
+
+impl TransactionCertificate {
+    /// Checks that the voting blocks are signed by a quorum of distinct authorities of the
+    /// committee, and that each of them votes for the transaction, or against it if rejected.
+    /// `get_block` returns the voting blocks, for example from a block store.
+    pub fn verify(
+        &self,
+        committee: &Committee,
+        get_block: impl Fn(BlockReference) -> Option<Data<StatementBlock>>,
+    ) -> eyre::Result<()> {
+        let mut voters = HashSet::new();
+        for (reference, signature) in &self.votes {
+            let Some(block) = get_block(*reference) else {
+                bail!("Voting block {reference} is not available");
+            };
+            ensure!(
+                block.reference() == reference,
+                "Block {} does not match voting block {reference}",
+                block.reference()
+            );
+            ensure!(
+                block.signature() == signature,
+                "Signature of voting block {reference} does not match"
+            );
+            block.verify(committee)?;
+            ensure!(
+                votes_for(&block, &self.locator, self.rejected),
+                "Block {reference} does not cast the certified vote for transaction {}",
+                self.locator
+            );
+            ensure!(
+                voters.insert(reference.authority),
+                "Authority {} voted more than once",
+                reference.authority
+            );
+        }
+        ensure!(
+            committee.is_quorum(committee.get_total_stake(&voters)),
+            "Transaction {} is not certified by a quorum",
+            self.locator
+        );
+        Ok(())
+    }
+}
+
+fn votes_for(block: &StatementBlock, locator: &TransactionLocator, rejected: bool) -> bool {
+    if rejected {
+        return block.statements().iter().any(|statement| {
+            matches!(statement, BaseStatement::Vote(voted, Vote::Reject(_)) if voted == locator)
+        });
+    }
+    if block.reference() == locator.block() {
+        return block
+            .shared_transactions()
+            .any(|(shared, _)| shared == *locator);
+    }
+    block.statements().iter().any(|statement| match statement {
+        BaseStatement::Vote(voted, Vote::Accept) => voted == locator,
+        BaseStatement::VoteRange(range) => {
+            range.block() == locator.block() && range.range().contains(&locator.offset())
+        }
+        _ => false,
+    })
+}
+
+/// Collects the voting blocks of the pending transactions of a transaction aggregator.
+#[derive(Default, Serialize, Deserialize)]
+pub struct VoteCollector {
+    pending: HashMap<TransactionLocator, PendingVotes>,
+    #[serde(skip)]
+    certified: Vec<CollectedVotes>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PendingVotes {
+    accept: Vec<BlockReference>,
+    reject: Vec<BlockReference>,
+}
+
+/// Blocks that certified a transaction, or rejected it.
+pub struct CollectedVotes {
+    pub locator: TransactionLocator,
+    pub rejected: bool,
+    pub voters: Vec<BlockReference>,
+}
+
+impl VoteCollector {
+    /// Transactions certified or rejected since the last call.
+    pub fn take_certified(&mut self) -> Vec<CollectedVotes> {
+        std::mem::take(&mut self.certified)
+    }
+
+    fn certify(&mut self, locator: TransactionLocator, rejected: bool) {
+        let votes = self.pending.remove(&locator).unwrap_or_default();
+        let voters = if rejected { votes.reject } else { votes.accept };
+        self.certified.push(CollectedVotes {
+            locator,
+            rejected,
+            voters,
+        });
+    }
+}
+
+impl ProcessedTransactionHandler<TransactionLocator> for VoteCollector {
+    fn transaction_processed(&mut self, k: TransactionLocator) {
+        self.certify(k, false);
+    }
+
+    fn transaction_rejected(&mut self, k: TransactionLocator) {
+        self.certify(k, true);
+    }
+
+    fn transaction_voted(&mut self, k: TransactionLocator, voter: BlockReference, vote: &Vote) {
+        let votes = self.pending.entry(k).or_default();
+        let voters = match vote {
+            Vote::Accept => &mut votes.accept,
+            Vote::Reject(_) => &mut votes.reject,
+        };
+        if voters
+            .iter()
+            .all(|existing| existing.authority != voter.authority)
+        {
+            voters.push(voter);
+        }
+    }
+}
+
+/// Certificates of the transactions certified or rejected on the fast path, which clients can
+/// wait for.
+#[derive(Clone, Default)]
+pub struct FastPathCertificates {
+    inner: Arc<Mutex<FastPathCertificatesInner>>,
+}
+
+#[derive(Default)]
+struct FastPathCertificatesInner {
+    certificates: HashMap<TransactionLocator, TransactionCertificate>,
+    // Certified transactions in the order they were certified, oldest first
+    order: VecDeque<TransactionLocator>,
+    waiters: HashMap<TransactionLocator, Vec<oneshot::Sender<TransactionCertificate>>>,
+}
+
+impl FastPathCertificates {
+    pub fn add(&self, certificate: TransactionCertificate) {
+        let mut inner = self.inner.lock();
+        if let Some(waiters) = inner.waiters.remove(&certificate.locator) {
+            for waiter in waiters {
+                // We don't mind if the receiver is dropped.
+                waiter.send(certificate.clone()).ok();
+            }
+        }
+        if inner.order.len() == RETAINED_CERTIFICATES {
+            let oldest = inner.order.pop_front().unwrap();
+            inner.certificates.remove(&oldest);
+        }
+        inner.order.push_back(certificate.locator);
+        inner.certificates.insert(certificate.locator, certificate);
+    }
+
+    pub fn get(&self, locator: &TransactionLocator) -> Option<TransactionCertificate> {
+        self.inner.lock().certificates.get(locator).cloned()
+    }
+
+    /// Waits for the transaction to be certified or rejected. Only the most recent certificates are
+    /// retained, and a transaction might never be certified, so callers should bound the wait with
+    /// a timeout.
+    /// Returns None if the block handler is dropped.
+    pub async fn wait(&self, locator: TransactionLocator) -> Option<TransactionCertificate> {
+        let receiver = {
+            let mut inner = self.inner.lock();
+            if let Some(certificate) = inner.certificates.get(&locator) {
+                return Some(certificate.clone());
+            }
+            let (sender, receiver) = oneshot::channel();
+            inner.waiters.entry(locator).or_default().push(sender);
+            receiver
+        };
+        receiver.await.ok()
+    }
+
+    /// Drops the waiters that gave up.
+    pub fn cleanup(&self) {
+        self.inner.lock().waiters.retain(|_, waiters| {
+            waiters.retain(|waiter| !waiter.is_closed());
+            !waiters.is_empty()
+        });
+    }
+
+    /// Wakes up all waiters with None.
+    pub fn close(&self) {
+        self.inner.lock().waiters.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_handler::{BlockHandler, FastPathBlockHandler};
+    use crate::block_validator::{AcceptAllTransactionValidator, TransactionValidator};
+    use crate::test_util::{committee, TestBlockWriter};
+    use crate::types::{AuthorityIndex, RoundNumber, Transaction, TransactionLocatorRange};
+
+    fn block(
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        statements: Vec<BaseStatement>,
+    ) -> Data<StatementBlock> {
+        // Includes a quorum of the previous round for the block to be valid
+        let includes = (0..3)
+            .map(|authority| BlockReference::new_test(authority, round - 1))
+            .collect();
+        Data::new(StatementBlock::new(
+            authority,
+            round,
+            includes,
+            statements,
+            0,
+            false,
+            0,
+            Default::default(),
+        ))
+    }
+
+    #[test]
+    fn test_fast_path_certificate() {
+        let committee = committee(4);
+        let mut writer = TestBlockWriter::new(&committee);
+        let (mut handler, _sender, certificates) = FastPathBlockHandler::new(
+            committee.clone(),
+            writer.block_store(),
+            AcceptAllTransactionValidator,
+            Default::default(),
+        );
+
+        let shared = block(0, 1, vec![BaseStatement::Share(Transaction::new(vec![1]))]);
+        let (locator, _) = shared.shared_transactions().next().unwrap();
+        writer.add_block(shared.clone());
+        let votes = handler.handle_blocks(&[shared.clone()], true);
+        assert!(matches!(votes[..], [BaseStatement::VoteRange(_)]));
+
+        // The own vote is counted when proposed, before the block is in the store
+        let own = block(3, 2, votes);
+        handler.handle_proposal(&own);
+        assert!(certificates.get(&locator).is_none());
+        writer.add_block(own.clone());
+
+        let vote = block(1, 2, vec![BaseStatement::Vote(locator, Vote::Accept)]);
+        writer.add_block(vote.clone());
+        handler.handle_blocks(&[vote.clone()], false);
+        let certificate = certificates.get(&locator).unwrap();
+        assert!(!certificate.rejected);
+        let voters: Vec<_> = certificate.votes.iter().map(|(r, _)| *r).collect();
+        assert_eq!(
+            voters,
+            vec![*shared.reference(), *own.reference(), *vote.reference()]
+        );
+
+        let block_store = writer.block_store();
+        let get_block = |reference| block_store.get_block(reference);
+        certificate.verify(&committee, get_block).unwrap();
+
+        let mut no_quorum = certificate.clone();
+        no_quorum.votes.pop();
+        assert!(no_quorum.verify(&committee, get_block).is_err());
+
+        let not_voting = block(2, 2, vec![]);
+        writer.add_block(not_voting.clone());
+        let mut wrong_vote = certificate.clone();
+        wrong_vote.votes[2].0 = *not_voting.reference();
+        assert!(wrong_vote.verify(&committee, get_block).is_err());
+
+        let mut duplicate = certificate;
+        duplicate.votes.push(duplicate.votes[0]);
+        assert!(duplicate.verify(&committee, get_block).is_err());
+    }
+
+    struct RejectEmpty;
+
+    impl TransactionValidator for RejectEmpty {
+        fn vote(&mut self, _locator: TransactionLocator, transaction: &Transaction) -> Vote {
+            if transaction.data().is_empty() {
+                Vote::Reject(None)
+            } else {
+                Vote::Accept
+            }
+        }
+    }
+
+    #[test]
+    fn test_fast_path_rejection() {
+        let committee = committee(4);
+        let mut writer = TestBlockWriter::new(&committee);
+        let (mut handler, _sender, certificates) = FastPathBlockHandler::new(
+            committee.clone(),
+            writer.block_store(),
+            RejectEmpty,
+            Default::default(),
+        );
+
+        let shared = block(
+            0,
+            1,
+            vec![
+                BaseStatement::Share(Transaction::new(vec![1])),
+                BaseStatement::Share(Transaction::new(vec![])),
+            ],
+        );
+        let locators: Vec<_> = shared.shared_transactions().map(|(l, _)| l).collect();
+        writer.add_block(shared.clone());
+        let votes = handler.handle_blocks(&[shared], true);
+        let accepted = TransactionLocatorRange::one(locators[0]);
+        assert!(matches!(&votes[..], [
+            BaseStatement::Vote(rejected, Vote::Reject(None)),
+            BaseStatement::VoteRange(range),
+        ] if *range == accepted && *rejected == locators[1]));
+
+        let own = block(3, 2, votes);
+        handler.handle_proposal(&own);
+        writer.add_block(own.clone());
+        let rejections: Vec<_> = (1..3)
+            .map(|authority| {
+                let statements = vec![BaseStatement::Vote(locators[1], Vote::Reject(None))];
+                let rejection = block(authority, 2, statements);
+                writer.add_block(rejection.clone());
+                rejection
+            })
+            .collect();
+        handler.handle_blocks(&rejections, false);
+
+        let certificate = certificates.get(&locators[1]).unwrap();
+        assert!(certificate.rejected);
+        assert_eq!(certificate.votes.len(), 3);
+        let block_store = writer.block_store();
+        certificate
+            .verify(&committee, |reference| block_store.get_block(reference))
+            .unwrap();
+        // The accepted transaction only has the votes of the proposer and of this authority
+        assert!(certificates.get(&locators[0]).is_none());
+    }
+}
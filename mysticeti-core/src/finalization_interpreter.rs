@@ -1,148 +1,385 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fast path finality over the dag. A block certifies a transaction once its causal history holds
+//! accept votes for the transaction from a quorum, and the transaction is final once blocks of a
+//! quorum of authorities certify it: every later leader then commits one of the certifying blocks.
+
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use crate::block_store::BlockStore;
-use crate::types::{AuthorityIndex, BaseStatement, TransactionLocator, Vote};
-use crate::{
-    committee::{Committee, QuorumThreshold, StakeAggregator},
-    data::Data,
-    types::{BlockReference, StatementBlock},
+use crate::committee::{Committee, QuorumThreshold, StakeAggregator};
+use crate::types::{
+    BaseStatement, BlockReference, RoundNumber, StatementBlock, TransactionLocator, Vote,
 };
 
-#[allow(dead_code)]
-pub struct FinalizationInterpreter<'a> {
-    transaction_aggregator:
-        HashMap<BlockReference, HashMap<TransactionLocator, StakeAggregator<QuorumThreshold>>>,
-    certificate_aggregator: HashMap<TransactionLocator, StakeAggregator<QuorumThreshold>>,
-    transaction_certificates: HashMap<TransactionLocator, HashSet<BlockReference>>,
+/// Transaction that became final, along with the certifying blocks that made it final.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FinalizedTransaction {
+    pub locator: TransactionLocator,
+    pub certifying_blocks: Vec<BlockReference>,
+}
+
+type Votes = HashMap<TransactionLocator, StakeAggregator<QuorumThreshold>>;
+
+pub struct FinalizationInterpreter {
     committee: Arc<Committee>,
-    block_store: &'a BlockStore,
-    finalized_transactions: HashSet<TransactionLocator>,
+    // Votes for the pending transactions added by each block to the causal history of its parent
+    block_votes: HashMap<BlockReference, BlockVotes>,
+    // Certifying blocks of the pending transactions, at most one per authority
+    certificates: HashMap<TransactionLocator, Certificates>,
+    // Final transactions, so that their votes still spreading through the dag are ignored
+    finalized: HashSet<TransactionLocator>,
+    // Blocks and transactions below this round are forgotten
+    cleanup_round: RoundNumber,
+}
+
+// Only the votes that are new to the chain of the author are stored with each block, so that a vote
+// is kept at most once per authority instead of once per block that has it in its causal history.
+struct BlockVotes {
+    // Previous block of the same author among the includes
+    parent: Option<BlockReference>,
+    votes: Votes,
+}
+
+#[derive(Default)]
+struct Certificates {
+    blocks: Vec<BlockReference>,
+    authorities: StakeAggregator<QuorumThreshold>,
 }
 
-#[allow(dead_code)]
-impl<'a> FinalizationInterpreter<'a> {
-    pub fn new(block_store: &'a BlockStore, committee: Arc<Committee>) -> Self {
+impl FinalizationInterpreter {
+    pub fn new(committee: Arc<Committee>) -> Self {
         Self {
-            transaction_aggregator: Default::default(),
-            certificate_aggregator: Default::default(),
-            transaction_certificates: Default::default(),
             committee,
-            block_store,
-            finalized_transactions: Default::default(),
+            block_votes: Default::default(),
+            certificates: Default::default(),
+            finalized: Default::default(),
+            cleanup_round: 0,
         }
     }
 
-    pub fn finalized_tx_certifying_blocks(
-        &mut self,
-    ) -> Vec<(TransactionLocator, HashSet<BlockReference>)> {
-        for round in 0..=self.block_store.highest_round() {
-            println!("ROUND: {}", round);
-            for block in self.block_store.get_blocks_by_round(round) {
-                self._finalized_tx_certifying_blocks(&block);
+    /// Processes a block after the blocks it includes, and returns the transactions that became
+    /// final with it. Votes of included blocks that were not processed, or were cleaned up, are
+    /// not counted.
+    pub fn add_block(&mut self, block: &StatementBlock) -> Vec<FinalizedTransaction> {
+        if block.round() < self.cleanup_round || self.block_votes.contains_key(block.reference()) {
+            return vec![];
+        }
+        let parent = block
+            .includes()
+            .iter()
+            .filter(|include| include.authority == block.author() && include.round < block.round())
+            .max_by_key(|include| include.round)
+            .copied();
+        let mut visited = HashSet::new();
+        let mut parent_votes = Votes::new();
+        if let Some(parent) = &parent {
+            self.collect_votes(parent, &mut visited, &mut parent_votes);
+        }
+        let mut history_votes = Votes::new();
+        for include in block.includes() {
+            self.collect_votes(include, &mut visited, &mut history_votes);
+        }
+
+        let mut voted = vec![];
+        for (locator, _) in block.shared_transactions() {
+            voted.push(locator);
+        }
+        for statement in block.statements() {
+            match statement {
+                BaseStatement::Vote(locator, Vote::Accept) => voted.push(*locator),
+                BaseStatement::VoteRange(range) => voted.extend(range.locators()),
+                BaseStatement::Vote(_, Vote::Reject(_))
+                | BaseStatement::Share(_)
+                | BaseStatement::NextCommittee(_) => {}
             }
         }
-        let mut result = vec![];
-        for (k, v) in &self.transaction_certificates {
-            if self.finalized_transactions.contains(k) {
-                result.push((*k, v.clone()));
+        for locator in voted {
+            if self.is_pending(&locator) {
+                history_votes
+                    .entry(locator)
+                    .or_default()
+                    .add(block.author(), &self.committee);
             }
         }
-        println!("Total finalized transactions: {}", result.len());
-        result
+
+        let mut votes = Votes::new();
+        let mut finalized = vec![];
+        for (locator, aggregator) in history_votes {
+            let mut total = parent_votes.get(&locator).cloned().unwrap_or_default();
+            let mut new_votes = StakeAggregator::new();
+            for voter in aggregator.voters() {
+                if !total.contains(voter) {
+                    new_votes.add(voter, &self.committee);
+                    total.add(voter, &self.committee);
+                }
+            }
+            // Without new votes the transaction was already certified by the parent if it could be
+            if new_votes.voters().next().is_none() {
+                continue;
+            }
+            // Blocks closing the epoch do not certify transactions
+            if !block.epoch_changed() && total.is_threshold(&self.committee) {
+                if let Some(certifying_blocks) = self.certify(&locator, block) {
+                    finalized.push(FinalizedTransaction {
+                        locator,
+                        certifying_blocks,
+                    });
+                    continue;
+                }
+            }
+            votes.insert(locator, new_votes);
+        }
+        self.block_votes
+            .insert(*block.reference(), BlockVotes { parent, votes });
+        finalized
     }
 
-    fn _finalized_tx_certifying_blocks(&mut self, block: &Data<StatementBlock>) {
-        if self.transaction_aggregator.contains_key(block.reference()) {
-            // already processed
+    /// Forgets the blocks and the transactions below `threshold_round`, along with their votes.
+    pub fn cleanup(&mut self, threshold_round: RoundNumber) {
+        if threshold_round <= self.cleanup_round {
             return;
         }
-        self.transaction_aggregator
-            .insert(*block.reference(), Default::default());
+        self.cleanup_round = threshold_round;
+        self.block_votes
+            .retain(|reference, _| reference.round >= threshold_round);
+        self.certificates
+            .retain(|locator, _| locator.block().round >= threshold_round);
+        self.finalized
+            .retain(|locator| locator.block().round >= threshold_round);
+    }
 
-        for (offset, statement) in block.statements().iter().enumerate() {
-            match statement {
-                BaseStatement::Vote(locator, vote) => {
-                    if let Vote::Accept = vote {
-                        self.vote(block, locator, block.author());
-                    }
-                }
-                BaseStatement::VoteRange(tx_locator_range) => {
-                    for locator in tx_locator_range.locators() {
-                        self.vote(block, &locator, block.author());
-                    }
-                }
-                BaseStatement::Share(_) => {
-                    let locator = TransactionLocator::new(*block.reference(), offset as u64);
-                    self.vote(block, &locator, block.author());
-                }
+    /// Number of transactions certified by some blocks, but not final yet.
+    pub fn pending_certificates(&self) -> usize {
+        self.certificates.len()
+    }
+
+    fn is_pending(&self, locator: &TransactionLocator) -> bool {
+        locator.block().round >= self.cleanup_round && !self.finalized.contains(locator)
+    }
+
+    // Adds the votes for the pending transactions in the causal history of the block, walking
+    // back the chain of its author until a block that was already visited or is no longer known.
+    fn collect_votes(
+        &self,
+        reference: &BlockReference,
+        visited: &mut HashSet<BlockReference>,
+        votes: &mut Votes,
+    ) {
+        let mut next = Some(*reference);
+        while let Some(reference) = next {
+            if !visited.insert(reference) {
+                return;
             }
-        }
-        for parent in block.includes() {
-            self._finalized_tx_certifying_blocks(&self.block_store.get_block(*parent).unwrap());
-            let parent_aggregator =
-                std::mem::take(self.transaction_aggregator.get_mut(parent).unwrap());
-            for (tx, parent_aggregator) in &parent_aggregator {
-                for voter in parent_aggregator.voters() {
-                    self.vote(block, tx, voter);
+            let Some(block_votes) = self.block_votes.get(&reference) else {
+                return;
+            };
+            for (locator, aggregator) in &block_votes.votes {
+                if !self.is_pending(locator) {
+                    continue;
+                }
+                let entry = votes.entry(*locator).or_default();
+                for voter in aggregator.voters() {
+                    entry.add(voter, &self.committee);
                 }
             }
-            // let _ = std::mem::replace(self.transaction_aggregator_for(parent), parent_aggregator);
-            self.transaction_aggregator
-                .insert(*parent, parent_aggregator);
+            next = block_votes.parent;
         }
     }
 
-    fn vote(
+    fn certify(
         &mut self,
-        block: &Data<StatementBlock>,
-        transaction: &TransactionLocator,
-        tx_voter: AuthorityIndex,
-    ) {
-        let block_transaction_aggregator = self
-            .transaction_aggregator
-            .get_mut(block.reference())
-            .unwrap();
-        if !block_transaction_aggregator.contains_key(transaction) {
-            block_transaction_aggregator
-                .insert(*transaction, StakeAggregator::<QuorumThreshold>::new());
-        }
-        if block_transaction_aggregator
-            .get_mut(transaction)
-            .unwrap()
-            .add(tx_voter, &self.committee)
-            && !block.epoch_changed()
+        locator: &TransactionLocator,
+        block: &StatementBlock,
+    ) -> Option<Vec<BlockReference>> {
+        let certificates = self.certificates.entry(*locator).or_default();
+        if certificates
+            .blocks
+            .iter()
+            .any(|certifying| certifying.authority == block.author())
         {
-            // this is a certifying block
-            if !self.transaction_certificates.contains_key(transaction) {
-                self.transaction_certificates
-                    .insert(*transaction, Default::default());
-            }
-            self.transaction_certificates
-                .get_mut(transaction)
-                .unwrap()
-                .insert(*block.reference());
-
-            if !self.certificate_aggregator.contains_key(transaction) {
-                self.certificate_aggregator
-                    .insert(*transaction, StakeAggregator::new());
-            }
+            return None;
+        }
+        certificates.blocks.push(*block.reference());
+        if !certificates
+            .authorities
+            .add(block.author(), &self.committee)
+        {
+            return None;
+        }
+        let certificates = self.certificates.remove(locator).unwrap();
+        self.finalized.insert(*locator);
+        Some(certificates.blocks)
+    }
+}
 
-            if self
-                .certificate_aggregator
-                .get_mut(transaction)
-                .unwrap()
-                .add(block.author(), &self.committee)
-            {
-                self.finalized_transactions.insert(*transaction);
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::committee;
+    use crate::types::{AuthorityIndex, Transaction};
+
+    fn block(
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[AuthorityIndex],
+        statements: Vec<BaseStatement>,
+    ) -> StatementBlock {
+        let includes = includes
+            .iter()
+            .map(|include| BlockReference::new_test(*include, round - 1))
+            .collect();
+        StatementBlock::new(
+            authority,
+            round,
+            includes,
+            statements,
+            0,
+            false,
+            0,
+            Default::default(),
+        )
+        .with_test_digest(Default::default())
+    }
+
+    #[test]
+    fn test_finalization_interpreter() {
+        let mut interpreter = FinalizationInterpreter::new(committee(4));
+        let shared = block(
+            0,
+            1,
+            &[0, 1, 2],
+            vec![BaseStatement::Share(Transaction::new(vec![1]))],
+        );
+        let (locator, _) = shared.shared_transactions().next().unwrap();
+        assert!(interpreter.add_block(&shared).is_empty());
+        for authority in 1..3 {
+            assert!(interpreter
+                .add_block(&block(authority, 1, &[0, 1, 2], vec![]))
+                .is_empty());
+        }
+
+        // Votes of round 2 only reach a quorum in the blocks of round 3 that include all of them
+        for authority in 0..3 {
+            let vote = BaseStatement::Vote(locator, Vote::Accept);
+            assert!(interpreter
+                .add_block(&block(authority, 2, &[0, 1, 2], vec![vote]))
+                .is_empty());
         }
+        assert_eq!(interpreter.pending_certificates(), 0);
+
+        // Certifying blocks of a quorum of authorities make the transaction final
+        assert!(interpreter
+            .add_block(&block(3, 3, &[0, 1], vec![]))
+            .is_empty());
+        assert_eq!(interpreter.pending_certificates(), 0);
+        assert!(interpreter
+            .add_block(&block(0, 3, &[0, 1, 2], vec![]))
+            .is_empty());
+        assert!(interpreter
+            .add_block(&block(1, 3, &[0, 1, 2], vec![]))
+            .is_empty());
+        assert_eq!(interpreter.pending_certificates(), 1);
+        let finalized = interpreter.add_block(&block(2, 3, &[0, 1, 2], vec![]));
+        let certifying_blocks: Vec<_> = (0..3)
+            .map(|authority| BlockReference::new_test(authority, 3))
+            .collect();
+        assert_eq!(
+            finalized,
+            vec![FinalizedTransaction {
+                locator,
+                certifying_blocks
+            }]
+        );
+        assert_eq!(interpreter.pending_certificates(), 0);
+
+        // A final transaction is not reported again
+        assert!(interpreter
+            .add_block(&block(3, 4, &[0, 1, 2], vec![]))
+            .is_empty());
+
+        interpreter.cleanup(4);
+        assert!(interpreter.block_votes.keys().all(|r| r.round >= 4));
+        assert!(interpreter.finalized.is_empty());
     }
 
-    fn transaction_aggregator_for(
-        &mut self,
-        block: &BlockReference,
-    ) -> &mut HashMap<TransactionLocator, StakeAggregator<QuorumThreshold>> {
-        self.transaction_aggregator.get_mut(block).unwrap()
+    #[test]
+    fn test_finalization_interpreter_cleanup() {
+        let mut interpreter = FinalizationInterpreter::new(committee(4));
+        let shared = block(
+            0,
+            1,
+            &[0, 1, 2],
+            vec![BaseStatement::Share(Transaction::new(vec![1]))],
+        );
+        let (locator, _) = shared.shared_transactions().next().unwrap();
+        interpreter.add_block(&shared);
+        for authority in 1..3 {
+            let vote = BaseStatement::Vote(locator, Vote::Accept);
+            interpreter.add_block(&block(authority, 2, &[0], vec![vote]));
+        }
+
+        // Transactions below the cleanup round are no longer tracked
+        interpreter.cleanup(2);
+        for authority in 0..3 {
+            assert!(interpreter
+                .add_block(&block(authority, 3, &[1, 2], vec![]))
+                .is_empty());
+        }
+        assert_eq!(interpreter.pending_certificates(), 0);
+    }
+
+    #[test]
+    fn test_finalization_interpreter_votes_bound() {
+        let mut interpreter = FinalizationInterpreter::new(committee(4));
+        let shared = block(
+            0,
+            1,
+            &[0, 1, 2, 3],
+            vec![BaseStatement::Share(Transaction::new(vec![1]))],
+        );
+        let (locator, _) = shared.shared_transactions().next().unwrap();
+        interpreter.add_block(&shared);
+        for authority in 1..4 {
+            interpreter.add_block(&block(authority, 1, &[0, 1, 2, 3], vec![]));
+        }
+        // The transaction never reaches a quorum and stays pending
+        let vote = BaseStatement::Vote(locator, Vote::Accept);
+        for authority in 0..4 {
+            let statements = if authority == 1 {
+                vec![vote.clone()]
+            } else {
+                vec![]
+            };
+            interpreter.add_block(&block(authority, 2, &[0, 1, 2, 3], statements));
+        }
+        for round in 3..20 {
+            for authority in 0..4 {
+                interpreter.add_block(&block(authority, round, &[0, 1, 2, 3], vec![]));
+            }
+        }
+
+        // Each vote is stored once per authority, not once per block
+        let stored_votes: usize = interpreter
+            .block_votes
+            .values()
+            .flat_map(|block_votes| block_votes.votes.values())
+            .map(|aggregator| aggregator.voters().count())
+            .sum();
+        assert_eq!(stored_votes, 2 * 4);
+        assert_eq!(interpreter.block_votes.len(), 19 * 4);
+
+        // A third vote is counted along with the ones stored in the earlier rounds
+        let vote = BaseStatement::Vote(locator, Vote::Accept);
+        interpreter.add_block(&block(2, 20, &[0, 1, 2, 3], vec![vote]));
+        assert!(interpreter
+            .add_block(&block(0, 21, &[0, 1, 2, 3], vec![]))
+            .is_empty());
+        assert_eq!(interpreter.pending_certificates(), 1);
+
+        interpreter.cleanup(10);
+        assert_eq!(interpreter.block_votes.len(), 10 * 4 + 2);
+        assert_eq!(interpreter.pending_certificates(), 0);
     }
 }
@@ -1,6 +1,8 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::runtime::select;
+use crate::sim_trace::{self, TraceEventKind};
 use crate::simulator::{Scheduler, Simulator, SimulatorState};
 use crate::types::AuthorityIndex;
 use futures::FutureExt;
@@ -13,7 +15,6 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll, Wake};
 use std::time::Duration;
-use tokio::select;
 use tokio::sync::{oneshot, Notify};
 
 #[derive(Default)]
@@ -166,6 +167,12 @@ impl SimulatorContext {
         })
     }
 
+    /// Leaves the context of a task that panicked while it was polled, so that the thread can run
+    /// another simulation.
+    pub fn clear() {
+        CONTEXT.with(|ctx| ctx.borrow_mut().take());
+    }
+
     pub fn exit() -> Self {
         CONTEXT.with(|ctx| {
             ctx.borrow_mut()
@@ -260,6 +267,7 @@ impl SimulatorState for SimulatedExecutorState {
                 let waker = waker.into();
                 let mut context = Context::from_waker(&waker);
                 let task = oc.get_mut();
+                sim_trace::record(TraceEventKind::Wake(task_id));
                 SimulatorContext::new(task_id, task.node).enter();
                 if let Poll::Ready(()) = task.f.as_mut().poll(&mut context) {
                     oc.remove();
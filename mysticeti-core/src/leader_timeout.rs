@@ -0,0 +1,150 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The time to wait for the leaders of a round before proposing without them. When adaptive, the
+//! timeout follows the observed round latency, grows with consecutive timeouts and shrinks back
+//! after rounds that complete in time.
+
+use std::time::Duration;
+
+use crate::config::LeaderTimeoutParameters;
+
+/// Weight of a new sample in the moving average of the round latency.
+const LATENCY_SAMPLE_WEIGHT: f64 = 0.1;
+
+pub struct LeaderTimeout {
+    parameters: LeaderTimeoutParameters,
+    timeout: Duration,
+    /// Moving average of the latency of the rounds that completed without a timeout.
+    average_latency: Option<Duration>,
+}
+
+impl LeaderTimeout {
+    pub fn new(initial: Duration, parameters: LeaderTimeoutParameters) -> Self {
+        let timeout = if parameters.adaptive {
+            initial.clamp(parameters.min_timeout, parameters.max_timeout)
+        } else {
+            initial
+        };
+        Self {
+            parameters,
+            timeout,
+            average_latency: None,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Called when the round advanced `latency` after the previous round, without a timeout.
+    pub fn round_completed(&mut self, latency: Duration) {
+        if !self.parameters.adaptive {
+            return;
+        }
+        let average_latency = match self.average_latency {
+            Some(average) => {
+                average.mul_f64(1.0 - LATENCY_SAMPLE_WEIGHT)
+                    + latency.mul_f64(LATENCY_SAMPLE_WEIGHT)
+            }
+            None => latency,
+        };
+        self.average_latency = Some(average_latency);
+        let target = average_latency.mul_f64(self.parameters.latency_percentage as f64 / 100.0);
+        let shrunk = self
+            .timeout
+            .mul_f64(1.0 - self.parameters.shrink_percentage.min(100) as f64 / 100.0);
+        self.set_timeout(target.max(shrunk));
+    }
+
+    /// Called when the leaders of the round did not arrive within the timeout.
+    pub fn timed_out(&mut self) {
+        if !self.parameters.adaptive {
+            return;
+        }
+        let grown = self
+            .timeout
+            .mul_f64(1.0 + self.parameters.backoff_percentage as f64 / 100.0);
+        self.set_timeout(grown);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout.clamp(self.parameters.min_timeout, self.parameters.max_timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn assert_timeout(leader_timeout: &LeaderTimeout, expected_millis: u64) {
+        let timeout = leader_timeout.timeout().as_secs_f64() * 1000.0;
+        assert!(
+            (timeout - expected_millis as f64).abs() < 0.001,
+            "timeout {timeout}ms, expected {expected_millis}ms"
+        );
+    }
+
+    fn parameters() -> LeaderTimeoutParameters {
+        LeaderTimeoutParameters {
+            adaptive: true,
+            min_timeout: millis(100),
+            max_timeout: millis(10_000),
+            latency_percentage: 200,
+            backoff_percentage: 100,
+            shrink_percentage: 50,
+        }
+    }
+
+    #[test]
+    fn test_fixed_leader_timeout() {
+        let parameters = LeaderTimeoutParameters {
+            adaptive: false,
+            ..parameters()
+        };
+        let mut leader_timeout = LeaderTimeout::new(millis(2_000), parameters);
+        leader_timeout.timed_out();
+        leader_timeout.round_completed(millis(10));
+        assert_timeout(&leader_timeout, 2_000);
+    }
+
+    #[test]
+    fn test_adaptive_leader_timeout() {
+        let mut leader_timeout = LeaderTimeout::new(millis(2_000), parameters());
+        // Shrinks gradually after successful rounds, down to a multiple of the observed latency
+        leader_timeout.round_completed(millis(300));
+        assert_timeout(&leader_timeout, 1_000);
+        leader_timeout.round_completed(millis(300));
+        assert_timeout(&leader_timeout, 600);
+        leader_timeout.round_completed(millis(300));
+        assert_timeout(&leader_timeout, 600);
+
+        // Grows with consecutive timeouts, up to the maximum
+        leader_timeout.timed_out();
+        assert_timeout(&leader_timeout, 1_200);
+        leader_timeout.timed_out();
+        assert_timeout(&leader_timeout, 2_400);
+        for _ in 0..10 {
+            leader_timeout.timed_out();
+        }
+        assert_timeout(&leader_timeout, 10_000);
+
+        // Shrinks again after successful rounds, but stays above a multiple of slower rounds
+        leader_timeout.round_completed(millis(300));
+        assert_timeout(&leader_timeout, 5_000);
+        for _ in 0..20 {
+            leader_timeout.round_completed(millis(3_000));
+        }
+        assert!(leader_timeout.timeout() > millis(4_000));
+
+        // Never below the minimum
+        for _ in 0..100 {
+            leader_timeout.round_completed(millis(1));
+        }
+        assert_timeout(&leader_timeout, 100);
+    }
+}
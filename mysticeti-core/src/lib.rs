@@ -5,7 +5,11 @@ pub mod block_handler;
 mod block_manager;
 mod block_store;
 pub mod block_validator;
+#[cfg(test)]
+#[cfg(feature = "simulator")]
+mod byzantine;
 pub mod commit_observer;
+pub mod commit_stream;
 pub mod committee;
 pub mod config;
 pub mod consensus;
@@ -14,27 +18,37 @@ mod core_thread;
 mod crypto;
 mod data;
 mod epoch_close;
-mod finalization_interpreter;
+pub mod equivocation;
+pub mod fast_path;
+pub mod finalization_interpreter;
 #[cfg(test)]
 #[cfg(feature = "simulator")]
 mod future_simulator;
+mod leader_timeout;
 #[allow(dead_code)] // todo - delete if unused after a while
 mod lock;
 mod log;
+mod mempool;
 pub mod metrics;
 pub mod net_sync;
 pub mod network;
+mod peer_limits;
 pub mod prometheus;
 mod range_map;
+pub mod rpc;
 mod runtime;
 mod serde;
 #[cfg(test)]
 #[cfg(feature = "simulator")]
+mod sim_trace;
+#[cfg(test)]
+#[cfg(feature = "simulator")]
 mod simulated_network;
 #[cfg(test)]
 mod simulator;
 #[cfg(feature = "simulator")]
 mod simulator_tracing;
+pub mod snapshot;
 mod stat;
 mod state;
 mod syncer;
@@ -43,9 +57,11 @@ mod synchronizer;
 mod test_util;
 mod threshold_clock;
 mod transactions_generator;
+pub mod transport;
 pub mod types;
 pub mod validator;
 mod wal;
+pub mod wal_inspect;
 
 // re-export
 pub use crate::block_handler::SimpleBlockHandler;
@@ -0,0 +1,460 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transactions submitted to this validator that wait to be proposed in an own block.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use tokio::sync::oneshot;
+
+use crate::config::MempoolParameters;
+use crate::consensus::linearizer::CommittedSubDag;
+use crate::crypto::TransactionDigest;
+use crate::types::{RoundNumber, StatementBlock, Transaction, TransactionLocator};
+
+type Notify = oneshot::Sender<TransactionLocator>;
+
+/// Transactions shared by a block of another authority are proposed again if the block is not
+/// committed by the time a leader this many rounds later is.
+const COMMIT_WAIT_ROUNDS: RoundNumber = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MempoolError {
+    /// The transaction does not fit in a block.
+    TooLarge,
+    /// The mempool is full of transactions with the same or a higher priority.
+    Full,
+}
+
+pub struct Mempool {
+    parameters: MempoolParameters,
+    // Transactions waiting to be proposed
+    pending: HashMap<TransactionDigest, PendingTransaction>,
+    // Pending transactions by decreasing priority, then in the order they arrived
+    order: BTreeSet<(Reverse<u64>, u64, TransactionDigest)>,
+    pending_bytes: usize,
+    // Transactions returned for the next own block, notified once the block is proposed
+    proposing: HashMap<TransactionDigest, Vec<Notify>>,
+    // Transactions shared by blocks of other authorities, with the round of the block, set aside
+    // until the block is committed
+    included: HashMap<TransactionDigest, (RoundNumber, PendingTransaction)>,
+    // Transactions already in the dag, the oldest ones are forgotten first
+    seen: HashMap<TransactionDigest, TransactionLocator>,
+    seen_order: VecDeque<TransactionDigest>,
+    next_sequence: u64,
+}
+
+struct PendingTransaction {
+    transaction: Transaction,
+    priority: u64,
+    sequence: u64,
+    notify: Vec<Notify>,
+}
+
+impl Mempool {
+    pub fn new(parameters: MempoolParameters) -> Self {
+        Self {
+            parameters,
+            pending: Default::default(),
+            order: Default::default(),
+            pending_bytes: 0,
+            proposing: Default::default(),
+            included: Default::default(),
+            seen: Default::default(),
+            seen_order: Default::default(),
+            next_sequence: 0,
+        }
+    }
+
+    /// Adds a submitted transaction, `notify` receives its locator once it is in an own block or
+    /// in a committed block of another authority.
+    /// A transaction that is already pending or proposed is not added again: the submitter is
+    /// notified along with the earlier ones, and a resubmission with a higher priority raises the
+    /// priority of the pending transaction. When the mempool is full, the pending transactions with
+    /// the lowest priority are evicted to make room for a transaction with a higher priority, and
+    /// their submitters are dropped.
+    pub fn insert(
+        &mut self,
+        transaction: Transaction,
+        priority: u64,
+        notify: Option<Notify>,
+    ) -> Result<(), MempoolError> {
+        let digest = TransactionDigest::new(&transaction);
+        if let Some(locator) = self.seen.get(&digest) {
+            if let Some(notify) = notify {
+                // We don't mind if the receiver is dropped.
+                notify.send(*locator).ok();
+            }
+            return Ok(());
+        }
+        if let Some(proposing) = self.proposing.get_mut(&digest) {
+            proposing.extend(notify);
+            return Ok(());
+        }
+        if let Some((_, included)) = self.included.get_mut(&digest) {
+            included.notify.extend(notify);
+            return Ok(());
+        }
+        if let Some(pending) = self.pending.get_mut(&digest) {
+            pending.notify.extend(notify);
+            if priority > pending.priority {
+                self.order
+                    .remove(&(Reverse(pending.priority), pending.sequence, digest));
+                pending.priority = priority;
+                self.order
+                    .insert((Reverse(pending.priority), pending.sequence, digest));
+            }
+            return Ok(());
+        }
+
+        let size = transaction.data().len();
+        if size > self.parameters.max_block_bytes {
+            return Err(MempoolError::TooLarge);
+        }
+        self.make_room(size, priority)?;
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.add_pending(
+            digest,
+            PendingTransaction {
+                transaction,
+                priority,
+                sequence,
+                notify: notify.into_iter().collect(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Takes the transactions for the next own block, by decreasing priority and within the block
+    /// limits. A transaction that does not fit stops the batch, so that it is not overtaken by
+    /// transactions with a lower priority.
+    pub fn next_batch(&mut self) -> Vec<Transaction> {
+        let mut batch = vec![];
+        let mut bytes = 0;
+        while batch.len() < self.parameters.max_block_transactions {
+            let Some((_, _, digest)) = self.order.first() else {
+                break;
+            };
+            let size = self.pending[digest].transaction.data().len();
+            if bytes + size > self.parameters.max_block_bytes {
+                break;
+            }
+            let digest = *digest;
+            let pending = self.remove_pending(&digest);
+            bytes += size;
+            self.proposing.insert(digest, pending.notify);
+            batch.push(pending.transaction);
+        }
+        batch
+    }
+
+    /// Notifies the submitters of the transactions shared by an own block.
+    pub fn proposed(&mut self, block: &StatementBlock) {
+        for (locator, transaction) in block.shared_transactions() {
+            let digest = TransactionDigest::new(transaction);
+            if let Some(notify) = self.proposing.remove(&digest) {
+                for notify in notify {
+                    notify.send(locator).ok();
+                }
+            }
+            self.mark_seen(digest, locator);
+        }
+    }
+
+    /// Sets aside the pending transactions already shared by a block of another authority, so that
+    /// they are not proposed again while the block may still be committed.
+    pub fn included(&mut self, block: &StatementBlock) {
+        for (_, transaction) in block.shared_transactions() {
+            let digest = TransactionDigest::new(transaction);
+            if self.pending.contains_key(&digest) {
+                let pending = self.remove_pending(&digest);
+                self.included.insert(digest, (block.round(), pending));
+            }
+        }
+    }
+
+    /// Removes the transactions of the committed blocks, their submitters receive the locator in
+    /// the committed block. The transactions set aside for blocks that are still not committed
+    /// `COMMIT_WAIT_ROUNDS` rounds below the last committed leader wait to be proposed again.
+    pub fn committed(&mut self, committed: &[CommittedSubDag]) {
+        for block in committed.iter().flat_map(|commit| &commit.blocks) {
+            for (locator, transaction) in block.shared_transactions() {
+                let digest = TransactionDigest::new(transaction);
+                let notify = match self.included.remove(&digest) {
+                    Some((_, included)) => included.notify,
+                    None if self.pending.contains_key(&digest) => {
+                        self.remove_pending(&digest).notify
+                    }
+                    None => vec![],
+                };
+                for notify in notify {
+                    notify.send(locator).ok();
+                }
+                self.mark_seen(digest, locator);
+            }
+        }
+        let Some(leader_round) = committed.last().map(|commit| commit.anchor.round) else {
+            return;
+        };
+        let expired: Vec<_> = self
+            .included
+            .iter()
+            .filter(|(_, (round, _))| round + COMMIT_WAIT_ROUNDS < leader_round)
+            .map(|(digest, _)| *digest)
+            .collect();
+        for digest in expired {
+            let (_, pending) = self.included.remove(&digest).unwrap();
+            // Dropping the notifications tells the submitters the transaction was not proposed
+            if self
+                .make_room(pending.transaction.data().len(), pending.priority)
+                .is_ok()
+            {
+                self.add_pending(digest, pending);
+            }
+        }
+    }
+
+    /// Number of transactions waiting to be proposed.
+    pub fn pending_transactions(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Total size in bytes of the transactions waiting to be proposed.
+    pub fn pending_bytes(&self) -> usize {
+        self.pending_bytes
+    }
+
+    fn make_room(&mut self, size: usize, priority: u64) -> Result<(), MempoolError> {
+        let mut evicted = vec![];
+        let mut count = self.pending.len();
+        let mut bytes = self.pending_bytes;
+        for (Reverse(lowest), _, digest) in self.order.iter().rev() {
+            if count < self.parameters.max_transactions && bytes + size <= self.parameters.max_bytes
+            {
+                break;
+            }
+            if *lowest >= priority {
+                return Err(MempoolError::Full);
+            }
+            count -= 1;
+            bytes -= self.pending[digest].transaction.data().len();
+            evicted.push(*digest);
+        }
+        if count >= self.parameters.max_transactions || bytes + size > self.parameters.max_bytes {
+            return Err(MempoolError::Full);
+        }
+        for digest in evicted {
+            // Dropping the notifications tells the submitters the transaction was not proposed
+            self.remove_pending(&digest);
+        }
+        Ok(())
+    }
+
+    fn add_pending(&mut self, digest: TransactionDigest, pending: PendingTransaction) {
+        self.order
+            .insert((Reverse(pending.priority), pending.sequence, digest));
+        self.pending_bytes += pending.transaction.data().len();
+        self.pending.insert(digest, pending);
+    }
+
+    fn remove_pending(&mut self, digest: &TransactionDigest) -> PendingTransaction {
+        let pending = self
+            .pending
+            .remove(digest)
+            .expect("Transaction must be pending");
+        self.order
+            .remove(&(Reverse(pending.priority), pending.sequence, *digest));
+        self.pending_bytes -= pending.transaction.data().len();
+        pending
+    }
+
+    fn mark_seen(&mut self, digest: TransactionDigest, locator: TransactionLocator) {
+        if self.seen.insert(digest, locator).is_some() {
+            return;
+        }
+        self.seen_order.push_back(digest);
+        while self.seen_order.len() > self.parameters.seen_capacity {
+            let forgotten = self.seen_order.pop_front().unwrap();
+            self.seen.remove(&forgotten);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::types::{BaseStatement, BlockReference};
+
+    fn parameters() -> MempoolParameters {
+        MempoolParameters {
+            max_transactions: 3,
+            max_bytes: 100,
+            max_block_transactions: 2,
+            max_block_bytes: 10,
+            seen_capacity: 2,
+        }
+    }
+
+    fn block(authority: u64, transactions: &[Vec<u8>]) -> StatementBlock {
+        let statements = transactions
+            .iter()
+            .map(|data| BaseStatement::Share(Transaction::new(data.clone())))
+            .collect();
+        StatementBlock::new(
+            authority,
+            1,
+            vec![],
+            statements,
+            0,
+            false,
+            0,
+            Default::default(),
+        )
+    }
+
+    fn insert(
+        mempool: &mut Mempool,
+        data: Vec<u8>,
+        priority: u64,
+    ) -> (
+        Result<(), MempoolError>,
+        oneshot::Receiver<TransactionLocator>,
+    ) {
+        let (notify, receiver) = oneshot::channel();
+        let result = mempool.insert(Transaction::new(data), priority, Some(notify));
+        (result, receiver)
+    }
+
+    fn commit(leader_round: RoundNumber, blocks: Vec<StatementBlock>) -> CommittedSubDag {
+        CommittedSubDag {
+            anchor: BlockReference::new_test(0, leader_round),
+            blocks: blocks.into_iter().map(Data::new).collect(),
+            timestamp_ms: 0,
+            height: 0,
+        }
+    }
+
+    fn batch_data(mempool: &mut Mempool) -> Vec<Vec<u8>> {
+        mempool
+            .next_batch()
+            .into_iter()
+            .map(Transaction::into_data)
+            .collect()
+    }
+
+    #[test]
+    fn test_mempool_priority_and_block_limits() {
+        let mut mempool = Mempool::new(parameters());
+        insert(&mut mempool, vec![1; 4], 0).0.unwrap();
+        insert(&mut mempool, vec![2; 4], 5).0.unwrap();
+        insert(&mut mempool, vec![3; 4], 5).0.unwrap();
+        assert_eq!(mempool.pending_bytes(), 12);
+        assert_eq!(
+            insert(&mut mempool, vec![4; 11], 9).0,
+            Err(MempoolError::TooLarge)
+        );
+
+        // Highest priority first, in arrival order within a priority, until the block is full
+        assert_eq!(batch_data(&mut mempool), vec![vec![2; 4], vec![3; 4]]);
+        insert(&mut mempool, vec![5; 8], 1).0.unwrap();
+        // The transaction with the lower priority does not overtake the one that does not fit
+        insert(&mut mempool, vec![6; 2], 0).0.unwrap();
+        assert_eq!(batch_data(&mut mempool), vec![vec![5; 8]]);
+        assert_eq!(batch_data(&mut mempool), vec![vec![1; 4], vec![6; 2]]);
+        assert_eq!(mempool.pending_transactions(), 0);
+        assert_eq!(mempool.pending_bytes(), 0);
+    }
+
+    #[test]
+    fn test_mempool_eviction() {
+        let mut mempool = Mempool::new(parameters());
+        let (_, mut evicted) = insert(&mut mempool, vec![1], 1);
+        insert(&mut mempool, vec![2], 2).0.unwrap();
+        insert(&mut mempool, vec![3], 3).0.unwrap();
+        assert_eq!(insert(&mut mempool, vec![4], 1).0, Err(MempoolError::Full));
+
+        // A higher priority evicts the lowest one
+        insert(&mut mempool, vec![4], 2).0.unwrap();
+        assert_eq!(mempool.pending_transactions(), 3);
+        assert_eq!(
+            evicted.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        );
+        assert_eq!(batch_data(&mut mempool), vec![vec![3], vec![2]]);
+        assert_eq!(batch_data(&mut mempool), vec![vec![4]]);
+    }
+
+    #[test]
+    fn test_mempool_deduplication() {
+        let mut mempool = Mempool::new(parameters());
+        let (_, mut first) = insert(&mut mempool, vec![1], 0);
+        insert(&mut mempool, vec![2], 1).0.unwrap();
+        // Resubmission raises the priority of the pending transaction
+        let (_, mut second) = insert(&mut mempool, vec![1], 2);
+        assert_eq!(mempool.pending_transactions(), 2);
+        assert_eq!(batch_data(&mut mempool), vec![vec![1], vec![2]]);
+
+        // Both submitters are notified once the transaction is proposed
+        let (_, mut proposing) = insert(&mut mempool, vec![1], 0);
+        let own = block(0, &[vec![1], vec![2]]);
+        mempool.proposed(&own);
+        let (locator, _) = own.shared_transactions().next().unwrap();
+        assert_eq!(first.try_recv(), Ok(locator));
+        assert_eq!(second.try_recv(), Ok(locator));
+        assert_eq!(proposing.try_recv(), Ok(locator));
+
+        // A proposed transaction is not proposed again
+        let (_, mut resubmitted) = insert(&mut mempool, vec![1], 0);
+        assert_eq!(resubmitted.try_recv(), Ok(locator));
+        assert_eq!(mempool.pending_transactions(), 0);
+    }
+
+    #[test]
+    fn test_mempool_included_until_commit() {
+        let mut mempool = Mempool::new(parameters());
+        let (_, mut included) = insert(&mut mempool, vec![1], 0);
+        insert(&mut mempool, vec![2], 0).0.unwrap();
+
+        // Transactions shared by another authority are not proposed while its block may commit
+        let other = block(1, &[vec![1], vec![3]]);
+        mempool.included(&other);
+        assert_eq!(batch_data(&mut mempool), vec![vec![2]]);
+        let (_, mut resubmitted) = insert(&mut mempool, vec![1], 0);
+        assert!(included.try_recv().is_err());
+
+        // Submitters are notified once the block is committed
+        mempool.committed(&[commit(2, vec![other.clone()])]);
+        let (locator, _) = other.shared_transactions().next().unwrap();
+        assert_eq!(included.try_recv(), Ok(locator));
+        assert_eq!(resubmitted.try_recv(), Ok(locator));
+
+        // Only the most recently committed transactions are remembered
+        mempool.committed(&[commit(3, vec![block(2, &[vec![4]])])]);
+        let (_, mut forgotten) = insert(&mut mempool, vec![1], 0);
+        assert!(forgotten.try_recv().is_err());
+        assert_eq!(mempool.pending_transactions(), 1);
+    }
+
+    #[test]
+    fn test_mempool_requeue_uncommitted() {
+        let mut mempool = Mempool::new(parameters());
+        let (_, mut included) = insert(&mut mempool, vec![1], 0);
+        mempool.included(&block(1, &[vec![1]]));
+        assert!(batch_data(&mut mempool).is_empty());
+
+        // Still set aside while the including block may be committed
+        mempool.committed(&[commit(1 + COMMIT_WAIT_ROUNDS, vec![])]);
+        assert!(batch_data(&mut mempool).is_empty());
+
+        // Proposed again once the block is too old to be committed
+        mempool.committed(&[commit(2 + COMMIT_WAIT_ROUNDS, vec![])]);
+        assert_eq!(batch_data(&mut mempool), vec![vec![1]]);
+        let own = block(0, &[vec![1]]);
+        mempool.proposed(&own);
+        let (locator, _) = own.shared_transactions().next().unwrap();
+        assert_eq!(included.try_recv(), Ok(locator));
+    }
+}
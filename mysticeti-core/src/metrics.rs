@@ -7,6 +7,7 @@ use crate::runtime;
 use crate::runtime::JoinHandle;
 use crate::stat::{histogram, DivUsize, HistogramSender, PreciseHistogram};
 use crate::types::{format_authority_index, AuthorityIndex};
+use parking_lot::Mutex;
 use prometheus::{
     register_counter_vec_with_registry, register_histogram_vec_with_registry,
     register_histogram_with_registry, register_int_counter_vec_with_registry,
@@ -20,6 +21,7 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use tabled::{Table, Tabled};
+use tokio::sync::mpsc;
 use tokio::sync::mpsc::Sender;
 use tokio::time::Instant;
 
@@ -39,14 +41,21 @@ pub struct Metrics {
     pub latency_squared_s: CounterVec,
     pub committed_leaders_total: IntCounterVec,
     pub leader_timeout_total: IntCounter,
+    pub leader_timeout_ms: IntGauge,
+    pub leader_reputation_score: IntGaugeVec,
+    pub leader_schedule_updates: IntCounter,
+    pub leader_schedule_swapped: IntGauge,
     pub inter_block_latency_s: HistogramVec,
 
     pub block_store_unloaded_blocks: IntCounter,
     pub block_store_loaded_blocks: IntCounter,
     pub block_store_entries: IntCounter,
     pub block_store_cleanup_util: IntCounter,
+    pub block_store_pruned_blocks: IntCounter,
 
     pub wal_mappings: IntGauge,
+    pub wal_segments: IntGauge,
+    pub wal_dropped_segments: IntCounter,
 
     pub core_lock_util: IntCounter,
     pub core_lock_enqueued: IntCounter,
@@ -57,11 +66,23 @@ pub struct Metrics {
 
     pub commit_handler_pending_certificates: IntGauge,
 
+    pub transactions_finalized: IntCounter,
+    pub transaction_finalized_latency_s: Histogram,
+    pub finalization_pending_certificates: IntGauge,
+
     pub missing_blocks: IntCounterVec,
     pub blocks_suspended: IntCounter,
+    pub equivocations_detected: IntCounterVec,
+    pub blocks_rejected: IntCounterVec,
+    pub peer_misbehavior: IntCounterVec,
+    pub peer_misbehavior_score: IntGaugeVec,
+    pub peer_bans: IntCounterVec,
     pub block_sync_requests_sent: IntCounterVec,
     pub block_sync_requests_received: IntCounterVec,
 
+    pub network_compression_input_bytes: IntCounter,
+    pub network_compression_output_bytes: IntCounter,
+
     pub transaction_certified_latency: HistogramSender<Duration>,
     pub certificate_committed_latency: HistogramSender<Duration>,
     pub transaction_committed_latency: HistogramSender<Duration>,
@@ -70,7 +91,9 @@ pub struct Metrics {
     pub proposed_block_transaction_count: HistogramSender<usize>,
     pub proposed_block_vote_count: HistogramSender<usize>,
 
-    pub connection_latency_sender: Vec<HistogramSender<Duration>>,
+    // Grows when a peer outside the initial committee connects, in a later epoch
+    connection_latency_sender: Arc<Mutex<Vec<HistogramSender<Duration>>>>,
+    new_connection_latency: mpsc::UnboundedSender<(PreciseHistogram<Duration>, String)>,
     pub connected_nodes: IntGauge,
 
     pub utilization_timer: IntCounterVec,
@@ -114,6 +137,8 @@ pub struct HistogramReporter<T> {
 
 pub struct VecHistogramReporter<T> {
     histograms: Vec<(PreciseHistogram<T>, String)>,
+    // Histograms added after the reporter started
+    new_histograms: Option<mpsc::UnboundedReceiver<(PreciseHistogram<T>, String)>>,
     gauge: IntGaugeVec,
 }
 
@@ -143,6 +168,7 @@ impl Metrics {
                 )
             })
             .unzip();
+        let (new_connection_latency, new_connection_latency_receiver) = mpsc::unbounded_channel();
         let reporter = MetricReporter {
             transaction_certified_latency: HistogramReporter::new_in_registry(
                 transaction_certified_latency_hist,
@@ -181,7 +207,8 @@ impl Metrics {
                 "peer",
                 registry,
                 "connection_latency",
-            ),
+            )
+            .with_new_histograms(new_connection_latency_receiver),
             block_commit_latency: HistogramReporter::new_in_registry(
                 block_commit_latency_hist,
                 registry,
@@ -240,6 +267,25 @@ impl Metrics {
                 registry,
             )
             .unwrap(),
+            leader_reputation_score: register_int_gauge_vec_with_registry!(
+                "leader_reputation_score",
+                "Reputation score of each authority in the last complete reputation window",
+                &["authority"],
+                registry,
+            )
+            .unwrap(),
+            leader_schedule_updates: register_int_counter_with_registry!(
+                "leader_schedule_updates",
+                "Number of times the leader schedule was updated from reputation scores",
+                registry,
+            )
+            .unwrap(),
+            leader_schedule_swapped: register_int_gauge_with_registry!(
+                "leader_schedule_swapped",
+                "Number of authorities currently swapped out of the leader schedule",
+                registry,
+            )
+            .unwrap(),
             inter_block_latency_s: register_histogram_vec_with_registry!(
                 "inter_block_latency_s",
                 "Buckets measuring the inter-block latency in seconds",
@@ -253,6 +299,12 @@ impl Metrics {
                 registry,
             )
             .unwrap(),
+            leader_timeout_ms: register_int_gauge_with_registry!(
+                "leader_timeout_ms",
+                "Current time to wait for the leaders of a round",
+                registry,
+            )
+            .unwrap(),
 
             block_store_loaded_blocks: register_int_counter_with_registry!(
                 "block_store_loaded_blocks",
@@ -266,6 +318,12 @@ impl Metrics {
                 registry,
             )
             .unwrap(),
+            block_store_pruned_blocks: register_int_counter_with_registry!(
+                "block_store_pruned_blocks",
+                "Blocks removed from the block store index when compacting the wal",
+                registry,
+            )
+            .unwrap(),
             block_store_entries: register_int_counter_with_registry!(
                 "block_store_entries",
                 "Number of entries in block store",
@@ -285,6 +343,18 @@ impl Metrics {
                 registry,
             )
             .unwrap(),
+            wal_segments: register_int_gauge_with_registry!(
+                "wal_segments",
+                "Number of segment files of the wal",
+                registry,
+            )
+            .unwrap(),
+            wal_dropped_segments: register_int_counter_with_registry!(
+                "wal_dropped_segments",
+                "Wal segments dropped by compaction",
+                registry,
+            )
+            .unwrap(),
 
             core_lock_util: register_int_counter_with_registry!(
                 "core_lock_util",
@@ -325,6 +395,26 @@ impl Metrics {
             )
             .unwrap(),
 
+            transactions_finalized: register_int_counter_with_registry!(
+                "transactions_finalized",
+                "Number of transactions final on the fast path",
+                registry,
+            )
+            .unwrap(),
+            transaction_finalized_latency_s: register_histogram_with_registry!(
+                "transaction_finalized_latency_s",
+                "The time from the creation of the block sharing a transaction to its finality",
+                LATENCY_SEC_BUCKETS.to_vec(),
+                registry,
+            )
+            .unwrap(),
+            finalization_pending_certificates: register_int_gauge_with_registry!(
+                "finalization_pending_certificates",
+                "Number of certified transactions that are not final yet",
+                registry,
+            )
+            .unwrap(),
+
             missing_blocks: register_int_counter_vec_with_registry!(
                 "missing_blocks",
                 "Number of missing blocks per authority",
@@ -347,6 +437,19 @@ impl Metrics {
             )
             .unwrap(),
 
+            network_compression_input_bytes: register_int_counter_with_registry!(
+                "network_compression_input_bytes",
+                "Size of the compressed block batches before compression",
+                registry,
+            )
+            .unwrap(),
+            network_compression_output_bytes: register_int_counter_with_registry!(
+                "network_compression_output_bytes",
+                "Size of the compressed block batches after compression, the compression ratio is input/output",
+                registry,
+            )
+            .unwrap(),
+
             utilization_timer: register_int_counter_vec_with_registry!(
                 "utilization_timer",
                 "Utilization timer",
@@ -379,6 +482,39 @@ impl Metrics {
                 registry
             ).unwrap(),
 
+            equivocations_detected: register_int_counter_vec_with_registry!(
+                "equivocations_detected",
+                "The number of equivocating blocks detected per authority",
+                &["authority"],
+                registry
+            ).unwrap(),
+
+            blocks_rejected: register_int_counter_vec_with_registry!(
+                "blocks_rejected",
+                "The number of received blocks rejected per author and reason (limits, epoch, consensus or validation)",
+                &["authority", "reason"],
+                registry
+            ).unwrap(),
+
+            peer_misbehavior: register_int_counter_vec_with_registry!(
+                "peer_misbehavior",
+                "The number of times each peer misbehaved, by kind of misbehavior",
+                &["authority", "misbehavior"],
+                registry
+            ).unwrap(),
+            peer_misbehavior_score: register_int_gauge_vec_with_registry!(
+                "peer_misbehavior_score",
+                "The misbehavior score of each peer after its last misbehavior",
+                &["authority"],
+                registry
+            ).unwrap(),
+            peer_bans: register_int_counter_vec_with_registry!(
+                "peer_bans",
+                "The number of times each peer was banned",
+                &["authority"],
+                registry
+            ).unwrap(),
+
             block_receive_latency: register_histogram_vec_with_registry!(
                 "block_receive_latency",
                 "The time it took for a block to reach our node. The metric is reported by block author.",
@@ -414,7 +550,8 @@ impl Metrics {
             proposed_block_transaction_count,
             proposed_block_vote_count,
 
-            connection_latency_sender,
+            connection_latency_sender: Arc::new(Mutex::new(connection_latency_sender)),
+            new_connection_latency,
             block_commit_latency,
             blocks_per_commit_count,
             sub_dags_per_commit_count
@@ -422,6 +559,19 @@ impl Metrics {
 
         (Arc::new(metrics), reporter)
     }
+
+    /// Sender of the latency of the connection to the peer. The histogram of a peer outside the
+    /// committee the metrics were created with is added on first use.
+    pub fn connection_latency_sender(&self, peer: AuthorityIndex) -> HistogramSender<Duration> {
+        let mut senders = self.connection_latency_sender.lock();
+        while senders.len() <= peer as usize {
+            let (hist, sender) = histogram();
+            let label = format_authority_index(senders.len() as AuthorityIndex).to_string();
+            self.new_connection_latency.send((hist, label)).ok();
+            senders.push(sender);
+        }
+        senders[peer as usize].clone()
+    }
 }
 
 pub trait AsPrometheusMetric {
@@ -474,7 +624,19 @@ impl<T: Ord + AddAssign + DivUsize + Copy + Default + AsPrometheusMetric> VecHis
         let gauge =
             register_int_gauge_vec_with_registry!(name, name, &[label, "v"], registry).unwrap();
 
-        Self { histograms, gauge }
+        Self {
+            histograms,
+            new_histograms: None,
+            gauge,
+        }
+    }
+
+    pub fn with_new_histograms(
+        mut self,
+        new_histograms: mpsc::UnboundedReceiver<(PreciseHistogram<T>, String)>,
+    ) -> Self {
+        self.new_histograms = Some(new_histograms);
+        self
     }
 
     pub fn report(&mut self) {
@@ -501,6 +663,11 @@ impl<T: Ord + AddAssign + DivUsize + Copy + Default + AsPrometheusMetric> VecHis
     }
 
     pub fn clear_receive_all(&mut self) {
+        if let Some(new_histograms) = &mut self.new_histograms {
+            while let Ok(histogram) = new_histograms.try_recv() {
+                self.histograms.push(histogram);
+            }
+        }
         self.histograms
             .iter_mut()
             .for_each(|(hist, _)| hist.clear_receive_all());
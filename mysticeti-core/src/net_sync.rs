@@ -1,12 +1,18 @@
 use crate::block_validator::BlockVerifier;
 use crate::commit_observer::CommitObserver;
-use crate::config::SynchronizerParameters;
+use crate::config::{
+    BlockLimits, LeaderTimeoutParameters, PeerLimitsParameters, SynchronizerParameters,
+};
 use crate::core::Core;
 use crate::core_thread::CoreThreadDispatcher;
 use crate::data::Data;
+use crate::equivocation::EquivocationProof;
+use crate::leader_timeout::LeaderTimeout;
 use crate::network::{Connection, Network, NetworkMessage};
+use crate::peer_limits::{Misbehavior, PeerRateLimits, PeerScores};
+use crate::runtime::select;
 use crate::runtime::Handle;
-use crate::runtime::{self, timestamp_utc};
+use crate::runtime::{self, timestamp_utc, TimeInstant};
 use crate::runtime::{JoinError, JoinHandle};
 use crate::syncer::{Signals, Syncer};
 use crate::types::{AuthorityIndex, BlockReference, StatementBlock};
@@ -17,12 +23,11 @@ use crate::{block_store::BlockStore, synchronizer::BlockDisseminator};
 use crate::{committee::Committee, synchronizer::BlockFetcher};
 use futures::future::join_all;
 use parking_lot::Mutex;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::select;
 use tokio::sync::watch::Receiver;
 use tokio::sync::{mpsc, oneshot, Notify};
 
@@ -75,6 +80,9 @@ pub struct NetworkSyncerInner<H: BlockHandler, C: CommitObserver> {
     epoch_close_signal: mpsc::Sender<()>,
     pub epoch_closing_time: Arc<AtomicU64>,
     connected_authorities: Arc<Mutex<ConnectedAuthorities>>,
+    block_limits: BlockLimits,
+    peer_limits: PeerLimitsParameters,
+    peer_scores: Mutex<PeerScores>,
 }
 
 impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C> {
@@ -88,12 +96,15 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         block_verifier: impl BlockVerifier,
         metrics: Arc<Metrics>,
         leader_timeout: Duration,
+        leader_timeout_parameters: LeaderTimeoutParameters,
         parameters: SynchronizerParameters,
+        peer_limits: PeerLimitsParameters,
         cleanup_enabled: bool,
     ) -> Self {
         let authority_index = core.authority();
         let handle = Handle::current();
         let committee = core.committee().clone();
+        let block_limits = core.block_limits().clone();
         let wal_syncer = core.wal_syncer();
         let block_store = core.block_store().clone();
         let epoch_closing_time = core.epoch_closing_time();
@@ -125,6 +136,9 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             epoch_close_signal: epoch_sender.clone(),
             epoch_closing_time,
             connected_authorities,
+            block_limits,
+            peer_scores: Mutex::new(PeerScores::new(peer_limits.clone(), committee.len())),
+            peer_limits,
         });
         let block_fetcher = Arc::new(BlockFetcher::start(
             authority_index,
@@ -140,7 +154,7 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             block_fetcher,
             Arc::new(block_verifier),
             metrics.clone(),
-            leader_timeout,
+            LeaderTimeout::new(leader_timeout, leader_timeout_parameters),
             parameters,
             cleanup_enabled,
             round_advanced_receiver,
@@ -174,12 +188,12 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         block_fetcher: Arc<BlockFetcher>,
         block_verifier: Arc<impl BlockVerifier>,
         metrics: Arc<Metrics>,
-        leader_timeout: Duration,
+        leader_timeout: LeaderTimeout,
         parameters: SynchronizerParameters,
         cleanup_enabled: bool,
         round_advanced_receiver: Receiver<RoundNumber>,
     ) {
-        let mut connections: HashMap<usize, JoinHandle<Option<()>>> = HashMap::new();
+        let mut connections: BTreeMap<usize, JoinHandle<Option<()>>> = BTreeMap::new();
         let handle = Handle::current();
         let leader_timeout_task = handle.spawn(Self::leader_timeout_task(
             inner.clone(),
@@ -187,6 +201,7 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             shutdown_grace_period,
             leader_timeout,
             round_advanced_receiver,
+            metrics.clone(),
         ));
         let cleanup_task = if cleanup_enabled {
             handle.spawn(Self::cleanup_task(inner.clone()))
@@ -200,8 +215,17 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                 task.await.ok();
             }
 
-            let sender = connection.sender.clone();
             let authority = peer_id as AuthorityIndex;
+            if inner
+                .peer_scores
+                .lock()
+                .is_banned(authority, timestamp_utc())
+            {
+                // Dropping the connection closes it, the peer keeps reconnecting until the ban ends
+                tracing::debug!("Refused connection from banned peer {authority}");
+                continue;
+            }
+            let sender = connection.sender.clone();
             block_fetcher
                 .register_authority(
                     authority,
@@ -261,34 +285,54 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         let authority = inner
             .committee
             .authority_safe(connection.peer_id as AuthorityIndex);
+        let peer = connection.peer_id as AuthorityIndex;
+        let mut rate_limits = PeerRateLimits::new(&inner.peer_limits, timestamp_utc());
         while let Some(message) = inner.recv_or_stopped(&mut connection.receiver).await {
-            match message {
+            let misbehavior = match message {
                 NetworkMessage::SubscribeOwnFrom(round) => {
-                    disseminator.disseminate_own_blocks(round).await
+                    if rate_limits.requests.try_take(1, timestamp_utc()) {
+                        disseminator.disseminate_own_blocks(round).await;
+                        None
+                    } else {
+                        Some(Misbehavior::RateLimited)
+                    }
                 }
                 NetworkMessage::Blocks(blocks) => {
-                    if let Ok(missing_blocks) =
+                    let bytes = blocks
+                        .iter()
+                        .map(|block| block.serialized_bytes().len() as u64)
+                        .sum();
+                    if !rate_limits.bytes.try_take(bytes, timestamp_utc()) {
+                        // Blocks we drop here are fetched again once they are missing
+                        Some(Misbehavior::RateLimited)
+                    } else if let Ok(missing_blocks) =
                         Self::process_blocks(&inner, &block_verifier, &metrics, blocks).await
                     {
                         // we only want to request missing blocks when a validator is sending us their block
                         // proposals, and not during a bulk catchup via our request (RequestBlocks) to avoid
                         // overwhelming the peer.
                         Self::request_missing_blocks(missing_blocks, &connection.sender);
+                        None
                     } else {
-                        break;
+                        Some(Misbehavior::InvalidBlock)
                     }
                 }
                 NetworkMessage::RequestBlocks(references) => {
                     if references.len() > MAXIMUM_BLOCK_REQUEST {
-                        // Terminate connection on receiving invalid message.
-                        break;
-                    }
-                    if disseminator
+                        Some(Misbehavior::MalformedMessage)
+                    } else if !rate_limits
+                        .requests
+                        .try_take(references.len() as u64, timestamp_utc())
+                    {
+                        Some(Misbehavior::RateLimited)
+                    } else if disseminator
                         .send_blocks(authority, references)
                         .await
                         .is_none()
                     {
                         break;
+                    } else {
+                        None
                     }
                 }
                 NetworkMessage::RequestBlocksResponse(blocks) => {
@@ -296,11 +340,22 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                         .await
                         .is_err()
                     {
-                        break;
+                        Some(Misbehavior::InvalidBlock)
+                    } else {
+                        None
                     }
                 }
                 NetworkMessage::BlockNotFound(_references) => {
                     // TODO: leverage this signal to request blocks from other peers
+                    None
+                }
+            };
+            if let Some(misbehavior) = misbehavior {
+                let banned = Self::report_misbehavior(&inner, &metrics, peer, misbehavior);
+                // Terminate connection on receiving incorrect block or invalid message, messages
+                // over the rate limits are only dropped
+                if banned || misbehavior != Misbehavior::RateLimited {
+                    break;
                 }
             }
         }
@@ -311,6 +366,35 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         None
     }
 
+    /// Updates the misbehavior score of the peer, and returns whether the peer got banned.
+    fn report_misbehavior(
+        inner: &Arc<NetworkSyncerInner<H, C>>,
+        metrics: &Arc<Metrics>,
+        peer: AuthorityIndex,
+        misbehavior: Misbehavior,
+    ) -> bool {
+        let now = timestamp_utc();
+        let hostname = inner.committee.authority_safe(peer).hostname();
+        let mut peer_scores = inner.peer_scores.lock();
+        let banned = peer_scores.report(peer, misbehavior, now);
+        metrics
+            .peer_misbehavior
+            .with_label_values(&[&hostname, misbehavior.label()])
+            .inc();
+        metrics
+            .peer_misbehavior_score
+            .with_label_values(&[&hostname])
+            .set(peer_scores.score(peer, now) as i64);
+        if banned {
+            metrics.peer_bans.with_label_values(&[&hostname]).inc();
+            tracing::warn!(
+                "Banned {hostname} for {:?} after {misbehavior:?}",
+                inner.peer_limits.ban_duration
+            );
+        }
+        banned
+    }
+
     async fn process_blocks(
         inner: &Arc<NetworkSyncerInner<H, C>>,
         block_verifier: &Arc<impl BlockVerifier>,
@@ -348,8 +432,43 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                         .as_secs_f64(),
                 );
 
+            // Check the protocol limits before anything else, as they are cheap to check
+            let size = block.serialized_bytes().len();
+            if let Err(e) = block.verify_limits(&inner.block_limits, size) {
+                metrics
+                    .blocks_rejected
+                    .with_label_values(&[&hostname, "limits"])
+                    .inc();
+                tracing::warn!(
+                    "Rejected oversized block {} from {}: {:?}",
+                    block.reference(),
+                    hostname,
+                    e
+                );
+                // Terminate connection on receiving incorrect block
+                return Err(e);
+            }
+            // Blocks of the previous or next epoch are expected around an epoch change, they are
+            // dropped without blaming the peer
+            if block.epoch().abs_diff(inner.committee.epoch()) == 1 {
+                metrics
+                    .blocks_rejected
+                    .with_label_values(&[&hostname, "epoch"])
+                    .inc();
+                tracing::debug!(
+                    "Dropped block {} of epoch {} from {}",
+                    block.reference(),
+                    block.epoch(),
+                    hostname
+                );
+                continue;
+            }
             // Verify blocks based on consensus rules
             if let Err(e) = block.verify(&inner.committee) {
+                metrics
+                    .blocks_rejected
+                    .with_label_values(&[&hostname, "consensus"])
+                    .inc();
                 tracing::warn!(
                     "Rejected incorrect block {} based on consensus rules from {}: {:?}",
                     block.reference(),
@@ -361,6 +480,10 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             }
             // Verify blocks based on customized validation rules
             if let Err(e) = block_verifier.verify(&block).await {
+                metrics
+                    .blocks_rejected
+                    .with_label_values(&[&hostname, "validation"])
+                    .inc();
                 tracing::warn!(
                     "Rejected incorrect block {} based on validation rules from {}: {:?}",
                     block.reference(),
@@ -402,11 +525,16 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         inner: Arc<NetworkSyncerInner<H, C>>,
         mut epoch_close_signal: mpsc::Receiver<()>,
         shutdown_grace_period: Duration,
-        leader_timeout: Duration,
+        mut leader_timeout: LeaderTimeout,
         mut round_advanced_receiver: Receiver<RoundNumber>,
+        metrics: Arc<Metrics>,
     ) -> Option<()> {
         let mut round = *round_advanced_receiver.borrow();
+        let mut round_start = TimeInstant::now();
         let mut timed_out = false;
+        metrics
+            .leader_timeout_ms
+            .set(leader_timeout.timeout().as_millis() as i64);
 
         loop {
             let closing_time = inner.epoch_closing_time.load(Ordering::Relaxed);
@@ -421,15 +549,22 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                 return None;
             }
             select! {
-                _sleep = runtime::sleep(leader_timeout), if !timed_out => {
+                _sleep = runtime::sleep(leader_timeout.timeout()), if !timed_out => {
                     tracing::debug!("Timeout {round}");
                     let connected_authorities = inner.connected_authorities.lock().authorities.clone();
                     inner.syncer.force_new_block(round, connected_authorities).await;
                     timed_out = true;
+                    leader_timeout.timed_out();
+                    metrics.leader_timeout_ms.set(leader_timeout.timeout().as_millis() as i64);
                 }
                 _changed = round_advanced_receiver.changed() => {
                     // restart loop as we have received a new quorum
                     round = *round_advanced_receiver.borrow();
+                    if !timed_out {
+                        leader_timeout.round_completed(round_start.elapsed());
+                        metrics.leader_timeout_ms.set(leader_timeout.timeout().as_millis() as i64);
+                    }
+                    round_start = TimeInstant::now();
                     timed_out = false;
                 }
                 _epoch_shutdown = runtime::sleep(shutdown_duration) => {
@@ -461,6 +596,16 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
     pub async fn await_completion(self) -> Result<(), JoinError> {
         self.main_task.await
     }
+
+    /// Completes once the epoch is closed and the shutdown grace period has elapsed.
+    pub async fn await_epoch_close(&self) {
+        self.inner.stopped().await;
+    }
+
+    /// Equivocation proofs collected from the blocks received so far.
+    pub fn equivocations(&self) -> Vec<EquivocationProof> {
+        self.inner.block_store.equivocations()
+    }
 }
 
 impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncerInner<H, C> {
@@ -585,17 +730,21 @@ mod tests {
 mod sim_tests {
     use super::NetworkSyncer;
     use crate::block_handler::TestBlockHandler;
+    use crate::byzantine::ByzantineBehavior;
     use crate::commit_observer::TestCommitObserver;
     use crate::config::Parameters;
     use crate::finalization_interpreter::FinalizationInterpreter;
     use crate::future_simulator::SimulatedExecutorState;
     use crate::runtime;
+    use crate::sim_trace::{self, SimulationTrace};
+    use crate::simulated_network::{Fault, FaultSchedule, Latency, LinkConditions};
     use crate::simulator_tracing::setup_simulator_tracing;
     use crate::syncer::{Signals, Syncer};
     use crate::test_util::{
         check_commits, print_stats, rng_at_seed, simulated_network_syncers,
-        simulated_network_syncers_with_epoch_duration,
+        simulated_network_syncers_with_byzantine, simulated_network_syncers_with_epoch_duration,
     };
+    use crate::types::{AuthorityIndex, BaseStatement, Vote};
     use std::sync::atomic::Ordering;
     use std::time::Duration;
 
@@ -684,14 +833,20 @@ mod sim_tests {
                 syncer.commit_observer().committed_leaders()
             );
 
-            let mut finalization_interpreter = FinalizationInterpreter::new(block_store, committee);
-            let finalized_tx_certifying_blocks =
-                finalization_interpreter.finalized_tx_certifying_blocks();
+            // Replays the dag, which is not cleaned up in this test
+            let mut finalization_interpreter = FinalizationInterpreter::new(committee);
+            let mut finalized = vec![];
+            for round in 0..=block_store.highest_round() {
+                for block in block_store.get_blocks_by_round(round) {
+                    finalized.extend(finalization_interpreter.add_block(&block));
+                }
+            }
+            assert!(!finalized.is_empty());
 
-            for (_, certificates) in finalized_tx_certifying_blocks {
+            for transaction in finalized {
                 // check if at least one certificate is committed
                 let mut committed = false;
-                for certifying_block in certificates {
+                for certifying_block in transaction.certifying_blocks {
                     if block_store.linked(
                         &block_store.get_block(*latest_committed_leader).unwrap(),
                         &block_store.get_block(certifying_block).unwrap(),
@@ -778,4 +933,260 @@ mod sim_tests {
         check_commits(&syncers);
         print_stats(&syncers, &mut reporters);
     }
+
+    #[test]
+    fn test_network_sync_sim_partition_heals() {
+        // Once healed, the link from 0 to 2 stays slower than the link back
+        let slow_link = Fault::Link {
+            from: 0,
+            to: 2,
+            conditions: LinkConditions {
+                latency: Latency::Fixed(Duration::from_millis(400)),
+                ..Default::default()
+            },
+        };
+        let faults = FaultSchedule::new()
+            .at(PARTITION_HEAL_TIME, Fault::Heal)
+            .at(PARTITION_HEAL_TIME, slow_link);
+        sim_trace::run_or_save(
+            "partition_heals",
+            0,
+            faults,
+            &test_network_sync_sim_partition_heals_async,
+        );
+    }
+
+    const PARTITION_HEAL_TIME: Duration = Duration::from_secs(20);
+
+    // No group has a quorum until the partition heals, after which the authorities commit again.
+    async fn test_network_sync_sim_partition_heals_async(faults: FaultSchedule) {
+        let (simulated_network, network_syncers, _) = simulated_network_syncers(4);
+        simulated_network.apply_fault(Fault::Partition(vec![vec![0, 1], vec![2, 3]]));
+        simulated_network.start_fault_schedule(faults);
+        simulated_network.connect_all().await;
+        runtime::sleep(PARTITION_HEAL_TIME * 2).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+
+        check_commits(&syncers);
+        for syncer in &syncers {
+            assert!(!syncer.commit_observer().committed_leaders().is_empty());
+            for block in syncer.core().block_store().get_blocks_by_round(2) {
+                assert!(block.meta_creation_time() >= PARTITION_HEAL_TIME);
+            }
+        }
+    }
+
+    #[test]
+    fn test_network_sync_sim_link_faults() {
+        // The same seed reproduces the same run, event by event
+        let trace = SimulationTrace::record(
+            1,
+            FaultSchedule::new(),
+            &test_network_sync_sim_link_faults_async,
+        );
+        assert_eq!(trace.failure, None);
+        trace
+            .replay(&test_network_sync_sim_link_faults_async)
+            .unwrap();
+    }
+
+    // Links are lossy, reordering and bandwidth limited, and authority 1 never hears from
+    // authority 0 directly.
+    async fn test_network_sync_sim_link_faults_async(faults: FaultSchedule) {
+        let (simulated_network, network_syncers, _) = simulated_network_syncers(4);
+        simulated_network.apply_fault(Fault::AllLinks(LinkConditions {
+            latency: Latency::Exponential {
+                min: Duration::from_millis(20),
+                mean: Duration::from_millis(60),
+            },
+            drop_probability: 0.02,
+            reorder_probability: 0.1,
+            bandwidth: Some(1_000_000),
+            up: true,
+        }));
+        simulated_network.apply_fault(Fault::Link {
+            from: 0,
+            to: 1,
+            conditions: LinkConditions::down(),
+        });
+        simulated_network.start_fault_schedule(faults);
+        simulated_network.connect_all().await;
+        runtime::sleep(Duration::from_secs(30)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            syncers.push(syncer);
+        }
+        check_commits(&syncers);
+        assert!(!syncers[0].commit_observer().committed_leaders().is_empty());
+    }
+
+    // Runs the committee with the given byzantine authorities, and checks that the honest
+    // authorities commit a consistent sequence of leaders. Returns the honest syncers.
+    async fn run_with_byzantine(
+        n: usize,
+        byzantine: Vec<(AuthorityIndex, ByzantineBehavior)>,
+    ) -> Vec<Syncer<TestBlockHandler, Signals, TestCommitObserver>> {
+        let byzantine_authorities: Vec<_> = byzantine.iter().map(|(a, _)| *a).collect();
+        let (simulated_network, network_syncers, _) =
+            simulated_network_syncers_with_byzantine(n, byzantine);
+        simulated_network.connect_all().await;
+        runtime::sleep(Duration::from_secs(20)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            let syncer = network_syncer.shutdown().await;
+            if !byzantine_authorities.contains(&syncer.core().authority()) {
+                syncers.push(syncer);
+            }
+        }
+
+        check_commits(&syncers);
+        for syncer in &syncers {
+            assert!(
+                !syncer.commit_observer().committed_leaders().is_empty(),
+                "Honest authority {} did not commit",
+                syncer.core().authority()
+            );
+        }
+        syncers
+    }
+
+    #[test]
+    fn test_network_sync_sim_byzantine_equivocate() {
+        SimulatedExecutorState::run(rng_at_seed(0), async {
+            let syncers = run_with_byzantine(4, vec![(3, ByzantineBehavior::Equivocate)]).await;
+            // The upper half of the committee received both blocks of every round
+            assert!(!syncers[2]
+                .core()
+                .block_store()
+                .equivocations_by(3)
+                .is_empty());
+        });
+    }
+
+    #[test]
+    fn test_network_sync_sim_byzantine_withhold() {
+        SimulatedExecutorState::run(rng_at_seed(0), async {
+            run_with_byzantine(4, vec![(0, ByzantineBehavior::Withhold(vec![1, 2]))]).await;
+        });
+    }
+
+    #[test]
+    fn test_network_sync_sim_byzantine_conflicting_votes() {
+        SimulatedExecutorState::run(rng_at_seed(0), async {
+            // Honest authorities do not count their own votes, so the committee is large enough
+            // for the other honest authorities to form a quorum without the byzantine one
+            let syncers =
+                run_with_byzantine(7, vec![(1, ByzantineBehavior::ConflictingVotes)]).await;
+            // The upper half of the committee counted the reject votes of the byzantine authority
+            for syncer in syncers
+                .iter()
+                .filter(|syncer| syncer.core().authority() >= 3)
+            {
+                let block_store = syncer.core().block_store();
+                let rejects = (1..=block_store.highest_round())
+                    .flat_map(|round| block_store.get_blocks_by_round(round))
+                    .filter(|block| block.author() == 1)
+                    .flat_map(|block| block.statements().clone())
+                    .filter(|statement| {
+                        matches!(statement, BaseStatement::Vote(_, Vote::Reject(_)))
+                    })
+                    .count();
+                assert!(rejects > 0, "Authority {}", syncer.core().authority());
+            }
+            // The reject votes of a single authority do not prevent the honest accept votes from
+            // certifying the transactions of the honest authorities
+            let round = syncers
+                .iter()
+                .map(|syncer| syncer.core().block_store().highest_round())
+                .min()
+                .unwrap()
+                - 10;
+            for proposer in &syncers {
+                let proposed = proposer
+                    .core()
+                    .block_handler()
+                    .proposed
+                    .iter()
+                    .filter(|locator| locator.block().round <= round);
+                for locator in proposed {
+                    for syncer in &syncers {
+                        assert!(
+                            syncer.core().block_handler().is_certified(locator),
+                            "Authority {} did not certify {locator}",
+                            syncer.core().authority()
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_network_sync_sim_ban_invalid_blocks() {
+        SimulatedExecutorState::run(rng_at_seed(0), async {
+            // Blocks voting twice for the same transactions fail verification
+            let (simulated_network, network_syncers, _) = simulated_network_syncers_with_byzantine(
+                4,
+                vec![(1, ByzantineBehavior::InvalidVotes)],
+            );
+            simulated_network.connect_all().await;
+            // Every invalid block costs a fraction of the ban score and drops the connection, the
+            // peer is banned once it keeps reconnecting with invalid blocks
+            for _ in 0..4 {
+                runtime::sleep(Duration::from_secs(2)).await;
+                simulated_network
+                    .connect_some(|a, b| a == 1 || b == 1)
+                    .await;
+            }
+            runtime::sleep(Duration::from_secs(12)).await;
+            let mut syncers = vec![];
+            for (authority, network_syncer) in network_syncers.into_iter().enumerate() {
+                let banned = network_syncer
+                    .inner
+                    .peer_scores
+                    .lock()
+                    .is_banned(1, runtime::timestamp_utc());
+                assert_eq!(banned, authority != 1, "Authority {authority}");
+                let syncer = network_syncer.shutdown().await;
+                if authority != 1 {
+                    syncers.push(syncer);
+                }
+            }
+            check_commits(&syncers);
+            assert!(!syncers[0].commit_observer().committed_leaders().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_network_sync_sim_byzantine_bad_includes() {
+        SimulatedExecutorState::run(rng_at_seed(0), async {
+            run_with_byzantine(4, vec![(2, ByzantineBehavior::BadIncludes)]).await;
+        });
+    }
+
+    #[test]
+    fn test_network_sync_sim_byzantine_replay_old_rounds() {
+        SimulatedExecutorState::run(rng_at_seed(0), async {
+            run_with_byzantine(4, vec![(0, ByzantineBehavior::ReplayOldRounds)]).await;
+        });
+    }
+
+    #[test]
+    fn test_network_sync_sim_byzantine_f_authorities() {
+        SimulatedExecutorState::run(rng_at_seed(0), async {
+            run_with_byzantine(
+                7,
+                vec![
+                    (0, ByzantineBehavior::Equivocate),
+                    (4, ByzantineBehavior::BadIncludes),
+                ],
+            )
+            .await;
+        });
+    }
 }
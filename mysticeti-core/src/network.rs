@@ -2,8 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::stat::HistogramSender;
+use crate::transport::{Transport, TransportStream};
 use crate::types::{AuthorityIndex, RoundNumber, StatementBlock};
-use crate::{config::Parameters, data::Data, runtime};
+use crate::{
+    config::{NetworkCompression, Parameters},
+    data::Data,
+    runtime,
+};
 use crate::{
     metrics::{print_network_address_table, Metrics},
     types::BlockReference,
@@ -19,8 +24,7 @@ use std::net::SocketAddr;
 use std::ops::Range;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::net::{TcpListener, TcpSocket, TcpStream};
 use tokio::runtime::Handle;
 use tokio::select;
@@ -31,6 +35,7 @@ use tokio::task::JoinHandle;
 use tokio::time::Instant;
 
 const PING_INTERVAL: Duration = Duration::from_secs(30);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum NetworkMessage {
@@ -73,6 +78,7 @@ impl Network {
         our_id: AuthorityIndex,
         local_addr: SocketAddr,
         metrics: Arc<Metrics>,
+        transport: Arc<dyn Transport>,
     ) -> Self {
         let addresses = parameters.all_network_addresses().collect::<Vec<_>>();
         print_network_address_table(&addresses);
@@ -82,6 +88,8 @@ impl Network {
             local_addr,
             metrics,
             parameters.network_connection_max_latency,
+            transport,
+            parameters.network_compression,
         )
         .await
     }
@@ -96,6 +104,8 @@ impl Network {
         local_addr: SocketAddr,
         metrics: Arc<Metrics>,
         network_connection_max_latency: Duration,
+        transport: Arc<dyn Transport>,
+        compression: NetworkCompression,
     ) -> Self {
         if our_id >= addresses.len() {
             panic!(
@@ -130,8 +140,11 @@ impl Network {
                     connection_sender: connection_sender.clone(),
                     bind_addr: translation_mode.bind_addr(local_addr),
                     active_immediately: id < our_id,
-                    latency_sender: metrics.connection_latency_sender.get(id).expect("Can not locate connection_latency_sender metric - did you initialize metrics with correct committee?").clone(),
-                    network_connection_max_latency
+                    latency_sender: metrics.connection_latency_sender(id as AuthorityIndex),
+                    network_connection_max_latency,
+                    transport: transport.clone(),
+                    compression,
+                    metrics: metrics.clone(),
                 }
                 .run(receiver),
             );
@@ -200,6 +213,9 @@ struct Worker {
     active_immediately: bool,
     latency_sender: HistogramSender<Duration>,
     network_connection_max_latency: Duration,
+    transport: Arc<dyn Transport>,
+    compression: NetworkCompression,
+    metrics: Arc<Metrics>,
 }
 
 struct WorkerConnection {
@@ -208,12 +224,18 @@ struct WorkerConnection {
     peer_id: usize,
     latency_sender: HistogramSender<Duration>,
     latency_last_value_sender: tokio::sync::watch::Sender<Duration>,
+    compression: NetworkCompression,
+    metrics: Arc<Metrics>,
 }
 
 impl Worker {
     const ACTIVE_HANDSHAKE: u64 = 0xFEFE0000;
     const PASSIVE_HANDSHAKE: u64 = 0x0000AEAE;
     const MAX_SIZE: u32 = 16 * 1024 * 1024;
+    // Set in the length prefix of messages that are compressed with the negotiated compression
+    const COMPRESSED_FLAG: u32 = 1 << 31;
+    // Smaller messages are not worth compressing
+    const MIN_COMPRESSION_SIZE: usize = 1024;
 
     async fn run(self, mut receiver: mpsc::UnboundedReceiver<TcpStream>) -> Option<()> {
         let initial_delay = if self.active_immediately {
@@ -269,6 +291,9 @@ impl Worker {
             tracing::warn!("Invalid passive handshake: {handshake}");
             return Ok(());
         }
+        let Some(stream) = self.upgrade(stream, true).await else {
+            return Ok(());
+        };
         let Some(connection) = self.make_connection().await else {
             // todo - pass signal to break the main loop
             return Ok(());
@@ -284,6 +309,9 @@ impl Worker {
             tracing::warn!("Invalid active handshake: {handshake}");
             return Ok(());
         }
+        let Some(stream) = self.upgrade(stream, false).await else {
+            return Ok(());
+        };
         let Some(connection) = self.make_connection().await else {
             // todo - pass signal to break the main loop
             return Ok(());
@@ -291,8 +319,31 @@ impl Worker {
         Self::handle_stream(stream, connection, self.network_connection_max_latency).await
     }
 
-    async fn handle_stream(
+    /// Runs the transport handshake, bounded by `HANDSHAKE_TIMEOUT` so that a peer that stops
+    /// responding mid-handshake can not hold the worker.
+    async fn upgrade(
+        &self,
         stream: TcpStream,
+        initiator: bool,
+    ) -> Option<Box<dyn TransportStream>> {
+        let upgrade = self
+            .transport
+            .upgrade(stream, self.peer_id as AuthorityIndex, initiator);
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, upgrade).await {
+            Ok(Ok(stream)) => Some(stream),
+            Ok(Err(err)) => {
+                tracing::warn!("Transport handshake with {} failed: {err}", self.peer_id);
+                None
+            }
+            Err(_) => {
+                tracing::warn!("Transport handshake with {} timed out", self.peer_id);
+                None
+            }
+        }
+    }
+
+    async fn handle_stream(
+        mut stream: Box<dyn TransportStream>,
         connection: WorkerConnection,
         network_connection_max_latency: Duration,
     ) -> io::Result<()> {
@@ -302,9 +353,16 @@ impl Worker {
             peer_id,
             latency_sender,
             latency_last_value_sender,
+            compression,
+            metrics,
         } = connection;
-        tracing::debug!("Connected to {}", peer_id);
-        let (reader, writer) = stream.into_split();
+        let compression = Self::negotiate_compression(&mut stream, compression).await?;
+        tracing::debug!(
+            "Connected to {} with compression {:?}",
+            peer_id,
+            compression
+        );
+        let (reader, writer) = tokio::io::split(stream);
         let (pong_sender, pong_receiver) = mpsc::channel(150);
         let write_fut = Self::handle_write_stream(
             writer,
@@ -313,21 +371,25 @@ impl Worker {
             latency_sender,
             latency_last_value_sender,
             network_connection_max_latency,
+            compression,
+            metrics,
         )
         .boxed();
-        let read_fut = Self::handle_read_stream(reader, sender, pong_sender).boxed();
+        let read_fut = Self::handle_read_stream(reader, sender, pong_sender, compression).boxed();
         let (r, _, _) = select_all([write_fut, read_fut]).await;
         tracing::debug!("Disconnected from {}", peer_id);
         r
     }
 
     async fn handle_write_stream(
-        mut writer: OwnedWriteHalf,
+        mut writer: WriteHalf<Box<dyn TransportStream>>,
         mut receiver: mpsc::Receiver<NetworkMessage>,
         mut pong_receiver: mpsc::Receiver<i64>,
         latency_sender: HistogramSender<Duration>,
         latency_last_value_sender: tokio::sync::watch::Sender<Duration>,
         network_connection_max_latency: Duration,
+        compression: NetworkCompression,
+        metrics: Arc<Metrics>,
     ) -> io::Result<()> {
         let start = Instant::now();
         let mut ping_deadline = start + PING_INTERVAL;
@@ -340,6 +402,7 @@ impl Worker {
                     assert!(ping_time > 0);
                     let ping = encode_ping(ping_time);
                     writer.write_all(&ping).await?;
+                    writer.flush().await?;
                 }
                 received = pong_receiver.recv() => {
                     // We have an embedded ping-pong protocol for measuring RTT:
@@ -359,6 +422,7 @@ impl Worker {
                             Some(pong) => {
                                 let pong = encode_ping(pong);
                                 writer.write_all(&pong).await?;
+                                writer.flush().await?;
                             },
                             None => {
                                 tracing::warn!("Invalid ping: {ping}");
@@ -397,25 +461,29 @@ impl Worker {
                 received = receiver.recv() => {
                     // todo - pass signal to break main loop
                     let Some(message) = received else {return Ok(())};
-                    let serialized = bincode::serialize(&message).expect("Serialization should not fail");
-                    writer.write_u32(serialized.len() as u32).await?;
+                    let (header, serialized) = Self::encode_message(&message, compression, &metrics);
+                    writer.write_u32(header).await?;
                     writer.write_all(&serialized).await?;
+                    writer.flush().await?;
                 }
             }
         }
     }
 
     async fn handle_read_stream(
-        mut stream: OwnedReadHalf,
+        mut stream: ReadHalf<Box<dyn TransportStream>>,
         sender: mpsc::Sender<NetworkMessage>,
         pong_sender: mpsc::Sender<i64>,
+        compression: NetworkCompression,
     ) -> io::Result<()> {
         // stdlib has a special fast implementation for generating n-size byte vectors,
         // see impl SpecFromElem for u8
         // Note that Box::new([0u8; Self::MAX_SIZE as usize]); does not work with large MAX_SIZE
         let mut buf = vec![0u8; Self::MAX_SIZE as usize].into_boxed_slice();
         loop {
-            let size = stream.read_u32().await?;
+            let header = stream.read_u32().await?;
+            let compressed = header & Self::COMPRESSED_FLAG != 0;
+            let size = header & !Self::COMPRESSED_FLAG;
             if size > Self::MAX_SIZE {
                 tracing::warn!("Invalid size: {size}");
                 return Ok(());
@@ -444,6 +512,21 @@ impl Worker {
             let buf = &mut buf[..size as usize];
             let read = stream.read_exact(buf).await?;
             assert_eq!(read, buf.len());
+            let decompressed;
+            let buf = if compressed {
+                match Self::decompress(buf, compression) {
+                    Ok(data) => {
+                        decompressed = data;
+                        &decompressed[..]
+                    }
+                    Err(err) => {
+                        tracing::warn!("Failed to decompress: {}", err);
+                        return Ok(());
+                    }
+                }
+            } else {
+                &*buf
+            };
             match bincode::deserialize::<NetworkMessage>(buf) {
                 Ok(message) => {
                     if sender.send(message).await.is_err() {
@@ -459,6 +542,76 @@ impl Worker {
         }
     }
 
+    /// Each side announces its configured compression, it is used only if both sides agree.
+    /// The announcement is exchanged whatever the local setting, so that peers configured
+    /// differently fall back to no compression instead of misreading each other's frames.
+    async fn negotiate_compression(
+        stream: &mut Box<dyn TransportStream>,
+        compression: NetworkCompression,
+    ) -> io::Result<NetworkCompression> {
+        stream.write_u8(compression_id(compression)).await?;
+        stream.flush().await?;
+        let remote = stream.read_u8().await?;
+        if compression_from_id(remote) == Some(compression) {
+            Ok(compression)
+        } else {
+            Ok(NetworkCompression::None)
+        }
+    }
+
+    /// Serializes the message and returns it with its length prefix. Block batches are compressed
+    /// if the connection has compression enabled and it reduces the message size.
+    fn encode_message(
+        message: &NetworkMessage,
+        compression: NetworkCompression,
+        metrics: &Metrics,
+    ) -> (u32, Vec<u8>) {
+        let serialized = bincode::serialize(message).expect("Serialization should not fail");
+        let compressible = matches!(
+            message,
+            NetworkMessage::Blocks(_) | NetworkMessage::RequestBlocksResponse(_)
+        );
+        if !compressible || serialized.len() < Self::MIN_COMPRESSION_SIZE {
+            return (serialized.len() as u32, serialized);
+        }
+        let compressed = match compression {
+            NetworkCompression::None => return (serialized.len() as u32, serialized),
+            NetworkCompression::Lz4 => lz4_flex::compress_prepend_size(&serialized),
+        };
+        metrics
+            .network_compression_input_bytes
+            .inc_by(serialized.len() as u64);
+        if compressed.len() >= serialized.len() {
+            metrics
+                .network_compression_output_bytes
+                .inc_by(serialized.len() as u64);
+            return (serialized.len() as u32, serialized);
+        }
+        metrics
+            .network_compression_output_bytes
+            .inc_by(compressed.len() as u64);
+        (compressed.len() as u32 | Self::COMPRESSED_FLAG, compressed)
+    }
+
+    fn decompress(buf: &[u8], compression: NetworkCompression) -> Result<Vec<u8>, String> {
+        match compression {
+            NetworkCompression::None => {
+                Err("Received compressed message, but compression was not negotiated".into())
+            }
+            NetworkCompression::Lz4 => {
+                // Check the size before lz4_flex allocates the output buffer
+                let Some(size) = buf.get(..4) else {
+                    return Err("Compressed message is too short".into());
+                };
+                let size = u32::from_le_bytes(size.try_into().unwrap());
+                if size > Self::MAX_SIZE {
+                    return Err(format!("Invalid decompressed size: {size}"));
+                }
+                lz4_flex::decompress_size_prepended(buf).map_err(|err| err.to_string())
+            }
+        }
+    }
+
     async fn make_connection(&self) -> Option<WorkerConnection> {
         let (network_in_sender, network_in_receiver) = mpsc::channel(1_000);
         let (network_out_sender, network_out_receiver) = mpsc::channel(1_000);
@@ -478,11 +631,14 @@ impl Worker {
             peer_id: self.peer_id,
             latency_sender: self.latency_sender.clone(),
             latency_last_value_sender,
+            compression: self.compression,
+            metrics: self.metrics.clone(),
         })
     }
 }
 
-// This is a bit ugly, but luckily it should go away when we use noise/tls authentication instead of network addresses
+// This is a bit ugly, but luckily it should go away when we route incoming connections by the identity
+// authenticated by the transport instead of network addresses
 enum SourceAddressTranslationMode {
     IpAndPort,
     IpOnly,
@@ -556,6 +712,21 @@ fn source_address_translation_mode(addresses: &[SocketAddr]) -> SourceAddressTra
     }
 }
 
+fn compression_id(compression: NetworkCompression) -> u8 {
+    match compression {
+        NetworkCompression::None => 0,
+        NetworkCompression::Lz4 => 1,
+    }
+}
+
+fn compression_from_id(id: u8) -> Option<NetworkCompression> {
+    match id {
+        0 => Some(NetworkCompression::None),
+        1 => Some(NetworkCompression::Lz4),
+        _ => None,
+    }
+}
+
 fn sample_delay(range: Range<Duration>) -> Duration {
     ThreadRng::default().gen_range(range)
 }
@@ -575,12 +746,113 @@ fn decode_ping(message: &[u8]) -> i64 {
 
 #[cfg(test)]
 mod test {
+    use super::{Network, NetworkMessage, Worker};
     use crate::committee::Committee;
-    use crate::config::Parameters;
+    use crate::config::{NetworkCompression, Parameters};
+    use crate::data::Data;
     use crate::metrics::Metrics;
     use crate::test_util::networks_and_addresses;
+    use crate::transport::TcpTransport;
+    use crate::types::{BaseStatement, StatementBlock, Transaction};
+    use futures::future::join_all;
     use prometheus::Registry;
     use std::collections::HashSet;
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[test]
+    fn compression_test() {
+        let committee = Committee::new_test(vec![1, 1, 1]);
+        let (metrics, _reporter) = Metrics::new(&Registry::default(), Some(&committee));
+        let statements = (0..100)
+            .map(|_| BaseStatement::Share(Transaction::new(vec![7u8; 512])))
+            .collect();
+        let block = StatementBlock::new(0, 1, vec![], statements, 0, false, 0, Default::default());
+        let message = NetworkMessage::Blocks(vec![Data::new(block)]);
+
+        let (header, encoded) = Worker::encode_message(&message, NetworkCompression::Lz4, &metrics);
+        assert_ne!(header & Worker::COMPRESSED_FLAG, 0);
+        assert_eq!((header & !Worker::COMPRESSED_FLAG) as usize, encoded.len());
+        let decoded = Worker::decompress(&encoded, NetworkCompression::Lz4).unwrap();
+        assert_eq!(decoded, bincode::serialize(&message).unwrap());
+        assert!(
+            metrics.network_compression_input_bytes.get()
+                > metrics.network_compression_output_bytes.get()
+        );
+        // A compressed message is rejected if compression was not negotiated
+        assert!(Worker::decompress(&encoded, NetworkCompression::None).is_err());
+
+        let (header, encoded) =
+            Worker::encode_message(&message, NetworkCompression::None, &metrics);
+        assert_eq!(header & Worker::COMPRESSED_FLAG, 0);
+        assert_eq!(encoded, bincode::serialize(&message).unwrap());
+
+        // Only block batches are compressed
+        let message = NetworkMessage::SubscribeOwnFrom(1);
+        let (header, _) = Worker::encode_message(&message, NetworkCompression::Lz4, &metrics);
+        assert_eq!(header & Worker::COMPRESSED_FLAG, 0);
+    }
+
+    #[tokio::test]
+    async fn network_mixed_compression_test() {
+        let committee = Committee::new_test(vec![1, 1]);
+        let addresses: Vec<_> = (0..2)
+            .map(|i| SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5301 + i)))
+            .collect();
+        let compressions = [NetworkCompression::Lz4, NetworkCompression::None];
+        let networks = compressions.iter().enumerate().map(|(i, compression)| {
+            Network::from_socket_addresses(
+                &addresses,
+                i,
+                addresses[i],
+                Metrics::new(&Registry::default(), Some(&committee)).0,
+                Parameters::DEFAULT_NETWORK_CONNECTION_MAX_LATENCY,
+                Arc::new(TcpTransport),
+                *compression,
+            )
+        });
+        let mut networks = join_all(networks).await;
+        let mut connections = vec![];
+        for network in networks.iter_mut() {
+            connections.push(network.connection_receiver.recv().await.unwrap());
+        }
+
+        // A block batch the lz4 side would compress reaches the side without compression
+        let statements = (0..100)
+            .map(|_| BaseStatement::Share(Transaction::new(vec![7u8; 512])))
+            .collect();
+        let block = Data::new(StatementBlock::new(
+            0,
+            1,
+            vec![],
+            statements,
+            0,
+            false,
+            0,
+            Default::default(),
+        ));
+        for (from, to) in [(0, 1), (1, 0)] {
+            connections[from]
+                .sender
+                .send(NetworkMessage::Blocks(vec![block.clone()]))
+                .await
+                .unwrap();
+            let received = timeout(Duration::from_secs(5), async {
+                loop {
+                    match connections[to].receiver.recv().await.unwrap() {
+                        NetworkMessage::Blocks(blocks) => break blocks,
+                        _ => continue,
+                    }
+                }
+            })
+            .await
+            .expect("Block batch must get through");
+            assert_eq!(received.len(), 1);
+            assert_eq!(received[0].reference(), block.reference());
+        }
+    }
 
     #[ignore]
     #[tokio::test]
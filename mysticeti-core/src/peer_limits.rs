@@ -0,0 +1,235 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rate limits on the messages of connected peers, and misbehavior scores that get peers banned.
+//! Time is passed in explicitly as the duration since the unix epoch, see `runtime::timestamp_utc`.
+
+use std::time::Duration;
+
+use crate::config::PeerLimitsParameters;
+use crate::types::AuthorityIndex;
+
+/// Token bucket refilled at a constant rate up to its capacity.
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Duration,
+}
+
+impl TokenBucket {
+    /// A zero `rate` disables the limit.
+    pub fn new(rate: u64, capacity: u64, now: Duration) -> Self {
+        Self {
+            rate: rate as f64,
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    /// Takes `amount` tokens if they are available. Amounts larger than the capacity take a full
+    /// bucket.
+    pub fn try_take(&mut self, amount: u64, now: Duration) -> bool {
+        if self.rate == 0.0 {
+            return true;
+        }
+        let elapsed = now.saturating_sub(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+        let amount = (amount as f64).min(self.capacity);
+        if self.tokens < amount {
+            return false;
+        }
+        self.tokens -= amount;
+        true
+    }
+}
+
+/// Rate limits of a single connection.
+pub struct PeerRateLimits {
+    pub requests: TokenBucket,
+    pub bytes: TokenBucket,
+}
+
+impl PeerRateLimits {
+    pub fn new(parameters: &PeerLimitsParameters, now: Duration) -> Self {
+        Self {
+            requests: TokenBucket::new(
+                parameters.requests_per_second,
+                parameters.requests_burst,
+                now,
+            ),
+            bytes: TokenBucket::new(parameters.bytes_per_second, parameters.bytes_burst, now),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// A block that failed verification.
+    InvalidBlock,
+    /// A message that breaks the protocol, e.g. a request for too many blocks.
+    MalformedMessage,
+    /// A message dropped because the peer exceeded its rate limits.
+    RateLimited,
+}
+
+impl Misbehavior {
+    /// The penalty in percent of the ban score, so that a single misbehavior never bans a peer.
+    pub fn penalty_percentage(&self) -> u64 {
+        match self {
+            Misbehavior::InvalidBlock => 25,
+            Misbehavior::MalformedMessage => 50,
+            Misbehavior::RateLimited => 1,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Misbehavior::InvalidBlock => "invalid_block",
+            Misbehavior::MalformedMessage => "malformed_message",
+            Misbehavior::RateLimited => "rate_limited",
+        }
+    }
+}
+
+/// Misbehavior scores of the peers, kept across their reconnections.
+pub struct PeerScores {
+    parameters: PeerLimitsParameters,
+    peers: Vec<PeerScore>,
+}
+
+#[derive(Default, Clone)]
+struct PeerScore {
+    score: f64,
+    last_update: Duration,
+    banned_until: Option<Duration>,
+}
+
+impl PeerScores {
+    pub fn new(parameters: PeerLimitsParameters, committee_size: usize) -> Self {
+        Self {
+            parameters,
+            peers: vec![PeerScore::default(); committee_size],
+        }
+    }
+
+    /// Adds the penalty of `misbehavior` to the score of `authority`, and returns whether the
+    /// peer got banned.
+    pub fn report(
+        &mut self,
+        authority: AuthorityIndex,
+        misbehavior: Misbehavior,
+        now: Duration,
+    ) -> bool {
+        let ban_score = self.parameters.ban_score as f64;
+        let ban_duration = self.parameters.ban_duration;
+        let peer = self.decayed(authority, now);
+        peer.score += ban_score * misbehavior.penalty_percentage() as f64 / 100.0;
+        if peer.score < ban_score {
+            return false;
+        }
+        peer.score = 0.0;
+        peer.banned_until = Some(now + ban_duration);
+        true
+    }
+
+    pub fn is_banned(&mut self, authority: AuthorityIndex, now: Duration) -> bool {
+        let peer = &mut self.peers[authority as usize];
+        match peer.banned_until {
+            Some(until) if now < until => true,
+            Some(_) => {
+                peer.banned_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn score(&mut self, authority: AuthorityIndex, now: Duration) -> u64 {
+        self.decayed(authority, now).score as u64
+    }
+
+    fn decayed(&mut self, authority: AuthorityIndex, now: Duration) -> &mut PeerScore {
+        let decay_per_second = self.parameters.score_decay_per_minute as f64 / 60.0;
+        let peer = &mut self.peers[authority as usize];
+        let elapsed = now.saturating_sub(peer.last_update).as_secs_f64();
+        peer.score = (peer.score - elapsed * decay_per_second).max(0.0);
+        peer.last_update = now;
+        peer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(10, 20, secs(0));
+        assert!(bucket.try_take(15, secs(0)));
+        assert!(!bucket.try_take(10, secs(0)));
+        // Refilled at the rate, up to the capacity
+        assert!(bucket.try_take(10, Duration::from_millis(500)));
+        assert!(!bucket.try_take(1, Duration::from_millis(500)));
+        assert!(bucket.try_take(20, secs(100)));
+        assert!(!bucket.try_take(1, secs(100)));
+        // Larger amounts than the capacity take a full bucket
+        assert!(bucket.try_take(1000, secs(102)));
+
+        let mut unlimited = TokenBucket::new(0, 0, secs(0));
+        assert!(unlimited.try_take(u64::MAX, secs(0)));
+    }
+
+    #[test]
+    fn test_peer_scores() {
+        let parameters = PeerLimitsParameters {
+            ban_score: 100,
+            score_decay_per_minute: 60,
+            ban_duration: secs(30),
+            ..Default::default()
+        };
+        let mut scores = PeerScores::new(parameters, 4);
+        assert!(!scores.report(1, Misbehavior::MalformedMessage, secs(0)));
+        assert_eq!(scores.score(1, secs(0)), 50);
+        // Scores decay over time
+        assert_eq!(scores.score(1, secs(20)), 30);
+        assert!(!scores.report(1, Misbehavior::MalformedMessage, secs(20)));
+        assert!(!scores.report(1, Misbehavior::RateLimited, secs(20)));
+        assert_eq!(scores.score(1, secs(20)), 81);
+        assert!(scores.report(1, Misbehavior::MalformedMessage, secs(20)));
+        assert!(scores.is_banned(1, secs(20)));
+        assert!(!scores.is_banned(0, secs(20)));
+
+        // The ban expires
+        assert!(scores.is_banned(1, secs(49)));
+        assert!(!scores.is_banned(1, secs(50)));
+        assert_eq!(scores.score(1, secs(50)), 0);
+
+        // A few invalid blocks ban
+        for _ in 0..3 {
+            assert!(!scores.report(2, Misbehavior::InvalidBlock, secs(50)));
+        }
+        assert_eq!(scores.score(2, secs(50)), 75);
+        assert!(scores.report(2, Misbehavior::InvalidBlock, secs(50)));
+        assert!(scores.is_banned(2, secs(50)));
+    }
+
+    #[test]
+    fn test_penalty_scales_with_ban_score() {
+        let parameters = PeerLimitsParameters {
+            ban_score: 1000,
+            ..Default::default()
+        };
+        let mut scores = PeerScores::new(parameters, 4);
+        assert!(!scores.report(1, Misbehavior::MalformedMessage, secs(0)));
+        assert_eq!(scores.score(1, secs(0)), 500);
+        assert!(!scores.report(2, Misbehavior::InvalidBlock, secs(0)));
+        assert_eq!(scores.score(2, secs(0)), 250);
+    }
+}
@@ -0,0 +1,729 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use axum::{
+    body::StreamBody,
+    extract::Query,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router, Server,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::oneshot::{self, channel, Sender};
+
+use crate::block_handler::TransactionSubmission;
+use crate::commit_stream::CommitHistory;
+use crate::consensus::linearizer::CommittedSubDag;
+use crate::crypto::BlockDigest;
+use crate::fast_path::{FastPathCertificates, TransactionCertificate};
+use crate::finalization_interpreter::FinalizedTransaction;
+use crate::runtime::{select, sleep, Handle, JoinHandle};
+use crate::serde::ByteRepr;
+use crate::types::{AuthorityIndex, BlockReference, Epoch, RoundNumber, TransactionLocator};
+
+pub const TRANSACTIONS_ROUTE: &str = "/transactions";
+pub const COMMITS_ROUTE: &str = "/commits";
+pub const CERTIFICATES_ROUTE: &str = "/certificates";
+pub const FINALIZED_ROUTE: &str = "/finalized";
+
+/// How long a certificate request waits for the transaction to be certified by default.
+const DEFAULT_CERTIFICATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SubmitTransactionRequest {
+    /// Hex encoded transaction bytes.
+    pub transaction: String,
+    /// Transactions with a higher priority are proposed first, and evict the transactions with a
+    /// lower priority when the mempool is full.
+    #[serde(default)]
+    pub priority: u64,
+}
+
+/// Locator of a submitted transaction in the block that proposed it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct TransactionReceipt {
+    pub authority: AuthorityIndex,
+    pub round: RoundNumber,
+    /// Hex encoded digest of the block.
+    pub digest: String,
+    pub offset: u64,
+}
+
+impl From<TransactionLocator> for TransactionReceipt {
+    fn from(locator: TransactionLocator) -> Self {
+        let block = locator.block();
+        Self {
+            authority: block.authority,
+            round: block.round,
+            digest: hex::encode(block.digest),
+            offset: locator.offset(),
+        }
+    }
+}
+
+/// Transaction to wait for, as returned in its receipt.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CertificateQuery {
+    pub authority: AuthorityIndex,
+    pub round: RoundNumber,
+    /// Hex encoded digest of the block.
+    pub digest: String,
+    pub offset: u64,
+    /// How long to wait for the transaction to be certified, defaults to 10 seconds.
+    pub timeout_ms: Option<u64>,
+}
+
+/// Fast path certificate of a transaction.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CertificateRecord {
+    pub transaction: TransactionReceipt,
+    /// Whether a quorum rejected the transaction, in which case it is never final on the fast path.
+    pub rejected: bool,
+    pub votes: Vec<VoteRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct VoteRecord {
+    pub block: BlockId,
+    /// Hex encoded signature of the block.
+    pub signature: String,
+}
+
+impl From<&TransactionCertificate> for CertificateRecord {
+    fn from(certificate: &TransactionCertificate) -> Self {
+        Self {
+            transaction: certificate.locator.into(),
+            rejected: certificate.rejected,
+            votes: certificate
+                .votes
+                .iter()
+                .map(|(reference, signature)| VoteRecord {
+                    block: reference.into(),
+                    signature: hex::encode(signature),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommitsQuery {
+    /// Epoch of the commits to stream, defaults to the current epoch. Heights start again from 1
+    /// in every epoch, so the request fails if the validator is in another epoch.
+    pub epoch: Option<Epoch>,
+    /// Height of the first commit to stream, defaults to the first commit of the epoch.
+    pub from_height: Option<u64>,
+}
+
+/// A committed sub-dag, streamed as one json line per commit.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CommitRecord {
+    pub epoch: Epoch,
+    pub height: u64,
+    pub leader: BlockId,
+    pub timestamp_ms: u64,
+    pub blocks: Vec<CommittedBlock>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BlockId {
+    pub authority: AuthorityIndex,
+    pub round: RoundNumber,
+    /// Hex encoded digest of the block.
+    pub digest: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CommittedBlock {
+    pub block: BlockId,
+    /// Hex encoded transactions shared by the block, in order.
+    pub transactions: Vec<String>,
+}
+
+impl From<&BlockReference> for BlockId {
+    fn from(reference: &BlockReference) -> Self {
+        Self {
+            authority: reference.authority,
+            round: reference.round,
+            digest: hex::encode(reference.digest),
+        }
+    }
+}
+
+impl CommitRecord {
+    pub fn new(epoch: Epoch, committed: &CommittedSubDag) -> Self {
+        let blocks = committed
+            .blocks
+            .iter()
+            .map(|block| CommittedBlock {
+                block: block.reference().into(),
+                transactions: block
+                    .shared_transactions()
+                    .map(|(_, transaction)| hex::encode(transaction.data()))
+                    .collect(),
+            })
+            .collect();
+        Self {
+            epoch,
+            height: committed.height,
+            leader: (&committed.anchor).into(),
+            timestamp_ms: committed.timestamp_ms,
+            blocks,
+        }
+    }
+}
+
+/// A transaction that became final on the fast path, streamed as one json line per transaction.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FinalizedRecord {
+    pub transaction: TransactionReceipt,
+    pub certifying_blocks: Vec<BlockId>,
+}
+
+impl From<&FinalizedTransaction> for FinalizedRecord {
+    fn from(finalized: &FinalizedTransaction) -> Self {
+        Self {
+            transaction: finalized.locator.into(),
+            certifying_blocks: finalized
+                .certifying_blocks
+                .iter()
+                .map(BlockId::from)
+                .collect(),
+        }
+    }
+}
+
+pub struct RpcServerHandle {
+    pub handle: JoinHandle<Result<(), hyper::Error>>,
+    /// The address the server is bound to, which differs from the requested one for port 0.
+    pub address: SocketAddr,
+    stop: Sender<()>,
+}
+
+impl RpcServerHandle {
+    pub async fn shutdown(self) {
+        self.stop.send(()).ok();
+        self.handle.await.ok();
+    }
+}
+
+/// Serves transaction submissions and the commit stream on `address`.
+/// Transactions are forwarded to the block handler through `sender` and the request completes once
+/// the transaction is in a proposed block. Submissions are rejected while the channel to the block
+/// handler is full, and fail when the mempool drops the transaction before it is proposed.
+/// Commits of the current epoch are streamed from `commits` as newline delimited json, starting
+/// from the requested height. The stream ends with the epoch.
+/// Certificate requests wait until the transaction is certified or rejected on the fast path, or
+/// until their timeout expires.
+/// Transactions that become final on the fast path from the time of the request are streamed from
+/// `finalized` as newline delimited json, if the validator tracks finality.
+pub fn start_rpc_server(
+    address: SocketAddr,
+    sender: mpsc::Sender<TransactionSubmission>,
+    commits: CommitHistory,
+    certificates: FastPathCertificates,
+    finalized: Option<broadcast::Sender<FinalizedTransaction>>,
+) -> RpcServerHandle {
+    let app = Router::new()
+        .route(TRANSACTIONS_ROUTE, post(submit_transaction))
+        .route(COMMITS_ROUTE, get(stream_commits))
+        .route(CERTIFICATES_ROUTE, get(wait_certificate))
+        .route(FINALIZED_ROUTE, get(stream_finalized))
+        .layer(Extension(sender))
+        .layer(Extension(commits))
+        .layer(Extension(certificates))
+        .layer(Extension(finalized));
+
+    let (stop, rx_stop) = channel();
+
+    let server = Server::bind(&address).serve(app.into_make_service());
+    let address = server.local_addr();
+    tracing::info!("Rpc server booted on {address}");
+    let handle = Handle::current().spawn(server.with_graceful_shutdown(async {
+        rx_stop.await.ok();
+    }));
+
+    RpcServerHandle {
+        handle,
+        address,
+        stop,
+    }
+}
+
+async fn submit_transaction(
+    sender: Extension<mpsc::Sender<TransactionSubmission>>,
+    Json(request): Json<SubmitTransactionRequest>,
+) -> Result<Json<TransactionReceipt>, (StatusCode, String)> {
+    let transaction = hex::decode(&request.transaction).map_err(|error| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Transaction is not hex encoded: {error}"),
+        )
+    })?;
+    let (locator_sender, locator_receiver) = oneshot::channel();
+    let submission = TransactionSubmission {
+        transaction,
+        priority: request.priority,
+        notify_when_proposed: locator_sender,
+    };
+    match sender.try_send(submission) {
+        Ok(()) => {}
+        Err(TrySendError::Full(_)) => {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many pending transactions".to_string(),
+            ))
+        }
+        Err(TrySendError::Closed(_)) => {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Validator is shutting down".to_string(),
+            ))
+        }
+    }
+    match locator_receiver.await {
+        Ok(locator) => Ok(Json(locator.into())),
+        Err(_) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Transaction was dropped before it was proposed".to_string(),
+        )),
+    }
+}
+
+async fn stream_commits(
+    commits: Extension<CommitHistory>,
+    Query(query): Query<CommitsQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let from_height = query.from_height.unwrap_or(1);
+    let epoch = query.epoch.unwrap_or_else(|| commits.epoch());
+    let Some(subscription) = commits.subscribe(epoch, from_height) else {
+        let current_epoch = commits.epoch();
+        return Err(match epoch.cmp(&current_epoch) {
+            Ordering::Greater => (
+                StatusCode::NOT_FOUND,
+                format!("Epoch {epoch} has not started, current epoch is {current_epoch}"),
+            ),
+            Ordering::Less => (
+                StatusCode::GONE,
+                format!("Epoch {epoch} is over, current epoch is {current_epoch}"),
+            ),
+            Ordering::Equal => (
+                StatusCode::GONE,
+                format!(
+                    "Commit {from_height} is no longer available, first available commit is {}",
+                    commits.first_height()
+                ),
+            ),
+        });
+    };
+    let lines = futures::stream::unfold(subscription, |mut subscription| async move {
+        let committed = subscription.next().await?;
+        let record = CommitRecord::new(subscription.epoch(), &committed);
+        let mut line = serde_json::to_vec(&record).expect("Failed to serialize commit");
+        line.push(b'\n');
+        Some((Ok::<_, Infallible>(line), subscription))
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(lines),
+    )
+        .into_response())
+}
+
+async fn stream_finalized(
+    finalized: Extension<Option<broadcast::Sender<FinalizedTransaction>>>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(finalized) = finalized.as_ref() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Finality tracking is disabled".to_string(),
+        ));
+    };
+    // A subscriber that falls too far behind misses transactions, the stream ends instead
+    let lines = futures::stream::unfold(finalized.subscribe(), |mut receiver| async move {
+        let transaction = match receiver.recv().await {
+            Ok(transaction) => transaction,
+            Err(RecvError::Lagged(_) | RecvError::Closed) => return None,
+        };
+        let record = FinalizedRecord::from(&transaction);
+        let mut line = serde_json::to_vec(&record).expect("Failed to serialize transaction");
+        line.push(b'\n');
+        Some((Ok::<_, Infallible>(line), receiver))
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(lines),
+    )
+        .into_response())
+}
+
+async fn wait_certificate(
+    certificates: Extension<FastPathCertificates>,
+    Query(query): Query<CertificateQuery>,
+) -> Result<Json<CertificateRecord>, (StatusCode, String)> {
+    let digest = hex::decode(&query.digest)
+        .ok()
+        .and_then(|digest| {
+            BlockDigest::try_copy_from_slice::<serde::de::value::Error>(&digest).ok()
+        })
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid block digest {}", query.digest),
+            )
+        })?;
+    let block = BlockReference {
+        authority: query.authority,
+        round: query.round,
+        digest,
+    };
+    let locator = TransactionLocator::new(block, query.offset);
+    let timeout = query
+        .timeout_ms
+        .map_or(DEFAULT_CERTIFICATE_TIMEOUT, Duration::from_millis);
+    select! {
+        certificate = certificates.wait(locator) => match certificate {
+            Some(certificate) => Ok(Json((&certificate).into())),
+            None => Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Validator is shutting down".to_string(),
+            )),
+        },
+        _ = sleep(timeout) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Transaction {locator} was not certified within {timeout:?}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_handler::{BlockHandler, SimpleBlockHandler};
+    use crate::block_store::CommitData;
+    use crate::data::Data;
+    use crate::test_util::{committee, TestBlockWriter};
+    use crate::types::{BaseStatement, StatementBlock, Transaction};
+    use std::time::Duration;
+
+    fn commit(writer: &mut TestBlockWriter, height: u64) -> CommittedSubDag {
+        let block = Data::new(StatementBlock::new(
+            0,
+            height,
+            vec![],
+            vec![BaseStatement::Share(Transaction::new(vec![height as u8]))],
+            0,
+            false,
+            0,
+            Default::default(),
+        ));
+        writer.add_block(block.clone());
+        CommittedSubDag::new(*block.reference(), vec![block], 0, height)
+    }
+
+    fn empty_commit_history() -> CommitHistory {
+        let writer = TestBlockWriter::new(&committee(4));
+        CommitHistory::new(0, writer.block_store(), &[])
+    }
+
+    async fn read_line<T: serde::de::DeserializeOwned>(
+        response: &mut reqwest::Response,
+        buffer: &mut Vec<u8>,
+    ) -> T {
+        loop {
+            if let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<_> = buffer.drain(..=end).collect();
+                return serde_json::from_slice(&line).unwrap();
+            }
+            let chunk = response.chunk().await.unwrap().unwrap();
+            buffer.extend_from_slice(&chunk);
+        }
+    }
+
+    async fn submit(address: SocketAddr, transaction: &[u8]) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://{address}{TRANSACTIONS_ROUTE}"))
+            .json(&SubmitTransactionRequest {
+                transaction: hex::encode(transaction),
+                priority: 0,
+            })
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rpc_receipt_after_proposal() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (mut block_handler, sender) = SimpleBlockHandler::new();
+        let server = start_rpc_server(
+            address,
+            sender,
+            empty_commit_history(),
+            Default::default(),
+            None,
+        );
+        let address = server.address;
+
+        let request = tokio::spawn(async move { submit(address, &[1, 2, 3]).await });
+        // Act as the core: collect the submitted transaction and propose it in an own block.
+        let statements = loop {
+            let statements = block_handler.handle_blocks(&[], true);
+            if !statements.is_empty() {
+                break statements;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        let block = Data::new(StatementBlock::new(
+            0,
+            1,
+            vec![],
+            statements,
+            0,
+            false,
+            0,
+            Default::default(),
+        ));
+        block_handler.handle_proposal(&block);
+
+        let response = request.await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let receipt: TransactionReceipt = response.json().await.unwrap();
+        let (locator, _) = block.shared_transactions().next().unwrap();
+        assert_eq!(receipt, locator.into());
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rpc_backpressure() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (sender, mut receiver) = mpsc::channel(1);
+        let server = start_rpc_server(
+            address,
+            sender.clone(),
+            empty_commit_history(),
+            Default::default(),
+            None,
+        );
+        let address = server.address;
+
+        let (locator_sender, _locator_receiver) = oneshot::channel();
+        let submission = TransactionSubmission {
+            transaction: vec![0],
+            priority: 0,
+            notify_when_proposed: locator_sender,
+        };
+        assert!(sender.try_send(submission).is_ok());
+        let response = submit(address, &[1]).await;
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        // Dropping the submission without proposing it fails the request.
+        receiver.recv().await.unwrap();
+        let request = tokio::spawn(async move { submit(address, &[2]).await });
+        drop(receiver.recv().await.unwrap());
+        let response = request.await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rpc_invalid_transaction() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (sender, _receiver) = mpsc::channel(1);
+        let server = start_rpc_server(
+            address,
+            sender,
+            empty_commit_history(),
+            Default::default(),
+            None,
+        );
+        let address = server.address;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{address}{TRANSACTIONS_ROUTE}"))
+            .json(&SubmitTransactionRequest {
+                transaction: "not hex".to_string(),
+                priority: 0,
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rpc_certificate() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (sender, _receiver) = mpsc::channel(1);
+        let certificates = FastPathCertificates::default();
+        let server = start_rpc_server(
+            address,
+            sender,
+            empty_commit_history(),
+            certificates.clone(),
+            None,
+        );
+        let address = server.address;
+
+        let block = BlockReference::new_test(0, 1);
+        let locator = TransactionLocator::new(block, 3);
+        let receipt = TransactionReceipt::from(locator);
+        let url = format!(
+            "http://{address}{CERTIFICATES_ROUTE}?authority={}&round={}&digest={}&offset={}",
+            receipt.authority, receipt.round, receipt.digest, receipt.offset
+        );
+
+        // The transaction is not certified in time
+        let response = reqwest::get(format!("{url}&timeout_ms=10")).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // The request completes once the transaction is certified
+        let request = tokio::spawn(reqwest::get(url));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let certificate = TransactionCertificate {
+            locator,
+            rejected: false,
+            votes: (0..3)
+                .map(|authority| (BlockReference::new_test(authority, 1), Default::default()))
+                .collect(),
+        };
+        certificates.add(certificate.clone());
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let record: CertificateRecord = response.json().await.unwrap();
+        assert_eq!(record, CertificateRecord::from(&certificate));
+        assert_eq!(record.votes.len(), 3);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rpc_commit_stream_resume() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut writer = TestBlockWriter::new(&committee(4));
+        let commits: Vec<_> = (1..=3).map(|height| commit(&mut writer, height)).collect();
+        let recovered: Vec<_> = commits[..2].iter().map(CommitData::from).collect();
+        let history = CommitHistory::new(0, writer.block_store(), &recovered);
+        let (sender, _receiver) = mpsc::channel(1);
+        let server = start_rpc_server(address, sender, history.clone(), Default::default(), None);
+        let address = server.address;
+
+        let mut response = reqwest::get(format!("http://{address}{COMMITS_ROUTE}?from_height=2"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let mut buffer = vec![];
+        let record: CommitRecord = read_line(&mut response, &mut buffer).await;
+        assert_eq!(record, CommitRecord::new(0, &commits[1]));
+        assert_eq!(record.blocks[0].transactions, vec!["02".to_string()]);
+
+        // New commits are streamed as they are added
+        history.add(&commits[2..]);
+        let record: CommitRecord = read_line(&mut response, &mut buffer).await;
+        assert_eq!(record, CommitRecord::new(0, &commits[2]));
+
+        // The stream ends with the epoch
+        history.start_epoch(1, writer.block_store(), &[]);
+        assert!(response.chunk().await.unwrap().is_none());
+
+        // Heights of the previous epoch are not served from the new one
+        let response = reqwest::get(format!(
+            "http://{address}{COMMITS_ROUTE}?epoch=0&from_height=3"
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::GONE);
+        let response = reqwest::get(format!("http://{address}{COMMITS_ROUTE}?epoch=2"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rpc_commit_stream_pruned() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut writer = TestBlockWriter::new(&committee(4));
+        let commits: Vec<_> = (1..=3).map(|height| commit(&mut writer, height)).collect();
+        let recovered: Vec<_> = commits.iter().map(CommitData::from).collect();
+        let history = CommitHistory::new(0, writer.block_store(), &recovered);
+        history.start_epoch(1, writer.block_store(), &recovered[2..]);
+        let (sender, _receiver) = mpsc::channel(1);
+        let server = start_rpc_server(address, sender, history, Default::default(), None);
+        let address = server.address;
+
+        let response = reqwest::get(format!("http://{address}{COMMITS_ROUTE}?from_height=1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::GONE);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rpc_finalized_stream() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (sender, _receiver) = mpsc::channel(1);
+        let (finalized, _) = broadcast::channel(16);
+        let server = start_rpc_server(
+            address,
+            sender,
+            empty_commit_history(),
+            Default::default(),
+            Some(finalized.clone()),
+        );
+        let address = server.address;
+
+        let mut response = reqwest::get(format!("http://{address}{FINALIZED_ROUTE}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let transaction = FinalizedTransaction {
+            locator: TransactionLocator::new(BlockReference::new_test(1, 1), 0),
+            certifying_blocks: (0..3)
+                .map(|authority| BlockReference::new_test(authority, 2))
+                .collect(),
+        };
+        finalized.send(transaction.clone()).unwrap();
+        let mut buffer = vec![];
+        let record: FinalizedRecord = read_line(&mut response, &mut buffer).await;
+        assert_eq!(record, FinalizedRecord::from(&transaction));
+        assert_eq!(record.certifying_blocks.len(), 3);
+
+        drop(response);
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rpc_finalized_disabled() {
+        let address: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (sender, _receiver) = mpsc::channel(1);
+        let server = start_rpc_server(
+            address,
+            sender,
+            empty_commit_history(),
+            Default::default(),
+            None,
+        );
+        let address = server.address;
+
+        let response = reqwest::get(format!("http://{address}{FINALIZED_ROUTE}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        server.shutdown().await;
+    }
+}
@@ -12,3 +12,22 @@ pub use tokio_mod::*;
 
 #[cfg(feature = "simulator")]
 pub use simulated::*;
+
+/// `tokio::select!` that polls its branches in the order they are written under the simulator,
+/// rather than starting from a random branch, so that simulated runs are reproducible from
+/// their seed.
+#[cfg(feature = "simulator")]
+macro_rules! select {
+    ($($tokens:tt)*) => {
+        tokio::select! { biased; $($tokens)* }
+    };
+}
+
+#[cfg(not(feature = "simulator"))]
+macro_rules! select {
+    ($($tokens:tt)*) => {
+        tokio::select! { $($tokens)* }
+    };
+}
+
+pub(crate) use select;
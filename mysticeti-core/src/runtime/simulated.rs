@@ -4,6 +4,7 @@
 pub use crate::future_simulator::JoinError;
 pub use crate::future_simulator::JoinHandle;
 use crate::future_simulator::{SimulatorContext, Sleep};
+use rand::RngCore;
 use std::future::Future;
 use std::time::Duration;
 
@@ -59,3 +60,8 @@ impl TimeInterval {
         TimeInstant::now()
     }
 }
+
+/// Runs `f` with the random number generator of the simulator, derived from its seed.
+pub fn with_rng<R, F: FnOnce(&mut dyn RngCore) -> R>(f: F) -> R {
+    SimulatorContext::with_rng(|rng| f(rng))
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use rand::RngCore;
 use std::time::{Duration, SystemTime};
 pub use tokio::runtime::Handle;
 pub use tokio::task::JoinError;
@@ -45,3 +46,9 @@ impl TimeInterval {
         TimeInstant(self.0.tick().await)
     }
 }
+
+/// Runs `f` with the random number generator of the runtime.
+#[allow(dead_code)]
+pub fn with_rng<R, F: FnOnce(&mut dyn RngCore) -> R>(f: F) -> R {
+    f(&mut rand::thread_rng())
+}
@@ -0,0 +1,381 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Traces of simulated runs. A trace holds the seed and the fault schedule of a run along with the
+//! tasks polled by the simulator, the messages carried by the simulated network and the rounds
+//! reached by the nodes. Runs are deterministic under their seed, so a trace saved from a failing
+//! test can be replayed to reproduce the failure, and its fault schedule can be shrunk to the
+//! faults needed to trigger it.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{BufReader, BufWriter};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use eyre::{bail, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::block_handler::BlockHandler;
+use crate::core::Core;
+use crate::future_simulator::{SimulatedExecutorState, SimulatorContext};
+use crate::network::NetworkMessage;
+use crate::simulated_network::{Fault, FaultSchedule};
+use crate::test_util::rng_at_seed;
+use crate::types::{AuthorityIndex, RoundNumber};
+
+/// Directory where the traces of failed runs are saved, a directory in the temp dir if unset.
+const TRACE_DIR_ENV: &str = "SIMULATOR_TRACE_DIR";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimulationTrace {
+    pub seed: u64,
+    pub faults: FaultSchedule,
+    /// Panic message of the run, None if it completed.
+    pub failure: Option<String>,
+    pub events: Vec<TraceEvent>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub time: Duration,
+    pub kind: TraceEventKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TraceEventKind {
+    /// The simulator polled a task.
+    Wake(usize),
+    /// A message was put on the link from `from` to `to`, with the latency it is delivered after,
+    /// or None if the link dropped it.
+    Delivery {
+        from: AuthorityIndex,
+        to: AuthorityIndex,
+        message: String,
+        latency: Option<Duration>,
+    },
+    /// The round or the last proposed round of a node changed.
+    NodeState {
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        proposed: RoundNumber,
+    },
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = RefCell::new(None);
+}
+
+#[derive(Default)]
+struct Recorder {
+    events: Vec<TraceEvent>,
+    // Last recorded (round, proposed) of every node
+    node_states: HashMap<AuthorityIndex, (RoundNumber, RoundNumber)>,
+}
+
+impl SimulationTrace {
+    /// Runs the scenario under the seed with the given fault schedule and records its trace.
+    /// A panic of the scenario is recorded as the failure of the run.
+    pub fn record<S, F>(seed: u64, faults: FaultSchedule, scenario: &S) -> Self
+    where
+        S: Fn(FaultSchedule) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        RECORDER.with(|recorder| *recorder.borrow_mut() = Some(Recorder::default()));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            SimulatedExecutorState::run(rng_at_seed(seed), scenario(faults.clone()))
+        }));
+        let recorder = RECORDER
+            .with(|recorder| recorder.borrow_mut().take())
+            .expect("Recorder is set for the duration of the run");
+        let failure = result.err().map(|payload| {
+            SimulatorContext::clear();
+            panic_message(payload)
+        });
+        Self {
+            seed,
+            faults,
+            failure,
+            events: recorder.events,
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failure.is_some()
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Runs the scenario again under the seed and the fault schedule of the trace, and checks
+    /// that the new run goes through the same events and ends the same way.
+    pub fn replay<S, F>(&self, scenario: &S) -> eyre::Result<SimulationTrace>
+    where
+        S: Fn(FaultSchedule) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let replayed = Self::record(self.seed, self.faults.clone(), scenario);
+        if let Some(index) = self.first_divergence(&replayed) {
+            bail!(
+                "Replay diverged at event {index}: recorded {:?}, replayed {:?}",
+                self.events.get(index),
+                replayed.events.get(index)
+            );
+        }
+        ensure!(
+            self.failure == replayed.failure,
+            "Replay ended with {:?}, recorded run ended with {:?}",
+            replayed.failure,
+            self.failure
+        );
+        Ok(replayed)
+    }
+
+    /// Index of the first event that differs between the two traces.
+    pub fn first_divergence(&self, other: &SimulationTrace) -> Option<usize> {
+        let common = self.events.len().min(other.events.len());
+        (0..common)
+            .find(|index| self.events[*index] != other.events[*index])
+            .or_else(|| (self.events.len() != other.events.len()).then_some(common))
+    }
+
+    /// Shrinks the fault schedule of a failed run by delta debugging: chunks of faults are removed
+    /// as long as the scenario still fails under the seed. Returns the trace of the failed run
+    /// with the smallest schedule found.
+    pub fn minimize<S, F>(&self, scenario: &S) -> SimulationTrace
+    where
+        S: Fn(FaultSchedule) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        assert!(
+            self.failed(),
+            "Only the trace of a failed run can be minimized"
+        );
+        let mut smallest = self.clone();
+        let mut granularity = 2;
+        while !smallest.faults.is_empty() {
+            let faults = smallest.faults.faults().to_vec();
+            granularity = granularity.min(faults.len());
+            let chunk = (faults.len() + granularity - 1) / granularity;
+            let reduced = (0..faults.len()).step_by(chunk).find_map(|start| {
+                let end = (start + chunk).min(faults.len());
+                let complement = faults[..start].iter().chain(&faults[end..]);
+                let trace = Self::record(self.seed, schedule(complement), scenario);
+                trace.failed().then_some(trace)
+            });
+            match reduced {
+                Some(trace) => {
+                    smallest = trace;
+                    granularity = granularity.saturating_sub(1).max(2);
+                }
+                None if granularity >= faults.len() => break,
+                None => granularity *= 2,
+            }
+        }
+        smallest
+    }
+}
+
+/// Runs the scenario and fails if it fails, after saving the trace of the run and the trace of
+/// its minimized fault schedule. The traces can be loaded back to replay the failure.
+pub fn run_or_save<S, F>(name: &str, seed: u64, faults: FaultSchedule, scenario: &S)
+where
+    S: Fn(FaultSchedule) -> F,
+    F: Future<Output = ()> + Send + 'static,
+{
+    let trace = SimulationTrace::record(seed, faults, scenario);
+    let Some(failure) = &trace.failure else {
+        return;
+    };
+    let dir = trace_dir();
+    fs::create_dir_all(&dir).expect("Failed to create trace directory");
+    let path = dir.join(format!("{name}-{seed}.json"));
+    trace.save(&path).expect("Failed to save trace");
+    let minimized = trace.minimize(scenario);
+    let minimized_path = dir.join(format!("{name}-{seed}.min.json"));
+    minimized
+        .save(&minimized_path)
+        .expect("Failed to save minimized trace");
+    panic!(
+        "Simulation {name} failed under seed {seed}: {failure}. Trace saved to {}, trace with {} of {} faults saved to {}",
+        path.display(),
+        minimized.faults.len(),
+        trace.faults.len(),
+        minimized_path.display()
+    );
+}
+
+pub fn record(kind: TraceEventKind) {
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            recorder.events.push(TraceEvent {
+                time: SimulatorContext::time(),
+                kind,
+            });
+        }
+    })
+}
+
+pub fn record_delivery(
+    from: usize,
+    to: usize,
+    message: &NetworkMessage,
+    latency: Option<Duration>,
+) {
+    record(TraceEventKind::Delivery {
+        from: from as AuthorityIndex,
+        to: to as AuthorityIndex,
+        message: describe(message),
+        latency,
+    })
+}
+
+pub fn record_node_state<H: BlockHandler>(core: &Core<H>) {
+    let authority = core.authority();
+    let state = (core.current_round(), core.last_proposed());
+    let changed = RECORDER.with(|recorder| {
+        recorder.borrow_mut().as_mut().map_or(false, |recorder| {
+            recorder.node_states.insert(authority, state) != Some(state)
+        })
+    });
+    if changed {
+        record(TraceEventKind::NodeState {
+            authority,
+            round: state.0,
+            proposed: state.1,
+        })
+    }
+}
+
+fn describe(message: &NetworkMessage) -> String {
+    match message {
+        NetworkMessage::SubscribeOwnFrom(round) => format!("SubscribeOwnFrom({round})"),
+        NetworkMessage::Blocks(blocks) => format!(
+            "Blocks({:?})",
+            blocks.iter().map(|b| *b.reference()).collect::<Vec<_>>()
+        ),
+        NetworkMessage::RequestBlocks(references) => format!("RequestBlocks({references:?})"),
+        NetworkMessage::RequestBlocksResponse(blocks) => format!(
+            "RequestBlocksResponse({:?})",
+            blocks.iter().map(|b| *b.reference()).collect::<Vec<_>>()
+        ),
+        NetworkMessage::BlockNotFound(references) => format!("BlockNotFound({references:?})"),
+    }
+}
+
+fn schedule<'a>(faults: impl Iterator<Item = &'a (Duration, Fault)>) -> FaultSchedule {
+    faults.fold(FaultSchedule::new(), |schedule, (time, fault)| {
+        schedule.at(*time, fault.clone())
+    })
+}
+
+fn trace_dir() -> PathBuf {
+    std::env::var_os(TRACE_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("mysticeti-simulator-traces"))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panic with a non-string payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime;
+    use crate::simulated_network::{Latency, LinkConditions};
+    use crate::test_util::simulated_network_syncers;
+
+    const MIN_COMMITS: usize = 15;
+
+    // Expects a steady flow of commits over 10 seconds
+    async fn scenario(faults: FaultSchedule) {
+        let (simulated_network, network_syncers, _) = simulated_network_syncers(4);
+        simulated_network.start_fault_schedule(faults);
+        simulated_network.connect_all().await;
+        runtime::sleep(Duration::from_secs(10)).await;
+        let mut syncers = vec![];
+        for network_syncer in network_syncers {
+            syncers.push(network_syncer.shutdown().await);
+        }
+        let committed = syncers[0].commit_observer().committed_leaders().len();
+        assert!(committed >= MIN_COMMITS, "Only {committed} commits");
+    }
+
+    // Only the partition, which never heals, stops the commits
+    fn faults() -> FaultSchedule {
+        let slow_link = LinkConditions {
+            latency: Latency::Fixed(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        FaultSchedule::new()
+            .at(
+                Duration::from_secs(1),
+                Fault::AllLinks(LinkConditions {
+                    drop_probability: 0.01,
+                    ..Default::default()
+                }),
+            )
+            .at(
+                Duration::from_secs(2),
+                Fault::Partition(vec![vec![0, 1], vec![2, 3]]),
+            )
+            .at(
+                Duration::from_secs(3),
+                Fault::Link {
+                    from: 2,
+                    to: 3,
+                    conditions: slow_link,
+                },
+            )
+    }
+
+    #[test]
+    fn test_simulation_trace_replay() {
+        let trace = SimulationTrace::record(3, faults(), &scenario);
+        assert!(trace.failed());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.json");
+        trace.save(&path).unwrap();
+        let loaded = SimulationTrace::load(&path).unwrap();
+        assert_eq!(loaded.events, trace.events);
+
+        let replayed = loaded.replay(&scenario).unwrap();
+        assert_eq!(replayed.failure, trace.failure);
+
+        let passing = SimulationTrace::record(3, FaultSchedule::new(), &scenario);
+        assert!(!passing.failed());
+        assert!(trace.first_divergence(&passing).is_some());
+        assert!(passing.replay(&scenario).is_ok());
+    }
+
+    #[test]
+    fn test_simulation_trace_minimize() {
+        let trace = SimulationTrace::record(4, faults(), &scenario);
+        let minimized = trace.minimize(&scenario);
+        assert!(minimized.failed());
+        assert_eq!(minimized.faults.len(), 1);
+        assert!(matches!(
+            minimized.faults.faults()[0],
+            (_, Fault::Partition(_))
+        ));
+    }
+}
@@ -1,25 +1,97 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::byzantine::{self, ByzantineBehavior, ByzantineLink};
 use crate::committee::Committee;
+use crate::crypto::{dummy_signer, Signer};
 use crate::future_simulator::SimulatorContext;
-use crate::network::{Connection, Network};
+use crate::network::{Connection, Network, NetworkMessage};
 use crate::runtime;
+use crate::sim_trace;
+use crate::types::AuthorityIndex;
+use parking_lot::Mutex;
 use rand::Rng;
-use std::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::ops::Range;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
+
+// This is one way latency distribution, e.g. 1/2 RTT
+const LATENCY_RANGE: Range<Duration> = Duration::from_millis(50)..Duration::from_millis(100);
 
 pub struct SimulatedNetwork {
     senders: Vec<mpsc::Sender<Connection>>,
+    // Behaviors of the byzantine authorities, applied to every message they send, and the signers
+    // they re-sign their rewritten blocks with
+    byzantine: HashMap<usize, (ByzantineBehavior, Arc<Signer>)>,
+    links: Links,
 }
 
-impl SimulatedNetwork {
-    // This is one way latency distribution, e.g. 1/2 RTT
-    const LATENCY_RANGE: Range<Duration> = Duration::from_millis(50)..Duration::from_millis(100);
+/// One way latency of the messages on a link.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Latency {
+    Fixed(Duration),
+    Uniform(Range<Duration>),
+    /// At least `min`, plus an exponentially distributed delay with the given mean.
+    Exponential {
+        min: Duration,
+        mean: Duration,
+    },
+}
+
+/// Conditions of the link carrying the messages from one authority to another.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LinkConditions {
+    pub latency: Latency,
+    /// Probability that a message is lost.
+    pub drop_probability: f64,
+    /// Probability that a message is held back for an extra latency, so that the messages sent
+    /// after it overtake it.
+    pub reorder_probability: f64,
+    /// Bytes per second the link can carry, unbounded if None.
+    pub bandwidth: Option<u64>,
+    /// Messages sent on a link that is down are held back until the link is up again, as a
+    /// retransmitting transport would.
+    pub up: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Fault {
+    /// Authorities only reach the authorities of their own group. Authorities missing from
+    /// all groups are isolated.
+    Partition(Vec<Vec<AuthorityIndex>>),
+    /// Sets the conditions of the link from `from` to `to`, the opposite direction is unchanged.
+    Link {
+        from: AuthorityIndex,
+        to: AuthorityIndex,
+        conditions: LinkConditions,
+    },
+    /// Sets the conditions of all links.
+    AllLinks(LinkConditions),
+    /// Restores the default conditions on all links.
+    Heal,
+}
+
+/// Faults applied to the network at the given times after the schedule is started.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FaultSchedule {
+    faults: Vec<(Duration, Fault)>,
+}
+
+#[derive(Clone)]
+struct Links {
+    // Conditions of the link from authority i to authority j at [i][j]
+    conditions: Arc<Mutex<Vec<Vec<LinkConditions>>>>,
+    changed: Arc<watch::Sender<()>>,
+}
 
-    pub fn new(committee: &Committee) -> (SimulatedNetwork, Vec<Network>) {
+impl SimulatedNetwork {
+    pub fn new(
+        committee: &Committee,
+        byzantine: Vec<(AuthorityIndex, ByzantineBehavior)>,
+    ) -> (SimulatedNetwork, Vec<Network>) {
         let (networks, senders): (Vec<_>, Vec<_>) = committee
             .authorities()
             .map(|_| {
@@ -30,7 +102,49 @@ impl SimulatedNetwork {
                 )
             })
             .unzip();
-        (Self { senders }, networks)
+        let byzantine = byzantine
+            .into_iter()
+            // The cores of the simulated committee sign with the dummy signer
+            .map(|(authority, behavior)| (authority as usize, (behavior, Arc::new(dummy_signer()))))
+            .collect();
+        let (changed, _) = watch::channel(());
+        let links = Links {
+            conditions: Arc::new(Mutex::new(vec![
+                vec![
+                    LinkConditions::default();
+                    senders.len()
+                ];
+                senders.len()
+            ])),
+            changed: Arc::new(changed),
+        };
+        (
+            Self {
+                senders,
+                byzantine,
+                links,
+            },
+            networks,
+        )
+    }
+
+    /// Applies the fault right away.
+    pub fn apply_fault(&self, fault: Fault) {
+        self.links.apply(fault);
+    }
+
+    /// Applies the faults of the schedule at their time, counted from now.
+    pub fn start_fault_schedule(&self, schedule: FaultSchedule) {
+        let links = self.links.clone();
+        let mut faults = schedule.faults;
+        faults.sort_by_key(|(time, _)| *time);
+        runtime::Handle::current().spawn(async move {
+            let start = runtime::TimeInstant::now();
+            for (time, fault) in faults {
+                runtime::sleep(time.saturating_sub(start.elapsed())).await;
+                links.apply(fault);
+            }
+        });
     }
 
     pub async fn connect_all(&self) {
@@ -53,8 +167,8 @@ impl SimulatedNetwork {
     }
 
     pub async fn connect(&self, a: usize, b: usize) {
-        let (a_sender, a_receiver) = Self::latency_channel();
-        let (b_sender, b_receiver) = Self::latency_channel();
+        let (a_sender, a_receiver) = self.latency_channel(b, a);
+        let (b_sender, b_receiver) = self.latency_channel(a, b);
         // the watch channels are responsible for disseminating the calculated latency for each connection.
         let (_al_sender, al_receiver) = tokio::sync::watch::channel(Duration::from_secs(0));
         let (_bl_sender, bl_receiver) = tokio::sync::watch::channel(Duration::from_secs(0));
@@ -76,15 +190,81 @@ impl SimulatedNetwork {
         b.send(b_connection).await.ok();
     }
 
-    fn latency_channel<T: Send + 'static + Debug>() -> (mpsc::Sender<T>, mpsc::Receiver<T>) {
+    /// Channel carrying the messages sent by `from` to `to`.
+    fn latency_channel(
+        &self,
+        from: usize,
+        to: usize,
+    ) -> (mpsc::Sender<NetworkMessage>, mpsc::Receiver<NetworkMessage>) {
         let (buf_sender, mut buf_receiver) = mpsc::channel(16);
         let (sender, receiver) = mpsc::channel(16);
+        let mut byzantine_link = self.byzantine.get(&from).map(|(behavior, signer)| {
+            ByzantineLink::new(
+                behavior.clone(),
+                signer.clone(),
+                from as AuthorityIndex,
+                to as AuthorityIndex,
+                self.senders.len(),
+            )
+        });
+        let to_byzantine = self.byzantine.contains_key(&to);
+        let links = self.links.clone();
+        let mut links_changed = self.links.changed.subscribe();
         runtime::Handle::current().spawn(async move {
             while let Some(message) = buf_receiver.recv().await {
-                let latency = SimulatorContext::with_rng(|rng| rng.gen_range(Self::LATENCY_RANGE));
-                // println!("{} {:?} lat {latency:?}", SimulatorContext::time().as_millis(), message);
+                let message = match byzantine_link.as_mut() {
+                    Some(link) => match link.outgoing(message) {
+                        Some(message) => message,
+                        None => continue,
+                    },
+                    None => message,
+                };
+                let message = if to_byzantine {
+                    match byzantine::incoming(to as AuthorityIndex, message) {
+                        Some(message) => message,
+                        None => continue,
+                    }
+                } else {
+                    message
+                };
+                // Held back while the link is down
+                let conditions = loop {
+                    links_changed.borrow_and_update();
+                    let conditions = links.get(from, to);
+                    if conditions.up {
+                        break conditions;
+                    }
+                    if links_changed.changed().await.is_err() {
+                        return;
+                    }
+                };
+                if conditions.drop_probability > 0.0
+                    && SimulatorContext::with_rng(|rng| rng.gen_bool(conditions.drop_probability))
+                {
+                    sim_trace::record_delivery(from, to, &message, None);
+                    continue;
+                }
+                let mut latency = conditions.latency.sample();
+                if let Some(bandwidth) = conditions.bandwidth {
+                    let size = bincode::serialized_size(&message).expect("Serialization failed");
+                    latency += Duration::from_secs_f64(size as f64 / bandwidth as f64);
+                }
+                if conditions.reorder_probability > 0.0
+                    && SimulatorContext::with_rng(|rng| {
+                        rng.gen_bool(conditions.reorder_probability)
+                    })
+                {
+                    let delay = latency + conditions.latency.sample();
+                    sim_trace::record_delivery(from, to, &message, Some(delay));
+                    let sender = sender.clone();
+                    runtime::Handle::current().spawn(async move {
+                        runtime::sleep(delay).await;
+                        sender.send(message).await.ok();
+                    });
+                    continue;
+                }
+                sim_trace::record_delivery(from, to, &message, Some(latency));
                 runtime::sleep(latency).await;
-                // println!("{} snd {:?} lat {latency:?}", SimulatorContext::time().as_millis(), message);
                 if sender.send(message).await.is_err() {
                     return;
                 }
@@ -93,3 +273,104 @@ impl SimulatedNetwork {
         (buf_sender, receiver)
     }
 }
+
+impl Links {
+    fn get(&self, from: usize, to: usize) -> LinkConditions {
+        self.conditions.lock()[from][to].clone()
+    }
+
+    fn apply(&self, fault: Fault) {
+        let mut conditions = self.conditions.lock();
+        match fault {
+            Fault::Partition(groups) => {
+                let group_of = |authority: usize| {
+                    groups
+                        .iter()
+                        .position(|group| group.contains(&(authority as AuthorityIndex)))
+                };
+                for (from, links) in conditions.iter_mut().enumerate() {
+                    for (to, link) in links.iter_mut().enumerate() {
+                        link.up = group_of(from).is_some() && group_of(from) == group_of(to);
+                    }
+                }
+            }
+            Fault::Link {
+                from,
+                to,
+                conditions: link,
+            } => conditions[from as usize][to as usize] = link,
+            Fault::AllLinks(link) => {
+                for links in conditions.iter_mut() {
+                    links.fill(link.clone());
+                }
+            }
+            Fault::Heal => {
+                for links in conditions.iter_mut() {
+                    links.fill(LinkConditions::default());
+                }
+            }
+        }
+        drop(conditions);
+        self.changed.send_replace(());
+    }
+}
+
+impl Latency {
+    fn sample(&self) -> Duration {
+        match self {
+            Latency::Fixed(latency) => *latency,
+            Latency::Uniform(range) => {
+                SimulatorContext::with_rng(|rng| rng.gen_range(range.clone()))
+            }
+            Latency::Exponential { min, mean } => {
+                let uniform: f64 = SimulatorContext::with_rng(|rng| rng.gen());
+                *min + mean.mul_f64(-(1.0 - uniform).ln())
+            }
+        }
+    }
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        Self {
+            latency: Latency::Uniform(LATENCY_RANGE),
+            drop_probability: 0.0,
+            reorder_probability: 0.0,
+            bandwidth: None,
+            up: true,
+        }
+    }
+}
+
+impl LinkConditions {
+    /// Default link that is down.
+    pub fn down() -> Self {
+        Self {
+            up: false,
+            ..Default::default()
+        }
+    }
+}
+
+impl FaultSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn at(mut self, time: Duration, fault: Fault) -> Self {
+        self.faults.push((time, fault));
+        self
+    }
+
+    pub fn faults(&self) -> &[(Duration, Fault)] {
+        &self.faults
+    }
+
+    pub fn len(&self) -> usize {
+        self.faults.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faults.is_empty()
+    }
+}
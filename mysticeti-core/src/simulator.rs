@@ -16,6 +16,8 @@ where
     states: Vec<S>,
     time: Duration,
     events: BinaryHeap<ScheduledEvent<S::Event>>,
+    // Sequence number of the next scheduled event
+    next_seq: u64,
     rng: Option<StdRng>,
 }
 
@@ -40,16 +42,20 @@ where
             states,
             time: Default::default(),
             events: Default::default(),
+            next_seq: 0,
             rng: Some(rng),
         }
     }
 
     pub fn schedule_event(&mut self, after: Duration, state: usize, event: S::Event) {
-        self.events.push(ScheduledEvent {
-            time: self.time.saturating_add(after),
-            state,
-            event,
-        });
+        let time = self.time.saturating_add(after);
+        self.push(ScheduledEvent::new(time, state, event));
+    }
+
+    fn push(&mut self, mut event: ScheduledEvent<S::Event>) {
+        event.seq = self.next_seq;
+        self.next_seq += 1;
+        self.events.push(event);
     }
 
     /// returns true if complete
@@ -91,7 +97,9 @@ impl<'a, E: SimulatorState + 'static> Drop for SchedulerEnterGuard<'a, E> {
     fn drop(&mut self) {
         let (events, rng) = Scheduler::exit();
         self.simulator.rng = Some(rng);
-        self.simulator.events.extend(events);
+        for event in events {
+            self.simulator.push(event);
+        }
     }
 }
 
@@ -103,7 +111,9 @@ impl<E: 'static> Scheduler<E> {
     pub fn schedule_event(after: Duration, state: usize, event: E) -> Duration {
         Self::with(|scheduler| {
             let time = scheduler.time.saturating_add(after);
-            scheduler.events.push(ScheduledEvent { time, state, event });
+            scheduler
+                .events
+                .push(ScheduledEvent::new(time, state, event));
             time
         })
     }
@@ -164,27 +174,40 @@ where
 
 struct ScheduledEvent<E> {
     time: Duration,
+    // Events scheduled for the same time run in the order they were scheduled
+    seq: u64,
     state: usize,
     event: E,
 }
 
-// PartialOrd and Ord are implemented as inverse order on self.time
+impl<E> ScheduledEvent<E> {
+    fn new(time: Duration, state: usize, event: E) -> Self {
+        Self {
+            time,
+            seq: 0,
+            state,
+            event,
+        }
+    }
+}
+
+// PartialOrd and Ord are implemented as inverse order on (self.time, self.seq)
 // This is because rust's BinaryHeap is a max heap, and we care about events with lowest time
 impl<E> PartialOrd for ScheduledEvent<E> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        other.time.partial_cmp(&self.time)
+        Some(self.cmp(other))
     }
 }
 
 impl<E> PartialEq for ScheduledEvent<E> {
     fn eq(&self, other: &Self) -> bool {
-        self.time.eq(&other.time)
+        (self.time, self.seq).eq(&(other.time, other.seq))
     }
 }
 
 impl<E> Ord for ScheduledEvent<E> {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.time.cmp(&self.time)
+        (other.time, other.seq).cmp(&(self.time, self.seq))
     }
 }
 
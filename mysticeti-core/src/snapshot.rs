@@ -0,0 +1,457 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+
+use eyre::{bail, ensure};
+use minibytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::block_store::{
+    BlockStore, CommitData, OwnBlockData, WAL_ENTRY_BLOCK, WAL_ENTRY_COMMIT, WAL_ENTRY_HORIZON,
+    WAL_ENTRY_LEADER_SCHEDULE, WAL_ENTRY_STATE,
+};
+use crate::committee::Committee;
+use crate::crypto::{SignatureBytes, Signer};
+use crate::data::Data;
+use crate::types::{AuthorityIndex, BlockReference, Epoch, RoundNumber, StatementBlock};
+use crate::wal::{WalPosition, WalWriter};
+
+/// State of a validator at a committed leader, signed by the validator that produced it.
+///
+/// A validator without local state can seed its wal from a snapshot instead of syncing the
+/// whole dag from genesis. The snapshot carries all blocks at or above the `horizon` round
+/// (plus the available blocks of the last commit below it), blocks below the horizon are assumed
+/// to be committed and are never fetched or linearized by the bootstrapped validator.
+///
+/// Bootstrapping an authority that lost its wal is only safe if the snapshot is recent enough to
+/// contain its latest proposed block, otherwise the authority may equivocate. Bootstrapping is
+/// refused if the snapshot contains no block of the authority at all.
+pub struct Snapshot {
+    content: SnapshotContent,
+    signature: SignatureBytes,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotContent {
+    author: AuthorityIndex,
+    epoch: Epoch,
+    commit: CommitData,
+    horizon: RoundNumber,
+    // Returned by CommitObserver::aggregator_state
+    committed_state: Bytes,
+    // Returned by BlockHandler::state
+    handler_state: Bytes,
+    // Returned by LeaderSchedule::state
+    leader_schedule: Bytes,
+    blocks: Vec<Data<StatementBlock>>,
+}
+
+impl Snapshot {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        author: AuthorityIndex,
+        committee: &Committee,
+        commit: CommitData,
+        committed_state: Bytes,
+        handler_state: Bytes,
+        leader_schedule: Bytes,
+        block_store: &BlockStore,
+        retain_rounds: RoundNumber,
+        signer: &Signer,
+    ) -> Self {
+        // Genesis blocks are never part of a snapshot, so the horizon is at least 1
+        let horizon = commit
+            .leader
+            .round
+            .saturating_sub(retain_rounds)
+            .max(block_store.horizon())
+            .max(1);
+        // Committed blocks below the horizon are included when still available,
+        // so that the commit can be replayed to the commit observer
+        let mut blocks: Vec<_> = commit
+            .sub_dag
+            .iter()
+            .filter(|reference| reference.round < horizon && reference.round > 0)
+            .filter_map(|reference| block_store.get_block(*reference))
+            .collect();
+        for round in horizon..=block_store.highest_round() {
+            blocks.extend(block_store.get_blocks_by_round(round));
+        }
+        blocks.sort_by_key(|block| block.round());
+        let content = SnapshotContent {
+            author,
+            epoch: committee.epoch(),
+            commit,
+            horizon,
+            committed_state,
+            handler_state,
+            leader_schedule,
+            blocks,
+        };
+        let signature = signer.sign_snapshot(&content.to_bytes());
+        Self { content, signature }
+    }
+
+    /// Checks the signature of the snapshot producer and that the snapshot contains all the blocks
+    /// of its commit above the horizon, as well as every block in the snapshot.
+    pub fn verify(&self, committee: &Committee) -> eyre::Result<()> {
+        let content = &self.content;
+        ensure!(
+            content.epoch == committee.epoch(),
+            "Snapshot epoch {} doesn't match committee epoch {}",
+            content.epoch,
+            committee.epoch()
+        );
+        let Some(public_key) = committee.get_public_key(content.author) else {
+            bail!("Unknown snapshot author {}", content.author)
+        };
+        if let Err(e) = public_key.verify_snapshot(&content.to_bytes(), &self.signature) {
+            bail!("Snapshot signature verification has failed: {:?}", e);
+        }
+        ensure!(
+            content.horizon > 0,
+            "Snapshot horizon must be above genesis"
+        );
+        ensure!(
+            content.commit.sub_dag.contains(&content.commit.leader),
+            "Snapshot commit does not contain its leader {}",
+            content.commit.leader
+        );
+        let references: HashSet<_> = content
+            .blocks
+            .iter()
+            .map(|block| *block.reference())
+            .collect();
+        for reference in &content.commit.sub_dag {
+            ensure!(
+                reference.round < content.horizon || references.contains(reference),
+                "Snapshot is missing committed block {}",
+                reference
+            );
+        }
+        for block in &content.blocks {
+            block.verify(committee)?;
+        }
+        Ok(())
+    }
+
+    pub fn author(&self) -> AuthorityIndex {
+        self.content.author
+    }
+
+    pub fn height(&self) -> u64 {
+        self.content.commit.height
+    }
+
+    pub fn leader(&self) -> BlockReference {
+        self.content.commit.leader
+    }
+
+    pub fn horizon(&self) -> RoundNumber {
+        self.content.horizon
+    }
+
+    pub fn blocks(&self) -> &[Data<StatementBlock>] {
+        &self.content.blocks
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&(&self.content, &self.signature)).expect("Serialization failed")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        let (content, signature) = bincode::deserialize(bytes)?;
+        Ok(Self { content, signature })
+    }
+
+    /// Writes the snapshot to a temporary file first, so that a crash never leaves a partially
+    /// written snapshot at `path`.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.to_bytes())?;
+        fs::rename(tmp, path)
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let bytes = fs::read(path)?;
+        Ok(Self::from_bytes(&bytes)?)
+    }
+
+    /// Seeds an empty wal with the content of the snapshot, so that `BlockStore::open` recovers
+    /// the validator at the snapshot commit. Fails without writing anything if the snapshot has
+    /// no block of `authority`: proposing again from genesis could equivocate with the blocks the
+    /// authority proposed before losing its wal.
+    pub(crate) fn bootstrap(
+        &self,
+        authority: AuthorityIndex,
+        wal_writer: &mut WalWriter,
+    ) -> eyre::Result<()> {
+        let content = &self.content;
+        // Our latest block known to the snapshot producer becomes our last own block,
+        // all other blocks are pending and will be included by our next proposal.
+        let Some(own_block) = content
+            .blocks
+            .iter()
+            .filter(|block| block.author() == authority)
+            .max_by_key(|block| block.round())
+            .cloned()
+        else {
+            bail!(
+                "Snapshot at commit height {} has no block of authority {authority}",
+                content.commit.height
+            );
+        };
+        let mut own_rounds = HashSet::new();
+        let mut next_entry = WalPosition::MAX;
+        for block in &content.blocks {
+            if block.reference() == own_block.reference() {
+                continue;
+            }
+            // The block store keeps a single own block per round
+            if block.author() == authority && !own_rounds.insert(block.round()) {
+                continue;
+            }
+            let position = wal_writer.write(WAL_ENTRY_BLOCK, block.serialized_bytes())?;
+            if next_entry == WalPosition::MAX {
+                next_entry = position;
+            }
+        }
+        OwnBlockData {
+            next_entry,
+            block: own_block,
+        }
+        .write_to_wal(wal_writer);
+        wal_writer.write(WAL_ENTRY_STATE, &content.handler_state)?;
+        wal_writer.write(WAL_ENTRY_LEADER_SCHEDULE, &content.leader_schedule)?;
+        let commits =
+            bincode::serialize(&(slice::from_ref(&content.commit), &content.committed_state))
+                .expect("Commits serialization failed");
+        wal_writer.write(WAL_ENTRY_COMMIT, &commits)?;
+        let horizon = bincode::serialize(&content.horizon).expect("Serialization failed");
+        wal_writer.write(WAL_ENTRY_HORIZON, &horizon)?;
+        wal_writer.sync()?;
+        Ok(())
+    }
+}
+
+/// Writes snapshots to a file on a background thread, so that commits never wait for the disk.
+/// A snapshot is skipped while another one is already waiting to be written.
+pub struct SnapshotWriter {
+    sender: mpsc::SyncSender<Snapshot>,
+}
+
+impl SnapshotWriter {
+    pub fn start(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Snapshot>(1);
+        thread::Builder::new()
+            .name("snapshot-writer".to_string())
+            .spawn(move || {
+                // Stops once the core is dropped
+                while let Ok(snapshot) = receiver.recv() {
+                    match snapshot.write(&path) {
+                        Ok(()) => tracing::info!(
+                            "Written snapshot at commit height {} with {} blocks",
+                            snapshot.height(),
+                            snapshot.blocks().len()
+                        ),
+                        Err(e) => tracing::warn!("Failed to write snapshot to {path:?}: {e}"),
+                    }
+                }
+            })
+            .expect("Failed to spawn snapshot-writer");
+        Self { sender }
+    }
+
+    pub fn write(&self, snapshot: Snapshot) {
+        match self.sender.try_send(snapshot) {
+            Ok(()) => {}
+            Err(TrySendError::Full(snapshot)) => tracing::warn!(
+                "Skipping snapshot at commit height {}, previous snapshot is not written yet",
+                snapshot.height()
+            ),
+            Err(TrySendError::Disconnected(_)) => {
+                panic!("Snapshot writer is not expected to stop")
+            }
+        }
+    }
+}
+
+impl SnapshotContent {
+    fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Serialization failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_handler::TestBlockHandler;
+    use crate::commit_observer::{CommitObserver, TestCommitObserver};
+    use crate::config::{Parameters, SnapshotParameters};
+    use crate::consensus::linearizer::CommittedSubDag;
+    use crate::core::{Core, CoreOptions};
+    use crate::crypto::dummy_signer;
+    use crate::test_util::{
+        committee_and_cores_persisted_epoch_duration, first_transaction_for_authority, test_metrics,
+    };
+    use crate::wal::walf;
+    use std::sync::Arc;
+
+    type Commits = Vec<(u64, BlockReference, Vec<BlockReference>)>;
+
+    /// Every core proposes a block, all blocks are delivered to every core and then every core
+    /// tries to commit. Returns the commit data of the first core.
+    fn run_round(
+        cores: &mut [Core<TestBlockHandler>],
+        observers: &mut [TestCommitObserver],
+        commits: &mut [Commits],
+    ) -> Vec<CommitData> {
+        let blocks: Vec<_> = cores
+            .iter_mut()
+            .filter_map(|core| core.try_new_block())
+            .collect();
+        let mut first_commit_data = None;
+        for ((core, observer), commits) in cores.iter_mut().zip(observers).zip(commits) {
+            core.add_blocks(blocks.clone());
+            let committed = observer.handle_commit(core.try_commit());
+            commits.extend(committed.iter().map(summary));
+            let commit_data = core.handle_committed_subdag(committed, &observer.aggregator_state());
+            first_commit_data.get_or_insert(commit_data);
+        }
+        first_commit_data.unwrap()
+    }
+
+    fn summary(sub_dag: &CommittedSubDag) -> (u64, BlockReference, Vec<BlockReference>) {
+        let mut blocks: Vec<_> = sub_dag.blocks.iter().map(|b| *b.reference()).collect();
+        blocks.sort();
+        (sub_dag.height, sub_dag.anchor, blocks)
+    }
+
+    #[test]
+    fn snapshot_bootstrap() {
+        let parameters = Parameters {
+            snapshot_parameters: SnapshotParameters {
+                interval: 0,
+                retain_rounds: 5,
+            },
+            ..Default::default()
+        };
+        let (committee, mut cores, mut observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, None, &parameters);
+        let mut commits: Vec<Commits> = vec![vec![]; 4];
+
+        let mut last_commit = None;
+        for _ in 0..20 {
+            let commit_data = run_round(&mut cores, &mut observers, &mut commits);
+            last_commit = commit_data.last().cloned().or(last_commit);
+        }
+        let last_commit = last_commit.expect("Should have committed");
+        let snapshot = cores[0].snapshot(last_commit, observers[0].aggregator_state());
+        assert!(snapshot.horizon() > 1);
+        let snapshot = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        snapshot.verify(&committee).unwrap();
+
+        // Replace the last validator with one bootstrapped from the snapshot
+        let authority = 3;
+        let (mut wal_writer, wal_reader) = walf(tempfile::tempfile().unwrap()).unwrap();
+        snapshot.bootstrap(authority, &mut wal_writer).unwrap();
+        let (core_recovered, commit_observer_recovered) = BlockStore::open(
+            authority,
+            Arc::new(wal_reader),
+            &wal_writer,
+            test_metrics(),
+            &committee,
+        );
+        assert_eq!(
+            core_recovered.last_committed_leader,
+            Some(snapshot.leader())
+        );
+        let block_handler = TestBlockHandler::new(
+            first_transaction_for_authority(authority),
+            committee.clone(),
+            authority,
+            test_metrics(),
+        );
+        let core = Core::open(
+            block_handler,
+            authority,
+            committee.clone(),
+            &parameters,
+            test_metrics(),
+            core_recovered,
+            wal_writer,
+            CoreOptions::test(),
+            Arc::new(dummy_signer()),
+        );
+        assert_eq!(core.last_own_block(), cores[3].last_own_block());
+        observers[3] = TestCommitObserver::new(
+            core.block_store().clone(),
+            committee.clone(),
+            core.block_handler().transaction_time.clone(),
+            test_metrics(),
+            Default::default(),
+            commit_observer_recovered,
+        );
+        cores[3] = core;
+        commits[3].clear();
+        let snapshot_commits = commits[0].len();
+
+        for _ in 0..20 {
+            run_round(&mut cores, &mut observers, &mut commits);
+        }
+        // The bootstrapped validator produces the same sequence of commits as the others
+        assert!(!commits[3].is_empty());
+        assert_eq!(commits[3], commits[0][snapshot_commits..]);
+    }
+
+    #[test]
+    fn snapshot_verification() {
+        let (committee, cores, observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, None, &Parameters::default());
+        let commit = CommitData {
+            leader: *cores[0].last_own_block().reference(),
+            sub_dag: vec![*cores[0].last_own_block().reference()],
+            height: 1,
+        };
+        let snapshot = cores[0].snapshot(commit, observers[0].aggregator_state());
+        snapshot.verify(&committee).unwrap();
+
+        let mut tampered = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        tampered.content.commit.height = 2;
+        assert!(tampered.verify(&committee).is_err());
+
+        let mut tampered = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        tampered.content.author = 1;
+        assert!(tampered.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn snapshot_bootstrap_without_own_block() {
+        let (committee, mut cores, observers, _reporters) =
+            committee_and_cores_persisted_epoch_duration(4, None, &Parameters::default());
+        let block = cores[0].try_new_block().unwrap();
+        let commit = CommitData {
+            leader: *block.reference(),
+            sub_dag: vec![*block.reference()],
+            height: 1,
+        };
+        let snapshot = cores[0].snapshot(commit, observers[0].aggregator_state());
+        snapshot.verify(&committee).unwrap();
+
+        // Authority 1 proposed blocks that are unknown to the snapshot
+        let (mut wal_writer, wal_reader) = walf(tempfile::tempfile().unwrap()).unwrap();
+        assert!(snapshot.bootstrap(1, &mut wal_writer).is_err());
+        assert!(wal_reader.iter_until(&wal_writer).next().is_none());
+
+        let (mut wal_writer, wal_reader) = walf(tempfile::tempfile().unwrap()).unwrap();
+        snapshot.bootstrap(0, &mut wal_writer).unwrap();
+        assert!(wal_reader.iter_until(&wal_writer).next().is_some());
+    }
+}
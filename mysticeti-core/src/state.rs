@@ -17,6 +17,8 @@ pub struct CoreRecoveredState {
     pub state: Option<Bytes>,
     pub unprocessed_blocks: Vec<Data<StatementBlock>>,
     pub last_committed_leader: Option<BlockReference>,
+    pub leader_schedule: Option<Bytes>,
+    pub epoch_change: Option<Bytes>,
 }
 
 #[derive(Default)]
@@ -29,6 +31,10 @@ pub struct RecoveredStateBuilder {
     last_committed_leader: Option<BlockReference>,
     committed_sub_dags: Vec<CommitData>,
     committed_state: Option<Bytes>,
+    // Only used once the commit entry following it is written
+    pending_leader_schedule: Option<Bytes>,
+    leader_schedule: Option<Bytes>,
+    epoch_change: Option<Bytes>,
 }
 
 impl RecoveredStateBuilder {
@@ -58,7 +64,18 @@ impl RecoveredStateBuilder {
         self.unprocessed_blocks.clear();
     }
 
+    pub fn leader_schedule(&mut self, leader_schedule: Bytes) {
+        self.pending_leader_schedule = Some(leader_schedule);
+    }
+
+    pub fn epoch_change(&mut self, epoch_change: Bytes) {
+        self.epoch_change = Some(epoch_change);
+    }
+
     pub fn commit_data(&mut self, commits: Vec<CommitData>, committed_state: Bytes) {
+        if let Some(leader_schedule) = self.pending_leader_schedule.take() {
+            self.leader_schedule = Some(leader_schedule);
+        }
         for commit_data in commits {
             self.last_committed_leader = Some(commit_data.leader);
             if let Some(cur_last_commit) = self.committed_sub_dags.last() {
@@ -85,6 +102,8 @@ impl RecoveredStateBuilder {
             state: self.state,
             unprocessed_blocks: self.unprocessed_blocks,
             last_committed_leader: self.last_committed_leader,
+            leader_schedule: self.leader_schedule,
+            epoch_change: self.epoch_change,
         };
         let commit_observer_recovered = CommitObserverRecoveredState {
             sub_dags: self.committed_sub_dags,
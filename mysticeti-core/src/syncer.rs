@@ -174,6 +174,14 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
         &self.core
     }
 
+    pub fn cleanup(&mut self) {
+        self.core.cleanup();
+    }
+
+    pub fn into_core(self) -> Core<H> {
+        self.core
+    }
+
     #[cfg(test)]
     pub fn scheduler_state_id(&self) -> usize {
         self.core.authority() as usize
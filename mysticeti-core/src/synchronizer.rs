@@ -1,11 +1,11 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::HashMap, env, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, env, sync::Arc, time::Duration};
 
 use futures::future::join_all;
 use itertools::Itertools;
-use rand::{seq::SliceRandom, thread_rng};
+use rand::seq::SliceRandom;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::Sender;
@@ -18,7 +18,7 @@ use crate::{
     metrics::Metrics,
     net_sync::{self, NetworkSyncerInner},
     network::NetworkMessage,
-    runtime::{sleep, Handle, JoinHandle},
+    runtime::{self, sleep, Handle, JoinHandle},
     types::{AuthorityIndex, BlockReference, RoundNumber},
 };
 
@@ -268,7 +268,8 @@ struct BlockFetcherWorker<B: BlockHandler, C: CommitObserver> {
     id: AuthorityIndex,
     inner: Arc<NetworkSyncerInner<B, C>>,
     receiver: mpsc::Receiver<BlockFetcherMessage>,
-    senders: HashMap<
+    // Ordered by authority so that peers are sampled deterministically under the simulator
+    senders: BTreeMap<
         AuthorityIndex,
         (
             Sender<NetworkMessage>,
@@ -308,7 +309,7 @@ where
 
     async fn run(mut self) -> Option<()> {
         loop {
-            tokio::select! {
+            runtime::select! {
                 _ = sleep(self.parameters.sample_precision) => self.sync_strategy().await,
                 message = self.receiver.recv() => {
                     match message {
@@ -352,10 +353,7 @@ where
         }
 
         // just sort them by ascending order to help facilitate the processing once responses arrive
-        to_request = to_request
-            .into_iter()
-            .sorted_by(|b1, b2| Ord::cmp(&b1.round, &b2.round))
-            .collect::<Vec<_>>();
+        to_request = to_request.into_iter().sorted().collect::<Vec<_>>();
 
         for chunks in to_request.chunks(net_sync::MAXIMUM_BLOCK_REQUEST) {
             let Some((peer, permit)) = self.sample_peer(&[self.id]) else {
@@ -392,10 +390,12 @@ where
             .collect::<Vec<_>>();
 
         static NUMBER_OF_PEERS: usize = 6;
-        let senders = senders
-            .choose_multiple_weighted(&mut thread_rng(), NUMBER_OF_PEERS, |item| item.2)
-            .expect("Weighted choice error: latency values incorrect!")
-            .collect::<Vec<_>>();
+        let senders = runtime::with_rng(|rng| {
+            senders
+                .choose_multiple_weighted(rng, NUMBER_OF_PEERS, |item| item.2)
+                .expect("Weighted choice error: latency values incorrect!")
+                .collect::<Vec<_>>()
+        });
 
         for (peer, sender, _latency) in senders {
             if let Ok(permit) = sender.try_reserve() {
@@ -4,12 +4,15 @@
 use crate::block_handler::{BlockHandler, TestBlockHandler};
 use crate::block_store::{BlockStore, BlockWriter, OwnBlockData, WAL_ENTRY_BLOCK};
 use crate::block_validator::AcceptAllBlockVerifier;
+#[cfg(feature = "simulator")]
+use crate::byzantine::ByzantineBehavior;
 use crate::commit_observer::TestCommitObserver;
 use crate::committee::Committee;
-use crate::config::Parameters;
+use crate::config::{NetworkCompression, Parameters};
 use crate::core::{Core, CoreOptions};
 use crate::crypto::dummy_signer;
 use crate::data::Data;
+use crate::equivocation::EquivocationProof;
 #[cfg(feature = "simulator")]
 use crate::future_simulator::OverrideNodeContext;
 use crate::metrics::MetricReporter;
@@ -19,10 +22,11 @@ use crate::network::Network;
 #[cfg(feature = "simulator")]
 use crate::simulated_network::SimulatedNetwork;
 use crate::syncer::{Syncer, SyncerSignals};
+use crate::transport::TcpTransport;
 use crate::types::{
     format_authority_index, AuthorityIndex, BlockReference, RoundNumber, StatementBlock,
 };
-use crate::wal::{open_file_for_wal, walf, WalPosition, WalWriter};
+use crate::wal::{open_wal, walf, WalPosition, WalWriter};
 use futures::future::join_all;
 use prometheus::Registry;
 use rand::rngs::StdRng;
@@ -101,13 +105,13 @@ pub fn committee_and_cores_persisted_epoch_duration(
                 authority,
                 metrics.clone(),
             );
-            let wal_file = if let Some(path) = path {
+            let (wal_writer, wal_reader) = if let Some(path) = path {
                 let wal_path = path.join(format!("{:03}.wal", authority));
-                open_file_for_wal(&wal_path).unwrap()
+                open_wal(wal_path)
             } else {
-                tempfile::tempfile().unwrap()
-            };
-            let (wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
+                walf(tempfile::tempfile().unwrap())
+            }
+            .expect("Failed to open wal");
             let (core_recovered, commit_observer_recovered) = BlockStore::open(
                 authority,
                 Arc::new(wal_reader),
@@ -126,7 +130,7 @@ pub fn committee_and_cores_persisted_epoch_duration(
                 core_recovered,
                 wal_writer,
                 CoreOptions::test(),
-                dummy_signer(),
+                Arc::new(dummy_signer()),
             );
             let commit_observer = TestCommitObserver::new(
                 core.block_store().clone(),
@@ -143,7 +147,7 @@ pub fn committee_and_cores_persisted_epoch_duration(
     (committee, cores, commit_observers, reporters)
 }
 
-fn first_transaction_for_authority(authority: AuthorityIndex) -> u64 {
+pub fn first_transaction_for_authority(authority: AuthorityIndex) -> u64 {
     authority * 1_000_000
 }
 
@@ -201,6 +205,8 @@ pub async fn networks_and_addresses(
                     *address,
                     metrics.clone(),
                     network_connection_max_latency,
+                    Arc::new(TcpTransport),
+                    NetworkCompression::Lz4,
                 )
             });
     let networks = join_all(networks).await;
@@ -226,10 +232,40 @@ pub fn simulated_network_syncers_with_epoch_duration(
     SimulatedNetwork,
     Vec<NetworkSyncer<TestBlockHandler, TestCommitObserver>>,
     Vec<MetricReporter>,
+) {
+    simulated_network_syncers_byzantine_epoch_duration(n, rounds_in_epoch, vec![])
+}
+
+/// Same as simulated_network_syncers, with the given authorities following a byzantine behavior.
+#[cfg(feature = "simulator")]
+pub fn simulated_network_syncers_with_byzantine(
+    n: usize,
+    byzantine: Vec<(AuthorityIndex, ByzantineBehavior)>,
+) -> (
+    SimulatedNetwork,
+    Vec<NetworkSyncer<TestBlockHandler, TestCommitObserver>>,
+    Vec<MetricReporter>,
+) {
+    simulated_network_syncers_byzantine_epoch_duration(
+        n,
+        Parameters::DEFAULT_ROUNDS_IN_EPOCH,
+        byzantine,
+    )
+}
+
+#[cfg(feature = "simulator")]
+pub fn simulated_network_syncers_byzantine_epoch_duration(
+    n: usize,
+    rounds_in_epoch: RoundNumber,
+    byzantine: Vec<(AuthorityIndex, ByzantineBehavior)>,
+) -> (
+    SimulatedNetwork,
+    Vec<NetworkSyncer<TestBlockHandler, TestCommitObserver>>,
+    Vec<MetricReporter>,
 ) {
     let (committee, cores, commit_observers, reporters) =
         committee_and_cores_epoch_duration(n, rounds_in_epoch);
-    let (simulated_network, networks) = SimulatedNetwork::new(&committee);
+    let (simulated_network, networks) = SimulatedNetwork::new(&committee, byzantine);
     let mut network_syncers = vec![];
     let parameters = Parameters::default();
     for ((network, core), commit_observer) in networks.into_iter().zip(cores).zip(commit_observers)
@@ -244,7 +280,9 @@ pub fn simulated_network_syncers_with_epoch_duration(
             AcceptAllBlockVerifier,
             test_metrics(),
             parameters.leader_timeout,
+            parameters.leader_timeout_parameters.clone(),
             parameters.synchronizer_parameters.clone(),
+            parameters.peer_limits.clone(),
             parameters.enable_cleanup,
         );
         drop(node_context);
@@ -278,7 +316,9 @@ pub async fn network_syncers_with_epoch_duration(
             AcceptAllBlockVerifier,
             test_metrics(),
             parameters.leader_timeout,
+            parameters.leader_timeout_parameters.clone(),
             parameters.synchronizer_parameters.clone(),
+            parameters.peer_limits.clone(),
             parameters.enable_cleanup,
         );
         network_syncers.push(network_syncer);
@@ -429,6 +469,10 @@ impl BlockWriter for TestBlockWriter {
     fn insert_own_block(&mut self, block: &OwnBlockData) {
         (&mut self.wal_writer, &self.block_store).insert_own_block(block)
     }
+
+    fn insert_equivocation(&mut self, proof: EquivocationProof) {
+        (&mut self.wal_writer, &self.block_store).insert_equivocation(proof)
+    }
 }
 
 /// Build a fully interconnected dag up to the specified round. This function starts building the
@@ -0,0 +1,431 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+
+use crate::committee::Committee;
+use crate::config::NetworkTransport;
+use crate::crypto::{SignatureBytes, Signer, SIGNATURE_SIZE};
+use crate::types::AuthorityIndex;
+
+/// A bidirectional byte stream returned by a [`Transport`]. Implementations may buffer written
+/// data until the stream is flushed.
+pub trait TransportStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> TransportStream for T {}
+
+/// Transport secures the tcp connections established by the `Network`.
+#[async_trait]
+pub trait Transport: Send + Sync + 'static {
+    /// Performs the transport handshake on a freshly established connection to `peer`.
+    /// `initiator` is set on the side that opened the connection.
+    /// Fails if the remote side can not prove that it is `peer`.
+    async fn upgrade(
+        &self,
+        stream: TcpStream,
+        peer: AuthorityIndex,
+        initiator: bool,
+    ) -> io::Result<Box<dyn TransportStream>>;
+}
+
+pub fn build_transport(
+    kind: NetworkTransport,
+    authority: AuthorityIndex,
+    committee: Arc<Committee>,
+    signer: Arc<Signer>,
+) -> Arc<dyn Transport> {
+    match kind {
+        NetworkTransport::Tcp => Arc::new(TcpTransport),
+        NetworkTransport::Noise => Arc::new(NoiseTransport::new(authority, committee, signer)),
+    }
+}
+
+/// Plain tcp, the remote peer is trusted to be the one the connection was routed to.
+pub struct TcpTransport;
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn upgrade(
+        &self,
+        stream: TcpStream,
+        _peer: AuthorityIndex,
+        _initiator: bool,
+    ) -> io::Result<Box<dyn TransportStream>> {
+        Ok(Box::new(stream))
+    }
+}
+
+/// Noise NN handshake followed by an exchange of signatures over the handshake hash.
+///
+/// The Noise handshake establishes an encrypted channel with ephemeral keys, then each side signs
+/// the handshake hash with its committee key. Since the handshake hash is unique to the channel,
+/// the signature can not be relayed to authenticate a different connection.
+pub struct NoiseTransport {
+    authority: AuthorityIndex,
+    committee: Arc<Committee>,
+    signer: Arc<Signer>,
+}
+
+const NOISE_PARAMS: &str = "Noise_NN_25519_ChaChaPoly_BLAKE2s";
+const NOISE_MAX_MESSAGE_SIZE: usize = 65535;
+const NOISE_TAG_SIZE: usize = 16;
+const NOISE_MAX_PAYLOAD_SIZE: usize = NOISE_MAX_MESSAGE_SIZE - NOISE_TAG_SIZE;
+const NOISE_LENGTH_SIZE: usize = 2;
+// Authority index followed by the handshake signature
+const AUTH_MESSAGE_SIZE: usize = 8 + SIGNATURE_SIZE;
+
+impl NoiseTransport {
+    pub fn new(authority: AuthorityIndex, committee: Arc<Committee>, signer: Arc<Signer>) -> Self {
+        Self {
+            authority,
+            committee,
+            signer,
+        }
+    }
+
+    fn transcript(handshake_hash: &[u8], initiator: bool) -> Vec<u8> {
+        // The role is included so that a peer can not reflect our own signature back to us
+        let mut transcript = Vec::with_capacity(handshake_hash.len() + 1);
+        transcript.push(initiator as u8);
+        transcript.extend_from_slice(handshake_hash);
+        transcript
+    }
+}
+
+#[async_trait]
+impl Transport for NoiseTransport {
+    async fn upgrade(
+        &self,
+        mut stream: TcpStream,
+        peer: AuthorityIndex,
+        initiator: bool,
+    ) -> io::Result<Box<dyn TransportStream>> {
+        let builder = snow::Builder::new(NOISE_PARAMS.parse().expect("Invalid noise parameters"));
+        let mut buf = vec![0u8; NOISE_MAX_MESSAGE_SIZE];
+        let noise = if initiator {
+            let mut handshake = builder.build_initiator().map_err(noise_error)?;
+            // -> e
+            let len = handshake
+                .write_message(&[], &mut buf)
+                .map_err(noise_error)?;
+            write_frame(&mut stream, &buf[..len]).await?;
+            // <- e, ee
+            let frame = read_frame(&mut stream).await?;
+            handshake
+                .read_message(&frame, &mut buf)
+                .map_err(noise_error)?;
+            handshake
+        } else {
+            let mut handshake = builder.build_responder().map_err(noise_error)?;
+            let frame = read_frame(&mut stream).await?;
+            handshake
+                .read_message(&frame, &mut buf)
+                .map_err(noise_error)?;
+            let len = handshake
+                .write_message(&[], &mut buf)
+                .map_err(noise_error)?;
+            write_frame(&mut stream, &buf[..len]).await?;
+            handshake
+        };
+        let handshake_hash = noise.get_handshake_hash().to_vec();
+        let noise = noise.into_transport_mode().map_err(noise_error)?;
+        let mut stream = NoiseStream::new(stream, noise);
+
+        let signature = self
+            .signer
+            .sign_handshake(&Self::transcript(&handshake_hash, initiator));
+        let mut auth = [0u8; AUTH_MESSAGE_SIZE];
+        auth[..8].copy_from_slice(&self.authority.to_le_bytes());
+        auth[8..].copy_from_slice(signature.as_ref());
+        stream.write_all(&auth).await?;
+        stream.flush().await?;
+
+        stream.read_exact(&mut auth).await?;
+        let mut remote = [0u8; 8];
+        remote.copy_from_slice(&auth[..8]);
+        let remote = AuthorityIndex::from_le_bytes(remote);
+        if remote != peer {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Expected authority {peer}, remote claims to be {remote}"),
+            ));
+        }
+        let Some(public_key) = self.committee.get_public_key(peer) else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Unknown authority {peer}"),
+            ));
+        };
+        let mut signature = [0u8; SIGNATURE_SIZE];
+        signature.copy_from_slice(&auth[8..]);
+        let signature = SignatureBytes::new(signature);
+        public_key
+            .verify_handshake(&Self::transcript(&handshake_hash, !initiator), &signature)
+            .map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("Invalid handshake signature from authority {peer}: {err}"),
+                )
+            })?;
+        Ok(Box::new(stream))
+    }
+}
+
+fn noise_error(err: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+async fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> io::Result<()> {
+    stream.write_u16(frame.len() as u16).await?;
+    stream.write_all(frame).await
+}
+
+async fn read_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let len = stream.read_u16().await?;
+    let mut frame = vec![0u8; len as usize];
+    stream.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+/// Encrypted stream on top of tcp. Plaintext is split into Noise messages, each sent as
+/// a u16 length prefix followed by the ciphertext.
+struct NoiseStream {
+    stream: TcpStream,
+    noise: snow::TransportState,
+    // Ciphertext frame currently being read
+    read_frame: Vec<u8>,
+    read_frame_len: Option<usize>,
+    read_position: usize,
+    // Decrypted data not yet returned to the reader
+    plaintext: Vec<u8>,
+    plaintext_position: usize,
+    // Encrypted data not yet written to the socket
+    write_buffer: Vec<u8>,
+    write_position: usize,
+}
+
+impl NoiseStream {
+    fn new(stream: TcpStream, noise: snow::TransportState) -> Self {
+        Self {
+            stream,
+            noise,
+            read_frame: vec![0u8; NOISE_MAX_MESSAGE_SIZE],
+            read_frame_len: None,
+            read_position: 0,
+            plaintext: Vec::new(),
+            plaintext_position: 0,
+            write_buffer: Vec::new(),
+            write_position: 0,
+        }
+    }
+
+    /// Reads the next ciphertext frame. Returns false if the stream ended on a frame boundary.
+    fn poll_read_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        loop {
+            let target = self.read_frame_len.unwrap_or(NOISE_LENGTH_SIZE);
+            if self.read_position == target {
+                match self.read_frame_len {
+                    None => {
+                        let len = u16::from_be_bytes([self.read_frame[0], self.read_frame[1]]);
+                        self.read_frame_len = Some(len as usize);
+                        self.read_position = 0;
+                        continue;
+                    }
+                    Some(_) => return Poll::Ready(Ok(true)),
+                }
+            }
+            let mut buf = ReadBuf::new(&mut self.read_frame[self.read_position..target]);
+            ready!(Pin::new(&mut self.stream).poll_read(cx, &mut buf))?;
+            let read = buf.filled().len();
+            if read == 0 {
+                if self.read_frame_len.is_none() && self.read_position == 0 {
+                    return Poll::Ready(Ok(false));
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.read_position += read;
+        }
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.write_position < self.write_buffer.len() {
+            let written = ready!(Pin::new(&mut self.stream)
+                .poll_write(cx, &self.write_buffer[self.write_position..]))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_position += written;
+        }
+        self.write_buffer.clear();
+        self.write_position = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for NoiseStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.plaintext_position == this.plaintext.len() {
+            if !ready!(this.poll_read_frame(cx))? {
+                // End of stream
+                return Poll::Ready(Ok(()));
+            }
+            let len = this.read_frame_len.take().expect("Frame length is set");
+            this.read_position = 0;
+            this.plaintext.resize(NOISE_MAX_MESSAGE_SIZE, 0);
+            let decrypted = this
+                .noise
+                .read_message(&this.read_frame[..len], &mut this.plaintext)
+                .map_err(noise_error)?;
+            this.plaintext.truncate(decrypted);
+            this.plaintext_position = 0;
+        }
+        let available = &this.plaintext[this.plaintext_position..];
+        let len = available.len().min(buf.remaining());
+        buf.put_slice(&available[..len]);
+        this.plaintext_position += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for NoiseStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        let len = buf.len().min(NOISE_MAX_PAYLOAD_SIZE);
+        let start = this.write_buffer.len();
+        this.write_buffer
+            .resize(start + NOISE_LENGTH_SIZE + NOISE_MAX_MESSAGE_SIZE, 0);
+        let encrypted = this
+            .noise
+            .write_message(
+                &buf[..len],
+                &mut this.write_buffer[start + NOISE_LENGTH_SIZE..],
+            )
+            .map_err(noise_error)?;
+        this.write_buffer[start..start + NOISE_LENGTH_SIZE]
+            .copy_from_slice(&(encrypted as u16).to_be_bytes());
+        this.write_buffer
+            .truncate(start + NOISE_LENGTH_SIZE + encrypted);
+        // The data is accepted at this point, the remainder of the frame is written on the next
+        // write or flush
+        if let Poll::Ready(Err(err)) = this.poll_write_buffer(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::committee::Authority;
+    use tokio::net::TcpListener;
+
+    fn signer(seed: u8) -> Signer {
+        Signer(Box::new(ed25519_consensus::SigningKey::from([seed; 32])))
+    }
+
+    fn committee() -> Arc<Committee> {
+        let authorities = (0..2)
+            .map(|i| Authority::new(1, signer(i).public_key(), String::new()))
+            .collect();
+        Committee::new(authorities, 0)
+    }
+
+    async fn connect(
+        client: NoiseTransport,
+        server: NoiseTransport,
+    ) -> (
+        io::Result<Box<dyn TransportStream>>,
+        io::Result<Box<dyn TransportStream>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            server.upgrade(stream, 0, false).await
+        });
+        let stream = TcpStream::connect(address).await.unwrap();
+        let client = client.upgrade(stream, 1, true).await;
+        (client, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn noise_transport_test() {
+        let committee = committee();
+        let (client, server) = connect(
+            NoiseTransport::new(0, committee.clone(), Arc::new(signer(0))),
+            NoiseTransport::new(1, committee, Arc::new(signer(1))),
+        )
+        .await;
+        let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+        // Larger than a single noise message
+        let message: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        client.write_u32(message.len() as u32).await.unwrap();
+        client.write_all(&message).await.unwrap();
+        client.flush().await.unwrap();
+        let len = server.read_u32().await.unwrap();
+        let mut received = vec![0u8; len as usize];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, message);
+
+        server.write_u64(42).await.unwrap();
+        server.flush().await.unwrap();
+        assert_eq!(client.read_u64().await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn noise_transport_rejects_wrong_key_test() {
+        let committee = committee();
+        // Authority 0 signs with a key that does not match the committee
+        let (client, server) = connect(
+            NoiseTransport::new(0, committee.clone(), Arc::new(signer(5))),
+            NoiseTransport::new(1, committee, Arc::new(signer(1))),
+        )
+        .await;
+        assert!(server.is_err());
+        // The client might not notice, since the server can reject it after the exchange
+        drop(client);
+    }
+
+    #[tokio::test]
+    async fn noise_transport_rejects_wrong_authority_test() {
+        let committee = committee();
+        // Authority 1 pretends to be authority 0 using its own key
+        let (_client, server) = connect(
+            NoiseTransport::new(1, committee.clone(), Arc::new(signer(1))),
+            NoiseTransport::new(1, committee, Arc::new(signer(1))),
+        )
+        .await;
+        assert!(server.is_err());
+    }
+}
@@ -15,12 +15,15 @@ pub type Stake = u64;
 pub type PublicKey = crate::crypto::PublicKey;
 
 use crate::committee::{Committee, VoteRangeBuilder};
+use crate::config::BlockLimits;
 use crate::crypto::{AsBytes, CryptoHash, SignatureBytes, Signer};
 use crate::data::Data;
+use crate::epoch_close::CommitteeProposal;
 use crate::threshold_clock::threshold_clock_valid_non_genesis;
 use digest::Digest;
 use eyre::{bail, ensure};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::ops::Range;
@@ -61,6 +64,8 @@ pub enum BaseStatement {
     Vote(TransactionLocator, Vote),
     // For now only accept votes are batched
     VoteRange(TransactionLocatorRange),
+    /// Authority proposes the committee of the next epoch, only in its blocks closing the epoch.
+    NextCommittee(Box<CommitteeProposal>),
 }
 
 impl Hash for BlockReference {
@@ -221,6 +226,14 @@ impl StatementBlock {
         &self.reference
     }
 
+    /// Overrides the block digest, so tests can refer to blocks by author and round with
+    /// `BlockReference::new_test`. Such blocks fail verification.
+    #[cfg(test)]
+    pub fn with_test_digest(mut self, digest: BlockDigest) -> Self {
+        self.reference.digest = digest;
+        self
+    }
+
     pub fn includes(&self) -> &Vec<BlockReference> {
         &self.includes
     }
@@ -301,6 +314,16 @@ impl StatementBlock {
         self.epoch_marker
     }
 
+    /// The committee of the next epoch proposed by the author, if any.
+    pub fn committee_proposal(&self) -> Option<&CommitteeProposal> {
+        self.statements
+            .iter()
+            .find_map(|statement| match statement {
+                BaseStatement::NextCommittee(proposal) => Some(&**proposal),
+                _ => None,
+            })
+    }
+
     pub fn epoch(&self) -> Epoch {
         self.epoch
     }
@@ -346,8 +369,13 @@ impl StatementBlock {
         if let Err(e) = pub_key.verify_block(self) {
             bail!("Block signature verification has failed: {:?}", e);
         }
+        let mut unique_includes = HashSet::with_capacity(self.includes.len());
         for include in &self.includes {
-            // Also check duplicate includes?
+            ensure!(
+                unique_includes.insert(include),
+                "Include {:?} is duplicated",
+                include
+            );
             ensure!(
                 committee.known_authority(include.authority),
                 "Include {:?} references unknown authority",
@@ -360,12 +388,40 @@ impl StatementBlock {
                 round
             );
         }
+        // Shared transactions are opaque and may repeat, but a transaction is voted at most once
+        let mut votes: HashMap<BlockReference, Vec<Range<u64>>> = HashMap::new();
+        let mut proposed = false;
         for statement in &self.statements {
-            // Also check duplicate statements?
             match statement {
                 BaseStatement::Share(_) => {}
-                BaseStatement::Vote(_, _) => {}
-                BaseStatement::VoteRange(range) => range.verify()?,
+                BaseStatement::NextCommittee(proposal) => {
+                    ensure!(
+                        self.epoch_marker && !proposed,
+                        "Next committee is proposed more than once or in an open epoch"
+                    );
+                    proposal.verify(self.epoch)?;
+                    proposed = true;
+                }
+                BaseStatement::Vote(locator, _) => votes
+                    .entry(*locator.block())
+                    .or_default()
+                    .push(locator.offset()..locator.offset() + 1),
+                BaseStatement::VoteRange(range) => {
+                    range.verify()?;
+                    if !range.is_empty() {
+                        votes.entry(*range.block()).or_default().push(range.range());
+                    }
+                }
+            }
+        }
+        for (block, ranges) in votes.iter_mut() {
+            ranges.sort_by_key(|range| range.start);
+            for pair in ranges.windows(2) {
+                ensure!(
+                    pair[0].end <= pair[1].start,
+                    "Transactions of block {:?} are voted more than once",
+                    block
+                );
             }
         }
         ensure!(
@@ -375,6 +431,28 @@ impl StatementBlock {
         Ok(())
     }
 
+    /// Checks the block against the protocol limits, `size` is the size of the serialized block.
+    pub fn verify_limits(&self, limits: &BlockLimits, size: usize) -> eyre::Result<()> {
+        ensure!(
+            self.includes.len() <= limits.max_includes,
+            "Block includes {} blocks, limit is {}",
+            self.includes.len(),
+            limits.max_includes
+        );
+        ensure!(
+            self.statements.len() <= limits.max_statements,
+            "Block has {} statements, limit is {}",
+            self.statements.len(),
+            limits.max_statements
+        );
+        ensure!(
+            size <= limits.max_bytes,
+            "Block size is {size} bytes, limit is {}",
+            limits.max_bytes
+        );
+        Ok(())
+    }
+
     pub fn detailed(&self) -> Detailed {
         Detailed(self)
     }
@@ -701,6 +779,7 @@ impl fmt::Display for BaseStatement {
                 "+{}:{}:{}",
                 range.block, range.offset_start_inclusive, range.offset_end_exclusive
             ),
+            BaseStatement::NextCommittee(proposal) => write!(f, "committee:{}", proposal.epoch()),
         }
     }
 }
@@ -888,6 +967,51 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_verify_duplicates_and_limits() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let block = |includes: Vec<AuthorityIndex>, statements: Vec<BaseStatement>| {
+            let includes = includes
+                .into_iter()
+                .map(|authority| BlockReference::new_test(authority, 0))
+                .collect();
+            StatementBlock::new(0, 1, includes, statements, 0, false, 0, Default::default())
+        };
+        let voted = BlockReference::new_test(1, 1);
+        let vote_range = |range: Range<u64>| {
+            BaseStatement::VoteRange(TransactionLocatorRange::new(voted, range))
+        };
+        let vote =
+            |offset: u64| BaseStatement::Vote(TransactionLocator::new(voted, offset), Vote::Accept);
+
+        let valid = block(
+            vec![0, 1, 2],
+            vec![vote_range(0..2), vote(2), vote_range(3..3)],
+        );
+        valid.verify(&committee).unwrap();
+        assert!(block(vec![0, 1, 1, 2], vec![]).verify(&committee).is_err());
+        assert!(block(vec![0, 1, 2], vec![vote(1), vote_range(0..2)])
+            .verify(&committee)
+            .is_err());
+        assert!(block(vec![0, 1, 2], vec![vote(3), vote(3)])
+            .verify(&committee)
+            .is_err());
+
+        let limits = BlockLimits {
+            max_includes: 3,
+            max_statements: 3,
+            max_bytes: 1000,
+        };
+        valid.verify_limits(&limits, 1000).unwrap();
+        assert!(valid.verify_limits(&limits, 1001).is_err());
+        assert!(block(vec![0, 1, 2, 3], vec![])
+            .verify_limits(&limits, 0)
+            .is_err());
+        assert!(block(vec![0, 1, 2], vec![vote(0); 4])
+            .verify_limits(&limits, 0)
+            .is_err());
+    }
+
     #[test]
     fn test_draw_dag() {
         let d = Dag::draw("A1:[A0, B1]; B2:[B1]").0;
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
 use std::time::Duration;
 use std::{
     env,
@@ -13,31 +15,40 @@ use ::prometheus::Registry;
 use eyre::{eyre, Context, Result};
 use parking_lot::Mutex;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{broadcast, mpsc};
 
-use crate::block_handler::{BlockHandler, SimpleBlockHandler};
-use crate::block_validator::{AcceptAllBlockVerifier, BlockVerifier};
+use crate::block_handler::{BlockHandler, FastPathBlockHandler, TransactionSubmission};
+use crate::block_validator::{AcceptAllBlockVerifier, BlockVerifier, TransactionValidator};
 use crate::commit_observer::{
     CommitObserver, CommitObserverRecoveredState, SimpleCommitObserver, TestCommitObserver,
 };
+use crate::commit_stream::CommitHistory;
 use crate::consensus::linearizer::CommittedSubDag;
 use crate::crypto::Signer;
+use crate::epoch_close::{EpochChange, NextCommittee};
+use crate::equivocation::EquivocationProof;
+use crate::fast_path::FastPathCertificates;
+use crate::finalization_interpreter::FinalizedTransaction;
 use crate::metrics::MetricReporter;
 use crate::metrics::MetricReporterHandle;
 use crate::prometheus::PrometheusServerHandle;
-use crate::runtime::TimeInstant;
+use crate::rpc::{self, RpcServerHandle};
+use crate::runtime::{JoinHandle, TimeInstant};
+use crate::snapshot::Snapshot;
 use crate::state::CoreRecoveredState;
 use crate::transactions_generator::TransactionGeneratorHandle;
+use crate::transport::build_transport;
 use crate::types::TransactionLocator;
-use crate::wal::{walf, WalWriter};
+use crate::wal::WalWriter;
 use crate::{
     block_handler::BenchmarkFastPathBlockHandler,
     committee::Committee,
-    config::{Parameters, PrivateConfig},
+    config::{Identifier, Parameters, PrivateConfig},
     core::Core,
     metrics::Metrics,
     net_sync::NetworkSyncer,
     network::Network,
-    prometheus,
+    prometheus, runtime,
     runtime::JoinError,
     types::AuthorityIndex,
     wal,
@@ -45,13 +56,26 @@ use crate::{
 use crate::{block_store::BlockStore, log::TransactionLog};
 use crate::{core::CoreOptions, transactions_generator::TransactionGenerator};
 
+/// Number of finalized transactions buffered for each subscriber.
+const FINALIZED_TRANSACTIONS_CHANNEL_SIZE: usize = 16 * 1024;
+
 pub(crate) type TransactionTimeMap = Arc<Mutex<HashMap<TransactionLocator, TimeInstant>>>;
 
+/// Builds the block handler and the commit observer of a new epoch from the block handler of the
+/// previous epoch, the committee and the block store of the new epoch.
+type NextEpoch<B, C> = Box<
+    dyn FnMut(B, &Arc<Committee>, &BlockStore, CommitObserverRecoveredState) -> (B, C)
+        + Send
+        + Sync,
+>;
+
 pub struct CommitConsumer {
     // A sender to forward the committed sub dags to
     sender: UnboundedSender<CommittedSubDag>,
     // The last commit height that the consumer has consumed up to. This is useful for crash/recovery
     // so mysticeti can replay the commits from `last_sent_height + 1`.
+    // Commit heights start again from 1 in every epoch, this is the height in the epoch the validator
+    // restarts in.
     last_sent_height: u64,
 }
 
@@ -69,10 +93,36 @@ pub struct Validator<
     B: BlockHandler + 'static = BenchmarkFastPathBlockHandler,
     C: CommitObserver + 'static = TestCommitObserver<TransactionLog>,
 > {
-    network_synchronizer: NetworkSyncer<B, C>,
+    epochs_task: JoinHandle<()>,
+    stop: mpsc::Sender<()>,
+    current_epoch: Arc<Mutex<CurrentEpoch>>,
+    next_committee: NextCommittee,
+    finalized_transactions: broadcast::Sender<FinalizedTransaction>,
     metrics_handle: PrometheusServerHandle,
     reporter_handle: MetricReporterHandle,
     transaction_generator_handle: Option<TransactionGeneratorHandle>,
+    rpc_handle: Option<RpcServerHandle>,
+    _components: PhantomData<fn() -> (B, C)>,
+}
+
+struct CurrentEpoch {
+    committee: Arc<Committee>,
+    block_store: BlockStore,
+}
+
+/// Runs the epochs of a validator one after the other: once an epoch is closed, the network
+/// syncer of the epoch is torn down and a new one is started for the committee of the next epoch.
+struct Epochs<B: BlockHandler + 'static, C: CommitObserver + 'static, V: BlockVerifier + Clone> {
+    authority: AuthorityIndex,
+    parameters: Parameters,
+    config: PrivateConfig,
+    signer: Arc<Signer>,
+    metrics: Arc<Metrics>,
+    block_verifier: V,
+    next_committee: NextCommittee,
+    finalized_transactions: broadcast::Sender<FinalizedTransaction>,
+    current_epoch: Arc<Mutex<CurrentEpoch>>,
+    next_epoch: NextEpoch<B, C>,
 }
 
 impl Validator<BenchmarkFastPathBlockHandler, TestCommitObserver<TransactionLog>> {
@@ -87,8 +137,16 @@ impl Validator<BenchmarkFastPathBlockHandler, TestCommitObserver<TransactionLog>
     ) -> Result<Validator<BenchmarkFastPathBlockHandler, TestCommitObserver<TransactionLog>>> {
         let (metrics, reporter, metrics_handle) =
             Self::init_metrics(authority, committee.clone(), parameters, None)?;
-        let (core_recovered, commit_observer_recovered, wal_writer) =
-            Self::init_storage(authority, committee.clone(), &config, metrics.clone());
+        // The addresses of the authorities change along with the committee in a later epoch
+        let mut parameters = parameters.clone();
+        let (committee, core_recovered, commit_observer_recovered, wal_writer) = Self::init_storage(
+            authority,
+            committee,
+            &mut parameters,
+            &config,
+            metrics.clone(),
+        );
+        let parameters = &parameters;
         let reporter_handle = reporter.start();
 
         // Boot the validator node.
@@ -100,6 +158,7 @@ impl Validator<BenchmarkFastPathBlockHandler, TestCommitObserver<TransactionLog>
             core_recovered.block_store.clone(),
             metrics.clone(),
             transaction_time.clone(),
+            parameters.mempool.within(&parameters.block_limits),
         );
         let tps = env::var("TPS");
         let tps = tps.map(|t| t.parse::<usize>().expect("Failed to parse TPS variable"));
@@ -135,16 +194,41 @@ impl Validator<BenchmarkFastPathBlockHandler, TestCommitObserver<TransactionLog>
         let commit_observer = TestCommitObserver::new(
             core_recovered.block_store.clone(),
             committee.clone(),
-            transaction_time,
+            transaction_time.clone(),
             metrics.clone(),
             committed_transaction_log,
             commit_observer_recovered,
         );
 
+        let storage = config.storage().clone();
+        let observer_metrics = metrics.clone();
+        let next_epoch: NextEpoch<_, _> = Box::new(
+            move |block_handler: BenchmarkFastPathBlockHandler,
+                  committee: &Arc<Committee>,
+                  block_store: &BlockStore,
+                  commit_observer_recovered| {
+                let block_handler =
+                    block_handler.next_epoch(committee.clone(), &storage, block_store.clone());
+                let committed_transaction_log =
+                    TransactionLog::start(storage.committed_transactions_log())
+                        .expect("Failed to open committed transaction log for write");
+                let commit_observer = TestCommitObserver::new(
+                    block_store.clone(),
+                    committee.clone(),
+                    transaction_time.clone(),
+                    observer_metrics.clone(),
+                    committed_transaction_log,
+                    commit_observer_recovered,
+                );
+                (block_handler, commit_observer)
+            },
+        );
+
         Validator::start_internal(
             authority,
             committee,
             parameters,
+            &config,
             signer,
             metrics,
             metrics_handle,
@@ -154,13 +238,17 @@ impl Validator<BenchmarkFastPathBlockHandler, TestCommitObserver<TransactionLog>
             wal_writer,
             block_handler,
             commit_observer,
+            next_epoch,
             AcceptAllBlockVerifier,
         )
         .await
     }
 }
 
-impl Validator<SimpleBlockHandler> {
+impl Validator<FastPathBlockHandler> {
+    /// Starts a validator proposing the transactions submitted on the returned sender. Clients
+    /// wait for the fast path certificates of their transactions on the returned certificates.
+    /// The validator votes on the transactions of other authorities with `transaction_validator`.
     #[allow(clippy::too_many_arguments)]
     pub async fn start_production(
         authority: AuthorityIndex,
@@ -170,30 +258,79 @@ impl Validator<SimpleBlockHandler> {
         registry: Registry,
         signer: Signer,
         consumer: CommitConsumer,
-        block_verifier: impl BlockVerifier,
+        block_verifier: impl BlockVerifier + Clone,
+        transaction_validator: impl TransactionValidator,
     ) -> Result<(
-        Validator<SimpleBlockHandler, SimpleCommitObserver>,
-        tokio::sync::mpsc::Sender<(Vec<u8>, tokio::sync::oneshot::Sender<()>)>,
+        Validator<FastPathBlockHandler, SimpleCommitObserver>,
+        mpsc::Sender<TransactionSubmission>,
+        FastPathCertificates,
     )> {
         let (metrics, reporter, metrics_handle) =
             Self::init_metrics(authority, committee.clone(), parameters, Some(registry))?;
-        let (core_recovered, commit_observer_recovered, wal_writer) =
-            Self::init_storage(authority, committee.clone(), &config, metrics.clone());
+        // The addresses of the authorities change along with the committee in a later epoch
+        let mut parameters = parameters.clone();
+        let (committee, core_recovered, commit_observer_recovered, wal_writer) = Self::init_storage(
+            authority,
+            committee,
+            &mut parameters,
+            &config,
+            metrics.clone(),
+        );
+        let parameters = &parameters;
         let reporter_handle = reporter.start();
-        let (block_handler, tx_sender) = SimpleBlockHandler::new();
+        let (block_handler, tx_sender, certificates) = FastPathBlockHandler::new(
+            committee.clone(),
+            core_recovered.block_store.clone(),
+            transaction_validator,
+            parameters.mempool.within(&parameters.block_limits),
+        );
+        let commit_history = CommitHistory::new(
+            committee.epoch(),
+            core_recovered.block_store.clone(),
+            &commit_observer_recovered.sub_dags,
+        );
 
         let commit_observer = SimpleCommitObserver::new(
             core_recovered.block_store.clone(),
-            consumer.sender,
+            consumer.sender.clone(),
             consumer.last_sent_height,
             commit_observer_recovered,
             metrics.clone(),
+        )
+        .with_commit_history(commit_history.clone());
+
+        // The block handler keeps the submitted transactions and the certificates across epochs
+        let observer_metrics = metrics.clone();
+        let epoch_commit_history = commit_history.clone();
+        let next_epoch: NextEpoch<_, _> = Box::new(
+            move |block_handler: FastPathBlockHandler,
+                  committee: &Arc<Committee>,
+                  block_store: &BlockStore,
+                  commit_observer_recovered: CommitObserverRecoveredState| {
+                epoch_commit_history.start_epoch(
+                    committee.epoch(),
+                    block_store.clone(),
+                    &commit_observer_recovered.sub_dags,
+                );
+                let commit_observer = SimpleCommitObserver::new(
+                    block_store.clone(),
+                    consumer.sender.clone(),
+                    0,
+                    commit_observer_recovered,
+                    observer_metrics.clone(),
+                )
+                .with_commit_history(epoch_commit_history.clone());
+                let block_handler =
+                    block_handler.next_epoch(committee.clone(), block_store.clone());
+                (block_handler, commit_observer)
+            },
         );
 
-        let validator = Validator::start_internal(
+        let mut validator = Validator::start_internal(
             authority,
             committee,
             parameters,
+            &config,
             signer,
             metrics,
             metrics_handle,
@@ -203,11 +340,29 @@ impl Validator<SimpleBlockHandler> {
             wal_writer,
             block_handler,
             commit_observer,
+            next_epoch,
             block_verifier,
         )
         .await?;
 
-        Ok((validator, tx_sender))
+        // Clients submit transactions and subscribe to commits over the network only if the
+        // validator has an rpc address.
+        if let Some(rpc_address) = parameters.rpc_address(authority) {
+            let mut binding_rpc_address = rpc_address;
+            binding_rpc_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+            validator.rpc_handle = Some(rpc::start_rpc_server(
+                binding_rpc_address,
+                tx_sender.clone(),
+                commit_history,
+                certificates.clone(),
+                parameters
+                    .enable_finality_tracking
+                    .then(|| validator.finalized_transactions.clone()),
+            ));
+            tracing::info!("Validator {authority} serving rpc on {rpc_address}");
+        }
+
+        Ok((validator, tx_sender, certificates))
     }
 }
 
@@ -217,6 +372,7 @@ impl<B: BlockHandler + 'static, C: CommitObserver + 'static> Validator<B, C> {
         authority: AuthorityIndex,
         committee: Arc<Committee>,
         parameters: &Parameters,
+        config: &PrivateConfig,
         signer: Signer,
         metrics: Arc<Metrics>,
         metrics_handle: PrometheusServerHandle,
@@ -226,55 +382,51 @@ impl<B: BlockHandler + 'static, C: CommitObserver + 'static> Validator<B, C> {
         wal_writer: WalWriter,
         block_handler: B,
         commit_observer: C,
-        block_verifier: impl BlockVerifier,
+        next_epoch: NextEpoch<B, C>,
+        block_verifier: impl BlockVerifier + Clone,
     ) -> Result<Self> {
-        let network_address = parameters
-            .network_address(authority)
-            .ok_or(eyre!("No network address for authority {authority}"))
-            .wrap_err("Unknown authority")?;
-        let mut binding_network_address = network_address;
-        binding_network_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
-        tracing::info!("Binding to local address {}", binding_network_address);
-
-        let core = Core::open(
-            block_handler,
+        let current_epoch = Arc::new(Mutex::new(CurrentEpoch {
+            committee: committee.clone(),
+            block_store: core_recovered.block_store.clone(),
+        }));
+        let (finalized_transactions, _) = broadcast::channel(FINALIZED_TRANSACTIONS_CHANNEL_SIZE);
+        let epochs = Epochs {
             authority,
-            committee.clone(),
-            parameters,
-            metrics.clone(),
-            core_recovered,
-            wal_writer,
-            CoreOptions::default(),
-            signer,
-        );
-
-        let network = Network::load(
-            parameters,
-            authority,
-            binding_network_address,
-            metrics.clone(),
-        )
-        .await;
-        let network_synchronizer = NetworkSyncer::start(
-            network,
-            core,
-            parameters.wave_length(),
-            commit_observer,
-            parameters.shutdown_grace_period(),
-            block_verifier,
+            parameters: parameters.clone(),
+            config: config.clone(),
+            signer: Arc::new(signer),
             metrics,
-            parameters.leader_timeout,
-            parameters.synchronizer_parameters.clone(),
-            parameters.enable_cleanup,
-        );
-
-        tracing::info!("Validator {authority} listening on {network_address}");
+            block_verifier,
+            next_committee: NextCommittee::default(),
+            finalized_transactions: finalized_transactions.clone(),
+            current_epoch: current_epoch.clone(),
+            next_epoch,
+        };
+        let next_committee = epochs.next_committee.clone();
+        let network_synchronizer = epochs
+            .start_epoch(
+                committee,
+                core_recovered,
+                wal_writer,
+                block_handler,
+                commit_observer,
+            )
+            .await?;
+        let (stop, stop_receiver) = mpsc::channel(1);
+        let epochs_task =
+            runtime::Handle::current().spawn(epochs.run(network_synchronizer, stop_receiver));
 
         Ok(Self {
-            network_synchronizer,
+            epochs_task,
+            stop,
+            current_epoch,
+            next_committee,
+            finalized_transactions,
             metrics_handle,
             reporter_handle,
             transaction_generator_handle,
+            rpc_handle: None,
+            _components: PhantomData,
         })
     }
 
@@ -284,14 +436,44 @@ impl<B: BlockHandler + 'static, C: CommitObserver + 'static> Validator<B, C> {
         Result<(), JoinError>,
         Result<Result<(), hyper::Error>, JoinError>,
     ) {
-        tokio::join!(
-            self.network_synchronizer.await_completion(),
-            self.metrics_handle.handle
-        )
+        tokio::join!(self.epochs_task, self.metrics_handle.handle)
+    }
+
+    /// Returns the equivocation proofs detected by this validator in the current epoch,
+    /// ordered by (author, round).
+    pub fn equivocations(&self) -> Vec<EquivocationProof> {
+        self.current_epoch.lock().block_store.equivocations()
+    }
+
+    /// The committee of the epoch the validator is currently in.
+    pub fn committee(&self) -> Arc<Committee> {
+        self.current_epoch.lock().committee.clone()
+    }
+
+    /// Sets the committee of the next epoch and the addresses of its authorities. The validator
+    /// proposes it when it starts to close the current epoch, the next epoch starts with the
+    /// committee proposed by a quorum. Fails once the validator started to close the epoch.
+    pub fn set_next_committee(
+        &self,
+        committee: &Committee,
+        identifiers: Vec<Identifier>,
+    ) -> Result<()> {
+        self.next_committee.set(committee, identifiers)
+    }
+
+    /// Subscribes to the transactions that become final on the fast path in any epoch, when
+    /// finality tracking is enabled. A subscriber that falls behind by more than
+    /// FINALIZED_TRANSACTIONS_CHANNEL_SIZE transactions misses the oldest ones.
+    pub fn subscribe_finalized_transactions(&self) -> broadcast::Receiver<FinalizedTransaction> {
+        self.finalized_transactions.subscribe()
     }
 
     pub async fn stop(self) {
-        self.network_synchronizer.shutdown().await;
+        if let Some(handle) = self.rpc_handle {
+            handle.shutdown().await;
+        }
+        self.stop.send(()).await.ok();
+        self.epochs_task.await.ok();
         self.reporter_handle.shutdown().await;
         self.metrics_handle.shutdown().await;
         if let Some(handle) = self.transaction_generator_handle {
@@ -331,25 +513,250 @@ impl<B: BlockHandler + 'static, C: CommitObserver + 'static> Validator<B, C> {
         Ok((metrics, reporter, metrics_handle))
     }
 
+    /// Opens the storage of the latest epoch the validator has started. Epochs that were closed
+    /// and followed by an epoch with local storage are skipped.
     fn init_storage(
         authority: AuthorityIndex,
-        committee: Arc<Committee>,
+        mut committee: Arc<Committee>,
+        parameters: &mut Parameters,
         config: &PrivateConfig,
         metrics: Arc<Metrics>,
-    ) -> (CoreRecoveredState, CommitObserverRecoveredState, WalWriter) {
-        // Open the block store.
-        let wal_file =
-            wal::open_file_for_wal(config.storage().wal()).expect("Failed to open wal file");
-        let (wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
-        let (core_recovered, commit_observer_recovered) = BlockStore::open(
+    ) -> (
+        Arc<Committee>,
+        CoreRecoveredState,
+        CommitObserverRecoveredState,
+        WalWriter,
+    ) {
+        loop {
+            let (core_recovered, commit_observer_recovered, wal_writer) =
+                open_epoch_storage(authority, &committee, config, metrics.clone());
+            let epoch_change = core_recovered.epoch_change.as_ref().map(|epoch_change| {
+                EpochChange::from_bytes(epoch_change).expect("Failed to deserialize epoch change")
+            });
+            match epoch_change {
+                Some(epoch_change)
+                    if config
+                        .storage()
+                        .epoch_wal(epoch_change.next_committee.epoch())
+                        .exists() =>
+                {
+                    tracing::info!(
+                        "Epoch {} is closed, recovering epoch {}",
+                        committee.epoch(),
+                        epoch_change.next_committee.epoch()
+                    );
+                    committee = epoch_change.next_committee;
+                    parameters.identifiers = epoch_change.identifiers;
+                }
+                _ => {
+                    return (
+                        committee,
+                        core_recovered,
+                        commit_observer_recovered,
+                        wal_writer,
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl<B: BlockHandler + 'static, C: CommitObserver + 'static, V: BlockVerifier + Clone>
+    Epochs<B, C, V>
+{
+    async fn run(
+        mut self,
+        mut network_synchronizer: NetworkSyncer<B, C>,
+        mut stop: mpsc::Receiver<()>,
+    ) {
+        loop {
+            let stopped = tokio::select! {
+                _ = network_synchronizer.await_epoch_close() => false,
+                _ = stop.recv() => true,
+            };
+            let syncer = network_synchronizer.shutdown().await;
+            if stopped {
+                return;
+            }
+            let core = syncer.into_core();
+            let Some(epoch_change) = core.epoch_change().cloned() else {
+                tracing::info!(
+                    "Epoch {} is closed and there is no next epoch",
+                    core.committee().epoch()
+                );
+                return;
+            };
+            let EpochChange {
+                next_committee: committee,
+                identifiers,
+                ..
+            } = epoch_change;
+            if !committee.known_authority(self.authority) {
+                tracing::info!(
+                    "Validator {} is not part of the committee of epoch {}",
+                    self.authority,
+                    committee.epoch()
+                );
+                return;
+            }
+            tracing::info!(
+                "Starting epoch {} with {} authorities",
+                committee.epoch(),
+                committee.len()
+            );
+            self.parameters.identifiers = identifiers;
+            let block_handler = core.into_block_handler();
+            let (core_recovered, commit_observer_recovered, wal_writer) = open_epoch_storage(
+                self.authority,
+                &committee,
+                &self.config,
+                self.metrics.clone(),
+            );
+            let (block_handler, commit_observer) = (self.next_epoch)(
+                block_handler,
+                &committee,
+                &core_recovered.block_store,
+                commit_observer_recovered,
+            );
+            *self.current_epoch.lock() = CurrentEpoch {
+                committee: committee.clone(),
+                block_store: core_recovered.block_store.clone(),
+            };
+            network_synchronizer = match self
+                .start_epoch(
+                    committee,
+                    core_recovered,
+                    wal_writer,
+                    block_handler,
+                    commit_observer,
+                )
+                .await
+            {
+                Ok(network_synchronizer) => network_synchronizer,
+                Err(e) => {
+                    tracing::error!("Failed to start the next epoch: {e:?}");
+                    return;
+                }
+            };
+        }
+    }
+
+    async fn start_epoch(
+        &self,
+        committee: Arc<Committee>,
+        core_recovered: CoreRecoveredState,
+        wal_writer: WalWriter,
+        block_handler: B,
+        commit_observer: C,
+    ) -> Result<NetworkSyncer<B, C>> {
+        let authority = self.authority;
+        let parameters = &self.parameters;
+        parameters.block_limits.validate(&committee)?;
+        let network_address = parameters
+            .network_address(authority)
+            .ok_or(eyre!("No network address for authority {authority}"))
+            .wrap_err("Unknown authority")?;
+        let mut binding_network_address = network_address;
+        binding_network_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        tracing::info!("Binding to local address {}", binding_network_address);
+
+        let transport = build_transport(
+            parameters.network_transport,
             authority,
-            Arc::new(wal_reader),
-            &wal_writer,
-            metrics.clone(),
-            &committee,
+            committee.clone(),
+            self.signer.clone(),
         );
-        (core_recovered, commit_observer_recovered, wal_writer)
+        let core = Core::open(
+            block_handler,
+            authority,
+            committee.clone(),
+            parameters,
+            self.metrics.clone(),
+            core_recovered,
+            wal_writer,
+            CoreOptions::default(),
+            self.signer.clone(),
+        )
+        .with_snapshot_path(self.config.storage().snapshot())
+        .with_next_committee(self.next_committee.clone())
+        .with_finalized_transactions(self.finalized_transactions.clone());
+
+        let network = Network::load(
+            parameters,
+            authority,
+            binding_network_address,
+            self.metrics.clone(),
+            transport,
+        )
+        .await;
+        let network_synchronizer = NetworkSyncer::start(
+            network,
+            core,
+            parameters.wave_length(),
+            commit_observer,
+            parameters.shutdown_grace_period(),
+            self.block_verifier.clone(),
+            self.metrics.clone(),
+            parameters.leader_timeout,
+            parameters.leader_timeout_parameters.clone(),
+            parameters.synchronizer_parameters.clone(),
+            parameters.peer_limits.clone(),
+            parameters.enable_cleanup,
+        );
+
+        tracing::info!(
+            "Validator {authority} listening on {network_address} in epoch {}",
+            committee.epoch()
+        );
+        Ok(network_synchronizer)
+    }
+}
+
+fn open_epoch_storage(
+    authority: AuthorityIndex,
+    committee: &Arc<Committee>,
+    config: &PrivateConfig,
+    metrics: Arc<Metrics>,
+) -> (CoreRecoveredState, CommitObserverRecoveredState, WalWriter) {
+    // Open the block store.
+    let (wal_writer, wal_reader) =
+        wal::open_wal(config.storage().epoch_wal(committee.epoch())).expect("Failed to open wal");
+    let (core_recovered, commit_observer_recovered) = BlockStore::open(
+        authority,
+        Arc::new(wal_reader),
+        &wal_writer,
+        metrics,
+        committee,
+    );
+    (core_recovered, commit_observer_recovered, wal_writer)
+}
+
+/// Seeds the empty storage of a validator with a snapshot produced by another validator
+/// (or by this one before it lost its storage), so that the next start recovers from the
+/// snapshot commit instead of syncing from genesis. Does nothing if the validator already
+/// has local state.
+pub fn bootstrap_from_snapshot(
+    authority: AuthorityIndex,
+    committee: &Committee,
+    config: &PrivateConfig,
+    snapshot_path: &Path,
+) -> Result<()> {
+    let snapshot = Snapshot::load(snapshot_path)
+        .wrap_err(format!("Failed to load snapshot {snapshot_path:?}"))?;
+    snapshot.verify(committee).wrap_err("Invalid snapshot")?;
+    let (mut wal_writer, wal_reader) =
+        wal::open_wal(config.storage().epoch_wal(committee.epoch()))?;
+    if wal_reader.iter_until(&wal_writer).next().is_some() {
+        tracing::info!("Validator {authority} has local state, not bootstrapping from snapshot");
+        return Ok(());
     }
+    snapshot.bootstrap(authority, &mut wal_writer)?;
+    tracing::info!(
+        "Bootstrapped validator {authority} from snapshot of validator {} at commit height {}",
+        snapshot.author(),
+        snapshot.height()
+    );
+    Ok(())
 }
 
 #[cfg(test)]
@@ -367,7 +774,7 @@ mod smoke_tests {
     use crate::crypto::dummy_signer;
     use crate::runtime::sleep;
     use crate::{
-        committee::Committee,
+        committee::{Authority, Committee},
         config::{Parameters, PrivateConfig},
         prometheus,
         types::AuthorityIndex,
@@ -433,6 +840,23 @@ mod smoke_tests {
         }
     }
 
+    /// Ensure a validator refuses to start if its blocks cannot include the whole committee.
+    #[tokio::test]
+    async fn validator_rejects_small_block_limits() {
+        let committee_size = 4;
+        let ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST); committee_size];
+
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let mut parameters = Parameters::new_for_benchmarks(ips).with_port_offset(500);
+        parameters.block_limits.max_includes = committee_size - 1;
+
+        let tempdir = TempDir::new("validator_rejects_small_block_limits").unwrap();
+        let private = PrivateConfig::new_for_benchmarks(tempdir.as_ref(), 0);
+        let result =
+            Validator::start_benchmarking(0, committee, &parameters, private, dummy_signer()).await;
+        assert!(result.is_err());
+    }
+
     /// Ensure validators can sync missing blocks
     #[tokio::test]
     async fn validator_sync() {
@@ -593,4 +1017,71 @@ mod smoke_tests {
             validators.push(validator);
         }
     }
+
+    /// Ensure validators move to the committee of the next epoch once the epoch is closed.
+    #[tokio::test]
+    async fn validator_reconfiguration() {
+        let committee_size = 4;
+        let ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST); committee_size];
+
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let mut parameters = Parameters::new_for_benchmarks(ips.clone()).with_port_offset(400);
+        parameters.rounds_in_epoch = 20;
+        parameters.shutdown_grace_period = Duration::from_millis(500);
+        // Authority 3 leaves the committee in the next epoch, the others listen on new addresses
+        let authorities = (0..3).map(|_| Authority::test_from_stake(1)).collect();
+        let next_committee = Committee::new(authorities, 1);
+        let next_identifiers = Parameters::new_for_benchmarks(ips[..3].to_vec())
+            .with_port_offset(450)
+            .identifiers;
+
+        let tempdir = TempDir::new("validator_reconfiguration").unwrap();
+        let mut validators = Vec::new();
+        for i in 0..committee_size {
+            let authority = i as AuthorityIndex;
+            let private = PrivateConfig::new_for_benchmarks(tempdir.as_ref(), authority);
+
+            let validator = Validator::start_benchmarking(
+                authority,
+                committee.clone(),
+                &parameters,
+                private,
+                dummy_signer(),
+            )
+            .await
+            .unwrap();
+            validator
+                .set_next_committee(&next_committee, next_identifiers.clone())
+                .unwrap();
+            validators.push(validator);
+        }
+
+        // The validators of the next committee make progress in the next epoch
+        let progress = async {
+            loop {
+                time::sleep(Duration::from_millis(100)).await;
+                let progressed = validators[..3].iter().all(|validator| {
+                    let current_epoch = validator.current_epoch.lock();
+                    current_epoch.committee.epoch() == 1
+                        && current_epoch.block_store.highest_round() > 10
+                });
+                if progressed {
+                    break;
+                }
+            }
+        };
+        let timeout = Parameters::DEFAULT_LEADER_TIMEOUT * 30;
+        tokio::select! {
+            _ = progress => (),
+            _ = time::sleep(timeout) => panic!("Failed to start the next epoch within a few timeouts"),
+        }
+
+        // The validator that left the committee stops after the epoch is closed
+        let left = validators.pop().unwrap();
+        assert_eq!(left.committee().epoch(), 0);
+        tokio::time::timeout(timeout, left.epochs_task)
+            .await
+            .expect("Validator not in the next committee must stop")
+            .unwrap();
+    }
 }
@@ -3,27 +3,43 @@
 
 use memmap2::{Mmap, MmapOptions};
 use minibytes::Bytes;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
 use std::io::{IoSlice, Seek, SeekFrom, Write};
-use std::path::Path;
-use std::{fmt, io};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fmt, fs, io};
 
 pub struct WalWriter {
     file: File,
     pos: u64,
+    // Start position of the segment that `file` belongs to
+    segment_start: u64,
+    segments: Arc<Segments>,
 }
 
 pub struct WalReader {
-    file: File,
+    segments: Arc<Segments>,
     maps: Mutex<BTreeMap<u64, Bytes>>,
 }
 
 pub struct WalSyncer {
-    file: File,
+    segments: Arc<Segments>,
+}
+
+/// Files backing the wal. A wal opened from a single file has exactly one segment starting at 0 that
+/// is never rotated. A wal opened from a directory is split into files of SEGMENT_SIZE bytes,
+/// named after the position of their first entry, so that old segments can be dropped (see WalWriter::compact)
+/// without changing positions of the entries in the remaining segments.
+struct Segments {
+    dir: Option<PathBuf>,
+    files: RwLock<BTreeMap<u64, File>>,
+    // Segments removed from disk by the last compaction. They are kept open until the next compaction,
+    // so that reads racing with the compaction still succeed.
+    removed: Mutex<BTreeMap<u64, File>>,
 }
 
 #[derive(
@@ -49,6 +65,48 @@ pub fn open_file_for_wal(p: impl AsRef<Path>) -> io::Result<File> {
         .open(p)
 }
 
+/// Opens a segmented wal in the given directory, creating it if needed.
+/// For backward compatibility, if the path points to an existing file it is opened as a single file wal.
+pub fn open_wal(path: impl AsRef<Path>) -> io::Result<(WalWriter, WalReader)> {
+    let path = path.as_ref();
+    if path.is_file() {
+        return walf(open_file_for_wal(path)?);
+    }
+    fs::create_dir_all(path)?;
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(start) = name
+            .to_str()
+            .and_then(|name| name.strip_suffix(SEGMENT_SUFFIX))
+            .and_then(|start| start.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        if start % SEGMENT_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Wal segment {name:?} is not aligned to the segment size"),
+            ));
+        }
+        files.insert(start, open_file_for_wal(entry.path())?);
+    }
+    if files.is_empty() {
+        files.insert(0, open_file_for_wal(segment_path(path, 0))?);
+    }
+    let (segment_start, file) = files.last_key_value().expect("Wal has segments");
+    let segment_start = *segment_start;
+    let mut file = file.try_clone()?;
+    let pos = segment_start + file.seek(SeekFrom::End(0))?;
+    let segments = Arc::new(Segments {
+        dir: Some(path.to_path_buf()),
+        files: RwLock::new(files),
+        removed: Default::default(),
+    });
+    Ok(make_writer_reader(file, pos, segment_start, segments))
+}
+
 /// Creates wal reader and wal writer on the file.
 /// WalWriter methods generally take &mut references so normally only one thread can access WalWriter at a time.
 /// WalReader methods take & reference, you can wrap WalReader in Arc and safely share it across different threads.
@@ -82,15 +140,36 @@ pub fn wal(path: impl AsRef<Path>) -> io::Result<(WalWriter, WalReader)> {
 }
 
 fn make_wal(file: File) -> io::Result<(WalWriter, WalReader)> {
+    let pos = file.metadata()?.len();
+    let segments = Arc::new(Segments {
+        dir: None,
+        files: RwLock::new([(0, file.try_clone()?)].into()),
+        removed: Default::default(),
+    });
+    Ok(make_writer_reader(file, pos, 0, segments))
+}
+
+fn make_writer_reader(
+    file: File,
+    pos: u64,
+    segment_start: u64,
+    segments: Arc<Segments>,
+) -> (WalWriter, WalReader) {
     let reader = WalReader {
-        file: file.try_clone().expect("Failed to clone fd"),
+        segments: segments.clone(),
         maps: Default::default(),
     };
     let writer = WalWriter {
-        pos: file.metadata()?.len(),
         file,
+        pos,
+        segment_start,
+        segments,
     };
-    Ok((writer, reader))
+    (writer, reader)
+}
+
+fn segment_path(dir: &Path, start: u64) -> PathBuf {
+    dir.join(format!("{start:020}{SEGMENT_SUFFIX}"))
 }
 
 #[cfg(not(test))]
@@ -104,6 +183,10 @@ const MAP_MASK: u64 = !0xffff;
 const ZERO_MAP: [u8; MAP_SIZE as usize] = [0u8; MAP_SIZE as usize];
 const _: () = assert_constants();
 
+// Segments are always a multiple of MAP_SIZE, so that a mapping never spans two segment files
+const SEGMENT_SIZE: u64 = MAP_SIZE * 4;
+const SEGMENT_SUFFIX: &str = ".wal";
+
 pub const MAX_ENTRY_SIZE: usize = (MAP_SIZE - HEADER_LEN_BYTES) as usize;
 
 // todo - we still allocate 64 bits for crc in wal header, reconsider it
@@ -116,6 +199,9 @@ const fn assert_constants() {
     if u64::MAX - MAP_MASK != MAP_SIZE - 1 {
         panic!("MAP_MASK and MAP_SIZE do not match");
     }
+    if SEGMENT_SIZE % MAP_SIZE != 0 {
+        panic!("SEGMENT_SIZE must be a multiple of MAP_SIZE");
+    }
     // Checks mask is in form 1...10....0
     check_zeroes(MAP_MASK);
 }
@@ -164,12 +250,20 @@ impl WalWriter {
         if offset(self.pos) != offset(self.pos + len - 1) {
             let extra_len = offset(self.pos + len - 1) - self.pos;
             let extra = &ZERO_MAP[0..(extra_len as usize)];
-            buffs.push(IoSlice::new(extra));
-            written_expected += extra.len();
+            if self.segments.segment_start(self.pos + extra_len) != self.segment_start {
+                // Pad the segment to its full size, so that mappings never go past the end of file
+                self.file.write_all(extra)?;
+            } else {
+                buffs.push(IoSlice::new(extra));
+                written_expected += extra.len();
+            }
             self.pos += extra_len;
             debug_assert_eq!(offset(self.pos), self.pos);
             debug_assert_eq!(offset(self.pos), offset(self.pos + len - 1));
         }
+        if self.segments.segment_start(self.pos) != self.segment_start {
+            self.rotate()?;
+        }
         let mut crc = crc32fast::Hasher::new();
         for slice in v {
             crc.update(slice);
@@ -197,14 +291,150 @@ impl WalWriter {
     /// In mysticeti specifically this allows to have an independent syncer thread that
     /// does not share locks with consensus thread.
     pub fn syncer(&self) -> io::Result<WalSyncer> {
-        let file = self.file.try_clone()?;
-        Ok(WalSyncer { file })
+        Ok(WalSyncer {
+            segments: self.segments.clone(),
+        })
+    }
+
+    /// Drops all segments that only contain entries before the given position, returns the number of dropped segments.
+    /// Positions of the entries in the remaining segments stay valid. The segment currently written to is never dropped.
+    /// Does nothing for a single file wal.
+    pub fn compact(&mut self, before: WalPosition) -> io::Result<usize> {
+        let Some(dir) = &self.segments.dir else {
+            return Ok(0);
+        };
+        let mut files = self.segments.files.write();
+        let dropped: Vec<_> = self.droppable(&files, before).collect();
+        let mut removed = self.segments.removed.lock();
+        // Segments removed by the previous compaction can be closed now
+        removed.clear();
+        for start in &dropped {
+            fs::remove_file(segment_path(dir, *start))?;
+            let file = files.remove(start).expect("Segment exists");
+            removed.insert(*start, file);
+        }
+        if !dropped.is_empty() {
+            tracing::debug!("Dropped {} wal segments before {before}", dropped.len());
+        }
+        Ok(dropped.len())
+    }
+
+    /// Number of segments that WalWriter::compact would drop for the given position.
+    pub fn segments_before(&self, before: WalPosition) -> usize {
+        if self.segments.dir.is_none() {
+            return 0;
+        }
+        self.droppable(&self.segments.files.read(), before).count()
+    }
+
+    fn droppable<'a>(
+        &'a self,
+        files: &'a BTreeMap<u64, File>,
+        before: WalPosition,
+    ) -> impl Iterator<Item = u64> + 'a {
+        files.keys().copied().filter(move |start| {
+            *start != self.segment_start && *start + SEGMENT_SIZE <= before.start
+        })
+    }
+
+    pub fn segments(&self) -> usize {
+        self.segments.files.read().len()
+    }
+
+    /// Discards all entries starting at the given position, subsequent writes continue from there.
+    /// Must not be used while there are outstanding buffers returned by WalReader,
+    /// as they may point to the truncated part of the wal.
+    pub fn truncate(&mut self, position: WalPosition) -> io::Result<()> {
+        let start = self.segments.segment_start(position.start);
+        let mut files = self.segments.files.write();
+        let Some(file) = files.get(&start) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Wal segment for position {position} does not exist"),
+            ));
+        };
+        file.set_len(position.start - start)?;
+        file.sync_data()?;
+        let mut file = file.try_clone()?;
+        file.seek(SeekFrom::End(0))?;
+        if let Some(dir) = &self.segments.dir {
+            let after: Vec<_> = files.range(start + 1..).map(|(start, _)| *start).collect();
+            for start in after {
+                fs::remove_file(segment_path(dir, start))?;
+                files.remove(&start);
+            }
+        }
+        self.file = file;
+        self.segment_start = start;
+        self.pos = position.start;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let dir = self
+            .segments
+            .dir
+            .as_ref()
+            .expect("Only segmented wal can rotate");
+        // Entries of the previous segment must be durable once the syncer moves to the new one
+        self.file.sync_data()?;
+        let start = self.segments.segment_start(self.pos);
+        let file = open_file_for_wal(segment_path(dir, start))?;
+        self.file = file.try_clone()?;
+        self.segment_start = start;
+        self.segments.files.write().insert(start, file);
+        Ok(())
     }
 }
 
 impl WalSyncer {
     pub fn sync(&self) -> io::Result<()> {
-        self.file.sync_data()
+        let files = self.segments.files.read();
+        let (_, file) = files.last_key_value().expect("Wal has segments");
+        file.sync_data()
+    }
+}
+
+impl Segments {
+    fn segment_start(&self, pos: u64) -> u64 {
+        if self.dir.is_some() {
+            pos - pos % SEGMENT_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn first_start(&self) -> u64 {
+        *self
+            .files
+            .read()
+            .first_key_value()
+            .expect("Wal has segments")
+            .0
+    }
+
+    fn map(&self, offset: u64) -> io::Result<Mmap> {
+        let start = self.segment_start(offset);
+        let files = self.files.read();
+        let removed;
+        let file = match files.get(&start) {
+            Some(file) => file,
+            None => {
+                removed = self.removed.lock();
+                removed.get(&start).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Wal segment for offset {offset} was compacted"),
+                    )
+                })?
+            }
+        };
+        unsafe {
+            MmapOptions::new()
+                .offset(offset - start)
+                .len(MAP_SIZE as usize)
+                .map(file)
+        }
     }
 }
 
@@ -241,19 +471,30 @@ impl WalReader {
             if crc == 0 {
                 return Ok(None);
             }
-            panic!(
-                "Non-zero crc at len 0, crc: {crc}, position:{}",
-                position.start
-            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Non-zero crc at len 0, crc: {crc}, position:{}",
+                    position.start
+                ),
+            ));
+        }
+        if len < HEADER_LEN_BYTES || buf_offset + len as usize > bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid entry length {len} at position {}", position.start),
+            ));
         }
         let bytes = bytes.slice(buf_offset + HEADER_LEN_BYTES_USIZE..buf_offset + (len as usize));
         let actual_crc = crc32fast::hash(bytes.as_ref()) as u64;
         if actual_crc != crc {
-            // todo - return error
-            panic!(
-                "Crc mismatch, expected {}, found {} at position {}:{}",
-                crc, actual_crc, position.start, len
-            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Crc mismatch, expected {}, found {} at position {}:{}",
+                    crc, actual_crc, position.start, len
+                ),
+            ));
         }
         Ok(Some((tag, bytes)))
     }
@@ -268,10 +509,14 @@ impl WalReader {
 
     // Iter all entries up to writer position at the time iter_until(...) is called
     pub fn iter_until(&self, w: &WalWriter) -> WalIterator {
+        let start = WalPosition {
+            start: self.segments.first_start(),
+        };
         WalIterator {
             wal_reader: self,
-            position: Some(WalPosition { start: 0 }),
+            position: Some(start),
             end_position: w.pos,
+            valid_until: start,
         }
     }
 
@@ -279,12 +524,7 @@ impl WalReader {
         let mut maps = self.maps.lock();
         let bytes = match maps.entry(offset) {
             Entry::Vacant(va) => {
-                let mmap = unsafe {
-                    MmapOptions::new()
-                        .offset(offset)
-                        .len(MAP_SIZE as usize)
-                        .map(&self.file)?
-                };
+                let mmap = self.segments.map(offset)?;
                 va.insert(mmap.into())
             }
             Entry::Occupied(oc) => oc.into_mut(),
@@ -309,39 +549,59 @@ pub struct WalIterator<'a> {
     wal_reader: &'a WalReader,
     position: Option<WalPosition>,
     end_position: u64,
+    // End of the last entry that was successfully read
+    valid_until: WalPosition,
 }
 
 impl<'a> Iterator for WalIterator<'a> {
     type Item = (WalPosition, (Tag, Bytes));
 
     fn next(&mut self) -> Option<Self::Item> {
-        let position = self.position.take()?;
+        self.try_next().expect("Failed to read wal")
+    }
+}
+
+impl<'a> WalIterator<'a> {
+    /// Same as Iterator::next, but returns an error instead of panicking when the wal is corrupted.
+    /// The iterator stops after the first error.
+    pub fn try_next(&mut self) -> io::Result<Option<(WalPosition, (Tag, Bytes))>> {
+        let Some(position) = self.position.take() else {
+            return Ok(None);
+        };
         tracing::trace!("Iter read {}", position.start);
         // Either read from current position, or try next mapping, but only once
-        if let Some(item) = self.try_position(position) {
-            return Some(item);
+        if let Some(item) = self.try_position(position)? {
+            return Ok(Some(item));
         }
         if position.first_in_map() {
-            return None;
+            return Ok(None);
         }
         tracing::trace!("Iter fallback read {}", position.next_start_offset().start);
         // todo - need to consider crash recovery here
         // Either need to reset writer position, or read all offsets until writer position
         self.try_position(position.next_start_offset())
     }
-}
 
-impl<'a> WalIterator<'a> {
-    fn try_position(&mut self, position: WalPosition) -> Option<(WalPosition, (Tag, Bytes))> {
+    /// Position right after the last entry returned by the iterator.
+    /// When the iterator stopped at a corrupted entry, this is where the valid part of the wal ends.
+    pub fn valid_until(&self) -> WalPosition {
+        self.valid_until
+    }
+
+    fn try_position(
+        &mut self,
+        position: WalPosition,
+    ) -> io::Result<Option<(WalPosition, (Tag, Bytes))>> {
         if position.start >= self.end_position {
-            return None;
+            return Ok(None);
         }
-        let (tag, data) = self
-            .wal_reader
-            .try_read(position)
-            .expect("Failed to read wal")?;
-        self.position = Some(position.add(data.len() as u64 + HEADER_LEN_BYTES));
-        Some((position, (tag, data)))
+        let Some((tag, data)) = self.wal_reader.try_read(position)? else {
+            return Ok(None);
+        };
+        let next = position.add(data.len() as u64 + HEADER_LEN_BYTES);
+        self.position = Some(next);
+        self.valid_until = next;
+        Ok(Some((position, (tag, data))))
     }
 }
 
@@ -470,6 +730,97 @@ mod tests {
         assert_eq!(bytes2.as_ref(), &bytes);
     }
 
+    #[test]
+    fn test_segmented_wal() {
+        let temp = tempdir::TempDir::new("test_wal").unwrap();
+        let dir = temp.path().join("wal");
+        let (mut writer, reader) = open_wal(&dir).unwrap();
+        let bytes = vec![7u8; (MAP_SIZE - HEADER_LEN_BYTES) as usize - 16];
+        let positions: Vec<_> = (0..10).map(|i| writer.write(i, &bytes).unwrap()).collect();
+        assert!(writer.segments() > 2);
+        for (i, pos) in positions.iter().enumerate() {
+            assert_eq!(&bytes, rd(&reader, *pos, i as Tag).as_ref());
+        }
+        drop(reader);
+        drop(writer);
+
+        let (mut writer, reader) = open_wal(&dir).unwrap();
+        let mut iter = reader.iter_until(&writer);
+        for (i, pos) in positions.iter().enumerate() {
+            assert_eq!(&bytes, rd_it(&mut iter, i as Tag, *pos).as_ref());
+        }
+        assert!(iter.next().is_none());
+        drop(iter);
+
+        // Only segments entirely before the position are dropped
+        let keep = positions[5];
+        let segments = writer.segments();
+        let expected = writer.segments_before(keep);
+        assert_eq!(expected as u64, keep.start / SEGMENT_SIZE);
+        assert_eq!(writer.compact(keep).unwrap(), expected);
+        assert_eq!(writer.segments(), segments - expected);
+        assert_eq!(writer.segments_before(keep), 0);
+        for (i, pos) in positions.iter().enumerate().skip(5) {
+            assert_eq!(&bytes, rd(&reader, *pos, i as Tag).as_ref());
+        }
+        let last_pos = writer.write(42, &bytes).unwrap();
+        drop(reader);
+        drop(writer);
+
+        let (writer, reader) = open_wal(&dir).unwrap();
+        assert_eq!(writer.segments(), segments - expected);
+        let mut iter = reader.iter_until(&writer);
+        let (first_pos, _) = iter.next().unwrap();
+        assert!(first_pos <= keep);
+        assert!(first_pos.start >= expected as u64 * SEGMENT_SIZE);
+        let (pos, (tag, data)) = iter.last().unwrap();
+        assert_eq!(pos, last_pos);
+        assert_eq!(tag, 42);
+        assert_eq!(&bytes, data.as_ref());
+        assert!(reader.read(positions[0]).is_err());
+    }
+
+    #[test]
+    fn test_single_file_wal_is_not_compacted() {
+        let temp = tempdir::TempDir::new("test_wal").unwrap();
+        let file = temp.path().join("wal");
+        let (mut writer, reader) = wal(&file).unwrap();
+        let bytes = vec![7u8; (MAP_SIZE - HEADER_LEN_BYTES) as usize - 16];
+        let positions: Vec<_> = (0..10).map(|i| writer.write(i, &bytes).unwrap()).collect();
+        assert_eq!(writer.segments(), 1);
+        assert_eq!(writer.compact(positions[9]).unwrap(), 0);
+        assert_eq!(&bytes, rd(&reader, positions[0], 0).as_ref());
+        drop(writer);
+        // An existing file is opened as a single file wal
+        let (writer, _reader) = open_wal(&file).unwrap();
+        assert_eq!(writer.segments(), 1);
+    }
+
+    #[test]
+    fn test_wal_truncate() {
+        let temp = tempdir::TempDir::new("test_wal").unwrap();
+        let dir = temp.path().join("wal");
+        let (mut writer, reader) = open_wal(&dir).unwrap();
+        let bytes = vec![7u8; (MAP_SIZE - HEADER_LEN_BYTES) as usize - 16];
+        let positions: Vec<_> = (0..10).map(|i| writer.write(i, &bytes).unwrap()).collect();
+        let segments = writer.segments();
+        drop(reader);
+
+        // Truncating into an earlier segment removes all segments after it
+        writer.truncate(positions[2]).unwrap();
+        assert!(writer.segments() < segments);
+        let pos = writer.write(42, &bytes).unwrap();
+        assert_eq!(pos, positions[2]);
+        drop(writer);
+
+        let (writer, reader) = open_wal(&dir).unwrap();
+        let mut iter = reader.iter_until(&writer);
+        assert_eq!(&bytes, rd_it(&mut iter, 0, positions[0]).as_ref());
+        assert_eq!(&bytes, rd_it(&mut iter, 1, positions[1]).as_ref());
+        assert_eq!(&bytes, rd_it(&mut iter, 42, positions[2]).as_ref());
+        assert!(iter.try_next().unwrap().is_none());
+    }
+
     #[track_caller]
     // Read from position, assert tag
     fn rd(reader: &WalReader, pos: WalPosition, tag: Tag) -> Bytes {
@@ -0,0 +1,408 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+use eyre::{ensure, Context};
+use minibytes::Bytes;
+use serde::Serialize;
+
+use crate::block_store::{
+    CommitData, OwnBlockData, WAL_ENTRY_BLOCK, WAL_ENTRY_COMMIT, WAL_ENTRY_EPOCH_CHANGE,
+    WAL_ENTRY_EQUIVOCATION, WAL_ENTRY_HORIZON, WAL_ENTRY_LEADER_SCHEDULE, WAL_ENTRY_OWN_BLOCK,
+    WAL_ENTRY_PAYLOAD, WAL_ENTRY_STATE,
+};
+use crate::data::Data;
+use crate::epoch_close::EpochChange;
+use crate::equivocation::EquivocationProof;
+use crate::types::{
+    AuthorityIndex, BaseStatement, BlockReference, Epoch, RoundNumber, StatementBlock, TimestampNs,
+};
+use crate::wal::{self, Tag, WalPosition, WalReader, WalWriter};
+
+/// Offline access to the wal of a validator, used by the `mysticeti wal` commands.
+/// The validator owning the wal must not be running while the wal is inspected.
+pub struct WalInspector {
+    writer: WalWriter,
+    reader: WalReader,
+}
+
+/// Result of reading the wal up to its end or up to the first corrupted entry.
+pub struct WalScan {
+    pub entries: usize,
+    /// End of the last valid entry.
+    pub valid_until: WalPosition,
+    pub corruption: Option<io::Error>,
+}
+
+#[derive(Default)]
+pub struct WalStats {
+    pub segments: usize,
+    // Number of entries and their total size for each tag
+    pub entries: BTreeMap<Tag, (usize, usize)>,
+    // Number of blocks, lowest and highest round for each authority
+    pub blocks: BTreeMap<AuthorityIndex, (usize, RoundNumber, RoundNumber)>,
+    pub last_own_block: Option<BlockReference>,
+    pub last_commit: Option<(u64, BlockReference)>,
+    pub horizon: RoundNumber,
+    pub scan: Option<WalScan>,
+}
+
+#[derive(Serialize)]
+struct BlockJson {
+    position: String,
+    own: bool,
+    reference: String,
+    author: AuthorityIndex,
+    round: RoundNumber,
+    digest: String,
+    epoch: Epoch,
+    epoch_changed: bool,
+    meta_creation_time_ns: TimestampNs,
+    includes: Vec<String>,
+    statements: Vec<String>,
+}
+
+impl WalInspector {
+    pub fn open(path: &Path) -> eyre::Result<Self> {
+        ensure!(path.exists(), "Wal '{}' does not exist", path.display());
+        let (writer, reader) =
+            wal::open_wal(path).wrap_err(format!("Failed to open wal '{}'", path.display()))?;
+        Ok(Self { writer, reader })
+    }
+
+    /// Calls the closure for every entry of the wal, stopping at the first corrupted entry.
+    pub fn scan(
+        &self,
+        mut f: impl FnMut(WalPosition, Tag, Bytes) -> eyre::Result<()>,
+    ) -> eyre::Result<WalScan> {
+        let mut iter = self.reader.iter_until(&self.writer);
+        let mut entries = 0;
+        let corruption = loop {
+            match iter.try_next() {
+                Ok(Some((position, (tag, data)))) => {
+                    entries += 1;
+                    f(position, tag, data)?;
+                }
+                Ok(None) => break None,
+                Err(err) => break Some(err),
+            }
+        };
+        Ok(WalScan {
+            entries,
+            valid_until: iter.valid_until(),
+            corruption,
+        })
+    }
+
+    /// Writes one line per entry: position, tag, size and a short description of the content.
+    pub fn dump(&self, out: &mut impl Write) -> eyre::Result<WalScan> {
+        self.scan(|position, tag, data| {
+            writeln!(
+                out,
+                "{:>12} {:<12} {:>8} {}",
+                position.to_string(),
+                tag_name(tag),
+                data.len(),
+                describe(tag, data)
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn stats(&self) -> eyre::Result<WalStats> {
+        let mut stats = WalStats {
+            segments: self.writer.segments(),
+            ..Default::default()
+        };
+        let scan = self.scan(|_, tag, data| {
+            let entry = stats.entries.entry(tag).or_default();
+            entry.0 += 1;
+            entry.1 += data.len();
+            match tag {
+                WAL_ENTRY_BLOCK => {
+                    let block = Data::<StatementBlock>::from_bytes(data)?;
+                    stats.add_block(&block);
+                }
+                WAL_ENTRY_OWN_BLOCK => {
+                    let (_, block) = OwnBlockData::from_bytes(data)?;
+                    stats.add_block(&block);
+                    stats.last_own_block = Some(*block.reference());
+                }
+                WAL_ENTRY_COMMIT => {
+                    let (commits, _): (Vec<CommitData>, Bytes) = bincode::deserialize(&data)?;
+                    if let Some(commit) = commits.last() {
+                        stats.last_commit = Some((commit.height, commit.leader));
+                    }
+                }
+                WAL_ENTRY_HORIZON => {
+                    stats.horizon = stats.horizon.max(bincode::deserialize(&data)?);
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+        stats.scan = Some(scan);
+        Ok(stats)
+    }
+
+    /// Checks crc of all entries, the returned scan reports the first corrupted entry if any.
+    pub fn verify(&self) -> eyre::Result<WalScan> {
+        self.scan(|_, _, _| Ok(()))
+    }
+
+    /// Drops everything after the last valid entry if the wal is corrupted.
+    /// Returns the scan of the wal before the truncation.
+    pub fn truncate_at_corruption(&mut self) -> eyre::Result<WalScan> {
+        let scan = self.verify()?;
+        if scan.corruption.is_some() {
+            // All buffers are dropped at this point, so the mappings of the truncated part can be released
+            self.reader.cleanup();
+            self.writer
+                .truncate(scan.valid_until)
+                .wrap_err("Failed to truncate wal")?;
+        }
+        Ok(scan)
+    }
+
+    /// Writes all blocks in the wal as json, one block per line.
+    pub fn export_blocks(&self, out: &mut impl Write) -> eyre::Result<WalScan> {
+        self.scan(|position, tag, data| {
+            let (own, block) = match tag {
+                WAL_ENTRY_BLOCK => (false, Data::<StatementBlock>::from_bytes(data)?),
+                WAL_ENTRY_OWN_BLOCK => (true, OwnBlockData::from_bytes(data)?.1),
+                _ => return Ok(()),
+            };
+            serde_json::to_writer(&mut *out, &BlockJson::new(position, own, &block))?;
+            writeln!(out)?;
+            Ok(())
+        })
+    }
+}
+
+impl WalStats {
+    fn add_block(&mut self, block: &StatementBlock) {
+        let (count, min_round, max_round) =
+            self.blocks
+                .entry(block.author())
+                .or_insert((0, RoundNumber::MAX, 0));
+        *count += 1;
+        *min_round = (*min_round).min(block.round());
+        *max_round = (*max_round).max(block.round());
+    }
+}
+
+impl BlockJson {
+    fn new(position: WalPosition, own: bool, block: &StatementBlock) -> Self {
+        Self {
+            position: position.to_string(),
+            own,
+            reference: block.reference().to_string(),
+            author: block.author(),
+            round: block.round(),
+            digest: hex::encode(block.digest()),
+            epoch: block.epoch(),
+            epoch_changed: block.epoch_changed(),
+            meta_creation_time_ns: block.meta_creation_time_ns(),
+            includes: block
+                .includes()
+                .iter()
+                .map(|include| format!("{include}{}", include.digest))
+                .collect(),
+            statements: block.statements().iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+pub fn tag_name(tag: Tag) -> &'static str {
+    match tag {
+        WAL_ENTRY_BLOCK => "block",
+        WAL_ENTRY_PAYLOAD => "payload",
+        WAL_ENTRY_OWN_BLOCK => "own_block",
+        WAL_ENTRY_STATE => "state",
+        WAL_ENTRY_COMMIT => "commit",
+        WAL_ENTRY_EQUIVOCATION => "equivocation",
+        WAL_ENTRY_HORIZON => "horizon",
+        WAL_ENTRY_LEADER_SCHEDULE => "leader_schedule",
+        WAL_ENTRY_EPOCH_CHANGE => "epoch_change",
+        _ => "unknown",
+    }
+}
+
+fn describe(tag: Tag, data: Bytes) -> String {
+    let description = match tag {
+        WAL_ENTRY_BLOCK => {
+            Data::<StatementBlock>::from_bytes(data).map(|block| describe_block(&block))
+        }
+        WAL_ENTRY_PAYLOAD => bincode::deserialize::<Vec<BaseStatement>>(&data)
+            .map(|statements| format!("statements={}", statements.len())),
+        WAL_ENTRY_OWN_BLOCK => OwnBlockData::from_bytes(data).map(|(own_block, block)| {
+            format!(
+                "{} next_entry={}",
+                describe_block(&block),
+                own_block.next_entry
+            )
+        }),
+        WAL_ENTRY_COMMIT => {
+            bincode::deserialize::<(Vec<CommitData>, Bytes)>(&data).map(|(commits, _)| {
+                match (commits.first(), commits.last()) {
+                    (Some(first), Some(last)) => format!(
+                        "heights={}..={} leader={}",
+                        first.height, last.height, last.leader
+                    ),
+                    _ => "no commits".to_string(),
+                }
+            })
+        }
+        WAL_ENTRY_EQUIVOCATION => bincode::deserialize::<EquivocationProof>(&data)
+            .map(|proof| format!("author={} round={}", proof.author(), proof.round())),
+        WAL_ENTRY_HORIZON => {
+            bincode::deserialize::<RoundNumber>(&data).map(|horizon| format!("round={horizon}"))
+        }
+        WAL_ENTRY_EPOCH_CHANGE => EpochChange::from_bytes(&data).map(|change| {
+            format!(
+                "epoch={} next_epoch={} authorities={}",
+                change.epoch,
+                change.next_committee.epoch(),
+                change.next_committee.len()
+            )
+        }),
+        _ => Ok(String::new()),
+    };
+    description.unwrap_or_else(|err| format!("failed to deserialize: {err}"))
+}
+
+fn describe_block(block: &StatementBlock) -> String {
+    format!(
+        "{}{} includes={} statements={}",
+        block.reference(),
+        block.digest(),
+        block.includes().len(),
+        block.statements().len()
+    )
+}
+
+impl fmt::Display for WalScan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} valid entries until {}",
+            self.entries, self.valid_until
+        )?;
+        if let Some(corruption) = &self.corruption {
+            write!(f, ", corrupted entry after it: {corruption}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WalStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "segments: {}", self.segments)?;
+        if let Some(scan) = &self.scan {
+            writeln!(f, "entries: {scan}")?;
+        }
+        for (tag, (count, size)) in &self.entries {
+            writeln!(
+                f,
+                "  {:<12} {count:>10} entries {size:>14} bytes",
+                tag_name(*tag)
+            )?;
+        }
+        writeln!(f, "blocks:")?;
+        for (authority, (count, min_round, max_round)) in &self.blocks {
+            writeln!(
+                f,
+                "  authority {authority:>4} {count:>10} blocks, rounds {min_round}..={max_round}"
+            )?;
+        }
+        if let Some(own_block) = &self.last_own_block {
+            writeln!(f, "last own block: {own_block}")?;
+        }
+        if let Some((height, leader)) = &self.last_commit {
+            writeln!(f, "last commit: height {height}, leader {leader}")?;
+        }
+        write!(f, "horizon: {}", self.horizon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::BlockDigest;
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom};
+
+    fn block(authority: AuthorityIndex, round: RoundNumber) -> Data<StatementBlock> {
+        let block = StatementBlock::new(
+            authority,
+            round,
+            vec![],
+            vec![],
+            0,
+            false,
+            0,
+            Default::default(),
+        );
+        Data::new(block.with_test_digest(BlockDigest::new_test(round as u8)))
+    }
+
+    #[test]
+    fn inspect_and_repair_wal() {
+        let temp = tempdir::TempDir::new("inspect_wal").unwrap();
+        let path = temp.path().join("wal");
+        let (mut writer, _reader) = wal::open_wal(&path).unwrap();
+        for round in 1..=3 {
+            writer
+                .write(WAL_ENTRY_BLOCK, block(1, round).serialized_bytes())
+                .unwrap();
+        }
+        let horizon = bincode::serialize(&(2 as RoundNumber)).unwrap();
+        let corrupted = writer.write(WAL_ENTRY_HORIZON, &horizon).unwrap();
+        drop(writer);
+
+        let inspector = WalInspector::open(&path).unwrap();
+        let stats = inspector.stats().unwrap();
+        assert_eq!(stats.entries[&WAL_ENTRY_BLOCK].0, 3);
+        assert_eq!(stats.blocks[&1], (3, 1, 3));
+        assert_eq!(stats.horizon, 2);
+        let mut json = vec![];
+        assert_eq!(inspector.export_blocks(&mut json).unwrap().entries, 4);
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(json.lines().count(), 3);
+        let first: serde_json::Value = serde_json::from_str(json.lines().next().unwrap()).unwrap();
+        assert_eq!(first["reference"], "B1");
+        drop(inspector);
+
+        // Corrupt the payload of the last entry
+        let segment = std::fs::read_dir(&path).unwrap().next().unwrap().unwrap();
+        let mut file = OpenOptions::new().write(true).open(segment.path()).unwrap();
+        let offset = corrupted.to_string().parse::<u64>().unwrap() + 16;
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[0xff]).unwrap();
+        drop(file);
+
+        let mut inspector = WalInspector::open(&path).unwrap();
+        let scan = inspector.verify().unwrap();
+        assert_eq!(scan.entries, 3);
+        assert_eq!(scan.valid_until, corrupted);
+        assert!(scan.corruption.is_some());
+        let mut dump = vec![];
+        inspector.dump(&mut dump).unwrap();
+        assert_eq!(String::from_utf8(dump).unwrap().lines().count(), 3);
+        assert!(inspector
+            .truncate_at_corruption()
+            .unwrap()
+            .corruption
+            .is_some());
+        drop(inspector);
+
+        let inspector = WalInspector::open(&path).unwrap();
+        let scan = inspector.verify().unwrap();
+        assert_eq!(scan.entries, 3);
+        assert!(scan.corruption.is_none());
+    }
+}
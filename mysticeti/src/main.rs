@@ -2,14 +2,14 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    fs,
+    fs, io,
     net::{IpAddr, Ipv4Addr},
     path::PathBuf,
     sync::Arc,
 };
 
 use clap::{command, Parser};
-use eyre::{Context, Result};
+use eyre::{bail, Context, Result};
 use futures::future;
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::{fmt, EnvFilter};
@@ -19,7 +19,8 @@ use mysticeti_core::{
     config::{Parameters, Print, PrivateConfig},
     dummy_signer,
     types::AuthorityIndex,
-    validator::Validator,
+    validator::{self, Validator},
+    wal_inspect::WalInspector,
 };
 
 #[derive(Parser)]
@@ -55,6 +56,16 @@ enum Operation {
         /// Path to the file holding the private validator configurations (including keys).
         #[clap(long, value_name = "FILE")]
         private_config_path: String,
+        /// Path to a snapshot to bootstrap from, only used if the validator storage is empty.
+        #[clap(long, value_name = "FILE")]
+        snapshot: Option<PathBuf>,
+        /// Path to the file holding the committee this validator proposes for the next epoch. The
+        /// validators move to the committee proposed by a quorum once the current epoch is closed.
+        #[clap(long, value_name = "FILE", requires = "next_parameters_path")]
+        next_committee_path: Option<String>,
+        /// Path to the parameters file holding the network addresses of the next committee.
+        #[clap(long, value_name = "FILE", requires = "next_committee_path")]
+        next_parameters_path: Option<String>,
     },
     /// Deploy a local validator for test. Dryrun mode uses default keys and committee configurations.
     DryRun {
@@ -71,6 +82,48 @@ enum Operation {
         #[clap(long, value_name = "INT")]
         committee_size: usize,
     },
+    /// Inspect or repair the wal of a validator. The validator must not be running.
+    Wal {
+        #[clap(subcommand)]
+        operation: WalOperation,
+    },
+}
+
+#[derive(Parser)]
+enum WalOperation {
+    /// Print one line per wal entry.
+    Dump {
+        /// Path to the wal (the segments directory or a single wal file).
+        #[clap(long, value_name = "PATH")]
+        path: PathBuf,
+    },
+    /// Print the number and size of the entries per type and the blocks per authority.
+    Stats {
+        /// Path to the wal (the segments directory or a single wal file).
+        #[clap(long, value_name = "PATH")]
+        path: PathBuf,
+    },
+    /// Check the crc of all wal entries, fails if the wal is corrupted.
+    VerifyCrc {
+        /// Path to the wal (the segments directory or a single wal file).
+        #[clap(long, value_name = "PATH")]
+        path: PathBuf,
+    },
+    /// Drop everything after the last valid entry of a corrupted wal.
+    TruncateAtCorruption {
+        /// Path to the wal (the segments directory or a single wal file).
+        #[clap(long, value_name = "PATH")]
+        path: PathBuf,
+    },
+    /// Write all blocks of the wal as json, one block per line.
+    ExportBlocksAsJson {
+        /// Path to the wal (the segments directory or a single wal file).
+        #[clap(long, value_name = "PATH")]
+        path: PathBuf,
+        /// The file to write to, defaults to stdout.
+        #[clap(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
@@ -93,16 +146,23 @@ async fn main() -> Result<()> {
             committee_path,
             parameters_path,
             private_config_path,
+            snapshot,
+            next_committee_path,
+            next_parameters_path,
         } => {
             run(
                 authority,
                 committee_path,
                 parameters_path,
                 private_config_path,
+                snapshot,
+                next_committee_path,
+                next_parameters_path,
             )
             .await?
         }
         Operation::Testbed { committee_size } => testbed(committee_size).await?,
+        Operation::Wal { operation } => wal(operation)?,
         Operation::DryRun {
             authority,
             committee_size,
@@ -161,6 +221,9 @@ async fn run(
     committee_path: String,
     parameters_path: String,
     private_config_path: String,
+    snapshot: Option<PathBuf>,
+    next_committee_path: Option<String>,
+    next_parameters_path: Option<String>,
 ) -> Result<()> {
     tracing::info!("Starting validator {authority}");
 
@@ -175,15 +238,73 @@ async fn run(
 
     let committee = Arc::new(committee);
 
+    if let Some(snapshot) = snapshot {
+        validator::bootstrap_from_snapshot(authority, &committee, &private, &snapshot)?;
+    }
+
     // Boot the validator node.
     let validator =
         Validator::start_benchmarking(authority, committee, &parameters, private, dummy_signer())
             .await?;
+    if let (Some(next_committee_path), Some(next_parameters_path)) =
+        (next_committee_path, next_parameters_path)
+    {
+        let next_committee = Committee::load(&next_committee_path).wrap_err(format!(
+            "Failed to load next committee file '{next_committee_path}'"
+        ))?;
+        let next_parameters = Parameters::load(&next_parameters_path).wrap_err(format!(
+            "Failed to load next parameters file '{next_parameters_path}'"
+        ))?;
+        validator
+            .set_next_committee(&next_committee, next_parameters.identifiers)
+            .wrap_err("Failed to set the next committee")?;
+    }
     let (network_result, _metrics_result) = validator.await_completion().await;
     network_result.expect("Validator failed");
     Ok(())
 }
 
+/// Run an offline wal operation.
+fn wal(operation: WalOperation) -> Result<()> {
+    match operation {
+        WalOperation::Dump { path } => {
+            let scan = WalInspector::open(&path)?.dump(&mut io::stdout().lock())?;
+            println!("{scan}");
+        }
+        WalOperation::Stats { path } => {
+            println!("{}", WalInspector::open(&path)?.stats()?);
+        }
+        WalOperation::VerifyCrc { path } => {
+            let scan = WalInspector::open(&path)?.verify()?;
+            if scan.corruption.is_some() {
+                bail!("Wal '{}' is corrupted: {scan}", path.display());
+            }
+            println!("{scan}");
+        }
+        WalOperation::TruncateAtCorruption { path } => {
+            let scan = WalInspector::open(&path)?.truncate_at_corruption()?;
+            if scan.corruption.is_some() {
+                println!("Truncated wal at {}: {scan}", scan.valid_until);
+            } else {
+                println!("Wal is not corrupted, nothing to truncate: {scan}");
+            }
+        }
+        WalOperation::ExportBlocksAsJson { path, output } => {
+            let inspector = WalInspector::open(&path)?;
+            let scan = match output {
+                Some(output) => {
+                    let file = fs::File::create(&output)
+                        .wrap_err(format!("Failed to create file '{}'", output.display()))?;
+                    inspector.export_blocks(&mut io::BufWriter::new(file))?
+                }
+                None => inspector.export_blocks(&mut io::stdout().lock())?,
+            };
+            eprintln!("{scan}");
+        }
+    }
+    Ok(())
+}
+
 async fn testbed(committee_size: usize) -> Result<()> {
     tracing::info!("Starting testbed with committee size {committee_size}");
 
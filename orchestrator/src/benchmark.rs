@@ -100,17 +100,120 @@ pub enum LoadType {
     /// Submit a fixed set of loads (one per benchmark run).
     Fixed(Vec<usize>),
 
-    /// Search for the breaking point of the L-graph.
-    // TODO: Doesn't work very well, use tps regression as additional signal.
-    #[allow(dead_code)]
+    /// Search for the breaking point of the L-graph, i.e., the highest load that the system
+    /// sustains without latency inflation nor throughput regression (see `out_of_capacity`).
     Search {
         /// The initial load to test (and use a baseline).
         starting_load: usize,
-        /// The maximum number of iterations before converging on a breaking point.
+        /// The maximum number of loads to test before converging on a breaking point.
         max_iterations: usize,
+        /// The number of benchmark runs of each load. A load is sustained only if the 95%
+        /// confidence intervals of its throughput and latency are within capacity.
+        trials: usize,
+        /// The search stops once the breaking point is known within this percentage of load.
+        precision: usize,
     },
 }
 
+/// The mean of a measure across the trials of a load, with the 95% confidence interval of the
+/// mean and the spread of the trials.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    /// The mean of the trials.
+    pub mean: f64,
+    /// The half width of the confidence interval (zero for a single trial).
+    pub margin: f64,
+    /// The lowest measure among the trials.
+    pub min: f64,
+    /// The highest measure among the trials.
+    pub max: f64,
+}
+
+impl Estimate {
+    /// The two-sided 95% quantiles of the Student's t-distribution, by degrees of freedom.
+    const T_QUANTILES: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    /// The two-sided 95% quantile of the normal distribution, beyond the tabulated t-quantiles.
+    const Z_QUANTILE: f64 = 1.96;
+
+    /// Estimate the mean of a measure from (at least one) trials.
+    pub fn new(samples: &[f64]) -> Self {
+        assert!(
+            !samples.is_empty(),
+            "Cannot estimate a measure without trials"
+        );
+        let n = samples.len();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let margin = if n < 2 {
+            0.0
+        } else {
+            let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+            let quantile = Self::T_QUANTILES
+                .get(n - 2)
+                .copied()
+                .unwrap_or(Self::Z_QUANTILE);
+            quantile * (variance / n as f64).sqrt()
+        };
+        Self {
+            mean,
+            margin,
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    /// The lower bound of the confidence interval.
+    pub fn lower(&self) -> f64 {
+        self.mean - self.margin
+    }
+
+    /// The upper bound of the confidence interval.
+    pub fn upper(&self) -> f64 {
+        self.mean + self.margin
+    }
+}
+
+/// The throughput (tx/s) and latency (s) of the trials of a load.
+struct LoadEstimate {
+    load: usize,
+    tps: Estimate,
+    latency: Estimate,
+}
+
+/// The outcome of a search for the breaking point of the L-graph.
+pub struct BreakingPoint {
+    /// The highest load sustained by the system.
+    pub load: usize,
+    /// The throughput measured at that load, i.e., the saturation throughput.
+    pub tps: Estimate,
+    /// The lowest load found out of capacity (if any).
+    pub upper_bound: Option<usize>,
+}
+
+impl Display for BreakingPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tps = format!(
+            "{:.0} ± {:.0} tx/s (trials from {:.0} to {:.0} tx/s)",
+            self.tps.mean, self.tps.margin, self.tps.min, self.tps.max
+        );
+        match self.upper_bound {
+            Some(upper_bound) => write!(
+                f,
+                "{tps} between {} and {upper_bound} tx/s of load",
+                self.load
+            ),
+            None => write!(
+                f,
+                "at least {tps}, no tested load out of capacity up to {} tx/s",
+                self.load
+            ),
+        }
+    }
+}
+
 /// Generate benchmark parameters (one set of parameters per run).
 // TODO: The rusty thing to do would be to implement Iter.
 pub struct BenchmarkParametersGenerator<T> {
@@ -126,10 +229,15 @@ pub struct BenchmarkParametersGenerator<T> {
     duration: Duration,
     /// The load of the next benchmark run.
     next_load: Option<usize>,
+    /// The results of the runs of the load currently tested.
+    trial_results: Vec<MeasurementsCollection<T>>,
+    /// The lowest latency of the loads that did not regress the throughput, against which
+    /// latency inflation is measured.
+    baseline_latency: Option<Duration>,
     /// Temporary hold a lower bound of the breaking point.
-    lower_bound_result: Option<MeasurementsCollection<T>>,
+    lower_bound: Option<LoadEstimate>,
     /// Temporary hold an upper bound of the breaking point.
-    upper_bound_result: Option<MeasurementsCollection<T>>,
+    upper_bound: Option<LoadEstimate>,
     /// The current number of iterations.
     iterations: usize,
 }
@@ -154,6 +262,13 @@ impl<T: BenchmarkType> Iterator for BenchmarkParametersGenerator<T> {
 impl<T: BenchmarkType> BenchmarkParametersGenerator<T> {
     /// The default benchmark duration.
     const DEFAULT_DURATION: Duration = Duration::from_secs(180);
+    /// A load is out of capacity if its latency exceeds the baseline latency by this factor.
+    const MAX_LATENCY_INFLATION: u32 = 3;
+    /// A load is out of capacity if its throughput is below this percentage of the load.
+    const MIN_THROUGHPUT_PERCENTAGE: u64 = 80;
+    /// A load is out of capacity if its throughput is lower than the one of a lower load by more
+    /// than this percentage, to tolerate the noise of the measurements.
+    const THROUGHPUT_TOLERANCE_PERCENTAGE: u64 = 5;
 
     /// make a new generator.
     pub fn new(nodes: usize, mut load_type: LoadType) -> Self {
@@ -174,8 +289,10 @@ impl<T: BenchmarkType> BenchmarkParametersGenerator<T> {
             faults: FaultsType::default(),
             duration: Self::DEFAULT_DURATION,
             next_load,
-            lower_bound_result: None,
-            upper_bound_result: None,
+            trial_results: Vec::new(),
+            baseline_latency: None,
+            lower_bound: None,
+            upper_bound: None,
             iterations: 0,
         }
     }
@@ -198,92 +315,161 @@ impl<T: BenchmarkType> BenchmarkParametersGenerator<T> {
         self
     }
 
-    /// Detects whether the latest benchmark parameters run the system out of capacity.
-    fn out_of_capacity(
-        last_result: &MeasurementsCollection<T>,
-        new_result: &MeasurementsCollection<T>,
-    ) -> bool {
-        let Some(first_label) = new_result.labels().next() else {
-            return false;
-        };
+    /// The throughput and latency of a benchmark run: the lowest throughput and the highest
+    /// average latency among its workloads.
+    fn throughput_and_latency(result: &MeasurementsCollection<T>) -> (u64, Duration) {
+        let tps = result.labels().map(|x| result.aggregate_tps(x)).min();
+        let latency = result.labels().map(|x| result.aggregate_average_latency(x));
+        (tps.unwrap_or_default(), latency.max().unwrap_or_default())
+    }
 
-        // We consider the system is out of capacity if the latency increased by over 5x with
-        // respect to the latest run.
-        let threshold = last_result.aggregate_average_latency(first_label) * 5;
-        let high_latency = new_result.aggregate_average_latency(first_label) > threshold;
+    /// The throughput and latency of the trials of a load.
+    fn estimate(trials: &[MeasurementsCollection<T>]) -> LoadEstimate {
+        let (tps, latency): (Vec<_>, Vec<_>) = trials
+            .iter()
+            .map(|x| {
+                let (tps, latency) = Self::throughput_and_latency(x);
+                (tps as f64, latency.as_secs_f64())
+            })
+            .unzip();
+        LoadEstimate {
+            load: trials[0].transaction_load(),
+            tps: Estimate::new(&tps),
+            latency: Estimate::new(&latency),
+        }
+    }
 
-        // Or if the throughput is less than 2/3 of the input rate.
-        let last_load = new_result.transaction_load() as u64;
-        let no_throughput_increase = new_result.aggregate_tps(first_label) < (2 * last_load / 3);
+    /// Whether a load may regress the throughput: the throughput does not surely keep up with
+    /// the load, or may be lower than the one of a lower load that the system sustains (beyond
+    /// the tolerance).
+    fn throughput_regression(lower_bound: Option<&LoadEstimate>, result: &LoadEstimate) -> bool {
+        let tps = result.tps.lower();
+        let below_load =
+            tps * 100.0 < (result.load as u64 * Self::MIN_THROUGHPUT_PERCENTAGE) as f64;
+        let below_lower_bound = lower_bound
+            .filter(|x| x.load < result.load)
+            .map_or(false, |x| {
+                tps * 100.0 < x.tps.mean * (100 - Self::THROUGHPUT_TOLERANCE_PERCENTAGE) as f64
+            });
+        below_load || below_lower_bound
+    }
 
-        high_latency || no_throughput_increase
+    /// Detects whether a load may be out of capacity: either its throughput may regress or its
+    /// latency may inflate with respect to the baseline. Only the loads whose confidence
+    /// intervals are within capacity are sustained.
+    fn out_of_capacity(
+        baseline_latency: Option<Duration>,
+        lower_bound: Option<&LoadEstimate>,
+        result: &LoadEstimate,
+    ) -> bool {
+        let high_latency = baseline_latency.map_or(false, |baseline| {
+            result.latency.upper() > (baseline * Self::MAX_LATENCY_INFLATION).as_secs_f64()
+        });
+        high_latency || Self::throughput_regression(lower_bound, result)
     }
 
     /// Register a new benchmark measurements collection. These results are used to determine
     /// whether the system reached its breaking point.
     pub fn register_result(&mut self, result: MeasurementsCollection<T>) {
-        self.next_load = match &mut self.load_type {
-            LoadType::Fixed(loads) => {
-                if loads.is_empty() {
-                    None
-                } else {
-                    Some(loads.remove(0))
-                }
-            }
-            LoadType::Search { max_iterations, .. } => {
-                // Terminate the the search.
-                if self.iterations >= *max_iterations {
-                    None
-
-                // Search for the breaking point.
+        self.next_load = match &self.load_type {
+            LoadType::Fixed(_) => self.next_fixed_load(),
+            LoadType::Search {
+                max_iterations,
+                trials,
+                precision,
+                ..
+            } => {
+                let (max_iterations, trials, precision) = (*max_iterations, *trials, *precision);
+                let load = result.transaction_load();
+                self.trial_results.push(result);
+
+                // Repeat the same load until all its trials ran.
+                if self.trial_results.len() < trials {
+                    Some(load)
                 } else {
                     self.iterations += 1;
-                    match (&mut self.lower_bound_result, &mut self.upper_bound_result) {
-                        (None, None) => {
-                            let next = result.transaction_load() * 2;
-                            self.lower_bound_result = Some(result);
-                            Some(next)
-                        }
-                        (Some(lower), None) => {
-                            if Self::out_of_capacity(lower, &result) {
-                                let next =
-                                    (lower.transaction_load() + result.transaction_load()) / 2;
-                                self.upper_bound_result = Some(result);
-                                Some(next)
-                            } else {
-                                let next = result.transaction_load() * 2;
-                                *lower = result;
-                                Some(next)
-                            }
-                        }
-                        (Some(lower), Some(upper)) => {
-                            if Self::out_of_capacity(lower, &result) {
-                                *upper = result;
-                            } else {
-                                *lower = result;
-                            }
-                            Some((lower.transaction_load() + upper.transaction_load()) / 2)
-                        }
-                        _ => panic!("Benchmark parameters generator is in an incoherent state"),
-                    }
+                    self.register_trials();
+                    self.next_search_load(max_iterations, precision)
                 }
             }
         };
     }
+
+    fn next_fixed_load(&mut self) -> Option<usize> {
+        match &mut self.load_type {
+            LoadType::Fixed(loads) if !loads.is_empty() => Some(loads.remove(0)),
+            _ => None,
+        }
+    }
+
+    /// Decide whether the trials of the current load are out of capacity, and update the bounds
+    /// of the breaking point accordingly.
+    fn register_trials(&mut self) {
+        let trials = std::mem::take(&mut self.trial_results);
+        let estimate = Self::estimate(&trials);
+        let lower_bound = self.lower_bound.as_ref();
+
+        // Only loads that do not regress the throughput set the baseline latency.
+        if !Self::throughput_regression(lower_bound, &estimate) {
+            let latency = Duration::from_secs_f64(estimate.latency.mean);
+            let baseline = self.baseline_latency.get_or_insert(latency);
+            *baseline = (*baseline).min(latency);
+        }
+
+        if Self::out_of_capacity(self.baseline_latency, lower_bound, &estimate) {
+            self.upper_bound = Some(estimate);
+        } else {
+            self.lower_bound = Some(estimate);
+        }
+    }
+
+    /// The next load to test: double the load until it is out of capacity (or halve it until
+    /// it is not), then bisect until the breaking point is known within the precision.
+    fn next_search_load(&self, max_iterations: usize, precision: usize) -> Option<usize> {
+        if self.iterations >= max_iterations {
+            return None;
+        }
+        let lower = self.lower_bound.as_ref().map(|x| x.load);
+        let upper = self.upper_bound.as_ref().map(|x| x.load);
+        match (lower, upper) {
+            (Some(lower), None) => Some(lower * 2),
+            (None, Some(upper)) => Some(upper / 2).filter(|x| *x > 0),
+            (Some(lower), Some(upper)) => {
+                let next = (lower + upper) / 2;
+                let converged = (upper - lower) * 100 <= lower * precision;
+                (!converged && next != lower).then_some(next)
+            }
+            (None, None) => panic!("Benchmark parameters generator is in an incoherent state"),
+        }
+    }
+
+    /// The index of the next run among the trials of its load.
+    pub fn trial(&self) -> usize {
+        self.trial_results.len()
+    }
+
+    /// The breaking point found by the search (if any).
+    pub fn breaking_point(&self) -> Option<BreakingPoint> {
+        let lower = self.lower_bound.as_ref()?;
+        Some(BreakingPoint {
+            load: lower.load,
+            tps: lower.tps,
+            upper_bound: self.upper_bound.as_ref().map(|x| x.load),
+        })
+    }
 }
 
 #[cfg(test)]
 pub mod test {
-    use std::{fmt::Display, str::FromStr};
+    use std::{fmt::Display, str::FromStr, time::Duration};
 
     use serde::{Deserialize, Serialize};
 
-    use crate::{
-        measurement::{Measurement, MeasurementsCollection},
-        settings::Settings,
-    };
+    use crate::{measurement::MeasurementsCollection, settings::Settings};
 
-    use super::{BenchmarkParametersGenerator, BenchmarkType, LoadType};
+    use super::{
+        BenchmarkParameters, BenchmarkParametersGenerator, BenchmarkType, Estimate, LoadType,
+    };
 
     /// Mock benchmark type for unit tests.
     #[derive(
@@ -307,69 +493,186 @@ pub mod test {
 
     impl BenchmarkType for TestBenchmarkType {}
 
-    #[test]
-    fn set_lower_bound() {
-        let settings = Settings::new_for_test();
-        let nodes = 4;
+    /// Make a search starting at the specified load, with a precision of 5%.
+    fn search(
+        starting_load: usize,
+        trials: usize,
+    ) -> BenchmarkParametersGenerator<TestBenchmarkType> {
         let load = LoadType::Search {
-            starting_load: 100,
-            max_iterations: 10,
+            starting_load,
+            max_iterations: 20,
+            trials,
+            precision: 5,
         };
-        let mut generator = BenchmarkParametersGenerator::<TestBenchmarkType>::new(nodes, load);
-        let parameters = generator.next().unwrap();
+        BenchmarkParametersGenerator::new(4, load)
+    }
 
-        let collection = MeasurementsCollection::new(&settings, parameters);
-        generator.register_result(collection);
+    /// The result of a benchmark run with the specified throughput and latency.
+    fn result(
+        parameters: BenchmarkParameters<TestBenchmarkType>,
+        tps: usize,
+        latency_ms: u64,
+    ) -> MeasurementsCollection<TestBenchmarkType> {
+        let latency = Duration::from_millis(latency_ms);
+        MeasurementsCollection::new_for_test(parameters, tps, latency)
+    }
+
+    #[test]
+    fn set_lower_bound() {
+        let mut generator = search(100, 1);
+        let parameters = generator.next().unwrap();
+        generator.register_result(result(parameters, 100, 500));
 
         let next_parameters = generator.next();
         assert!(next_parameters.is_some());
         assert_eq!(next_parameters.unwrap().load, 200);
 
-        assert!(generator.lower_bound_result.is_some());
-        assert_eq!(
-            generator.lower_bound_result.unwrap().transaction_load(),
-            100
-        );
-        assert!(generator.upper_bound_result.is_none());
+        assert_eq!(generator.lower_bound.unwrap().load, 100);
+        assert!(generator.upper_bound.is_none());
     }
 
     #[test]
     fn set_upper_bound() {
-        let settings = Settings::new_for_test();
-        let nodes = 4;
-        let load = LoadType::Search {
-            starting_load: 100,
-            max_iterations: 10,
-        };
-        let mut generator = BenchmarkParametersGenerator::<TestBenchmarkType>::new(nodes, load);
+        let mut generator = search(100, 1);
         let first_parameters = generator.next().unwrap();
 
-        // Register a first result (zero latency). This sets the lower bound.
-        let collection = MeasurementsCollection::new(&settings, first_parameters);
-        generator.register_result(collection);
+        // Register a first result. This sets the lower bound.
+        generator.register_result(result(first_parameters, 100, 500));
         let second_parameters = generator.next().unwrap();
 
-        // Register a second result (with positive latency). This sets the upper bound.
-        let mut collection = MeasurementsCollection::new(&settings, second_parameters);
-        let (label, measurement) = Measurement::new_for_test();
-        collection.add(1, label, measurement);
-        generator.register_result(collection);
+        // Register a second result (with inflated latency). This sets the upper bound.
+        generator.register_result(result(second_parameters, 200, 2_000));
 
         // Ensure the next load is between the upper and the lower bound.
         let third_parameters = generator.next();
         assert!(third_parameters.is_some());
         assert_eq!(third_parameters.unwrap().load, 150);
 
-        assert!(generator.lower_bound_result.is_some());
-        assert_eq!(
-            generator.lower_bound_result.unwrap().transaction_load(),
-            100
-        );
-        assert!(generator.upper_bound_result.is_some());
+        assert_eq!(generator.lower_bound.unwrap().load, 100);
+        assert_eq!(generator.upper_bound.unwrap().load, 200);
+    }
+
+    #[test]
+    fn throughput_regression() {
+        let mut generator = search(100, 1);
+        let parameters = generator.next().unwrap();
+        generator.register_result(result(parameters, 100, 500));
+
+        // The throughput does not keep up with the load.
+        let parameters = generator.next().unwrap();
+        generator.register_result(result(parameters.clone(), 150, 500));
+        assert_eq!(generator.next().unwrap().load, 150);
+        assert!(generator.upper_bound.is_some());
+
+        // The throughput is lower than the one of a lower load, beyond the tolerance.
+        type Generator = BenchmarkParametersGenerator<TestBenchmarkType>;
+        let estimate = |load, tps| {
+            let parameters = BenchmarkParameters {
+                load,
+                ..parameters.clone()
+            };
+            Generator::estimate(&[result(parameters, tps, 500)])
+        };
+        let lower_bound = estimate(200, 200);
+        let regression = estimate(220, 185);
+        let tolerated = estimate(220, 195);
+        assert!(Generator::throughput_regression(
+            Some(&lower_bound),
+            &regression
+        ));
+        assert!(!Generator::throughput_regression(
+            Some(&lower_bound),
+            &tolerated
+        ));
+        assert!(!Generator::throughput_regression(None, &regression));
+    }
+
+    #[test]
+    fn confidence_interval() {
+        let single = Estimate::new(&[100.0]);
+        assert_eq!((single.mean, single.margin), (100.0, 0.0));
+
+        let estimate = Estimate::new(&[90.0, 100.0, 110.0]);
+        assert_eq!(estimate.mean, 100.0);
+        assert_eq!((estimate.min, estimate.max), (90.0, 110.0));
+        // Standard error of 10 / sqrt(3), with 2 degrees of freedom.
+        assert!((estimate.margin - 4.303 * 10.0 / 3f64.sqrt()).abs() < 1e-9);
+        assert!(estimate.lower() < 90.0 && estimate.upper() > 110.0);
+    }
+
+    #[test]
+    fn repeated_trials() {
+        let mut generator = search(100, 3);
+        for trial in 0..3 {
+            assert_eq!(generator.trial(), trial);
+            let parameters = generator.next().unwrap();
+            assert_eq!(parameters.load, 100);
+            generator.register_result(result(parameters, 100, 500));
+        }
+
+        // Trials that consistently keep up with the load sustain it.
+        for (tps, latency) in [(195, 480), (200, 520), (205, 500)] {
+            let parameters = generator.next().unwrap();
+            assert_eq!(parameters.load, 200);
+            generator.register_result(result(parameters, tps, latency));
+        }
+        assert_eq!(generator.next().unwrap().load, 400);
+        assert_eq!(generator.trial(), 0);
+
+        // A load is out of capacity if its latency may inflate, even if most trials do not.
+        for latency in [500, 2_000, 500] {
+            let parameters = generator.next().unwrap();
+            generator.register_result(result(parameters, 400, latency));
+        }
+        assert_eq!(generator.next().unwrap().load, 300);
+        assert_eq!(generator.iterations, 3);
+
+        // The breaking point reports the spread of the trials.
+        let breaking_point = generator.breaking_point().unwrap();
+        assert_eq!(breaking_point.load, 200);
+        assert_eq!(breaking_point.tps.mean, 200.0);
         assert_eq!(
-            generator.upper_bound_result.unwrap().transaction_load(),
-            200
+            (breaking_point.tps.min, breaking_point.tps.max),
+            (195.0, 205.0)
         );
+        assert!(breaking_point.tps.margin > 0.0);
+        assert_eq!(breaking_point.upper_bound, Some(400));
+    }
+
+    #[test]
+    fn find_breaking_point() {
+        // A system with a capacity of 730 tx/s, whose latency explodes beyond.
+        let capacity = 730;
+        let mut generator = search(100, 1);
+        let mut runs = 0;
+        while let Some(parameters) = generator.next() {
+            let load = parameters.load;
+            let (tps, latency) = if load <= capacity {
+                (load, 500)
+            } else {
+                (capacity, 5_000)
+            };
+            generator.register_result(result(parameters, tps, latency));
+            runs += 1;
+        }
+
+        // Loads 100, 200, 400, 800, 600, 700, 750 and 725.
+        assert_eq!(runs, 8);
+        let breaking_point = generator.breaking_point().unwrap();
+        assert_eq!(breaking_point.load, 725);
+        assert_eq!(breaking_point.tps.mean, 725.0);
+        assert_eq!(breaking_point.tps.margin, 0.0);
+        assert_eq!(breaking_point.upper_bound, Some(750));
+    }
+
+    #[test]
+    fn overloaded_starting_load() {
+        // The search lowers the load until the system keeps up.
+        let mut generator = search(1_000, 1);
+        let parameters = generator.next().unwrap();
+        generator.register_result(result(parameters, 300, 500));
+        assert_eq!(generator.next().unwrap().load, 500);
+        assert!(generator.breaking_point().is_none());
     }
 
     #[test]
@@ -379,6 +682,8 @@ pub mod test {
         let load = LoadType::Search {
             starting_load: 100,
             max_iterations: 0,
+            trials: 1,
+            precision: 5,
         };
         let mut generator = BenchmarkParametersGenerator::<TestBenchmarkType>::new(nodes, load);
         let parameters = generator.next().unwrap();
@@ -0,0 +1,236 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    env,
+    fmt::Display,
+    fs, io,
+    net::Ipv4Addr,
+    os::unix,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    error::{CloudProviderError, CloudProviderResult},
+    settings::Settings,
+};
+
+use super::{Instance, ServerProviderClient};
+
+/// A testbed made of processes on the local machine. Each instance is a home directory under the
+/// testbed's root directory, in which its commands run (see `SshConnectionManager::local`). All
+/// instances share the repository from which the orchestrator runs, and nodes bind to distinct
+/// ports of the loopback address (see `Parameters::BENCHMARK_PORT_OFFSET`).
+pub struct LocalClient {
+    settings: Settings,
+    /// The directory holding the home directories of the instances.
+    root: PathBuf,
+    /// The local checkout of the repository.
+    repository: PathBuf,
+}
+
+impl Display for LocalClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Local ({})", self.root.display())
+    }
+}
+
+impl LocalClient {
+    /// The file describing an instance, in its home directory.
+    const INSTANCE_FILE: &'static str = "instance.json";
+
+    /// Make a new local client. The orchestrator must run from the root of the repository.
+    pub fn new(settings: Settings) -> Self {
+        let root = Self::root(&settings);
+        Self::new_with_root(settings, root)
+    }
+
+    fn new_with_root(settings: Settings, root: PathBuf) -> Self {
+        Self {
+            settings,
+            root,
+            repository: env::current_dir().expect("Failed to read the current directory"),
+        }
+    }
+
+    /// The directory holding the home directories of the instances of the testbed.
+    pub fn root(settings: &Settings) -> PathBuf {
+        env::temp_dir().join(format!("{}-local", settings.testbed_id))
+    }
+
+    /// The home directory of an instance.
+    pub fn home(root: &Path, instance: &Instance) -> PathBuf {
+        root.join(&instance.id)
+    }
+
+    /// Make an error signaling a failure to access the files of a local instance.
+    fn error<P: AsRef<Path>>(path: P, error: io::Error) -> CloudProviderError {
+        CloudProviderError::LocalInstanceError {
+            path: path.as_ref().display().to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Persist the description of an instance, so that it is listed by later invocations.
+    fn save(&self, instance: &Instance) -> CloudProviderResult<()> {
+        let path = Self::home(&self.root, instance).join(Self::INSTANCE_FILE);
+        let content = serde_json::to_vec_pretty(instance)?;
+        fs::write(&path, content).map_err(|e| Self::error(&path, e))
+    }
+
+    /// Link the repository and the rust toolchain into the home directory of a new instance.
+    fn setup_home(&self, home: &Path) -> CloudProviderResult<()> {
+        let mut links = vec![(self.repository.clone(), self.settings.repository_name())];
+        let user_home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+        for (variable, directory) in [("CARGO_HOME", ".cargo"), ("RUSTUP_HOME", ".rustup")] {
+            let target = env::var_os(variable)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| user_home.join(directory));
+            if target.exists() {
+                links.push((target, directory.into()));
+            }
+        }
+        for (target, name) in links {
+            let link = home.join(name);
+            unix::fs::symlink(&target, &link).map_err(|e| Self::error(&link, e))?;
+        }
+        Ok(())
+    }
+
+    /// Set the status of the specified instances.
+    fn set_status<'a, I>(&self, instances: I, status: &str) -> CloudProviderResult<()>
+    where
+        I: Iterator<Item = &'a Instance>,
+    {
+        for instance in instances {
+            let mut instance = instance.clone();
+            instance.status = status.into();
+            self.save(&instance)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ServerProviderClient for LocalClient {
+    const USERNAME: &'static str = "local";
+    const REMOTE: bool = false;
+
+    async fn list_instances(&self) -> CloudProviderResult<Vec<Instance>> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Self::error(&self.root, e)),
+        };
+
+        let mut instances = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| Self::error(&self.root, e))?.path();
+            let file = path.join(Self::INSTANCE_FILE);
+            if file.exists() {
+                let content = fs::read(&file).map_err(|e| Self::error(&file, e))?;
+                instances.push(serde_json::from_slice::<Instance>(&content)?);
+            }
+        }
+        instances.sort_by_key(|instance| instance.id.parse::<usize>().unwrap_or(usize::MAX));
+        Ok(instances)
+    }
+
+    async fn start_instances<'a, I>(&self, instances: I) -> CloudProviderResult<()>
+    where
+        I: Iterator<Item = &'a Instance> + Send,
+    {
+        self.set_status(instances, "running")
+    }
+
+    async fn stop_instances<'a, I>(&self, instances: I) -> CloudProviderResult<()>
+    where
+        I: Iterator<Item = &'a Instance> + Send,
+    {
+        self.set_status(instances, "stopped")
+    }
+
+    async fn create_instance<S>(&self, region: S) -> CloudProviderResult<Instance>
+    where
+        S: Into<String> + Serialize + Send,
+    {
+        fs::create_dir_all(&self.root).map_err(|e| Self::error(&self.root, e))?;
+
+        // Take the first free index, creating the directory fails if it is already taken.
+        let mut id = 0;
+        let home = loop {
+            let home = self.root.join(id.to_string());
+            match fs::create_dir(&home) {
+                Ok(()) => break home,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => id += 1,
+                Err(e) => return Err(Self::error(&home, e)),
+            }
+        };
+        self.setup_home(&home)?;
+
+        let instance = Instance {
+            id: id.to_string(),
+            region: region.into(),
+            main_ip: Ipv4Addr::LOCALHOST,
+            tags: vec![self.settings.testbed_id.clone()],
+            specs: self.settings.specs.clone(),
+            status: "running".into(),
+        };
+        self.save(&instance)?;
+        Ok(instance)
+    }
+
+    async fn delete_instance(&self, instance: Instance) -> CloudProviderResult<()> {
+        // Symbolic links are removed without following them.
+        let home = Self::home(&self.root, &instance);
+        fs::remove_dir_all(&home).map_err(|e| Self::error(&home, e))
+    }
+
+    async fn register_ssh_public_key(&self, _public_key: String) -> CloudProviderResult<()> {
+        Ok(())
+    }
+
+    async fn instance_setup_commands(&self) -> CloudProviderResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        client::{local::LocalClient, ServerProviderClient},
+        settings::Settings,
+    };
+
+    #[tokio::test]
+    async fn local_instances() {
+        let settings = Settings::new_for_test();
+        let root = tempfile::tempdir().unwrap();
+        let client = LocalClient::new_with_root(settings.clone(), root.path().to_path_buf());
+        assert!(client.list_instances().await.unwrap().is_empty());
+
+        for _ in 0..3 {
+            client.create_instance("local").await.unwrap();
+        }
+        let instances = client.list_instances().await.unwrap();
+        let ids: Vec<_> = instances.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, ["0", "1", "2"]);
+        assert!(instances.iter().all(|x| x.is_active()));
+        let repository = LocalClient::home(root.path(), &instances[0]).join("repo");
+        assert!(repository.join("Cargo.toml").exists());
+
+        // Instances are persisted across clients.
+        client.stop_instances(instances[..2].iter()).await.unwrap();
+        let client = LocalClient::new_with_root(settings, root.path().to_path_buf());
+        let instances = client.list_instances().await.unwrap();
+        assert_eq!(instances.iter().filter(|x| x.is_inactive()).count(), 2);
+
+        // Deleted indices are reused.
+        client.delete_instance(instances[1].clone()).await.unwrap();
+        assert_eq!(client.list_instances().await.unwrap().len(), 2);
+        let instance = client.create_instance("local").await.unwrap();
+        assert_eq!(instance.id, "1");
+    }
+}
@@ -11,10 +11,11 @@ use serde::{Deserialize, Serialize};
 use super::error::CloudProviderResult;
 
 pub mod aws;
+pub mod local;
 pub mod vultr;
 
 /// Represents a cloud provider instance.
-#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 pub struct Instance {
     /// The unique identifier of the instance.
     pub id: String,
@@ -69,6 +70,10 @@ pub trait ServerProviderClient: Display {
     /// The username used to connect to the instances.
     const USERNAME: &'static str;
 
+    /// Whether the instances are remote machines accessed through ssh. Commands for local
+    /// instances run on this machine instead.
+    const REMOTE: bool = true;
+
     /// List all existing instances (regardless of their status).
     async fn list_instances(&self) -> CloudProviderResult<Vec<Instance>>;
 
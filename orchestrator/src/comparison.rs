@@ -0,0 +1,509 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use plotters::prelude::*;
+use prettytable::{row, Cell, Row, Table};
+
+use crate::{
+    benchmark::{BenchmarkParameters, BenchmarkType},
+    display,
+    error::{ReportError, ReportResult},
+    measurement::MeasurementsCollection,
+};
+
+/// The identifier of a measurement type.
+type Label = String;
+
+/// The parameters by which the runs of different result sets are aligned: the benchmark type,
+/// the committee size, the faults (as formatted in the file names) and the load.
+type RunId<T> = (T, usize, String, usize);
+
+/// The measurements of a set of benchmark runs, e.g., the results of a commit.
+pub struct ResultSet<T> {
+    /// The name of the result set in the reports.
+    pub name: String,
+    /// The measurements of each benchmark run.
+    pub collections: Vec<MeasurementsCollection<T>>,
+}
+
+impl<T: BenchmarkType> ResultSet<T> {
+    /// Load a result set from a measurements file or from a directory of measurements files.
+    pub fn load<P: AsRef<Path>>(path: P) -> ReportResult<Self> {
+        let path = path.as_ref();
+        let files = if path.is_dir() {
+            let pattern = path.join("measurements-*.json");
+            let mut files: Vec<_> = glob::glob(&pattern.to_string_lossy())
+                .map_err(|e| load_error(path, e))?
+                .filter_map(|x| x.ok())
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let collections = files
+            .iter()
+            .map(|file| MeasurementsCollection::load(file).map_err(|e| load_error(file, e)))
+            .collect::<ReportResult<Vec<_>>>()?;
+        if collections.is_empty() {
+            return Err(ReportError::EmptyResultSet(path.display().to_string()));
+        }
+
+        let name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Ok(Self { name, collections })
+    }
+}
+
+/// The statistics of a workload in a benchmark run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorkloadStatistics {
+    pub tps: u64,
+    pub average_latency: Duration,
+    pub p50_latency: Duration,
+    pub p99_latency: Duration,
+}
+
+impl WorkloadStatistics {
+    pub fn new<T: BenchmarkType>(collection: &MeasurementsCollection<T>, label: &Label) -> Self {
+        Self {
+            tps: collection.aggregate_tps(label),
+            average_latency: collection.aggregate_average_latency(label),
+            p50_latency: collection.aggregate_latency_percentile(label, 50.0),
+            p99_latency: collection.aggregate_latency_percentile(label, 99.0),
+        }
+    }
+
+    /// The mean statistics of (at least one) trials of the same benchmark run.
+    pub fn mean(trials: &[Self]) -> Self {
+        let n = trials.len() as u32;
+        let latency = |f: fn(&Self) -> Duration| trials.iter().map(f).sum::<Duration>() / n;
+        Self {
+            tps: trials.iter().map(|x| x.tps).sum::<u64>() / n as u64,
+            average_latency: latency(|x| x.average_latency),
+            p50_latency: latency(|x| x.p50_latency),
+            p99_latency: latency(|x| x.p99_latency),
+        }
+    }
+}
+
+/// A workload of a benchmark run, in each result set.
+pub struct Comparison<T> {
+    /// The parameters of the benchmark run.
+    pub parameters: BenchmarkParameters<T>,
+    /// The workload.
+    pub label: Label,
+    /// The statistics of the workload in each result set (if the result set measured it), the
+    /// mean of its trials.
+    pub statistics: Vec<Option<WorkloadStatistics>>,
+}
+
+impl<T> Comparison<T> {
+    /// The relative change (in percent) of a statistic of the specified result set, compared to
+    /// the baseline (the first result set).
+    pub fn change<F>(&self, result_set: usize, statistic: F) -> Option<f64>
+    where
+        F: Fn(&WorkloadStatistics) -> f64,
+    {
+        let baseline = statistic(self.statistics.first()?.as_ref()?);
+        let value = statistic(self.statistics.get(result_set)?.as_ref()?);
+        (baseline != 0.0).then_some((value - baseline) / baseline * 100.0)
+    }
+
+    /// Whether the specified result set lost more throughput, or more latency (median or tail),
+    /// than the threshold (in percent) compared to the baseline.
+    pub fn is_regression(&self, result_set: usize, threshold: f64) -> bool {
+        let tps = self.change(result_set, |x| x.tps as f64);
+        let p50 = self.change(result_set, |x| x.p50_latency.as_secs_f64());
+        let p99 = self.change(result_set, |x| x.p99_latency.as_secs_f64());
+        tps.map_or(false, |x| x < -threshold)
+            || p50.map_or(false, |x| x > threshold)
+            || p99.map_or(false, |x| x > threshold)
+    }
+}
+
+/// Compare result sets against the first one (the baseline), run by run.
+pub struct BenchmarkComparison<T> {
+    /// The result sets to compare, starting with the baseline.
+    result_sets: Vec<ResultSet<T>>,
+    /// The change (in percent) of throughput or latency considered as a regression.
+    threshold: f64,
+}
+
+impl<T: BenchmarkType> BenchmarkComparison<T> {
+    /// The default change (in percent) considered as a regression.
+    pub const DEFAULT_THRESHOLD: f64 = 10.0;
+
+    /// Make a new comparison of the specified result sets.
+    pub fn new(result_sets: Vec<ResultSet<T>>) -> Self {
+        Self {
+            result_sets,
+            threshold: Self::DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Set the change (in percent) of throughput or latency considered as a regression.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Align the workloads of the benchmark runs of all result sets by their parameters, merging
+    /// the trials of a run into their mean.
+    pub fn comparisons(&self) -> Vec<Comparison<T>> {
+        let mut runs = BTreeMap::new();
+        for (i, result_set) in self.result_sets.iter().enumerate() {
+            for collection in &result_set.collections {
+                let parameters = &collection.parameters;
+                let id: RunId<T> = (
+                    parameters.benchmark_type.clone(),
+                    parameters.nodes,
+                    format!("{:?}", parameters.faults),
+                    parameters.load,
+                );
+                for label in collection.labels() {
+                    let (_, trials) =
+                        runs.entry((id.clone(), label.clone())).or_insert_with(|| {
+                            (parameters.clone(), vec![Vec::new(); self.result_sets.len()])
+                        });
+                    trials[i].push(WorkloadStatistics::new(collection, label));
+                }
+            }
+        }
+        runs.into_iter()
+            .map(|((_, label), (parameters, trials))| Comparison {
+                parameters,
+                label,
+                statistics: trials
+                    .iter()
+                    .map(|x| (!x.is_empty()).then(|| WorkloadStatistics::mean(x)))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The number of workloads in which a result set regressed compared to the baseline.
+    pub fn regressions(&self) -> usize {
+        self.comparisons()
+            .iter()
+            .map(|x| {
+                (1..self.result_sets.len())
+                    .filter(|i| x.is_regression(*i, self.threshold))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Display a table comparing the result sets, flagging regressions.
+    pub fn display(&self) {
+        let mut table = Table::new();
+        table.set_format(display::default_table_format());
+
+        let baseline = self.result_sets.first().map(|x| x.name.as_str());
+        table.set_titles(row![bH11->format!(
+            "Benchmark Comparison (baseline: {}, threshold: {}%)",
+            baseline.unwrap_or_default(),
+            self.threshold
+        )]);
+        table.add_row(row![
+            b->"Parameters",
+            b->"Workload",
+            b->"Results",
+            b->"TPS",
+            b->"Latency (avg)",
+            b->"Latency (p50)",
+            b->"Latency (p99)",
+            b->"TPS change",
+            b->"p50 change",
+            b->"p99 change",
+            b->""
+        ]);
+
+        for comparison in self.comparisons() {
+            for (i, result_set) in self.result_sets.iter().enumerate() {
+                let mut cells = if i == 0 {
+                    vec![
+                        Cell::new(&comparison.parameters.to_string()),
+                        Cell::new(&comparison.label),
+                    ]
+                } else {
+                    vec![Cell::new(""), Cell::new("")]
+                };
+                cells.push(Cell::new(&result_set.name));
+
+                match &comparison.statistics[i] {
+                    Some(statistics) => {
+                        cells.push(Cell::new(&format!("{} tx/s", statistics.tps)));
+                        for latency in [
+                            statistics.average_latency,
+                            statistics.p50_latency,
+                            statistics.p99_latency,
+                        ] {
+                            cells.push(Cell::new(&format!("{} ms", latency.as_millis())));
+                        }
+                    }
+                    None => cells.extend((0..4).map(|_| Cell::new("-"))),
+                }
+
+                if i != 0 {
+                    let changes = [
+                        comparison.change(i, |x| x.tps as f64),
+                        comparison.change(i, |x| x.p50_latency.as_secs_f64()),
+                        comparison.change(i, |x| x.p99_latency.as_secs_f64()),
+                    ];
+                    for change in changes {
+                        let change = change.map(|x| format!("{x:+.1}%"));
+                        cells.push(Cell::new(change.as_deref().unwrap_or("-")));
+                    }
+                    if comparison.is_regression(i, self.threshold) {
+                        cells.push(Cell::new("regression").style_spec("bFr"));
+                    }
+                }
+                table.add_row(Row::new(cells));
+            }
+        }
+
+        display::newline();
+        table.printstd();
+        display::newline();
+
+        match self.regressions() {
+            0 => display::config("Regressions", "none"),
+            n => display::error(format!(
+                "{n} regression(s) beyond {}% compared to the baseline",
+                self.threshold
+            )),
+        }
+    }
+
+    /// Plot the L-graphs (median latency vs throughput, with the tail latency as error bar) of
+    /// each result set in the specified directory, one per set of benchmark parameters (but the
+    /// load) and workload. Return the paths of the plots.
+    pub fn plot<P: AsRef<Path>>(&self, directory: P) -> ReportResult<Vec<PathBuf>> {
+        std::fs::create_dir_all(&directory).map_err(|e| plot_error(directory.as_ref(), e))?;
+
+        // Group the benchmark runs by L-graph, in increasing order of load.
+        let mut graphs = BTreeMap::new();
+        for comparison in self.comparisons() {
+            let parameters = &comparison.parameters;
+            let name = format!(
+                "l-graph-{:?}-{:?}-{}-{}",
+                parameters.benchmark_type, parameters.faults, parameters.nodes, comparison.label
+            );
+            graphs.entry(name).or_insert_with(Vec::new).push(comparison);
+        }
+
+        let mut paths = Vec::new();
+        for (name, comparisons) in graphs {
+            let name: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let path = directory.as_ref().join(format!("{name}.svg"));
+            self.plot_l_graph(&path, &comparisons)
+                .map_err(|e| plot_error(&path, e))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    fn plot_l_graph(
+        &self,
+        path: &Path,
+        comparisons: &[Comparison<T>],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // The points (tps, p50 latency, p99 latency) of each result set.
+        let series: Vec<Vec<_>> = (0..self.result_sets.len())
+            .map(|i| {
+                comparisons
+                    .iter()
+                    .filter_map(|x| x.statistics[i])
+                    .map(|x| {
+                        let p50 = x.p50_latency.as_secs_f64();
+                        (x.tps as f64, p50, x.p99_latency.as_secs_f64().max(p50))
+                    })
+                    .collect()
+            })
+            .collect();
+        let points = series.iter().flatten();
+        let max_tps = points.clone().map(|x| x.0).fold(1.0, f64::max);
+        let max_latency = points.map(|x| x.2).fold(0.1, f64::max);
+
+        let parameters = &comparisons[0].parameters;
+        let title = format!(
+            "{} nodes ({}) - {} - {}",
+            parameters.nodes, parameters.faults, parameters.benchmark_type, comparisons[0].label
+        );
+
+        let root = SVGBackend::new(path, (960, 540)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 20))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(0.0..max_tps * 1.1, 0.0..max_latency * 1.1)?;
+        chart
+            .configure_mesh()
+            .x_desc("Throughput (tx/s)")
+            .y_desc("Latency (s), p50 to p99")
+            .draw()?;
+
+        for (i, points) in series.into_iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(
+                    points.iter().map(|(tps, p50, _)| (*tps, *p50)),
+                    color.stroke_width(2),
+                ))?
+                .label(&self.result_sets[i].name)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            chart.draw_series(points.iter().map(|(tps, p50, p99)| {
+                ErrorBar::new_vertical(*tps, *p50, *p50, *p99, color.filled(), 8)
+            }))?;
+        }
+
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+        Ok(())
+    }
+}
+
+fn load_error<E: Display>(path: &Path, error: E) -> ReportError {
+    ReportError::LoadError {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    }
+}
+
+fn plot_error<E: Display>(path: &Path, error: E) -> ReportError {
+    ReportError::PlotError {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        benchmark::{test::TestBenchmarkType, BenchmarkParameters},
+        faults::FaultsType,
+        measurement::MeasurementsCollection,
+    };
+
+    use super::{BenchmarkComparison, ResultSet};
+
+    /// A result set with one run per load, measuring the specified (tps, latency in ms).
+    fn result_set(name: &str, runs: &[(usize, usize, u64)]) -> ResultSet<TestBenchmarkType> {
+        let collections = runs
+            .iter()
+            .map(|(load, tps, latency)| {
+                let parameters = BenchmarkParameters::new(
+                    TestBenchmarkType,
+                    4,
+                    FaultsType::default(),
+                    *load,
+                    Duration::from_secs(10),
+                );
+                MeasurementsCollection::new_for_test(
+                    parameters,
+                    *tps,
+                    Duration::from_millis(*latency),
+                )
+            })
+            .collect();
+        ResultSet {
+            name: name.into(),
+            collections,
+        }
+    }
+
+    #[test]
+    fn align_result_sets() {
+        let baseline = result_set("baseline", &[(100, 100, 500), (200, 200, 500)]);
+        let other = result_set("other", &[(200, 150, 500), (300, 300, 600)]);
+        let comparison = BenchmarkComparison::new(vec![baseline, other]);
+
+        let comparisons = comparison.comparisons();
+        let loads: Vec<_> = comparisons.iter().map(|x| x.parameters.load).collect();
+        assert_eq!(loads, vec![100, 200, 300]);
+        assert!(comparisons[0].statistics[1].is_none());
+        assert!(comparisons[2].statistics[0].is_none());
+        assert_eq!(comparisons[1].statistics[1].unwrap().tps, 150);
+
+        // Only the loss of throughput at load 200 is beyond the threshold.
+        let tps_change = comparisons[1].change(1, |x| x.tps as f64).unwrap();
+        assert!((tps_change + 25.0).abs() < 1e-9);
+        assert!(comparisons[1].is_regression(1, 10.0));
+        assert!(!comparisons[1].is_regression(1, 30.0));
+        assert!(!comparisons[2].is_regression(1, 10.0));
+        assert_eq!(comparison.regressions(), 1);
+    }
+
+    #[test]
+    fn aggregate_trials() {
+        // The trials of the same run are saved in different files.
+        let directory = tempfile::tempdir().unwrap();
+        let trials = result_set("trials", &[(100, 90, 400), (100, 110, 600)]);
+        for (i, collection) in trials.collections.into_iter().enumerate() {
+            collection.with_trial(i).save(directory.path());
+        }
+        let baseline = ResultSet::<TestBenchmarkType>::load(directory.path()).unwrap();
+        assert_eq!(baseline.collections.len(), 2);
+
+        // The trials of the same run are merged into their mean.
+        let other = result_set("other", &[(100, 100, 500)]);
+        let comparison = BenchmarkComparison::new(vec![baseline, other]);
+        let comparisons = comparison.comparisons();
+        assert_eq!(comparisons.len(), 1);
+        let statistics = comparisons[0].statistics[0].unwrap();
+        assert_eq!(statistics.tps, 100);
+        assert_eq!(statistics.average_latency, Duration::from_millis(500));
+        assert_eq!(comparison.regressions(), 0);
+    }
+
+    #[test]
+    fn latency_regression() {
+        let baseline = result_set("baseline", &[(100, 100, 500)]);
+        let other = result_set("other", &[(100, 100, 600)]);
+        let comparison = BenchmarkComparison::new(vec![baseline, other]).with_threshold(15.0);
+        assert_eq!(comparison.regressions(), 1);
+        assert!(!comparison.comparisons()[0].is_regression(0, 15.0));
+    }
+
+    #[test]
+    fn plot_l_graphs() {
+        let baseline = result_set("baseline", &[(100, 100, 500), (200, 200, 500)]);
+        let other = result_set("other", &[(100, 100, 520), (200, 150, 800)]);
+        let comparison = BenchmarkComparison::new(vec![baseline, other]);
+
+        let directory = tempfile::tempdir().unwrap();
+        let paths = comparison.plot(directory.path()).unwrap();
+        assert_eq!(paths.len(), 1);
+        let svg = std::fs::read_to_string(&paths[0]).unwrap();
+        assert!(svg.contains("baseline") && svg.contains("other"));
+    }
+}
@@ -46,6 +46,9 @@ pub enum CloudProviderError {
 
     #[error("SSH key \"{0}\" not found")]
     SshKeyNotFound(String),
+
+    #[error("Failed to access local instance '{path}': {message}")]
+    LocalInstanceError { path: String, message: String },
 }
 
 pub type SshResult<T> = Result<T, SshError>;
@@ -83,6 +86,20 @@ pub enum MonitorError {
     GrafanaError(String),
 }
 
+pub type ReportResult<T> = Result<T, ReportError>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReportError {
+    #[error("Failed to load measurements '{path}': {message}")]
+    LoadError { path: String, message: String },
+
+    #[error("No measurements found in '{0}'")]
+    EmptyResultSet(String),
+
+    #[error("Failed to plot '{path}': {message}")]
+    PlotError { path: String, message: String },
+}
+
 pub type TestbedResult<T> = Result<T, TestbedError>;
 
 #[derive(thiserror::Error, Debug)]
@@ -3,6 +3,7 @@
 
 use std::{
     fmt::{Debug, Display},
+    str::FromStr,
     time::Duration,
 };
 
@@ -19,6 +20,11 @@ pub enum FaultsType {
         max_faults: usize,
         interval: Duration,
     },
+    /// Degrade the network of all nodes, and restore it every interval (if any).
+    Network {
+        conditions: NetworkConditions,
+        interval: Option<Duration>,
+    },
 }
 
 impl Default for FaultsType {
@@ -35,6 +41,13 @@ impl Debug for FaultsType {
                 max_faults,
                 interval,
             } => write!(f, "{max_faults}-{}cr", interval.as_secs()),
+            Self::Network {
+                conditions,
+                interval,
+            } => match interval {
+                Some(interval) => write!(f, "{conditions:?}-{}r", interval.as_secs()),
+                None => write!(f, "{conditions:?}"),
+            },
         }
     }
 }
@@ -53,10 +66,177 @@ impl Display for FaultsType {
                 max_faults,
                 interval,
             } => write!(f, "{max_faults} crash-recovery, {}s", interval.as_secs()),
+            Self::Network {
+                conditions,
+                interval,
+            } => match interval {
+                Some(interval) => {
+                    write!(f, "{conditions}, recovering every {}s", interval.as_secs())
+                }
+                None => write!(f, "{conditions}"),
+            },
+        }
+    }
+}
+
+/// A named group of nodes, identified by their index in the committee.
+#[derive(Clone, Serialize, Deserialize, Hash, PartialEq, Eq, Debug)]
+pub struct NodeGroup {
+    pub name: String,
+    pub nodes: Vec<usize>,
+}
+
+impl FromStr for NodeGroup {
+    type Err = String;
+
+    /// Parse a group of the form `name=0,1,2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, nodes) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected a group of the form 'name=0,1,2', got '{s}'"))?;
+        let nodes = nodes
+            .split(',')
+            .map(|x| {
+                x.trim()
+                    .parse()
+                    .map_err(|e| format!("Invalid node '{x}': {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name: name.into(),
+            nodes,
+        })
+    }
+}
+
+/// Degraded network conditions, emulated with netem (`tc`) and iptables on the nodes.
+#[derive(Clone, Default, Serialize, Deserialize, Hash, PartialEq, Eq)]
+pub struct NetworkConditions {
+    /// The delay added to the packets sent by the nodes.
+    pub latency: Duration,
+    /// The random variation of the delay.
+    pub jitter: Duration,
+    /// The per-mille of packets sent by the nodes that are dropped.
+    pub packet_loss: u32,
+    /// The maximum bandwidth of the nodes in Mbit/s (zero for unlimited).
+    pub bandwidth: u64,
+    /// Groups of nodes that cannot reach each other. Nodes outside of all groups are unaffected.
+    pub partition: Vec<NodeGroup>,
+}
+
+impl Debug for NetworkConditions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "net{}-{}-{}-{}-{}p",
+            self.latency.as_millis(),
+            self.jitter.as_millis(),
+            self.packet_loss,
+            self.bandwidth,
+            self.partition.len()
+        )
+    }
+}
+
+impl Display for NetworkConditions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut conditions = Vec::new();
+        if !self.latency.is_zero() || !self.jitter.is_zero() {
+            conditions.push(format!(
+                "{}±{}ms latency",
+                self.latency.as_millis(),
+                self.jitter.as_millis()
+            ));
+        }
+        if self.packet_loss != 0 {
+            conditions.push(format!("{} loss", self.packet_loss_percentage()));
+        }
+        if self.bandwidth != 0 {
+            conditions.push(format!("{} Mbit/s", self.bandwidth));
+        }
+        if !self.partition.is_empty() {
+            let groups: Vec<_> = self.partition.iter().map(|x| x.name.as_str()).collect();
+            conditions.push(format!("partition {}", groups.join("|")));
+        }
+        if conditions.is_empty() {
+            write!(f, "healthy network")
+        } else {
+            write!(f, "{}", conditions.join(", "))
         }
     }
 }
 
+impl NetworkConditions {
+    /// The iptables chain holding the rules of the partition.
+    const CHAIN: &'static str = "mysticeti-faults";
+    /// The network interface of the instances, i.e., the one of their default route.
+    const DEVICE: &'static str = "$(ip route show default | awk '{print $5; exit}')";
+
+    fn packet_loss_percentage(&self) -> String {
+        format!("{}.{}%", self.packet_loss / 10, self.packet_loss % 10)
+    }
+
+    /// The command restoring the network of a node.
+    pub fn restore_command() -> String {
+        let (device, chain) = (Self::DEVICE, Self::CHAIN);
+        [
+            format!("(sudo tc qdisc del dev {device} root || true)"),
+            format!("(sudo iptables -D INPUT -j {chain} || true)"),
+            format!("(sudo iptables -F {chain} || true)"),
+            format!("(sudo iptables -X {chain} || true)"),
+        ]
+        .join(" ; ")
+    }
+
+    /// The command degrading the network of the node at the specified index of the committee.
+    pub fn degrade_command(&self, node: usize, nodes: &[Instance]) -> String {
+        let mut command = vec![Self::restore_command()];
+
+        let mut netem = Vec::new();
+        if !self.latency.is_zero() || !self.jitter.is_zero() {
+            netem.push(format!(
+                "delay {}ms {}ms",
+                self.latency.as_millis(),
+                self.jitter.as_millis()
+            ));
+        }
+        if self.packet_loss != 0 {
+            netem.push(format!("loss {}", self.packet_loss_percentage()));
+        }
+        if self.bandwidth != 0 {
+            netem.push(format!("rate {}mbit", self.bandwidth));
+        }
+        if !netem.is_empty() {
+            command.push(format!(
+                "sudo tc qdisc add dev {} root netem {}",
+                Self::DEVICE,
+                netem.join(" ")
+            ));
+        }
+
+        // Drop the packets from the nodes of the other groups; they do the same on their side.
+        let group = self.partition.iter().find(|x| x.nodes.contains(&node));
+        if let Some(group) = group {
+            let chain = Self::CHAIN;
+            command.push(format!("sudo iptables -N {chain}"));
+            command.push(format!("sudo iptables -I INPUT -j {chain}"));
+            let others = self
+                .partition
+                .iter()
+                .filter(|x| x.name != group.name)
+                .flat_map(|x| x.nodes.iter())
+                .filter_map(|i| nodes.get(*i));
+            for instance in others {
+                command.push(format!(
+                    "sudo iptables -A {chain} -s {} -j DROP",
+                    instance.main_ip
+                ));
+            }
+        }
+        command.join(" && ")
+    }
+}
+
 /// The actions to apply to the testbed, i.e., which instances to crash and recover.
 #[derive(Default)]
 pub struct CrashRecoveryAction {
@@ -155,17 +335,119 @@ impl CrashRecoverySchedule {
                     CrashRecoveryAction::kill(instances)
                 }
             }
+
+            // Network faults do not crash nodes.
+            FaultsType::Network { .. } => CrashRecoveryAction::no_op(),
+        }
+    }
+}
+
+/// The commands to run on the testbed to degrade or restore the network of the nodes.
+#[derive(Default)]
+pub struct NetworkFaultsAction {
+    /// The command to run on each instance.
+    pub commands: Vec<(Instance, String)>,
+    /// Whether the commands degrade (rather than restore) the network.
+    pub degrade: bool,
+}
+
+impl Display for NetworkFaultsAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nodes = self.commands.len();
+        if self.degrade {
+            write!(f, "network of {nodes} node(s) degraded")
+        } else {
+            write!(f, "network of {nodes} node(s) restored")
+        }
+    }
+}
+
+impl NetworkFaultsAction {
+    pub fn no_op() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+pub struct NetworkFaultsSchedule {
+    /// The network conditions to apply and the recovery pattern to follow.
+    faults_type: FaultsType,
+    /// The nodes, in the order of the committee.
+    instances: Vec<Instance>,
+    /// Whether the network is currently degraded.
+    degraded: bool,
+}
+
+impl NetworkFaultsSchedule {
+    pub fn new(faults_type: FaultsType, instances: Vec<Instance>) -> Self {
+        Self {
+            faults_type,
+            instances,
+            degraded: false,
+        }
+    }
+
+    pub fn update(&mut self) -> NetworkFaultsAction {
+        match &self.faults_type {
+            // Degrade the network, and periodically restore it if there is a recovery interval.
+            FaultsType::Network {
+                conditions,
+                interval,
+            } => {
+                if !self.degraded {
+                    self.degraded = true;
+                    let commands = (0..self.instances.len())
+                        .map(|i| {
+                            let command = conditions.degrade_command(i, &self.instances);
+                            (self.instances[i].clone(), command)
+                        })
+                        .collect();
+                    NetworkFaultsAction {
+                        commands,
+                        degrade: true,
+                    }
+                } else if interval.is_some() {
+                    self.restore()
+                } else {
+                    NetworkFaultsAction::no_op()
+                }
+            }
+
+            // Crash faults leave the network untouched.
+            _ => NetworkFaultsAction::no_op(),
+        }
+    }
+
+    /// Restore the network of the nodes if it is degraded.
+    pub fn restore(&mut self) -> NetworkFaultsAction {
+        if !self.degraded {
+            return NetworkFaultsAction::no_op();
+        }
+        self.degraded = false;
+        let command = NetworkConditions::restore_command();
+        NetworkFaultsAction {
+            commands: self
+                .instances
+                .iter()
+                .map(|instance| (instance.clone(), command.clone()))
+                .collect(),
+            degrade: false,
         }
     }
 }
 
 #[cfg(test)]
 mod faults_tests {
-    use std::time::Duration;
+    use std::{net::Ipv4Addr, time::Duration};
 
     use crate::client::Instance;
 
-    use super::{CrashRecoverySchedule, FaultsType};
+    use super::{
+        CrashRecoverySchedule, FaultsType, NetworkConditions, NetworkFaultsSchedule, NodeGroup,
+    };
 
     #[test]
     fn crash_recovery_1_fault() {
@@ -270,4 +552,89 @@ mod faults_tests {
             assert_eq!(action.kill.len(), min_faults);
         }
     }
+
+    #[test]
+    fn parse_node_group() {
+        let group: NodeGroup = "west=0, 2,3".parse().unwrap();
+        assert_eq!(group.name, "west");
+        assert_eq!(group.nodes, vec![0, 2, 3]);
+
+        assert!("west".parse::<NodeGroup>().is_err());
+        assert!("west=0,a".parse::<NodeGroup>().is_err());
+    }
+
+    #[test]
+    fn network_faults() {
+        let instances: Vec<_> = (0..4)
+            .map(|i| Instance {
+                main_ip: Ipv4Addr::new(10, 0, 0, i + 1),
+                ..Instance::new_for_test(i.to_string())
+            })
+            .collect();
+        let conditions = NetworkConditions {
+            latency: Duration::from_millis(100),
+            jitter: Duration::from_millis(10),
+            packet_loss: 15,
+            bandwidth: 0,
+            partition: vec!["a=0,1".parse().unwrap(), "b=2".parse().unwrap()],
+        };
+        let mut schedule = NetworkFaultsSchedule::new(
+            FaultsType::Network {
+                conditions,
+                interval: Some(Duration::from_secs(60)),
+            },
+            instances,
+        );
+
+        let action = schedule.update();
+        assert!(action.degrade);
+        assert_eq!(action.commands.len(), 4);
+        let commands: Vec<_> = action.commands.iter().map(|(_, x)| x).collect();
+        for command in &commands {
+            assert!(command.contains("netem delay 100ms 10ms loss 1.5%"));
+            assert!(!command.contains("rate"));
+        }
+        // Nodes drop the packets of the other groups only.
+        assert!(commands[0].contains("-s 10.0.0.3 -j DROP"));
+        assert!(!commands[0].contains("-s 10.0.0.2 -j DROP"));
+        assert!(commands[2].contains("-s 10.0.0.1 -j DROP"));
+        assert!(commands[2].contains("-s 10.0.0.2 -j DROP"));
+        assert!(!commands[3].contains("iptables -A"));
+
+        // The network is periodically restored.
+        let action = schedule.update();
+        assert!(!action.degrade);
+        assert_eq!(action.commands.len(), 4);
+        assert!(schedule.update().degrade);
+    }
+
+    #[test]
+    fn permanent_network_faults() {
+        let instances = (0..4)
+            .map(|i| Instance::new_for_test(i.to_string()))
+            .collect();
+        let conditions = NetworkConditions {
+            bandwidth: 100,
+            ..Default::default()
+        };
+        let faults_type = FaultsType::Network {
+            conditions,
+            interval: None,
+        };
+        let mut schedule = NetworkFaultsSchedule::new(faults_type.clone(), instances);
+
+        let action = schedule.update();
+        assert!(action.degrade);
+        assert!(action.commands[0].1.contains("netem rate 100mbit"));
+        assert!(schedule.update().is_empty());
+        assert!(!schedule.restore().is_empty());
+        assert!(schedule.restore().is_empty());
+
+        // Network faults do not crash nodes.
+        let instances = (0..4)
+            .map(|i| Instance::new_for_test(i.to_string()))
+            .collect();
+        let action = CrashRecoverySchedule::new(faults_type, instances).update();
+        assert!(action.boot.is_empty() && action.kill.is_empty());
+    }
 }
@@ -1,13 +1,14 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{str::FromStr, time::Duration};
+use std::{collections::HashSet, str::FromStr, time::Duration};
 
 use benchmark::{BenchmarkParametersGenerator, LoadType};
 use clap::Parser;
-use client::{aws::AwsClient, vultr::VultrClient, ServerProviderClient};
+use client::{aws::AwsClient, local::LocalClient, vultr::VultrClient, ServerProviderClient};
+use comparison::{BenchmarkComparison, ResultSet};
 use eyre::{Context, Result};
-use faults::FaultsType;
+use faults::{FaultsType, NetworkConditions, NodeGroup};
 use measurement::MeasurementsCollection;
 use orchestrator::Orchestrator;
 use protocol::mysticeti::{MysticetiBenchmarkType, MysticetiProtocol};
@@ -17,6 +18,7 @@ use testbed::Testbed;
 
 pub mod benchmark;
 pub mod client;
+pub mod comparison;
 pub mod display;
 pub mod error;
 pub mod faults;
@@ -73,14 +75,35 @@ pub enum Operation {
         #[clap(long, value_name = "INT", default_value = "0", global = true)]
         faults: usize,
 
-        /// Whether the faulty nodes recover.
+        /// Whether the faulty nodes (or the degraded network) recover.
         #[clap(long, action, default_value = "false", global = true)]
         crash_recovery: bool,
 
-        /// The interval to crash nodes in seconds.
+        /// The interval to crash nodes (or degrade the network) in seconds.
         #[clap(long, value_parser = parse_duration, default_value = "60", global = true)]
         crash_interval: Duration,
 
+        /// The latency to add to the network of the nodes in milliseconds.
+        #[clap(long, value_parser = parse_millis, default_value = "0", global = true)]
+        latency: Duration,
+
+        /// The random variation of the added latency in milliseconds.
+        #[clap(long, value_parser = parse_millis, default_value = "0", global = true)]
+        jitter: Duration,
+
+        /// The percentage of packets dropped by the network of the nodes (e.g., 0.5).
+        #[clap(long, value_name = "FLOAT", value_parser = parse_percentage, default_value = "0", global = true)]
+        packet_loss: f64,
+
+        /// The bandwidth of the nodes in Mbit/s (zero for unlimited).
+        #[clap(long, value_name = "INT", default_value = "0", global = true)]
+        bandwidth: u64,
+
+        /// A group of nodes (e.g., 'west=0,1,2') that cannot reach the nodes of the other groups.
+        /// Repeat to partition the committee into several groups.
+        #[clap(long, value_name = "NAME=INT,..", global = true)]
+        partition: Vec<NodeGroup>,
+
         /// The minimum duration of the benchmark in seconds.
         #[clap(long, value_parser = parse_duration, default_value = "600", global = true)]
         duration: Duration,
@@ -129,6 +152,22 @@ pub enum Operation {
         #[clap(long, value_name = "FILE")]
         path: String,
     },
+
+    /// Compare result sets against the first one, and plot their L-graphs.
+    Compare {
+        /// The measurements files or directories (e.g., 'results/results-main') to compare. The
+        /// first one is the baseline.
+        #[clap(long, value_name = "PATH", num_args(2..), required = true)]
+        paths: Vec<String>,
+
+        /// The change of throughput or latency (in percent) flagged as a regression.
+        #[clap(long, value_name = "FLOAT", default_value = "10")]
+        threshold: f64,
+
+        /// The directory where to save the plots.
+        #[clap(long, value_name = "DIR", default_value = "plots")]
+        plots_dir: String,
+    },
 }
 
 #[derive(Parser)]
@@ -183,9 +222,15 @@ pub enum Load {
         /// The initial load (in tx/s) to test and use a baseline.
         #[clap(long, value_name = "INT", default_value = "250")]
         starting_load: usize,
-        /// The maximum number of iterations before converging on a breaking point.
-        #[clap(long, value_name = "INT", default_value = "5")]
+        /// The maximum number of loads to test before converging on a breaking point.
+        #[clap(long, value_name = "INT", default_value = "10")]
         max_iterations: usize,
+        /// The number of runs of each load, to estimate the confidence intervals of its measures.
+        #[clap(long, value_name = "INT", default_value = "3", value_parser = clap::value_parser!(u64).range(1..))]
+        trials: u64,
+        /// The precision (in percent of load) at which to stop searching the breaking point.
+        #[clap(long, value_name = "INT", default_value = "5")]
+        precision: usize,
     },
 }
 
@@ -194,6 +239,21 @@ fn parse_duration(arg: &str) -> Result<Duration, std::num::ParseIntError> {
     Ok(Duration::from_secs(seconds))
 }
 
+fn parse_millis(arg: &str) -> Result<Duration, std::num::ParseIntError> {
+    let millis = arg.parse()?;
+    Ok(Duration::from_millis(millis))
+}
+
+fn parse_percentage(arg: &str) -> Result<f64, String> {
+    let percentage: f64 = arg.parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..=100.0).contains(&percentage) {
+        return Err(format!(
+            "{percentage} is not a percentage between 0 and 100"
+        ));
+    }
+    Ok(percentage)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
@@ -217,6 +277,13 @@ async fn main() -> Result<()> {
                 .wrap_err("Failed to load cloud provider's token")?;
             let client = VultrClient::new(token, settings.clone());
 
+            // Execute the command.
+            run(settings, client, opts).await
+        }
+        CloudProvider::Local => {
+            // Create the client spawning the instances on the local machine.
+            let client = LocalClient::new(settings.clone());
+
             // Execute the command.
             run(settings, client, opts).await
         }
@@ -263,6 +330,11 @@ async fn run<C: ServerProviderClient>(settings: Settings, client: C, opts: Opts)
             faults,
             crash_recovery,
             crash_interval,
+            latency,
+            jitter,
+            packet_loss,
+            bandwidth,
+            partition,
             duration,
             scrape_interval,
             skip_testbed_update,
@@ -275,11 +347,20 @@ async fn run<C: ServerProviderClient>(settings: Settings, client: C, opts: Opts)
             load_type,
         } => {
             // Create a new orchestrator to instruct the testbed.
-            let username = testbed.username();
-            let private_key_file = settings.ssh_private_key_file.clone();
-            let ssh_manager = SshConnectionManager::new(username.into(), private_key_file)
-                .with_timeout(timeout)
-                .with_retries(retries);
+            let ssh_manager = if C::REMOTE {
+                let username = testbed.username();
+                let private_key_file = settings.ssh_private_key_file.clone();
+                SshConnectionManager::new(username.into(), private_key_file)
+                    .with_timeout(timeout)
+                    .with_retries(retries)
+            } else {
+                SshConnectionManager::local(LocalClient::root(&settings))
+            };
+
+            // Local instances run the code of the current checkout, and there is no dedicated
+            // machine for monitoring.
+            let skip_testbed_update = skip_testbed_update || !C::REMOTE;
+            let monitoring = monitoring && C::REMOTE;
 
             let instances = testbed.instances();
 
@@ -299,13 +380,49 @@ async fn run<C: ServerProviderClient>(settings: Settings, client: C, opts: Opts)
                 Load::Search {
                     starting_load,
                     max_iterations,
+                    trials,
+                    precision,
                 } => LoadType::Search {
                     starting_load,
                     max_iterations,
+                    trials: trials as usize,
+                    precision,
                 },
             };
 
-            let fault_type = if !crash_recovery || faults == 0 {
+            let network_conditions = NetworkConditions {
+                latency,
+                jitter,
+                packet_loss: (packet_loss * 10.0).round() as u32,
+                bandwidth,
+                partition,
+            };
+            let network_faults = network_conditions != NetworkConditions::default();
+            if network_faults {
+                if faults != 0 {
+                    eyre::bail!("Network faults cannot be combined with crash faults");
+                }
+                // Local instances share the network of the machine.
+                if !C::REMOTE {
+                    eyre::bail!("Network faults are not supported on local testbeds");
+                }
+                let mut partitioned = HashSet::new();
+                for node in network_conditions.partition.iter().flat_map(|x| &x.nodes) {
+                    if *node >= committee {
+                        eyre::bail!("Node {node} of the partition is not in the committee");
+                    }
+                    if !partitioned.insert(*node) {
+                        eyre::bail!("Node {node} is in more than one group of the partition");
+                    }
+                }
+            }
+
+            let fault_type = if network_faults {
+                FaultsType::Network {
+                    conditions: network_conditions,
+                    interval: crash_recovery.then_some(crash_interval),
+                }
+            } else if !crash_recovery || faults == 0 {
                 FaultsType::Permanent { faults }
             } else {
                 FaultsType::CrashRecovery {
@@ -342,6 +459,27 @@ async fn run<C: ServerProviderClient>(settings: Settings, client: C, opts: Opts)
         Operation::Summarize { path } => {
             MeasurementsCollection::<BenchmarkType>::load(path)?.display_summary()
         }
+
+        // Compare result sets and plot their L-graphs.
+        Operation::Compare {
+            paths,
+            threshold,
+            plots_dir,
+        } => {
+            let result_sets = paths
+                .iter()
+                .map(ResultSet::<BenchmarkType>::load)
+                .collect::<Result<Vec<_>, _>>()
+                .wrap_err("Failed to load result sets")?;
+            let comparison = BenchmarkComparison::new(result_sets).with_threshold(threshold);
+            comparison.display();
+            for path in comparison
+                .plot(plots_dir)
+                .wrap_err("Failed to plot L-graphs")?
+            {
+                display::config("Plot", path.display());
+            }
+        }
     }
     Ok(())
 }
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     io::BufRead,
     path::{Path, PathBuf},
@@ -25,6 +25,38 @@ type BucketId = String;
 /// The identifier of a measurement type.
 type Label = String;
 
+/// The latency percentiles of a workload, interpolated from the prometheus latency buckets.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+pub struct LatencyPercentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p99_9: Duration,
+}
+
+impl LatencyPercentiles {
+    /// Compute the percentiles from cumulative prometheus buckets.
+    pub fn from_buckets(buckets: &HashMap<BucketId, usize>) -> Self {
+        let percentile = |p| Measurement::percentile_from_buckets(buckets, p);
+        Self {
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+            p99_9: percentile(99.9),
+        }
+    }
+
+    /// The name and value of each percentile.
+    pub fn named(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("p50", self.p50),
+            ("p90", self.p90),
+            ("p99", self.p99),
+            ("p99.9", self.p99_9),
+        ]
+    }
+}
+
 /// A snapshot measurement at a given time.
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct Measurement {
@@ -116,6 +148,44 @@ impl Measurement {
         self.sum.checked_div(self.count as u32).unwrap_or_default()
     }
 
+    /// Compute a latency percentile (between 0 and 100), see `percentile_from_buckets`.
+    pub fn latency_percentile(&self, percentile: f64) -> Duration {
+        Self::percentile_from_buckets(&self.buckets, percentile)
+    }
+
+    /// Compute a latency percentile from cumulative prometheus buckets. Like prometheus'
+    /// `histogram_quantile`, the latency is linearly interpolated within the bucket holding the
+    /// percentile, and values in the last (infinite) bucket are reported as its lower bound.
+    pub fn percentile_from_buckets(
+        buckets: &HashMap<BucketId, usize>,
+        percentile: f64,
+    ) -> Duration {
+        let mut buckets: Vec<(f64, usize)> = buckets
+            .iter()
+            .filter_map(|(id, count)| id.parse().ok().map(|bound| (bound, *count)))
+            .collect();
+        buckets.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        let total = buckets.last().map(|(_, count)| *count).unwrap_or_default();
+        if total == 0 {
+            return Duration::default();
+        }
+        let rank = total as f64 * percentile.clamp(0.0, 100.0) / 100.0;
+
+        let (mut lower_bound, mut lower_count) = (0.0, 0);
+        for (bound, count) in buckets {
+            if count as f64 >= rank && count > lower_count {
+                if bound == f64::INFINITY {
+                    break;
+                }
+                let fraction = (rank - lower_count as f64) / (count - lower_count) as f64;
+                return Duration::from_secs_f64(lower_bound + (bound - lower_bound) * fraction);
+            }
+            (lower_bound, lower_count) = (bound, count);
+        }
+        Duration::from_secs_f64(lower_bound)
+    }
+
     /// Compute the standard deviation from the sum of squared latencies:
     /// `stdev = sqrt( squared_sum / count - avg^2 )`
     pub fn stdev_latency(&self) -> Duration {
@@ -154,6 +224,30 @@ impl Measurement {
             },
         )
     }
+
+    /// Make a measurement of transactions that all have the specified latency.
+    #[cfg(test)]
+    pub fn new_for_test_with_latency(timestamp: Duration, count: usize, latency: Duration) -> Self {
+        let bounds = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, f64::INFINITY];
+        let buckets = bounds
+            .iter()
+            .map(|bound| {
+                let count = if latency.as_secs_f64() <= *bound {
+                    count
+                } else {
+                    0
+                };
+                (bound.to_string(), count)
+            })
+            .collect();
+        Self {
+            timestamp,
+            buckets,
+            sum: latency * count as u32,
+            count,
+            squared_sum: Duration::from_secs_f64(latency.as_secs_f64().powf(2.0) * count as f64),
+        }
+    }
 }
 
 /// The identifier of the scrapers collecting the prometheus metrics.
@@ -167,6 +261,9 @@ pub struct MeasurementsCollection<T> {
     pub commit: String,
     /// The benchmark parameters of the current run.
     pub parameters: BenchmarkParameters<T>,
+    /// The index of the run among the trials of the same parameters.
+    #[serde(default)]
+    pub trial: usize,
     /// The data collected by each scraper.
     pub data: HashMap<Label, HashMap<ScraperId, Vec<Measurement>>>,
 }
@@ -178,10 +275,17 @@ impl<T: BenchmarkType> MeasurementsCollection<T> {
             machine_specs: settings.specs.clone(),
             commit: settings.repository.commit.clone(),
             parameters,
+            trial: 0,
             data: HashMap::new(),
         }
     }
 
+    /// Set the index of the run among the trials of the same parameters.
+    pub fn with_trial(mut self, trial: usize) -> Self {
+        self.trial = trial;
+        self
+    }
+
     /// Load a collection of measurement from a json file.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
         let data = fs::read(path)?;
@@ -189,6 +293,17 @@ impl<T: BenchmarkType> MeasurementsCollection<T> {
         Ok(measurements)
     }
 
+    /// Create a collection with a single measurement of the specified throughput and latency.
+    #[cfg(test)]
+    pub fn new_for_test(parameters: BenchmarkParameters<T>, tps: usize, latency: Duration) -> Self {
+        let settings = Settings::new_for_test();
+        let duration = Duration::from_secs(10);
+        let mut collection = Self::new(&settings, parameters);
+        let measurement = Measurement::new_for_test_with_latency(duration, tps * 10, latency);
+        collection.add(0, "default".into(), measurement);
+        collection
+    }
+
     /// Add a new measurement to the collection.
     pub fn add(&mut self, scraper_id: ScraperId, label: String, measurement: Measurement) {
         self.data
@@ -271,11 +386,56 @@ impl<T: BenchmarkType> MeasurementsCollection<T> {
             .unwrap_or_default()
     }
 
-    /// Save the collection of measurements as a json file.
+    /// Aggregate a latency percentile of multiple data points by merging their buckets.
+    pub fn aggregate_latency_percentile(&self, label: &Label, percentile: f64) -> Duration {
+        Measurement::percentile_from_buckets(&self.aggregate_buckets(label), percentile)
+    }
+
+    /// Aggregate the summarized latency percentiles of multiple data points by merging their
+    /// buckets.
+    pub fn aggregate_latency_percentiles(&self, label: &Label) -> LatencyPercentiles {
+        LatencyPercentiles::from_buckets(&self.aggregate_buckets(label))
+    }
+
+    /// Merge the latency buckets of the last data point of each scraper.
+    fn aggregate_buckets(&self, label: &Label) -> HashMap<BucketId, usize> {
+        let mut buckets = HashMap::new();
+        for data_point in self.all_measurements(label).iter().filter_map(|x| x.last()) {
+            for (id, count) in &data_point.buckets {
+                *buckets.entry(id.clone()).or_default() += count;
+            }
+        }
+        buckets
+    }
+
+    /// Summarize the aggregated measurements of each workload.
+    pub fn summary(&self) -> BTreeMap<Label, WorkloadSummary> {
+        self.labels()
+            .map(|label| {
+                let summary = WorkloadSummary {
+                    tps: self.aggregate_tps(label),
+                    average_latency: self.aggregate_average_latency(label),
+                    stdev_latency: self.aggregate_stdev_latency(label),
+                    latency_percentiles: self.aggregate_latency_percentiles(label),
+                };
+                (label.clone(), summary)
+            })
+            .collect()
+    }
+
+    /// Save the collection of measurements as a json file, along with their summary. Each trial
+    /// of the same parameters has its own file.
     pub fn save<P: AsRef<Path>>(&self, path: P) {
-        let json = serde_json::to_string_pretty(self).expect("Cannot serialize metrics");
+        let summarized = SummarizedMeasurements {
+            collection: self,
+            summary: self.summary(),
+        };
+        let json = serde_json::to_string_pretty(&summarized).expect("Cannot serialize metrics");
         let mut file = PathBuf::from(path.as_ref());
-        file.push(format!("measurements-{:?}.json", self.parameters));
+        file.push(format!(
+            "measurements-{:?}-{}.json",
+            self.parameters, self.trial
+        ));
         fs::write(file, json).unwrap();
     }
 
@@ -306,6 +466,10 @@ impl<T: BenchmarkType> MeasurementsCollection<T> {
             table.add_row(row![b->"TPS:", format!("{total_tps} tx/s")]);
             table.add_row(row![b->"Latency (avg):", format!("{} ms", average_latency.as_millis())]);
             table.add_row(row![b->"Latency (stdev):", format!("{} ms", stdev_latency.as_millis())]);
+            for (name, latency) in self.aggregate_latency_percentiles(label).named() {
+                let name = format!("Latency ({name}):");
+                table.add_row(row![b->name, format!("{} ms", latency.as_millis())]);
+            }
         }
 
         display::newline();
@@ -314,16 +478,35 @@ impl<T: BenchmarkType> MeasurementsCollection<T> {
     }
 }
 
+/// The aggregated measurements of a workload.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorkloadSummary {
+    pub tps: u64,
+    pub average_latency: Duration,
+    pub stdev_latency: Duration,
+    pub latency_percentiles: LatencyPercentiles,
+}
+
+/// The saved form of a collection of measurements, which `MeasurementsCollection::load` reads
+/// back ignoring the summary.
+#[derive(Serialize)]
+struct SummarizedMeasurements<'a, T> {
+    #[serde(flatten)]
+    collection: &'a MeasurementsCollection<T>,
+    /// The summary of each workload, for the tools processing the results.
+    summary: BTreeMap<Label, WorkloadSummary>,
+}
+
 #[cfg(test)]
 mod test {
-    use std::{collections::HashMap, time::Duration};
+    use std::{collections::HashMap, fs, time::Duration};
 
     use crate::{
         benchmark::test::TestBenchmarkType, protocol::test_protocol_metrics::TestProtocolMetrics,
         settings::Settings,
     };
 
-    use super::{BenchmarkParameters, Measurement, MeasurementsCollection};
+    use super::{BenchmarkParameters, Measurement, MeasurementsCollection, WorkloadSummary};
 
     #[test]
     fn average_latency() {
@@ -360,6 +543,32 @@ mod test {
         assert_eq!((stdev.as_secs_f64() * 10.0).round(), 7.0);
     }
 
+    #[test]
+    fn latency_percentile() {
+        let data = Measurement {
+            buckets: [("0.1", 0), ("0.5", 50), ("1", 90), ("2", 99), ("inf", 100)]
+                .iter()
+                .map(|(id, count)| (id.to_string(), *count))
+                .collect(),
+            count: 100,
+            ..Default::default()
+        };
+
+        // Interpolated within the bucket holding the percentile.
+        assert_eq!(data.latency_percentile(50.0), Duration::from_millis(500));
+        assert_eq!(data.latency_percentile(25.0), Duration::from_millis(300));
+        assert_eq!(
+            data.latency_percentile(95.0),
+            Duration::from_secs_f64(1.0 + 5.0 / 9.0)
+        );
+        // Values in the infinite bucket are reported as its lower bound.
+        assert_eq!(data.latency_percentile(99.9), Duration::from_secs(2));
+        assert_eq!(
+            Measurement::default().latency_percentile(50.0),
+            Duration::default()
+        );
+    }
+
     #[test]
     fn prometheus_parse() {
         let report = r#"
@@ -466,4 +675,40 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn aggregate_latency_percentiles() {
+        let settings = Settings::new_for_test();
+        let mut aggregator = MeasurementsCollection::<TestBenchmarkType>::new(
+            &settings,
+            BenchmarkParameters::default(),
+        );
+        let duration = Duration::from_secs(10);
+        let fast = Measurement::new_for_test_with_latency(duration, 90, Duration::from_secs(1));
+        let slow = Measurement::new_for_test_with_latency(duration, 10, Duration::from_secs(2));
+        aggregator.add(0, "owned".into(), fast);
+        aggregator.add(1, "owned".into(), slow);
+
+        // The buckets of the scrapers are merged before interpolating the percentiles.
+        let label = "owned".to_string();
+        let percentiles = aggregator.aggregate_latency_percentiles(&label);
+        assert_eq!(percentiles.p50.as_micros(), 777_777);
+        assert_eq!(percentiles.p90.as_micros(), 1_000_000);
+        assert_eq!(percentiles.p99.as_micros(), 1_900_000);
+        assert_eq!(percentiles.p99_9.as_micros(), 1_990_000);
+
+        // The summary is saved along with the measurements, and ignored when loading them.
+        let directory = tempfile::tempdir().unwrap();
+        aggregator.save(directory.path());
+        let file = directory
+            .path()
+            .join(format!("measurements-{:?}-0.json", aggregator.parameters));
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+        let summary: WorkloadSummary =
+            serde_json::from_value(json["summary"]["owned"].clone()).unwrap();
+        assert_eq!(summary.latency_percentiles, percentiles);
+        assert_eq!(summary.tps, 9);
+        let loaded = MeasurementsCollection::<TestBenchmarkType>::load(&file).unwrap();
+        assert_eq!(loaded.summary()[&label], summary);
+    }
 }
@@ -3,10 +3,11 @@
 
 use futures::future::select_all;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     fs::{self},
     marker::PhantomData,
     path::PathBuf,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 use tokio::select;
@@ -20,7 +21,7 @@ use crate::{
     client::Instance,
     display, ensure,
     error::{TestbedError, TestbedResult},
-    faults::CrashRecoverySchedule,
+    faults::{CrashRecoverySchedule, NetworkFaultsSchedule},
     logs::LogsAnalyzer,
     measurement::{Measurement, MeasurementsCollection},
     protocol::{ProtocolCommands, ProtocolMetrics},
@@ -58,6 +59,8 @@ pub struct Orchestrator<P, T> {
     dedicated_clients: usize,
     /// Whether to start a grafana and prometheus instance on a dedicate machine.
     monitoring: bool,
+    /// The nodes currently crashed by the faults schedule, which the node monitor ignores.
+    crashed_nodes: Arc<Mutex<HashSet<Instance>>>,
 }
 
 impl<P, T> Orchestrator<P, T> {
@@ -88,6 +91,7 @@ impl<P, T> Orchestrator<P, T> {
             log_processing: false,
             dedicated_clients: 0,
             monitoring: true,
+            crashed_nodes: Arc::default(),
         }
     }
 
@@ -217,10 +221,15 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         instances: Vec<Instance>,
         parameters: &BenchmarkParameters<T>,
     ) -> TestbedResult<NodeMonitorHandle> {
-        // Run one node per instance.
-        let targets = self
+        // Run one node per instance. The commands depend on the position of the nodes in the
+        // committee, so they are generated for all nodes (some may be recovering from a crash).
+        let (_, nodes, _) = self.select_instances(parameters)?;
+        let targets: Vec<_> = self
             .protocol_commands
-            .node_command(instances.clone(), parameters);
+            .node_command(nodes.clone(), parameters)
+            .into_iter()
+            .filter(|(instance, _)| instances.contains(instance))
+            .collect();
 
         let repo = self.settings.repository_name();
         let context = CommandContext::new()
@@ -232,9 +241,12 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
             .await?;
 
         // Wait until all nodes are reachable.
-        let commands = self
+        let commands: Vec<_> = self
             .protocol_commands
-            .nodes_metrics_command(instances.clone());
+            .nodes_metrics_command(nodes)
+            .into_iter()
+            .filter(|(instance, _)| instances.contains(instance))
+            .collect();
         self.ssh_manager.wait_for_success(commands).await;
 
         self.start_monitor(instances)
@@ -246,8 +258,9 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         let targets = self.protocol_commands.monitor_command(instances);
         let (handle, mut receiver) = NodeMonitorHandle::new();
         let ssh_manager = self.ssh_manager.clone();
+        let crashed_nodes = self.crashed_nodes.clone();
         tokio::spawn(async move {
-            let monitor = Self::run_monitor(ssh_manager, targets);
+            let monitor = Self::run_monitor(ssh_manager, targets, crashed_nodes);
             select! {
                 _ = monitor => {
                     unreachable!()
@@ -260,8 +273,15 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         Ok(handle)
     }
 
-    async fn run_monitor(ssh_manager: SshConnectionManager, targets: Vec<(Instance, String)>) {
+    async fn run_monitor(
+        ssh_manager: SshConnectionManager,
+        mut targets: Vec<(Instance, String)>,
+        crashed_nodes: Arc<Mutex<HashSet<Instance>>>,
+    ) {
         loop {
+            if targets.is_empty() {
+                return futures::future::pending().await;
+            }
             let r = ssh_manager.run_per_instance(targets.clone(), CommandContext::new());
             let (output, i, _) = select_all(r).await;
             let output = output.unwrap();
@@ -272,6 +292,11 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
                     panic!("Monitor command on {} failed with code: {}", i, code)
                 }
             };
+            // Nodes crashed on purpose are no longer monitored.
+            if crashed_nodes.lock().unwrap().contains(&targets[i].0) {
+                targets.remove(i);
+                continue;
+            }
             eprintln!("Node {} failed:\n{}", instance, result);
             std::process::exit(1);
         }
@@ -519,10 +544,11 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         Ok(())
     }
 
-    /// Collect metrics from the load generators.
+    /// Collect metrics from the load generators, for the specified trial of the parameters.
     pub async fn run(
         &self,
         parameters: &BenchmarkParameters<T>,
+        trial: usize,
     ) -> TestbedResult<MeasurementsCollection<T>> {
         display::action(format!(
             "Scraping metrics (at least {}s)",
@@ -535,63 +561,102 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         // Regularly scrape the client metrics.
         let metrics_commands = self.protocol_commands.clients_metrics_command(clients);
 
-        let mut aggregator = MeasurementsCollection::new(&self.settings, parameters.clone());
+        let mut aggregator =
+            MeasurementsCollection::new(&self.settings, parameters.clone()).with_trial(trial);
         let mut metrics_interval = time::interval(self.scrape_interval);
         metrics_interval.tick().await; // The first tick returns immediately.
 
         let faults_type = parameters.faults.clone();
-        let mut faults_schedule = CrashRecoverySchedule::new(faults_type, nodes.clone());
+        self.crashed_nodes.lock().unwrap().clear();
+        let mut faults_schedule = CrashRecoverySchedule::new(faults_type.clone(), nodes.clone());
+        let mut network_schedule = NetworkFaultsSchedule::new(faults_type, nodes.clone());
         let mut faults_interval = time::interval(self.crash_interval);
         faults_interval.tick().await; // The first tick returns immediately.
 
         let start = Instant::now();
-        loop {
-            tokio::select! {
-                // Scrape metrics.
-                now = metrics_interval.tick() => {
-                    let elapsed = now.duration_since(start).as_secs_f64().ceil() as u64;
-                    display::status(format!("{elapsed}s"));
-
-                    let stdio = self
-                        .ssh_manager
-                        .execute_per_instance(metrics_commands.clone(), CommandContext::default())
-                        .await?;
-                    for (i, (stdout, _stderr)) in stdio.iter().enumerate() {
-                        for (label, measurement) in Measurement::from_prometheus::<P>(stdout) {
-                            aggregator.add(i, label,measurement);
+        // Restore the network below even if scraping or a testbed update fails.
+        let scraped: TestbedResult<()> = async {
+            loop {
+                tokio::select! {
+                    // Scrape metrics.
+                    now = metrics_interval.tick() => {
+                        let elapsed = now.duration_since(start).as_secs_f64().ceil() as u64;
+                        display::status(format!("{elapsed}s"));
+
+                        // Skip the crashed nodes, their metrics are unavailable until they recover.
+                        let crashed_nodes = self.crashed_nodes.lock().unwrap().clone();
+                        let (scrapers, targets): (Vec<_>, Vec<_>) = metrics_commands
+                            .iter()
+                            .cloned()
+                            .enumerate()
+                            .filter(|(_, (instance, _))| !crashed_nodes.contains(instance))
+                            .unzip();
+                        let stdio = self
+                            .ssh_manager
+                            .execute_per_instance(targets, CommandContext::default())
+                            .await?;
+                        for (i, (stdout, _stderr)) in scrapers.into_iter().zip(stdio.iter()) {
+                            for (label, measurement) in Measurement::from_prometheus::<P>(stdout) {
+                                aggregator.add(i, label,measurement);
+                            }
                         }
-                    }
 
-                    let results_directory = &self.settings.results_dir;
-                    let commit = &self.settings.repository.commit;
-                    let path: PathBuf = [results_directory, &format!("results-{commit}").into()]
-                        .iter()
-                        .collect();
-                    fs::create_dir_all(&path).expect("Failed to create log directory");
-                    aggregator.save(path);
+                        let results_directory = &self.settings.results_dir;
+                        let commit = &self.settings.repository.commit;
+                        let path: PathBuf = [results_directory, &format!("results-{commit}").into()]
+                            .iter()
+                            .collect();
+                        fs::create_dir_all(&path).expect("Failed to create log directory");
+                        aggregator.save(path);
 
-                    if elapsed > parameters.duration .as_secs() {
-                        break;
-                    }
-                },
+                        if elapsed > parameters.duration .as_secs() {
+                            break;
+                        }
+                    },
+
+                    // Kill and recover nodes according to the input schedule.
+                    _ = faults_interval.tick() => {
+                        let  action = faults_schedule.update();
+                        if !action.kill.is_empty() {
+                            self.crashed_nodes.lock().unwrap().extend(action.kill.iter().cloned());
+                            self.ssh_manager.kill(action.kill.clone(), "node").await?;
+                        }
+                        if !action.boot.is_empty() {
+                            self.crashed_nodes.lock().unwrap().retain(|x| !action.boot.contains(x));
+                            // Monitor not yet supported for this
+                            let _: NodeMonitorHandle = self.boot_nodes(action.boot.clone(), parameters).await?;
+                        }
+                        if !action.kill.is_empty() || !action.boot.is_empty() {
+                            display::newline();
+                            display::config("Testbed update", action);
+                        }
 
-                // Kill and recover nodes according to the input schedule.
-                _ = faults_interval.tick() => {
-                    let  action = faults_schedule.update();
-                    if !action.kill.is_empty() {
-                        self.ssh_manager.kill(action.kill.clone(), "node").await?;
-                    }
-                    if !action.boot.is_empty() {
-                        // Monitor not yet supported for this
-                        let _: NodeMonitorHandle = self.boot_nodes(action.boot.clone(), parameters).await?;
-                    }
-                    if !action.kill.is_empty() || !action.boot.is_empty() {
-                        display::newline();
-                        display::config("Testbed update", action);
+                        let action = network_schedule.update();
+                        if !action.is_empty() {
+                            let commands = action.commands.clone();
+                            self.ssh_manager.execute_per_instance(commands, CommandContext::default()).await?;
+                            display::newline();
+                            display::config("Testbed update", action);
+                        }
                     }
                 }
             }
+            Ok(())
         }
+        .await;
+
+        // Leave the network of the testbed healthy for the next runs.
+        let action = network_schedule.restore();
+        let restored = if action.is_empty() {
+            Ok(())
+        } else {
+            self.ssh_manager
+                .execute_per_instance(action.commands, CommandContext::default())
+                .await
+                .map(|_| ())
+        };
+        scraped?;
+        restored?;
 
         display::done();
         Ok(aggregator)
@@ -624,8 +689,7 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         for (i, instance) in clients.iter().enumerate() {
             display::status(format!("{}/{}", i + 1, clients.len()));
 
-            let connection = self.ssh_manager.connect(instance.ssh_address()).await?;
-            let client_log_content = connection.download("client.log")?;
+            let client_log_content = self.ssh_manager.download(instance, "client.log").await?;
 
             let client_log_file = [path.clone(), format!("client-{i}.log").into()]
                 .iter()
@@ -643,8 +707,7 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         for (i, instance) in nodes.iter().enumerate() {
             display::status(format!("{}/{}", i + 1, nodes.len()));
 
-            let connection = self.ssh_manager.connect(instance.ssh_address()).await?;
-            let node_log_content = connection.download("node.log")?;
+            let node_log_content = self.ssh_manager.download(instance, "node.log").await?;
 
             let node_log_file = [path.clone(), format!("node-{i}.log").into()]
                 .iter()
@@ -705,7 +768,7 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
             self.run_clients(&parameters).await?;
 
             // Wait for the benchmark to terminate. Then save the results and print a summary.
-            let aggregator = self.run(&parameters).await?;
+            let aggregator = self.run(&parameters, generator.trial()).await?;
             aggregator.display_summary();
             generator.register_result(aggregator);
             drop(monitor);
@@ -723,6 +786,9 @@ impl<P: ProtocolCommands<T> + ProtocolMetrics, T: BenchmarkType> Orchestrator<P,
         }
 
         display::header("Benchmark completed");
+        if let Some(breaking_point) = generator.breaking_point() {
+            display::config("Breaking point", breaking_point);
+        }
         Ok(())
     }
 }
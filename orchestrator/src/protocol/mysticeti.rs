@@ -107,7 +107,7 @@ impl ProtocolCommands<MysticetiBenchmarkType> for MysticetiProtocol {
             .map(|i| {
                 (
                     i,
-                    "tail -f --pid=$(pidof mysticeti) -f /dev/null; tail -100 node.log".to_string(),
+                    "tail -f --pid=$(cat node.pid) -f /dev/null; tail -100 node.log".to_string(),
                 )
             })
             .collect()
@@ -141,8 +141,11 @@ impl ProtocolCommands<MysticetiBenchmarkType> for MysticetiProtocol {
                 .iter()
                 .collect();
 
+                // The node replaces the shell (cargo run also execs the binary), so that the pid
+                // file identifies the node among the ones of other (local) instances.
                 let env = env::var("ENV").unwrap_or_default();
                 let run = [
+                    "exec env",
                     &env,
                     &format!("{RUST_FLAGS} cargo run {CARGO_FLAGS} --bin mysticeti --"),
                     "run",
@@ -159,8 +162,10 @@ impl ProtocolCommands<MysticetiBenchmarkType> for MysticetiProtocol {
                 .join(" ");
                 let tps = format!("export TPS={}", parameters.load / parameters.nodes);
                 let tx_size = format!("export TRANSACTION_SIZE={}", parameters.benchmark_type.transaction_size);
-                let command = ["#!/bin/bash -e", "source $HOME/.cargo/env", &tps, &tx_size, &run].join("\\n");
-                let command = format!("echo -e '{command}' > mysticeti-start.sh && chmod +x mysticeti-start.sh && ./mysticeti-start.sh");
+                // Escaped to be expanded by the script rather than when starting it in the background.
+                let pid = "echo \\$\\$ > ~/node.pid";
+                let command = ["#!/bin/bash -e", "source $HOME/.cargo/env", &tps, &tx_size, pid, &run].join("\\n");
+                let command = format!("echo -e '{command}' > ~/mysticeti-start.sh && chmod +x ~/mysticeti-start.sh && ~/mysticeti-start.sh");
 
                 (instance, command)
             })
@@ -46,6 +46,9 @@ pub enum CloudProvider {
     Aws,
     #[serde(alias = "vultr")]
     Vultr,
+    /// Run all instances as processes on the local machine.
+    #[serde(alias = "local")]
+    Local,
 }
 
 /// The testbed settings. Those are topically specified in a file.
@@ -56,9 +59,12 @@ pub struct Settings {
     pub testbed_id: String,
     /// The cloud provider hosting the testbed.
     pub cloud_provider: CloudProvider,
-    /// The path to the secret token for authentication with the cloud provider.
+    /// The path to the secret token for authentication with the cloud provider. Not needed for
+    /// local testbeds.
+    #[serde(default)]
     pub token_file: PathBuf,
-    /// The ssh private key to access the instances.
+    /// The ssh private key to access the instances. Not needed for local testbeds.
+    #[serde(default)]
     pub ssh_private_key_file: PathBuf,
     /// The corresponding ssh public key registered on the instances. If not specified. the
     /// public key defaults the same path as the private key with an added extension 'pub'.
@@ -2,9 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
+    fs,
     io::{Read, Write},
     net::SocketAddr,
     path::{Path, PathBuf},
+    process::Stdio,
     time::Duration,
 };
 
@@ -12,10 +14,10 @@ use futures::future::try_join_all;
 use ssh2::{Channel, Session};
 use tokio::runtime::Handle;
 use tokio::task::JoinHandle;
-use tokio::{net::TcpStream, time::sleep};
+use tokio::{net::TcpStream, process::Command, time::sleep};
 
 use crate::{
-    client::Instance,
+    client::{local::LocalClient, Instance},
     ensure,
     error::{SshError, SshResult},
 };
@@ -105,6 +107,9 @@ pub struct SshConnectionManager {
     timeout: Option<Duration>,
     /// The number of retries before giving up to execute the command.
     retries: usize,
+    /// The root directory of a local testbed, whose instances run their commands on this machine
+    /// rather than through ssh.
+    local_root: Option<PathBuf>,
 }
 
 impl SshConnectionManager {
@@ -118,6 +123,19 @@ impl SshConnectionManager {
             private_key_file,
             timeout: None,
             retries: 0,
+            local_root: None,
+        }
+    }
+
+    /// Create a manager executing the commands of the instances of a local testbed on this
+    /// machine, each from the home directory of its instance (see `LocalClient`).
+    pub fn local(root: PathBuf) -> Self {
+        Self {
+            username: String::new(),
+            private_key_file: PathBuf::new(),
+            timeout: None,
+            retries: 0,
+            local_root: Some(root),
         }
     }
 
@@ -198,6 +216,10 @@ impl SshConnectionManager {
                 let context = context.clone();
 
                 tokio::spawn(async move {
+                    if let Some(root) = &ssh_manager.local_root {
+                        let connection = LocalConnection::new(root, &instance);
+                        return connection.execute(context.apply(command)).await;
+                    }
                     let connection = ssh_manager.connect(instance.ssh_address()).await?;
                     // SshConnection::execute is a blocking call, needs to go to blocking pool
                     Handle::current()
@@ -257,6 +279,19 @@ impl SshConnectionManager {
         }
     }
 
+    /// Download a file from the home directory of an instance.
+    pub async fn download<P: AsRef<Path>>(
+        &self,
+        instance: &Instance,
+        path: P,
+    ) -> SshResult<String> {
+        if let Some(root) = &self.local_root {
+            return LocalConnection::new(root, instance).download(path);
+        }
+        let connection = self.connect(instance.ssh_address()).await?;
+        connection.download(path)
+    }
+
     /// Kill a command running in the background of the specified instances.
     pub async fn kill<I>(&self, instances: I, command_id: &str) -> SshResult<()>
     where
@@ -450,3 +485,60 @@ impl SshConnection {
         Err(error.unwrap())
     }
 }
+
+/// Execution of the commands of a local instance, in a shell on this machine.
+pub struct LocalConnection {
+    /// The home directory of the instance, from which its commands run. Each instance also has
+    /// its own tmux server, even when the orchestrator itself runs in tmux.
+    home: PathBuf,
+    /// The address of the instance, to report errors.
+    address: SocketAddr,
+}
+
+impl LocalConnection {
+    pub fn new(root: &Path, instance: &Instance) -> Self {
+        Self {
+            home: LocalClient::home(root, instance),
+            address: instance.ssh_address(),
+        }
+    }
+
+    /// Execute a command and return both stdout and stderr.
+    pub async fn execute(&self, command: String) -> SshResult<(String, String)> {
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.home)
+            .env("HOME", &self.home)
+            .env("TMUX_TMPDIR", &self.home)
+            .env_remove("TMUX")
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|error| SshError::ConnectionError {
+                address: self.address,
+                error,
+            })?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        ensure!(
+            output.status.success(),
+            SshError::NonZeroExitCode {
+                address: self.address,
+                code: output.status.code().unwrap_or(-1),
+                message: stderr.clone()
+            }
+        );
+
+        Ok((stdout, stderr))
+    }
+
+    /// Read a file of the home directory of the instance.
+    pub fn download<P: AsRef<Path>>(&self, path: P) -> SshResult<String> {
+        fs::read_to_string(self.home.join(path)).map_err(|error| SshError::ConnectionError {
+            address: self.address,
+            error,
+        })
+    }
+}
@@ -8,7 +8,7 @@ use prettytable::{row, Table};
 use tokio::time::{self, Instant};
 
 use crate::{
-    client::ServerProviderClient,
+    client::{local::LocalClient, ServerProviderClient},
     display,
     error::{TestbedError, TestbedResult},
     settings::Settings,
@@ -30,8 +30,10 @@ pub struct Testbed<C> {
 impl<C: ServerProviderClient> Testbed<C> {
     /// Create a new testbed instance with the specified settings and client.
     pub async fn new(settings: Settings, client: C) -> TestbedResult<Self> {
-        let public_key = settings.load_ssh_public_key()?;
-        client.register_ssh_public_key(public_key).await?;
+        if C::REMOTE {
+            let public_key = settings.load_ssh_public_key()?;
+            client.register_ssh_public_key(public_key).await?;
+        }
         let instances = client.list_instances().await?;
 
         Ok(Self {
@@ -96,10 +98,15 @@ impl<C: ServerProviderClient> Testbed<C> {
                 if j % 5 == 0 {
                     table.add_row(row![]);
                 }
-                let private_key_file = self.settings.ssh_private_key_file.display();
-                let username = C::USERNAME;
-                let ip = instance.main_ip;
-                let connect = format!("ssh -i {private_key_file} {username}@{ip}");
+                let connect = if C::REMOTE {
+                    let private_key_file = self.settings.ssh_private_key_file.display();
+                    let username = C::USERNAME;
+                    let ip = instance.main_ip;
+                    format!("ssh -i {private_key_file} {username}@{ip}")
+                } else {
+                    let root = LocalClient::root(&self.settings);
+                    format!("cd {}", LocalClient::home(&root, instance).display())
+                };
                 if !instance.is_terminated() {
                     if instance.is_active() {
                         table.add_row(row![bFg->format!("{j}"), connect]);
@@ -141,7 +148,7 @@ impl<C: ServerProviderClient> Testbed<C> {
         };
 
         // Wait until the instances are booted.
-        if cfg!(not(test)) {
+        if cfg!(not(test)) && C::REMOTE {
             self.wait_until_reachable(instances.iter()).await?;
         }
         self.instances = self.client.list_instances().await?;
@@ -189,7 +196,7 @@ impl<C: ServerProviderClient> Testbed<C> {
         self.client.start_instances(available.iter()).await?;
 
         // Wait until the instances are started.
-        if cfg!(not(test)) {
+        if cfg!(not(test)) && C::REMOTE {
             self.wait_until_reachable(available.iter()).await?;
         }
         self.instances = self.client.list_instances().await?;